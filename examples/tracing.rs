@@ -0,0 +1,32 @@
+//! Logs the lifecycle of a session: run with
+//! `cargo run --example tracing --features tracing`.
+
+use std::time::{Duration, Instant};
+
+use delay_data_rs::{decoder::ThresholdDelayDecoder, session_store::DelaySessionStoreBuilder};
+use futures::StreamExt;
+use tracing::Level;
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_max_level(Level::TRACE)
+        .init();
+
+    let (store, mut results) =
+        DelaySessionStoreBuilder::<&str, _, _>::new(Duration::from_millis(100))
+            .key_formatter(|key, f| f.write_str(key))
+            .build_with_factory(|| ThresholdDelayDecoder::new(Duration::from_millis(20)));
+
+    let start = Instant::now();
+    for offset in [0, 10, 40, 50] {
+        store
+            .push_signal("client", start + Duration::from_millis(offset))
+            .await
+            .unwrap();
+    }
+
+    if let Some((key, bits)) = results.next().await {
+        println!("{key}: {bits}");
+    }
+}
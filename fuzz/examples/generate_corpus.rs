@@ -0,0 +1,94 @@
+//! Writes seed corpora for the fuzz targets from generated traffic.
+//!
+//! Run from the `fuzz` directory with `cargo run --example generate_corpus`.
+
+use std::{fs, io, path::Path, time::Duration};
+
+use delay_data_rs::{
+    decoder::AverageDelayDecoder,
+    encoder::ThresholdDelayEncoder,
+    record::signal_recorder,
+    session_store::DelaySessionStoreBuilder,
+    testing::{ArrivalModel, TrafficConfig, TrafficGenerator},
+};
+use delay_data_rs_fuzz::{encode_session_signals, FuzzSignal};
+
+const SEEDS: u64 = 4;
+const TIMEOUT_MILLIS: u8 = 40;
+
+fn main() -> io::Result<()> {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+    let session_signals = corpus.join("session_signals");
+    let record_codec = corpus.join("record_codec");
+    fs::create_dir_all(&session_signals)?;
+    fs::create_dir_all(&record_codec)?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()?;
+
+    for seed in 0..SEEDS {
+        let traffic = TrafficGenerator::new(
+            TrafficConfig {
+                keys: 8,
+                message_bits: 16,
+                noise_signals: 16,
+                arrival: ArrivalModel::Poisson {
+                    mean: Duration::from_millis(20),
+                },
+                start_spread: Duration::from_millis(50),
+                seed,
+                ..TrafficConfig::default()
+            },
+            ThresholdDelayEncoder::new(Duration::from_millis(5), Duration::from_millis(15)),
+        )
+        .generate(runtime.block_on(async { tokio::time::Instant::now().into_std() }));
+
+        let mut previous = traffic.events.first().map(|(_, instant)| *instant);
+        let signals: Vec<FuzzSignal> = traffic
+            .events
+            .iter()
+            .map(|(key, instant)| {
+                let gap = previous.map_or(Duration::ZERO, |previous| *instant - previous);
+                previous = Some(*instant);
+                FuzzSignal {
+                    key: *key as u8,
+                    gap_micros: u16::try_from(gap.as_micros()).unwrap_or(u16::MAX),
+                    skew_micros: 0,
+                }
+            })
+            .collect();
+        for (name, wheel) in [("task", false), ("wheel", true)] {
+            fs::write(
+                session_signals.join(format!("traffic-{seed}-{name}")),
+                encode_session_signals(wheel, TIMEOUT_MILLIS, &signals),
+            )?;
+        }
+
+        let recording = runtime.block_on(async {
+            let start = traffic.events.first().map(|(_, instant)| *instant);
+            let (tap, recorder) = signal_recorder(start.unwrap_or_else(std::time::Instant::now));
+            let (store, _stream) = DelaySessionStoreBuilder::<u16>::new(Duration::from_millis(
+                u64::from(TIMEOUT_MILLIS),
+            ))
+            .tap(tap)
+            .build();
+            for (key, instant) in &traffic.events {
+                store.try_push_signal(*key as u16, *instant, AverageDelayDecoder::new);
+            }
+            drop(store);
+
+            let mut recording = Vec::new();
+            recorder
+                .run(&mut recording, |key, out| {
+                    out.extend_from_slice(&key.to_le_bytes())
+                })
+                .await
+                .map(|_| recording)
+        })?;
+        fs::write(record_codec.join(format!("traffic-{seed}")), recording)?;
+    }
+
+    Ok(())
+}
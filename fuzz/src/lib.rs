@@ -0,0 +1,210 @@
+//! Drivers of the fuzz targets, shared with the corpus generator.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    time::{Duration, Instant},
+};
+
+use arbitrary::{Arbitrary, Unstructured};
+use delay_data_rs::{
+    decoder::AverageDelayDecoder,
+    instant_policy::InstantPolicy,
+    record::{replay_offline, replay_with, signal_recorder, ReplaySpeed},
+    session_store::DelaySessionStoreBuilder,
+    timer_wheel::TimerWheelConfig,
+};
+use futures::{future::join, StreamExt};
+
+/// Bytes of a `session_signals` input before its signals: a flags byte, the
+/// timeout in milliseconds, and the bytes the `InstantPolicy` is built from.
+pub const SESSION_HEADER_LEN: usize = 10;
+const POLICY_BYTES: std::ops::Range<usize> = 2..SESSION_HEADER_LEN;
+const SIGNAL_LEN: usize = 5;
+const MAX_SIGNALS: usize = 1024;
+
+const RECORD_SIGNAL_LEN: usize = 6;
+
+/// A signal of a `session_signals` input.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FuzzSignal {
+    pub key: u8,
+    /// Time waited before pushing, in microseconds.
+    pub gap_micros: u16,
+    /// Offset of the signal's instant from the time it is pushed, in
+    /// microseconds, so instants can arrive out of order.
+    pub skew_micros: i16,
+}
+
+/// Encodes a `session_signals` input with the default `InstantPolicy`.
+pub fn encode_session_signals(wheel: bool, timeout_millis: u8, signals: &[FuzzSignal]) -> Vec<u8> {
+    let mut data = vec![u8::from(wheel), timeout_millis];
+    data.resize(SESSION_HEADER_LEN, 0);
+    for signal in signals {
+        data.push(signal.key);
+        data.extend_from_slice(&signal.gap_micros.to_le_bytes());
+        data.extend_from_slice(&signal.skew_micros.to_le_bytes());
+    }
+    data
+}
+
+/// Pushes the signals of `data` into a store under paused time, checking that
+/// no key decodes more bits, or closes more sessions, than it was sent
+/// signals.
+pub fn session_signals(data: &[u8]) {
+    if data.len() < SESSION_HEADER_LEN {
+        return;
+    }
+
+    let wheel = data[0] & 1 != 0;
+    let timeout = Duration::from_millis(u64::from(data[1].max(1)));
+    let Ok(policy) = InstantPolicy::arbitrary(&mut Unstructured::new(&data[POLICY_BYTES])) else {
+        return;
+    };
+    let signals: Vec<FuzzSignal> = data[SESSION_HEADER_LEN..]
+        .chunks_exact(SIGNAL_LEN)
+        .take(MAX_SIGNALS)
+        .map(|chunk| FuzzSignal {
+            key: chunk[0],
+            gap_micros: u16::from_le_bytes([chunk[1], chunk[2]]),
+            skew_micros: i16::from_le_bytes([chunk[3], chunk[4]]),
+        })
+        .collect();
+
+    block_on_paused(async {
+        let mut builder = DelaySessionStoreBuilder::<u8>::new(timeout).instant_policy(policy);
+        if wheel {
+            builder = builder.timer_wheel(TimerWheelConfig {
+                shards: 4,
+                workers: 2,
+                tick: Duration::from_millis(1),
+            });
+        }
+        let (store, stream) = builder.build();
+
+        let pushes = async {
+            for signal in &signals {
+                tokio::time::sleep(Duration::from_micros(u64::from(signal.gap_micros))).await;
+                let now = tokio::time::Instant::now().into_std();
+                let skew = Duration::from_micros(u64::from(signal.skew_micros.unsigned_abs()));
+                let instant = if signal.skew_micros < 0 {
+                    now.checked_sub(skew).unwrap_or(now)
+                } else {
+                    now + skew
+                };
+                let _ = store
+                    .push_signal(signal.key, instant, AverageDelayDecoder::new)
+                    .await;
+            }
+            drop(store);
+        };
+        let (_, results) = join(pushes, stream.collect::<Vec<_>>()).await;
+
+        let mut sent = HashMap::<u8, usize>::new();
+        for signal in &signals {
+            *sent.entry(signal.key).or_default() += 1;
+        }
+        let mut decoded = HashMap::<u8, (usize, usize)>::new();
+        for (key, bits) in &results {
+            let (sessions, total_bits) = decoded.entry(*key).or_default();
+            *sessions += 1;
+            *total_bits += bits.len();
+        }
+        for (key, (sessions, total_bits)) in decoded {
+            let sent = sent.get(&key).copied().unwrap_or(0);
+            assert!(
+                sessions <= sent,
+                "key {key}: {sessions} sessions from {sent} signals"
+            );
+            assert!(
+                total_bits <= sent,
+                "key {key}: {total_bits} bits from {sent} signals"
+            );
+        }
+    });
+}
+
+/// Parses `data` as a recording, which must fail cleanly if malformed, then
+/// records its bytes reinterpreted as signals and checks that replaying the
+/// recording reproduces them.
+pub fn record_codec(data: &[u8]) {
+    block_on_paused(async {
+        let _ = replay_offline(
+            data,
+            decode_key,
+            Duration::from_millis(10),
+            AverageDelayDecoder::new,
+        )
+        .await;
+
+        let anchor = tokio::time::Instant::now().into_std();
+        let mut instant = anchor;
+        let signals: Vec<(u16, Instant)> = data
+            .chunks_exact(RECORD_SIGNAL_LEN)
+            .take(MAX_SIGNALS)
+            .map(|chunk| {
+                let key = u16::from_le_bytes([chunk[0], chunk[1]]);
+                let gap = u32::from_le_bytes([chunk[2], chunk[3], chunk[4], chunk[5]]);
+                instant += Duration::from_micros(u64::from(gap));
+                (key, instant)
+            })
+            .collect();
+
+        let (tap, recorder) = signal_recorder(anchor);
+        let (store, _stream) = DelaySessionStoreBuilder::<u16>::new(Duration::from_secs(1))
+            .tap(tap)
+            .build();
+        for (key, instant) in &signals {
+            store.try_push_signal(*key, *instant, AverageDelayDecoder::new);
+        }
+        drop(store);
+
+        let mut recording = Vec::new();
+        let written = recorder
+            .run(&mut recording, |key, out| {
+                out.extend_from_slice(&key.to_le_bytes())
+            })
+            .await
+            .expect("writing to a Vec cannot fail");
+        assert_eq!(written, signals.len() as u64);
+
+        let mut replayed = Vec::new();
+        replay_with(
+            &recording[..],
+            decode_key,
+            ReplaySpeed::AsFastAsPossible,
+            |key, instant| replayed.push((key, instant)),
+        )
+        .await
+        .expect("a recording parses");
+
+        assert_eq!(replayed.len(), signals.len());
+        if let (Some((_, first)), Some((_, replayed_first))) = (signals.first(), replayed.first()) {
+            for ((key, instant), (replayed_key, replayed_instant)) in signals.iter().zip(&replayed)
+            {
+                assert_eq!(key, replayed_key);
+                assert_eq!(
+                    instant.duration_since(*first),
+                    replayed_instant.duration_since(*replayed_first)
+                );
+            }
+        }
+    });
+}
+
+fn decode_key(bytes: &[u8]) -> io::Result<u16> {
+    bytes
+        .try_into()
+        .map(u16::from_le_bytes)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "key is not two bytes"))
+}
+
+fn block_on_paused<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .expect("building a current-thread runtime")
+        .block_on(future)
+}
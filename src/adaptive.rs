@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use bitvec::vec::BitVec;
+
+use crate::{
+    decoder::DelayDecoder,
+    encoder::DelayEncoder,
+    evaluate::{run, EvalReport, NoiseModel},
+};
+
+/// Bits of each trial message while tuning.
+const TUNE_MESSAGE_LEN: usize = 64;
+/// Trials per evaluated separation.
+const TUNE_TRIALS: usize = 64;
+/// Every separation is evaluated on the same messages and noise, so BERs of
+/// different separations are comparable.
+const TUNE_SEED: u64 = 0x7475_6e65;
+
+/// The delays a two-level encoder sends for each bit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct DelayLevels {
+    pub zero: Duration,
+    pub one: Duration,
+}
+
+impl DelayLevels {
+    pub fn encode(&self, bit: bool) -> Duration {
+        if bit {
+            self.one
+        } else {
+            self.zero
+        }
+    }
+
+    pub fn separation(&self) -> Duration {
+        self.one.saturating_sub(self.zero)
+    }
+
+    const fn with_separation(&self, separation: Duration) -> Self {
+        Self {
+            zero: self.zero,
+            one: self.zero.saturating_add(separation),
+        }
+    }
+}
+
+impl DelayEncoder for DelayLevels {
+    fn next_delay(&mut self, bit: bool) -> Duration {
+        Self::encode(self, bit)
+    }
+}
+
+/// An evaluated step of [`tune`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TuneStep {
+    pub levels: DelayLevels,
+    pub ber: f64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TuneReport {
+    /// The levels with the smallest separation found meeting the target BER,
+    /// or the widest evaluated if none did.
+    pub levels: DelayLevels,
+    pub report: EvalReport,
+    /// Whether `levels` meets the target BER.
+    pub converged: bool,
+    /// Every evaluated step, in order.
+    pub trajectory: Vec<TuneStep>,
+}
+
+/// Searches for the smallest separation between the delay levels at which
+/// the decoders of `decoder_factory` meet `target_ber` under `noise`.
+///
+/// Starting from `encoder_template`, the `one` level is moved away from the
+/// `zero` level, doubling the separation until the target is met, and then
+/// bisected between the widest failing and narrowest passing separations. A
+/// template that already meets the target is narrowed the same way. Each
+/// step evaluates the same messages and noise, so the search is deterministic.
+/// `decoder_factory` is given the levels to decode, e.g. to place a
+/// threshold between them.
+///
+/// # Panics
+///
+/// Panics if `max_iters` is zero.
+pub fn tune<D, F>(
+    encoder_template: DelayLevels,
+    mut decoder_factory: F,
+    noise: NoiseModel,
+    target_ber: f64,
+    max_iters: usize,
+) -> TuneReport
+where
+    D: DelayDecoder<Output = BitVec>,
+    F: FnMut(&DelayLevels) -> D,
+{
+    assert!(max_iters > 0, "tuning needs at least one iteration");
+
+    let mut trajectory = Vec::with_capacity(max_iters);
+    let mut evaluate = |levels: DelayLevels| {
+        let report = run(
+            || decoder_factory(&levels),
+            |bit| levels.encode(bit),
+            noise,
+            TUNE_MESSAGE_LEN,
+            TUNE_TRIALS,
+            TUNE_SEED,
+        );
+        trajectory.push(TuneStep {
+            levels,
+            ber: report.ber(),
+        });
+        report
+    };
+
+    let mut separation = encoder_template.separation().max(Duration::from_nanos(1));
+    // The widest failing and narrowest passing separations so far.
+    let mut failing: Option<Duration> = None;
+    let mut passing: Option<(Duration, EvalReport)> = None;
+    let mut widest = (separation, EvalReport::default());
+
+    for _ in 0..max_iters {
+        let report = evaluate(encoder_template.with_separation(separation));
+        if separation >= widest.0 {
+            widest = (separation, report);
+        }
+        if report.ber() <= target_ber {
+            passing = Some((separation, report));
+        } else {
+            failing = Some(separation);
+        }
+
+        separation = match (failing, &passing) {
+            (Some(failing), Some((passing, _))) => {
+                let next = failing + (*passing - failing) / 2;
+                if next == failing {
+                    break;
+                }
+                next
+            }
+            (Some(failing), None) => failing.saturating_mul(2),
+            (None, Some((passing, _))) => {
+                if *passing <= Duration::from_nanos(1) {
+                    break;
+                }
+                *passing / 2
+            }
+            (None, None) => unreachable!(),
+        };
+    }
+
+    let converged = passing.is_some();
+    let (separation, report) = passing.unwrap_or(widest);
+    TuneReport {
+        levels: encoder_template.with_separation(separation),
+        report,
+        converged,
+        trajectory,
+    }
+}
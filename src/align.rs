@@ -0,0 +1,54 @@
+use bitvec::{field::BitField, slice::BitSlice};
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// Finds where `preamble` starts in `bits`, tolerating up to `max_errors`
+/// flipped bits, e.g. because the first signals of a message were missed.
+///
+/// Returns the offset with the fewest differing bits, the earliest among
+/// ties, or `None` if no offset is within `max_errors` or `preamble` is
+/// empty.
+pub fn find_offset(bits: &BitSlice, preamble: &BitSlice, max_errors: usize) -> Option<usize> {
+    if preamble.is_empty() || preamble.len() > bits.len() {
+        return None;
+    }
+
+    let mut best: Option<(usize, usize)> = None;
+    for offset in 0..=bits.len() - preamble.len() {
+        let limit = best.map_or(max_errors, |(_, errors)| errors.saturating_sub(1));
+        if let Some(errors) = hamming_distance(&bits[offset..], preamble, limit) {
+            best = Some((offset, errors));
+            if errors == 0 {
+                break;
+            }
+        }
+    }
+
+    best.map(|(offset, _)| offset)
+}
+
+/// Returns the bits following the best match of `preamble`, as found by
+/// [`find_offset`].
+pub fn realign<'a>(
+    bits: &'a BitSlice,
+    preamble: &BitSlice,
+    max_errors: usize,
+) -> Option<&'a BitSlice> {
+    find_offset(bits, preamble, max_errors).map(|offset| &bits[offset + preamble.len()..])
+}
+
+/// Counts the bits of `preamble` that differ from the start of `bits` a word
+/// at a time, giving up as soon as there are more than `limit`.
+fn hamming_distance(bits: &BitSlice, preamble: &BitSlice, limit: usize) -> Option<usize> {
+    let mut errors = 0;
+    for (index, chunk) in preamble.chunks(WORD_BITS).enumerate() {
+        let start = index * WORD_BITS;
+        let window = &bits[start..start + chunk.len()];
+        errors += (window.load_le::<u64>() ^ chunk.load_le::<u64>()).count_ones() as usize;
+        if errors > limit {
+            return None;
+        }
+    }
+
+    Some(errors)
+}
@@ -0,0 +1,668 @@
+use std::{collections::VecDeque, ops::RangeInclusive, sync::Arc, time::Duration};
+
+use crate::{
+    decoder::{DecoderOutput, DelayDecoder, MetaDelayDecoder},
+    session_store::{delay_session_store, DelaySessionStore, DelaySessionStream},
+};
+
+const ENTROPY_BUCKETS: usize = 16;
+
+/// Like a decoder, but closes into a score describing the session's timing
+/// instead of the bits it may carry. Used for detecting timing channels
+/// rather than reading them.
+pub trait DelayAnalyzer {
+    type Score: DecoderOutput;
+
+    fn push_duration(&mut self, duration: Duration);
+    fn close(self) -> Self::Score;
+}
+
+/// Runs an analyzer where a store expects a decoder, closing into its score.
+#[derive(Clone, Default, Debug)]
+pub struct AnalyzerDecoder<A> {
+    analyzer: A,
+}
+
+impl<A> AnalyzerDecoder<A> {
+    pub const fn new(analyzer: A) -> Self {
+        Self { analyzer }
+    }
+}
+
+impl<A> DelayDecoder for AnalyzerDecoder<A>
+where
+    A: DelayAnalyzer,
+{
+    type Output = A::Score;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.analyzer.push_duration(duration);
+    }
+
+    fn close(self) -> A::Score {
+        self.analyzer.close()
+    }
+}
+
+impl<A: DelayAnalyzer, M: ?Sized> MetaDelayDecoder<M> for AnalyzerDecoder<A> {}
+
+/// Creates a store for sessions pushed with [`AnalyzerDecoder`]s, emitting
+/// their scores.
+pub fn delay_analysis_store<K, S>(
+    timeout_duration: Duration,
+) -> (DelaySessionStore<K, S, S>, DelaySessionStream<K, S>)
+where
+    S: DecoderOutput,
+{
+    delay_session_store(timeout_duration)
+}
+
+/// Statistics of a session's durations that separate covert timing channels
+/// from organic traffic. How to weigh and threshold them is up to the caller.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AnomalyScore {
+    /// Number of durations the statistics were computed from. Fewer than a
+    /// few dozen make every other field unreliable.
+    pub samples: u64,
+    /// Sarle's bimodality coefficient. Values above about 0.555 suggest a
+    /// bimodal distribution, as produced by a two-symbol delay encoding.
+    pub bimodality: f64,
+    /// Shannon entropy in bits of the durations quantized into 16
+    /// equal-width buckets between the shortest and longest duration.
+    pub entropy: f64,
+    /// The coefficient of variation. Poisson arrivals have about 1.
+    pub variation: f64,
+}
+
+impl DecoderOutput for AnomalyScore {
+    /// The durations the score was computed from.
+    fn symbol_count(&self) -> usize {
+        self.samples as usize
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct AnomalyAnalyzer {
+    durations: Vec<f64>,
+}
+
+impl AnomalyAnalyzer {
+    pub const fn new() -> Self {
+        Self {
+            durations: Vec::new(),
+        }
+    }
+}
+
+impl DelayAnalyzer for AnomalyAnalyzer {
+    type Score = AnomalyScore;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.durations.push(duration.as_secs_f64());
+    }
+
+    fn close(self) -> AnomalyScore {
+        let durations = self.durations;
+        let n = durations.len() as f64;
+        let mean = durations.iter().sum::<f64>() / n;
+
+        let moment = |power: i32| {
+            durations
+                .iter()
+                .map(|duration| (duration - mean).powi(power))
+                .sum::<f64>()
+                / n
+        };
+        let (m2, m3, m4) = (moment(2), moment(3), moment(4));
+
+        let bimodality = if durations.len() > 3 && m2 > 0.0 {
+            let skewness = m3 / m2.powf(1.5);
+            let excess_kurtosis = m4 / (m2 * m2) - 3.0;
+            (skewness * skewness + 1.0)
+                / (excess_kurtosis + 3.0 * (n - 1.0).powi(2) / ((n - 2.0) * (n - 3.0)))
+        } else {
+            0.0
+        };
+
+        let variation = if mean > 0.0 { m2.sqrt() / mean } else { 0.0 };
+
+        AnomalyScore {
+            samples: durations.len() as u64,
+            bimodality,
+            entropy: bucket_entropy(&durations),
+            variation,
+        }
+    }
+}
+
+fn bucket_entropy(durations: &[f64]) -> f64 {
+    let min = durations.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = durations.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max <= min {
+        return 0.0;
+    }
+
+    let mut counts = [0_u64; ENTROPY_BUCKETS];
+    for duration in durations {
+        let bucket = ((duration - min) / (max - min) * ENTROPY_BUCKETS as f64) as usize;
+        counts[bucket.min(ENTROPY_BUCKETS - 1)] += 1;
+    }
+
+    entropy(&counts, durations.len() as u64)
+}
+
+/// The empirical entropy of a session's quantized durations.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EntropyEstimate {
+    /// Number of durations the estimate was computed from. Short sequences
+    /// underestimate entropy, so consumers should discount them.
+    pub samples: u64,
+    /// Shannon entropy in bits per duration.
+    pub entropy: f64,
+    /// Entropy in bits of each bucket given the previous one, if enabled.
+    pub conditional_entropy: Option<f64>,
+}
+
+impl DecoderOutput for EntropyEstimate {
+    /// The durations the score was computed from.
+    fn symbol_count(&self) -> usize {
+        self.samples as usize
+    }
+}
+
+/// Estimates the Shannon entropy of durations quantized into buckets.
+///
+/// Payloads that are compressed or encrypted before being encoded into delays
+/// show close to one bit of entropy concentrated in two tight buckets, which
+/// organic traffic rarely does.
+#[derive(Clone, Debug)]
+pub struct EntropyAnalyzer {
+    boundaries: Vec<Duration>,
+    counts: Vec<u64>,
+    transitions: Option<Vec<u64>>,
+    previous: Option<usize>,
+}
+
+impl EntropyAnalyzer {
+    /// Quantizes durations into the buckets separated by the ascending
+    /// `boundaries`: a duration falls into the bucket after every boundary it
+    /// is at or above. With `conditional`, transitions between consecutive
+    /// buckets are counted too, for the conditional entropy.
+    pub fn new(boundaries: Vec<Duration>, conditional: bool) -> Self {
+        let buckets = boundaries.len() + 1;
+
+        Self {
+            boundaries,
+            counts: vec![0; buckets],
+            transitions: conditional.then(|| vec![0; buckets * buckets]),
+            previous: None,
+        }
+    }
+
+    /// Quantizes durations into `buckets` buckets of `width`, the last one
+    /// catching every longer duration.
+    pub fn uniform(width: Duration, buckets: usize, conditional: bool) -> Self {
+        let boundaries = (1..buckets)
+            .map(|bucket| width.saturating_mul(bucket as u32))
+            .collect();
+        Self::new(boundaries, conditional)
+    }
+
+    fn bucket(&self, duration: Duration) -> usize {
+        self.boundaries
+            .partition_point(|boundary| *boundary <= duration)
+    }
+}
+
+impl DelayAnalyzer for EntropyAnalyzer {
+    type Score = EntropyEstimate;
+
+    fn push_duration(&mut self, duration: Duration) {
+        let bucket = self.bucket(duration);
+        self.counts[bucket] += 1;
+
+        if let (Some(transitions), Some(previous)) = (&mut self.transitions, self.previous) {
+            transitions[previous * self.counts.len() + bucket] += 1;
+        }
+        self.previous = Some(bucket);
+    }
+
+    fn close(self) -> EntropyEstimate {
+        let samples = self.counts.iter().sum();
+
+        let conditional_entropy = self.transitions.map(|transitions| {
+            let buckets = self.counts.len();
+            let pairs = transitions.iter().sum::<u64>() as f64;
+
+            transitions
+                .chunks(buckets)
+                .map(|row| {
+                    let row_total = row.iter().sum::<u64>();
+                    let row_weight = row_total as f64 / pairs;
+                    row_weight * entropy(row, row_total)
+                })
+                .filter(|weighted| weighted.is_finite())
+                .sum()
+        });
+
+        EntropyEstimate {
+            samples,
+            entropy: entropy(&self.counts, samples),
+            conditional_entropy,
+        }
+    }
+}
+
+/// The entropy in bits of the distribution given by `counts`.
+fn entropy(counts: &[u64], total: u64) -> f64 {
+    let total = total as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+/// The empirical distribution of a training set of durations, shared by every
+/// [`KsAnalyzer`] comparing against it.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct BaselineDistribution {
+    sorted: Vec<Duration>,
+}
+
+impl BaselineDistribution {
+    pub fn from_samples(samples: &[Duration]) -> Arc<Self> {
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        Arc::new(Self { sorted })
+    }
+
+    pub fn len(&self) -> usize {
+        self.sorted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sorted.is_empty()
+    }
+}
+
+/// How far a session's durations deviate from a baseline distribution.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KsScore {
+    pub samples: u64,
+    /// The two-sample Kolmogorov–Smirnov statistic: the largest distance
+    /// between the two empirical distribution functions, from 0 to 1.
+    pub statistic: f64,
+    /// The asymptotic probability of a statistic at least this large if the
+    /// session followed the baseline. Rough for fewer than a few dozen
+    /// samples.
+    pub p_value: f64,
+}
+
+impl DecoderOutput for KsScore {
+    /// The durations the score was computed from.
+    fn symbol_count(&self) -> usize {
+        self.samples as usize
+    }
+}
+
+/// Scores sessions with a two-sample Kolmogorov–Smirnov test against a
+/// baseline distribution of normal clients' durations.
+#[derive(Clone, Debug)]
+pub struct KsAnalyzer {
+    baseline: Arc<BaselineDistribution>,
+    durations: Vec<Duration>,
+}
+
+impl KsAnalyzer {
+    pub const fn new(baseline: Arc<BaselineDistribution>) -> Self {
+        Self {
+            baseline,
+            durations: Vec::new(),
+        }
+    }
+}
+
+impl DelayAnalyzer for KsAnalyzer {
+    type Score = KsScore;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.durations.push(duration);
+    }
+
+    fn close(mut self) -> KsScore {
+        self.durations.sort_unstable();
+        let (session, baseline) = (&self.durations, &self.baseline.sorted);
+        let (n, m) = (session.len(), baseline.len());
+        if n == 0 || m == 0 {
+            return KsScore {
+                samples: n as u64,
+                statistic: 0.0,
+                p_value: 1.0,
+            };
+        }
+
+        let (mut i, mut j) = (0, 0);
+        let mut statistic: f64 = 0.0;
+        while i < n && j < m {
+            let next = session[i].min(baseline[j]);
+            while i < n && session[i] == next {
+                i += 1;
+            }
+            while j < m && baseline[j] == next {
+                j += 1;
+            }
+            statistic = statistic.max((i as f64 / n as f64 - j as f64 / m as f64).abs());
+        }
+
+        let effective = (n * m) as f64 / (n + m) as f64;
+        let root = effective.sqrt();
+        let lambda = (root + 0.12 + 0.11 / root) * statistic;
+
+        KsScore {
+            samples: n as u64,
+            statistic,
+            p_value: kolmogorov_survival(lambda),
+        }
+    }
+}
+
+/// The survival function of the Kolmogorov distribution.
+fn kolmogorov_survival(lambda: f64) -> f64 {
+    if lambda < 0.2 {
+        return 1.0;
+    }
+
+    let mut sum = 0.0;
+    for k in 1..=100 {
+        let k = f64::from(k);
+        let term = (-2.0 * k * k * lambda * lambda).exp();
+        sum += if k % 2.0 == 1.0 { term } else { -term };
+        if term < 1e-12 {
+            break;
+        }
+    }
+
+    (2.0 * sum).clamp(0.0, 1.0)
+}
+
+/// The strongest autocorrelation of a session's durations within a lag range.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PeriodicityScore {
+    pub samples: u64,
+    /// The lag with the highest autocorrelation, or 0 if no lag in range
+    /// could be evaluated.
+    pub lag: u64,
+    /// The autocorrelation at `lag`, from -1 to 1. Aperiodic traffic stays
+    /// near 0.
+    pub strength: f64,
+}
+
+impl DecoderOutput for PeriodicityScore {
+    /// The durations the score was computed from.
+    fn symbol_count(&self) -> usize {
+        self.samples as usize
+    }
+}
+
+/// Finds fixed frame timing by autocorrelating a session's durations over a
+/// range of lags, in O(samples × lags).
+///
+/// Only the latest `max_samples` durations are retained. With `binarize`,
+/// durations are first split at their mean into bits, as
+/// `AverageDelayDecoder` would, so periodicity in the encoded symbols is
+/// found regardless of jitter in the delays.
+#[derive(Clone, Debug)]
+pub struct PeriodicityAnalyzer {
+    lags: RangeInclusive<usize>,
+    max_samples: usize,
+    binarize: bool,
+    durations: VecDeque<f64>,
+}
+
+impl PeriodicityAnalyzer {
+    pub fn new(lags: RangeInclusive<usize>, max_samples: usize, binarize: bool) -> Self {
+        Self {
+            lags,
+            max_samples,
+            binarize,
+            durations: VecDeque::new(),
+        }
+    }
+}
+
+impl DelayAnalyzer for PeriodicityAnalyzer {
+    type Score = PeriodicityScore;
+
+    fn push_duration(&mut self, duration: Duration) {
+        if self.max_samples == 0 {
+            return;
+        }
+        if self.durations.len() == self.max_samples {
+            self.durations.pop_front();
+        }
+        self.durations.push_back(duration.as_secs_f64());
+    }
+
+    fn close(self) -> PeriodicityScore {
+        let n = self.durations.len();
+        let mut values: Vec<f64> = self.durations.into();
+        let mean = values.iter().sum::<f64>() / n as f64;
+        if self.binarize {
+            for value in &mut values {
+                *value = if *value >= mean { 1.0 } else { 0.0 };
+            }
+        }
+
+        let mut score = PeriodicityScore {
+            samples: n as u64,
+            lag: 0,
+            strength: 0.0,
+        };
+        // Checked before centering, as rounding in the mean would leave
+        // constant durations a tiny variance that every lag correlates with.
+        if values.windows(2).all(|pair| pair[0] == pair[1]) {
+            return score;
+        }
+
+        let mean = values.iter().sum::<f64>() / n as f64;
+        for value in &mut values {
+            *value -= mean;
+        }
+        let variance = values.iter().map(|value| value * value).sum::<f64>();
+
+        for lag in self.lags.filter(|lag| *lag > 0 && *lag < n) {
+            let covariance = values
+                .iter()
+                .zip(&values[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f64>();
+            let strength = covariance / variance;
+            if score.lag == 0 || strength > score.strength {
+                score.lag = lag as u64;
+                score.strength = strength;
+            }
+        }
+
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use futures::StreamExt;
+    use tokio::time::sleep;
+
+    use super::{
+        delay_analysis_store, AnalyzerDecoder, AnomalyAnalyzer, AnomalyScore, BaselineDistribution,
+        DelayAnalyzer, EntropyAnalyzer, KsAnalyzer, PeriodicityAnalyzer,
+    };
+
+    fn analyze<A: DelayAnalyzer>(
+        mut analyzer: A,
+        millis: impl IntoIterator<Item = u64>,
+    ) -> A::Score {
+        for millis in millis {
+            analyzer.push_duration(Duration::from_millis(millis));
+        }
+        analyzer.close()
+    }
+
+    /// Alternates 10 ms and 30 ms delays, as a two-symbol encoder would.
+    fn two_symbols() -> impl Iterator<Item = u64> {
+        (0..100).map(|n| if n % 2 == 0 { 10 } else { 30 })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn an_analysis_store_emits_the_scores_themselves() {
+        let (store, mut results) =
+            delay_analysis_store::<u32, AnomalyScore>(Duration::from_secs(1));
+        let mut instant = store.clock().now();
+        for n in 0..9 {
+            store
+                .push_signal(7, instant, || AnalyzerDecoder::new(AnomalyAnalyzer::new()))
+                .await
+                .unwrap();
+            instant += Duration::from_millis(if n % 2 == 0 { 10 } else { 30 });
+        }
+        sleep(Duration::from_secs(2)).await;
+
+        let (key, score) = results.next().await.unwrap();
+        assert_eq!(key, 7);
+        assert_eq!(score.samples, 8);
+        assert!((score.variation - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn two_symbol_delays_score_as_bimodal() {
+        let score = analyze(AnomalyAnalyzer::new(), two_symbols());
+        assert_eq!(score.samples, 100);
+        assert!(score.bimodality > 0.9);
+        assert!((score.entropy - 1.0).abs() < 1e-9);
+        assert!((score.variation - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn uniform_delays_score_below_the_bimodality_threshold() {
+        let score = analyze(AnomalyAnalyzer::new(), 1..=160);
+        assert_eq!(score.samples, 160);
+        // Sarle's coefficient of a uniform distribution is 5/9.
+        assert!(score.bimodality < 0.555);
+        assert!(score.entropy > 3.9);
+    }
+
+    #[test]
+    fn too_few_or_constant_delays_score_zero() {
+        let score = analyze(AnomalyAnalyzer::new(), [20, 20, 20, 20, 20]);
+        assert_eq!(score.bimodality, 0.0);
+        assert_eq!(score.entropy, 0.0);
+        assert_eq!(score.variation, 0.0);
+
+        assert_eq!(
+            analyze(AnomalyAnalyzer::new(), [10, 30, 10]).bimodality,
+            0.0
+        );
+    }
+
+    #[test]
+    fn two_symbols_carry_one_bit_and_a_cycle_none_given_the_last() {
+        let analyzer = EntropyAnalyzer::uniform(Duration::from_millis(20), 4, true);
+        let score = analyze(analyzer, two_symbols());
+        assert_eq!(score.samples, 100);
+        assert!((score.entropy - 1.0).abs() < 1e-9);
+        assert_eq!(score.conditional_entropy, Some(0.0));
+
+        // Every bucket in turn: two bits each, all predicted by the last.
+        let analyzer = EntropyAnalyzer::uniform(Duration::from_millis(20), 4, true);
+        let score = analyze(analyzer, (0..100).map(|n| n % 4 * 20 + 5));
+        assert!((score.entropy - 2.0).abs() < 1e-9);
+        assert_eq!(score.conditional_entropy, Some(0.0));
+
+        let analyzer = EntropyAnalyzer::uniform(Duration::from_millis(20), 4, false);
+        assert_eq!(analyze(analyzer, two_symbols()).conditional_entropy, None);
+    }
+
+    #[test]
+    fn durations_on_a_boundary_fall_into_the_bucket_above() {
+        let boundaries = vec![Duration::from_millis(10), Duration::from_millis(20)];
+        let mut analyzer = EntropyAnalyzer::new(boundaries, false);
+        for millis in [9, 10, 19, 20, 1000] {
+            analyzer.push_duration(Duration::from_millis(millis));
+        }
+        assert_eq!(analyzer.counts, [1, 2, 2]);
+    }
+
+    fn baseline() -> Arc<BaselineDistribution> {
+        let samples: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        BaselineDistribution::from_samples(&samples)
+    }
+
+    #[test]
+    fn a_session_shifted_off_the_baseline_scores_the_reference_p_value() {
+        let score = analyze(KsAnalyzer::new(baseline()), 21..=120);
+        assert_eq!(score.samples, 100);
+        assert!((score.statistic - 0.2).abs() < 1e-9);
+        // Q_KS((sqrt(50) + 0.12 + 0.11 / sqrt(50)) * 0.2) = 0.0313767.
+        assert!((score.p_value - 0.031_376_652).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sessions_matching_or_disjoint_from_the_baseline_score_the_extremes() {
+        let score = analyze(KsAnalyzer::new(baseline()), (1..=100).rev());
+        assert_eq!(score.statistic, 0.0);
+        assert_eq!(score.p_value, 1.0);
+
+        let score = analyze(KsAnalyzer::new(baseline()), 500..600);
+        assert_eq!(score.statistic, 1.0);
+        assert!(score.p_value < 1e-20);
+
+        let score = analyze(KsAnalyzer::new(baseline()), []);
+        assert_eq!((score.samples, score.p_value), (0, 1.0));
+    }
+
+    #[test]
+    fn framed_delays_correlate_at_the_frame_length() {
+        let frame = [10, 10, 30, 50];
+        let score = analyze(
+            PeriodicityAnalyzer::new(1..=8, 100, false),
+            (0..100).map(|n| frame[n % 4]),
+        );
+        assert_eq!(score.samples, 100);
+        assert_eq!(score.lag, 4);
+        assert!(score.strength > 0.9);
+
+        // Jitter hides the frame from the raw delays, but not from the bits.
+        let frame = [10, 10, 30, 30, 30];
+        let jittered = (0..100).map(|n| frame[n % 5] + n as u64 * 7 % 13);
+        let score = analyze(PeriodicityAnalyzer::new(2..=8, 100, true), jittered);
+        assert_eq!(score.lag, 5);
+        assert!(score.strength > 0.9);
+    }
+
+    #[test]
+    fn aperiodic_delays_correlate_at_no_lag() {
+        let mut state = 1_u64;
+        let noise = (0..200).map(|_| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1);
+            10 + (state >> 33) % 40
+        });
+        let score = analyze(PeriodicityAnalyzer::new(1..=16, 200, false), noise);
+        assert!(score.strength.abs() < 0.3, "{score:?}");
+    }
+
+    #[test]
+    fn only_the_latest_samples_are_kept() {
+        let score = analyze(PeriodicityAnalyzer::new(1..=4, 10, false), 0..100);
+        assert_eq!(score.samples, 10);
+
+        let score = analyze(PeriodicityAnalyzer::new(1..=4, 10, false), [20; 10]);
+        assert_eq!((score.lag, score.strength), (0, 0.0));
+    }
+}
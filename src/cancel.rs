@@ -0,0 +1,127 @@
+use std::{
+    future::Future,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "tokio-util")]
+use std::pin::Pin;
+
+#[cfg(feature = "tokio-util")]
+use tokio_util::{
+    sync::{CancellationToken, WaitForCancellationFutureOwned},
+    task::{task_tracker::TaskTrackerToken, TaskTracker},
+};
+
+/// A store's cancellation token, and the work that has to finish once it is
+/// cancelled before the store's result stream ends. Without the `tokio-util`
+/// feature a store is never cancelled.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Cancellation {
+    #[cfg(feature = "tokio-util")]
+    token: Option<CancellationToken>,
+    #[cfg(feature = "tokio-util")]
+    tracker: TaskTracker,
+}
+
+/// Work that may still emit results, see `Cancellation::in_flight`.
+pub(crate) struct InFlight {
+    #[cfg(feature = "tokio-util")]
+    _token: TaskTrackerToken,
+}
+
+impl Cancellation {
+    #[cfg(feature = "tokio-util")]
+    pub(crate) fn new(token: CancellationToken) -> Self {
+        Self {
+            token: Some(token),
+            tracker: TaskTracker::new(),
+        }
+    }
+
+    #[cfg(feature = "tokio-util")]
+    pub(crate) const fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        #[cfg(feature = "tokio-util")]
+        return self
+            .token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled);
+
+        #[cfg(not(feature = "tokio-util"))]
+        false
+    }
+
+    /// Waits until the store is cancelled, forever if it has no token.
+    pub(crate) async fn cancelled(&self) {
+        #[cfg(feature = "tokio-util")]
+        if let Some(token) = &self.token {
+            return token.cancelled().await;
+        }
+
+        futures::future::pending().await
+    }
+
+    /// Marks work that may still emit results until the returned guard is
+    /// dropped, so the drain after cancellation waits for it.
+    pub(crate) fn in_flight(&self) -> InFlight {
+        InFlight {
+            #[cfg(feature = "tokio-util")]
+            _token: self.tracker.token(),
+        }
+    }
+
+    /// Like `in_flight`, for the whole of `future`.
+    pub(crate) fn track<F>(&self, future: F) -> impl Future<Output = F::Output>
+    where
+        F: Future,
+    {
+        let in_flight = self.in_flight();
+        async move {
+            let _in_flight = in_flight;
+            future.await
+        }
+    }
+
+    /// Waits for the work in flight to finish. Work started afterwards is not
+    /// waited for.
+    #[cfg(feature = "tokio-util")]
+    pub(crate) async fn drained(&self) {
+        self.tracker.close();
+        self.tracker.wait().await;
+    }
+}
+
+/// Ends a result stream once its store has been cancelled and drained.
+#[derive(Default)]
+pub(crate) struct StreamEnd {
+    #[cfg(feature = "tokio-util")]
+    drained: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+}
+
+impl core::fmt::Debug for StreamEnd {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StreamEnd").finish_non_exhaustive()
+    }
+}
+
+impl StreamEnd {
+    #[cfg(feature = "tokio-util")]
+    pub(crate) fn new(drained: CancellationToken) -> Self {
+        Self {
+            drained: Some(Box::pin(drained.cancelled_owned())),
+        }
+    }
+
+    #[cfg_attr(not(feature = "tokio-util"), allow(unused_variables))]
+    pub(crate) fn poll_drained(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        #[cfg(feature = "tokio-util")]
+        if let Some(drained) = &mut self.drained {
+            return drained.as_mut().poll(cx);
+        }
+
+        Poll::Pending
+    }
+}
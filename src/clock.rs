@@ -0,0 +1,193 @@
+#[cfg(feature = "quanta")]
+use std::sync::{Mutex, PoisonError};
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::watch;
+
+/// A pending [`Clock::sleep_until`].
+pub type ClockSleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// The source of every instant a store captures by itself, such as the
+/// receive time stamped by `push_signal_now`, and of the timers its sessions
+/// time out by.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Completes once the clock reaches `deadline`. Defaults to Tokio's
+    /// timer, which is right for any clock running in step with it.
+    fn sleep_until(&self, deadline: Instant) -> ClockSleep {
+        Box::pin(tokio::time::sleep_until(deadline.into()))
+    }
+}
+
+/// The real clock, backed by `Instant::now()`.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Tokio's clock, which stands still while Tokio's time is paused and moves
+/// with `tokio::time::advance`. The default clock of stores and sessions.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests.
+///
+/// Sleeps on it complete once it is advanced or set past their deadline,
+/// independently of Tokio's timer, so a store or session using it times out
+/// exactly when the test says. Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<watch::Sender<Instant>>,
+}
+
+impl ManualClock {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            now: Arc::new(watch::Sender::new(start)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.send_modify(|now| *now += duration);
+    }
+
+    pub fn set(&self, instant: Instant) {
+        self.now.send_replace(instant);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.borrow()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> ClockSleep {
+        let mut receiver = self.now.subscribe();
+        Box::pin(async move {
+            // Once every clone is dropped, the clock can no longer get there.
+            if receiver.wait_for(|now| *now >= deadline).await.is_err() {
+                std::future::pending().await
+            }
+        })
+    }
+}
+
+/// A clock reading the TSC through `quanta`, for hosts where `Instant::now()`
+/// jitters at the scale of the encoded delays.
+///
+/// Readings are converted into the `Instant` domain by offsetting an anchor
+/// pair of a `quanta` and a std instant. Since the two clocks drift apart, the
+/// anchor is re-taken every `resync_interval`; readings never go backwards
+/// across a re-anchor, at the cost of briefly standing still if the std clock
+/// ran slower. Where the TSC is not invariant, `quanta` itself falls back to
+/// the OS monotonic clock, so readings stay correct but lose the precision
+/// gain.
+#[cfg(feature = "quanta")]
+#[derive(Debug)]
+pub struct QuantaClock {
+    clock: quanta::Clock,
+    resync_interval: Duration,
+    anchor: Mutex<QuantaAnchor>,
+}
+
+#[cfg(feature = "quanta")]
+#[derive(Debug)]
+struct QuantaAnchor {
+    quanta: quanta::Instant,
+    std: Instant,
+    last: Instant,
+}
+
+#[cfg(feature = "quanta")]
+impl QuantaClock {
+    pub fn new(resync_interval: Duration) -> Self {
+        let clock = quanta::Clock::new();
+        let std = Instant::now();
+
+        Self {
+            anchor: Mutex::new(QuantaAnchor {
+                quanta: clock.now(),
+                std,
+                last: std,
+            }),
+            clock,
+            resync_interval,
+        }
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Default for QuantaClock {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1))
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Clock for QuantaClock {
+    fn now(&self) -> Instant {
+        let mut anchor = self.anchor.lock().unwrap_or_else(PoisonError::into_inner);
+        let quanta = self.clock.now();
+        let elapsed = quanta.saturating_duration_since(anchor.quanta);
+
+        let now = if elapsed >= self.resync_interval {
+            anchor.quanta = quanta;
+            anchor.std = Instant::now();
+            anchor.std
+        } else {
+            anchor.std + elapsed
+        };
+
+        anchor.last = anchor.last.max(now);
+        anchor.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures::FutureExt;
+
+    use super::{Clock, ManualClock};
+
+    #[tokio::test]
+    async fn manual_sleeps_complete_once_a_clone_reaches_their_deadline() {
+        let start = Instant::now();
+        let clock = ManualClock::new(start);
+        let mut sleep = clock.sleep_until(start + Duration::from_secs(1));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        let clone = clock.clone();
+        clone.advance(Duration::from_millis(999));
+        assert_eq!(clock.now(), start + Duration::from_millis(999));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clone.advance(Duration::from_millis(1));
+        assert!(sleep.now_or_never().is_some());
+        assert!(clock.sleep_until(start).now_or_never().is_some());
+
+        clock.set(start + Duration::from_secs(5));
+        assert!(clock
+            .sleep_until(start + Duration::from_secs(5))
+            .now_or_never()
+            .is_some());
+    }
+}
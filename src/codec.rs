@@ -0,0 +1,570 @@
+use core::time::Duration;
+
+use bitvec::{slice::BitSlice, vec::BitVec};
+
+use crate::{
+    align::realign,
+    decoder::{DecoderOutput, DelayDecoder, MetaDelayDecoder, ReusableDelayDecoder},
+};
+
+/// A Hamming code carrying four data bits per codeword.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum HammingCode {
+    /// Hamming(7,4), correcting any single flipped bit. A double flip cannot
+    /// be told apart from a single one, so it is miscorrected, never
+    /// reported as uncorrectable.
+    #[default]
+    Hamming74,
+    /// Hamming(7,4) followed by a parity bit over the whole codeword,
+    /// correcting single flips and detecting double flips (SECDED).
+    Extended84,
+}
+
+impl HammingCode {
+    pub const fn codeword_len(self) -> usize {
+        match self {
+            Self::Hamming74 => 7,
+            Self::Extended84 => 8,
+        }
+    }
+}
+
+/// What [`hamming_correct`] found in the codewords it decoded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct CorrectionReport {
+    pub codewords: usize,
+    /// Codewords with a single flipped bit, which was corrected.
+    pub corrected: usize,
+    /// Codewords with a detected double flip, whose data bits are passed on
+    /// as received.
+    pub uncorrectable: usize,
+    /// Bits after the last complete codeword, which were not decoded.
+    pub trailing_bits: usize,
+}
+
+/// Encodes `data` with `code`, padding a trailing partial nibble with
+/// `false` bits. Bits are laid out as `p1 p2 d1 p4 d2 d3 d4`, followed by the
+/// overall parity bit for [`HammingCode::Extended84`].
+pub fn hamming_encode(data: &BitSlice, code: HammingCode) -> BitVec {
+    let mut bits = BitVec::with_capacity(data.len().div_ceil(4) * code.codeword_len());
+
+    for nibble in data.chunks(4) {
+        let d = |i: usize| nibble.get(i).is_some_and(|bit| *bit);
+        let (d1, d2, d3, d4) = (d(0), d(1), d(2), d(3));
+        let codeword = [d1 ^ d2 ^ d4, d1 ^ d3 ^ d4, d1, d2 ^ d3 ^ d4, d2, d3, d4];
+        bits.extend(codeword);
+        if code == HammingCode::Extended84 {
+            bits.push(codeword.iter().fold(false, |parity, bit| parity ^ bit));
+        }
+    }
+
+    bits
+}
+
+/// Decodes the codewords of `bits`, correcting single flipped bits, and
+/// returns their data bits concatenated.
+pub fn hamming_correct(bits: &BitSlice, code: HammingCode) -> (BitVec, CorrectionReport) {
+    let codewords = bits.chunks_exact(code.codeword_len());
+    let mut report = CorrectionReport {
+        trailing_bits: codewords.remainder().len(),
+        ..CorrectionReport::default()
+    };
+    let mut data = BitVec::with_capacity(bits.len() / code.codeword_len() * 4);
+
+    for codeword in codewords {
+        let mut word = [false; 7];
+        for (bit, received) in word.iter_mut().zip(codeword) {
+            *bit = *received;
+        }
+
+        // The syndrome is the 1-based position of a single flipped bit.
+        let syndrome = (1..=7)
+            .filter(|position| word[position - 1])
+            .fold(0, |syndrome, position| syndrome ^ position);
+        let parity_ok = match code {
+            HammingCode::Hamming74 => syndrome == 0,
+            HammingCode::Extended84 => !codeword.iter().by_vals().fold(false, |a, b| a ^ b),
+        };
+
+        report.codewords += 1;
+        match (syndrome, parity_ok) {
+            (0, true) => {}
+            // Only the overall parity bit flipped.
+            (0, false) => report.corrected += 1,
+            (position, false) => {
+                word[position - 1] = !word[position - 1];
+                report.corrected += 1;
+            }
+            (_, true) => report.uncorrectable += 1,
+        }
+
+        data.extend([word[2], word[4], word[5], word[6]]);
+    }
+
+    (data, report)
+}
+
+/// Corrects the bits of `decoder` with [`hamming_correct`] at close,
+/// returning the data bits.
+#[derive(Debug)]
+pub struct HammingDecoder<D> {
+    decoder: D,
+    code: HammingCode,
+}
+
+impl<D> HammingDecoder<D> {
+    pub const fn new(decoder: D, code: HammingCode) -> Self {
+        Self { decoder, code }
+    }
+}
+
+impl<D: DelayDecoder<Output = BitVec>> HammingDecoder<D> {
+    /// Like `close`, also returning what the correction found.
+    pub fn close_with_report(self) -> (BitVec, CorrectionReport) {
+        hamming_correct(&self.decoder.close(), self.code)
+    }
+}
+
+impl<D: DelayDecoder<Output = BitVec>> DelayDecoder for HammingDecoder<D> {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.decoder.push_duration(duration);
+    }
+
+    fn close(self) -> BitVec {
+        self.close_with_report().0
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        self.decoder
+            .snapshot()
+            .map(|bits| hamming_correct(&bits, self.code).0)
+    }
+}
+
+impl<D: MetaDelayDecoder<M, Output = BitVec>, M: ?Sized> MetaDelayDecoder<M> for HammingDecoder<D> {
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.decoder.push_duration_with(duration, meta);
+    }
+}
+
+impl<D> ReusableDelayDecoder for HammingDecoder<D>
+where
+    D: ReusableDelayDecoder<Output = BitVec>,
+{
+    fn close_and_reset(&mut self) -> BitVec {
+        hamming_correct(&self.decoder.close_and_reset(), self.code).0
+    }
+}
+
+/// The polynomial of CRC-8/SMBUS, x^8 + x^2 + x + 1 without the leading term.
+pub const CRC8_SMBUS: u8 = 0x07;
+
+/// A check over a frame's payload bits, sent as the frame's final bits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Checksum {
+    /// One bit making the number of set bits in the frame even.
+    EvenParity,
+    /// One bit making the number of set bits in the frame odd.
+    OddParity,
+    /// Eight bits of a CRC-8 with the given polynomial, initial value zero
+    /// and no reflection, most significant bit first, e.g. [`CRC8_SMBUS`].
+    Crc8 { polynomial: u8 },
+}
+
+impl Checksum {
+    /// The number of check bits at the end of a frame.
+    pub const fn bits(self) -> usize {
+        match self {
+            Self::EvenParity | Self::OddParity => 1,
+            Self::Crc8 { .. } => 8,
+        }
+    }
+
+    /// Computes the check bits of `payload`.
+    pub fn compute(self, payload: &BitSlice) -> BitVec {
+        let parity = payload.count_ones() % 2 == 1;
+        match self {
+            Self::EvenParity => BitVec::repeat(parity, 1),
+            Self::OddParity => BitVec::repeat(!parity, 1),
+            Self::Crc8 { polynomial } => {
+                let crc = payload.iter().by_vals().fold(0u8, |crc, bit| {
+                    let feedback = (crc & 0x80 != 0) ^ bit;
+                    if feedback {
+                        crc << 1 ^ polynomial
+                    } else {
+                        crc << 1
+                    }
+                });
+                (0..8).rev().map(|i| crc >> i & 1 == 1).collect()
+            }
+        }
+    }
+}
+
+/// A decoded frame whose final bits were checked as a [`Checksum`] over the
+/// bits before them.
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct DecodedFrame {
+    /// The bits before the checksum, or every bit if there were too few for
+    /// a checksum.
+    pub payload: BitVec,
+    pub checksum_ok: bool,
+}
+
+impl DecodedFrame {
+    /// The payload if the checksum matched, e.g. for a result mapper that
+    /// suppresses frames of random traffic.
+    pub fn into_valid(self) -> Option<BitVec> {
+        self.checksum_ok.then_some(self.payload)
+    }
+}
+
+impl DecoderOutput for DecodedFrame {
+    fn symbol_count(&self) -> usize {
+        self.payload.len()
+    }
+}
+
+/// Appends the check bits of `payload`, the inverse of [`verify_checksum`].
+pub fn append_checksum(payload: &BitSlice, checksum: Checksum) -> BitVec {
+    let mut frame = payload.to_bitvec();
+    frame.extend_from_bitslice(&checksum.compute(payload));
+    frame
+}
+
+/// Splits `frame` into its payload and its final `checksum.bits()` bits, and
+/// checks them.
+pub fn verify_checksum(frame: &BitSlice, checksum: Checksum) -> DecodedFrame {
+    let Some(split) = frame.len().checked_sub(checksum.bits()) else {
+        return DecodedFrame {
+            payload: frame.to_bitvec(),
+            checksum_ok: false,
+        };
+    };
+
+    let (payload, check) = frame.split_at(split);
+    DecodedFrame {
+        checksum_ok: checksum.compute(payload) == check,
+        payload: payload.to_bitvec(),
+    }
+}
+
+/// Checks the bits of `decoder` with [`verify_checksum`] at close.
+#[derive(Debug)]
+pub struct ChecksumDecoder<D> {
+    decoder: D,
+    checksum: Checksum,
+}
+
+impl<D> ChecksumDecoder<D> {
+    pub const fn new(decoder: D, checksum: Checksum) -> Self {
+        Self { decoder, checksum }
+    }
+}
+
+impl<D: DelayDecoder<Output = BitVec>> DelayDecoder for ChecksumDecoder<D> {
+    type Output = DecodedFrame;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.decoder.push_duration(duration);
+    }
+
+    fn close(self) -> DecodedFrame {
+        verify_checksum(&self.decoder.close(), self.checksum)
+    }
+
+    fn snapshot(&self) -> Option<DecodedFrame> {
+        self.decoder
+            .snapshot()
+            .map(|bits| verify_checksum(&bits, self.checksum))
+    }
+}
+
+impl<D: MetaDelayDecoder<M, Output = BitVec>, M: ?Sized> MetaDelayDecoder<M>
+    for ChecksumDecoder<D>
+{
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.decoder.push_duration_with(duration, meta);
+    }
+}
+
+impl<D> ReusableDelayDecoder for ChecksumDecoder<D>
+where
+    D: ReusableDelayDecoder<Output = BitVec>,
+{
+    fn close_and_reset(&mut self) -> DecodedFrame {
+        verify_checksum(&self.decoder.close_and_reset(), self.checksum)
+    }
+}
+
+/// Decoded bits after a preamble, as found by [`sync_preamble`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct SyncedFrame {
+    /// The bits after the preamble, empty if it was not found.
+    pub payload: BitVec,
+    pub synced: bool,
+}
+
+impl SyncedFrame {
+    /// The payload if the preamble was found.
+    pub fn into_synced(self) -> Option<BitVec> {
+        self.synced.then_some(self.payload)
+    }
+}
+
+impl DecoderOutput for SyncedFrame {
+    fn symbol_count(&self) -> usize {
+        self.payload.len()
+    }
+}
+
+/// Discards `bits` up to and including the best match of `preamble`, as
+/// found by [`realign`], e.g. garbage decoded from random traffic on the key
+/// before the message.
+pub fn sync_preamble(bits: &BitSlice, preamble: &BitSlice, max_errors: usize) -> SyncedFrame {
+    match realign(bits, preamble, max_errors) {
+        Some(payload) => SyncedFrame {
+            payload: payload.to_bitvec(),
+            synced: true,
+        },
+        None => SyncedFrame::default(),
+    }
+}
+
+/// Synchronizes the bits of `decoder` on a preamble with [`sync_preamble`] at
+/// close.
+#[derive(Debug)]
+pub struct PreambleSyncDecoder<D> {
+    decoder: D,
+    preamble: BitVec,
+    max_errors: usize,
+}
+
+impl<D> PreambleSyncDecoder<D> {
+    pub fn new(decoder: D, preamble: &BitSlice) -> Self {
+        Self {
+            decoder,
+            preamble: preamble.to_bitvec(),
+            max_errors: 0,
+        }
+    }
+
+    /// Sets how many bits of the preamble may be flipped for it to still
+    /// match. Zero unless set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_errors` is not less than the preamble's length, which
+    /// would match anywhere.
+    pub fn max_errors(mut self, max_errors: usize) -> Self {
+        assert!(
+            max_errors < self.preamble.len(),
+            "preamble errors must be fewer than its bits"
+        );
+        self.max_errors = max_errors;
+        self
+    }
+}
+
+impl<D: DelayDecoder<Output = BitVec>> DelayDecoder for PreambleSyncDecoder<D> {
+    type Output = SyncedFrame;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.decoder.push_duration(duration);
+    }
+
+    fn close(self) -> SyncedFrame {
+        sync_preamble(&self.decoder.close(), &self.preamble, self.max_errors)
+    }
+
+    fn snapshot(&self) -> Option<SyncedFrame> {
+        self.decoder
+            .snapshot()
+            .map(|bits| sync_preamble(&bits, &self.preamble, self.max_errors))
+    }
+}
+
+impl<D: MetaDelayDecoder<M, Output = BitVec>, M: ?Sized> MetaDelayDecoder<M>
+    for PreambleSyncDecoder<D>
+{
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.decoder.push_duration_with(duration, meta);
+    }
+}
+
+impl<D> ReusableDelayDecoder for PreambleSyncDecoder<D>
+where
+    D: ReusableDelayDecoder<Output = BitVec>,
+{
+    fn close_and_reset(&mut self) -> SyncedFrame {
+        sync_preamble(
+            &self.decoder.close_and_reset(),
+            &self.preamble,
+            self.max_errors,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::{
+        bitvec,
+        order::{Lsb0, Msb0},
+        vec::BitVec,
+    };
+
+    use super::{
+        append_checksum, hamming_correct, hamming_encode, sync_preamble, verify_checksum, Checksum,
+        ChecksumDecoder, HammingCode, HammingDecoder, CRC8_SMBUS,
+    };
+    use crate::{
+        decoder::DelayDecoder,
+        encoder::{DelayEncoder, ThresholdDelayEncoder},
+    };
+    use core::time::Duration;
+
+    #[test]
+    fn the_best_preamble_match_wins_over_an_earlier_one() {
+        // A near match with one flipped bit, then the preamble itself.
+        let bits = bitvec![1, 0, 1, 0, 0, 0, 1, 1, 1, 0, 1, 1, 0, 1, 0];
+        let preamble = bitvec![1, 1, 1, 0];
+
+        let frame = sync_preamble(&bits, &preamble, 1);
+        assert!(frame.synced);
+        assert_eq!(frame.payload, bitvec![1, 1, 0, 1, 0]);
+        assert!(!sync_preamble(&bits[..6], &preamble, 0).synced);
+    }
+
+    #[test]
+    fn a_flipped_delay_is_corrected_from_the_delays() {
+        let data = bitvec![1, 0, 1, 1, 0, 0, 1, 0];
+        let mut encoder =
+            ThresholdDelayEncoder::new(Duration::from_millis(10), Duration::from_millis(30));
+        let mut delays = encoder.encode(&hamming_encode(&data, HammingCode::Hamming74));
+        // One delay of the second codeword lands on the wrong side.
+        delays[7 + 2] = Duration::from_millis(30);
+
+        let mut decoder = HammingDecoder::new(encoder.decoder(), HammingCode::Hamming74);
+        for delay in delays {
+            decoder.push_duration(delay);
+        }
+        let (bits, report) = decoder.close_with_report();
+        assert_eq!(bits, data);
+        assert_eq!(report.corrected, 1);
+    }
+
+    #[test]
+    fn every_single_flip_is_corrected() {
+        let data = bitvec![1, 0, 1, 1, 0, 1, 1, 0];
+        for code in [HammingCode::Hamming74, HammingCode::Extended84] {
+            let encoded = hamming_encode(&data, code);
+            assert_eq!(encoded.len(), 2 * code.codeword_len());
+
+            for flip in 0..encoded.len() {
+                let mut received = encoded.clone();
+                let bit = !received[flip];
+                received.set(flip, bit);
+
+                let (decoded, report) = hamming_correct(&received, code);
+                assert_eq!(decoded, data, "{code:?}, bit {flip}");
+                assert_eq!(report.codewords, 2);
+                assert_eq!(report.corrected, 1);
+                assert_eq!(report.uncorrectable, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn double_flips_are_detected_only_by_the_extended_code() {
+        let data = bitvec![0, 1, 1, 0];
+        let flip_two = |code| {
+            let mut received = hamming_encode(&data, code);
+            for flip in [0, 4] {
+                let bit = !received[flip];
+                received.set(flip, bit);
+            }
+            hamming_correct(&received, code)
+        };
+
+        let (decoded, report) = flip_two(HammingCode::Hamming74);
+        assert_ne!(decoded, data);
+        assert_eq!((report.corrected, report.uncorrectable), (1, 0));
+
+        let (_, report) = flip_two(HammingCode::Extended84);
+        assert_eq!((report.corrected, report.uncorrectable), (0, 1));
+    }
+
+    #[test]
+    fn partial_nibbles_are_padded_and_partial_codewords_reported() {
+        let encoded = hamming_encode(&bitvec![1, 1], HammingCode::Hamming74);
+        assert_eq!(encoded.len(), 7);
+
+        let mut received = encoded;
+        received.extend_from_bitslice(&bitvec![1, 0, 1]);
+        let (decoded, report) = hamming_correct(&received, HammingCode::Hamming74);
+        assert_eq!(decoded, bitvec![1, 1, 0, 0]);
+        assert_eq!(report.trailing_bits, 3);
+    }
+
+    #[test]
+    fn crc8_matches_the_smbus_check_value() {
+        let payload = BitVec::<u8, Msb0>::from_slice(b"123456789");
+        let payload: BitVec = payload.iter().by_vals().collect();
+        let crc = Checksum::Crc8 {
+            polynomial: CRC8_SMBUS,
+        };
+        assert_eq!(crc.compute(&payload), bitvec![1, 1, 1, 1, 0, 1, 0, 0]);
+
+        let mut frame = append_checksum(&payload, crc);
+        assert_eq!(verify_checksum(&frame, crc).into_valid(), Some(payload));
+        frame.set(5, true);
+        assert!(!verify_checksum(&frame, crc).checksum_ok);
+    }
+
+    #[test]
+    fn parity_bits_make_the_set_bits_even_or_odd() {
+        let payload = bitvec![1, 0, 1, 1];
+        assert_eq!(Checksum::EvenParity.compute(&payload), bitvec![1]);
+        assert_eq!(Checksum::OddParity.compute(&payload), bitvec![0]);
+
+        let frame = verify_checksum(&bitvec![1, 0, 1, 1, 0], Checksum::EvenParity);
+        assert_eq!(frame.payload, payload);
+        assert!(!frame.checksum_ok);
+        assert!(verify_checksum(&bitvec![1, 0, 1, 1, 0], Checksum::OddParity).checksum_ok);
+    }
+
+    #[test]
+    fn frames_shorter_than_their_checksum_fail() {
+        let crc = Checksum::Crc8 {
+            polynomial: CRC8_SMBUS,
+        };
+        let frame = verify_checksum(&bitvec![1, 0, 1], crc);
+        assert_eq!(frame.payload, bitvec![1, 0, 1]);
+        assert_eq!(frame.into_valid(), None);
+    }
+
+    #[test]
+    fn a_checksum_decoder_checks_the_decoded_frame() {
+        let payload = bitvec![1, 1, 0, 1, 0, 0, 1];
+        let crc = Checksum::Crc8 {
+            polynomial: CRC8_SMBUS,
+        };
+        let mut encoder =
+            ThresholdDelayEncoder::new(Duration::from_millis(10), Duration::from_millis(30));
+        let mut delays = encoder.encode(&append_checksum(&payload, crc));
+
+        let decode = |delays: &[Duration]| {
+            let mut decoder = ChecksumDecoder::new(encoder.decoder(), crc);
+            for delay in delays {
+                decoder.push_duration(*delay);
+            }
+            decoder.close()
+        };
+        assert_eq!(decode(&delays).into_valid(), Some(payload));
+
+        delays[2] = Duration::from_millis(30);
+        assert!(!decode(&delays).checksum_ok);
+    }
+}
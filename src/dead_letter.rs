@@ -0,0 +1,158 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll},
+};
+
+use bitvec::vec::BitVec;
+use futures::Stream;
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
+
+/// Capacity of the dead-letter stream's channel.
+const DEAD_LETTER_CAPACITY: usize = 64;
+
+/// What a store does with a result while its result stream is full.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum ResultOverflow {
+    /// The session waits for room in the result stream.
+    #[default]
+    Wait,
+    /// The new result is dropped.
+    DropNewest,
+    /// The oldest result waiting in the result stream is dropped to make
+    /// room for the new one. With fair emission the stream refills from the
+    /// fair queues, so the new result may be dropped as well.
+    DropOldest,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum DeadLetterReason {
+    /// The result arrived while the result stream was full.
+    OverflowNewest,
+    /// The result was evicted from the full result stream by a newer one.
+    OverflowOldest,
+    /// The result stream was closed.
+    StreamClosed,
+    /// The result mapper returned `None` for what the session decoded.
+    Suppressed,
+}
+
+/// What a dead letter carries.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum DeadResult<T, O = BitVec> {
+    /// A mapped result, as it would have been emitted.
+    Mapped(T),
+    /// The decoder output of a result the mapper suppressed.
+    Unmapped(O),
+}
+
+/// A result the store dropped instead of emitting.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct DeadLetter<K, T, O = BitVec> {
+    pub key: K,
+    pub result: DeadResult<T, O>,
+    pub reason: DeadLetterReason,
+}
+
+struct Subscriber<K, T, O> {
+    sender: Sender<DeadLetter<K, T, O>>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Routes a store's dropped results to its dead-letter stream.
+pub(crate) struct DeadLetterHub<K, T, O> {
+    subscriber: Mutex<Option<Subscriber<K, T, O>>>,
+    suppressed: bool,
+}
+
+impl<K, T, O> DeadLetterHub<K, T, O> {
+    pub(crate) fn new(suppressed: bool) -> Self {
+        Self {
+            subscriber: Mutex::new(None),
+            suppressed,
+        }
+    }
+
+    /// Replaces the current subscriber, ending its stream.
+    pub(crate) fn subscribe(&self) -> DeadLetterStream<K, T, O> {
+        let (sender, receiver) = channel(DEAD_LETTER_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        *self
+            .subscriber
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Subscriber {
+            sender,
+            dropped: dropped.clone(),
+        });
+
+        DeadLetterStream { receiver, dropped }
+    }
+
+    /// Whether suppressed results should be routed here, so their decoder
+    /// output is worth keeping past the result mapper.
+    pub(crate) fn wants_suppressed(&self) -> bool {
+        self.suppressed
+            && self
+                .subscriber
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_ref()
+                .is_some_and(|subscriber| !subscriber.sender.is_closed())
+    }
+
+    /// Sends a dead letter to the subscriber if it has room for it.
+    pub(crate) fn route(&self, key: K, result: DeadResult<T, O>, reason: DeadLetterReason) {
+        let mut subscriber = self
+            .subscriber
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let Some(current) = &*subscriber else {
+            return;
+        };
+
+        match current.sender.try_send(DeadLetter {
+            key,
+            result,
+            reason,
+        }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                current.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Closed(_)) => *subscriber = None,
+        }
+    }
+}
+
+/// Results a store dropped, from [`DelaySessionStore::subscribe_dead_letters`].
+///
+/// Dead letters arriving while the stream's buffer is full are dropped for
+/// good and counted in [`DeadLetterStream::dropped`]. The stream ends once the
+/// store and all of its sessions are gone, or once the store is subscribed to
+/// again.
+///
+/// [`DelaySessionStore::subscribe_dead_letters`]: crate::session_store::DelaySessionStore::subscribe_dead_letters
+#[derive(Debug)]
+pub struct DeadLetterStream<K, T, O = BitVec> {
+    receiver: Receiver<DeadLetter<K, T, O>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<K, T, O> DeadLetterStream<K, T, O> {
+    /// Dead letters dropped because this stream's buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<K, T, O> Stream for DeadLetterStream<K, T, O> {
+    type Item = DeadLetter<K, T, O>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
@@ -1,57 +1,3290 @@
-use std::time::Duration;
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{cmp::Ordering, marker::PhantomData, mem::take, time::Duration};
 
-use bitvec::vec::BitVec;
+use bitvec::{slice::BitSlice, vec::BitVec};
 
 pub trait DelayDecoder {
+    /// What the decoder decodes a session into: a `BitVec` for decoders of
+    /// one bit per duration, or e.g. a `Vec<u8>` of multi-bit symbols.
+    /// Decoders written when `close` always returned a `BitVec` only need
+    /// `type Output = BitVec;`.
+    type Output: DecoderOutput;
+
     fn push_duration(&mut self, duration: Duration);
-    fn close(self) -> BitVec;
+
+    fn close(self) -> Self::Output;
+
+    /// Returns what `close` would return now, without closing, or `None` if
+    /// the decoder cannot tell before it is closed.
+    fn snapshot(&self) -> Option<Self::Output> {
+        None
+    }
+
+    /// The duration at or above which the decoder currently decodes `true`,
+    /// or `None` if it has no single one or cannot tell yet.
+    fn decision_threshold(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// A decoder taking in the metadata `M` of the signal that ended each
+/// duration, e.g. the method or path of the HTTP request. Sessions whose
+/// signals carry `M` need decoders of `MetaDelayDecoder<M>`, so a decoder
+/// expecting other metadata does not compile. Decoders that do not look at
+/// metadata are one for every `M`, and just push the duration.
+pub trait MetaDelayDecoder<M: ?Sized>: DelayDecoder {
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        let _ = meta;
+        self.push_duration(duration);
+    }
+}
+
+/// The output of a [`DelayDecoder`].
+pub trait DecoderOutput {
+    /// The number of symbols decoded, bits for a `BitVec`.
+    fn symbol_count(&self) -> usize;
+}
+
+impl DecoderOutput for BitVec {
+    fn symbol_count(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> DecoderOutput for Vec<T> {
+    fn symbol_count(&self) -> usize {
+        self.len()
+    }
+}
+
+/// An object-safe flavor of [`MetaDelayDecoder`], so decoders of different
+/// types can be boxed and held together. Every `MetaDelayDecoder<M>` is one.
+pub trait DynDelayDecoder<O = BitVec, M: ?Sized = ()> {
+    fn dyn_push_duration(&mut self, duration: Duration);
+    fn dyn_push_duration_with(&mut self, duration: Duration, meta: &M);
+    fn dyn_close(self: Box<Self>) -> O;
+    fn dyn_snapshot(&self) -> Option<O>;
+    fn dyn_decision_threshold(&self) -> Option<Duration>;
+}
+
+impl<D: MetaDelayDecoder<M>, M: ?Sized> DynDelayDecoder<D::Output, M> for D {
+    fn dyn_push_duration(&mut self, duration: Duration) {
+        self.push_duration(duration);
+    }
+
+    fn dyn_push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.push_duration_with(duration, meta);
+    }
+
+    fn dyn_close(self: Box<Self>) -> D::Output {
+        (*self).close()
+    }
+
+    fn dyn_snapshot(&self) -> Option<D::Output> {
+        self.snapshot()
+    }
+
+    fn dyn_decision_threshold(&self) -> Option<Duration> {
+        self.decision_threshold()
+    }
+}
+
+/// An object-safe flavor of [`ReusableDelayDecoder`]. Every
+/// `ReusableDelayDecoder` that is a `MetaDelayDecoder<M>` is one.
+pub trait DynReusableDelayDecoder<O = BitVec, M: ?Sized = ()>: DynDelayDecoder<O, M> {
+    fn dyn_close_and_reset(&mut self) -> O;
 }
 
+impl<D, M> DynReusableDelayDecoder<D::Output, M> for D
+where
+    D: ReusableDelayDecoder + MetaDelayDecoder<M>,
+    M: ?Sized,
+{
+    fn dyn_close_and_reset(&mut self) -> D::Output {
+        self.close_and_reset()
+    }
+}
+
+/// A decoder chosen at runtime, e.g. from a config file, for a
+/// `decoder_factory` that returns different decoders of the same output.
+pub type BoxedDelayDecoder<O = BitVec, M = ()> = Box<dyn DynDelayDecoder<O, M> + Send>;
+
+impl<O: DecoderOutput, M: ?Sized> DelayDecoder for Box<dyn DynDelayDecoder<O, M> + Send> {
+    type Output = O;
+
+    fn push_duration(&mut self, duration: Duration) {
+        (**self).dyn_push_duration(duration);
+    }
+
+    fn close(self) -> O {
+        self.dyn_close()
+    }
+
+    fn snapshot(&self) -> Option<O> {
+        (**self).dyn_snapshot()
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        (**self).dyn_decision_threshold()
+    }
+}
+
+impl<O: DecoderOutput, M: ?Sized> MetaDelayDecoder<M> for Box<dyn DynDelayDecoder<O, M> + Send> {
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        (**self).dyn_push_duration_with(duration, meta);
+    }
+}
+
+/// A decoder that can be closed in place and reused for a new session,
+/// keeping its internal buffers allocated.
+pub trait ReusableDelayDecoder: DelayDecoder {
+    /// Returns what `close` would have, leaving the decoder as if freshly
+    /// constructed.
+    fn close_and_reset(&mut self) -> Self::Output;
+}
+
+/// A decoder that decides some of its output as durations are pushed, so it
+/// can be released before the session closes.
+pub trait StreamingDelayDecoder: DelayDecoder {
+    /// Takes what was decided since the last call. What `close` and
+    /// `snapshot` return no longer includes it, so the taken outputs followed
+    /// by the output of `close` add up to what `close` alone would have
+    /// returned. Decoders that need every duration before deciding, e.g.
+    /// [`AverageDelayDecoder`], return nothing until closed.
+    fn take_decided(&mut self) -> Self::Output;
+}
+
+/// Emits `true` for every duration at or above `threshold`.
+///
+/// With a tolerance, durations within it of the threshold repeat the previous
+/// bit, or emit `false` if there is none yet, so jitter right at the boundary
+/// does not flip bits.
 #[derive(Debug)]
 pub struct ThresholdDelayDecoder {
     threshold: Duration,
+    tolerance: Duration,
+    previous_bit: bool,
     bits: BitVec,
 }
 
+impl ThresholdDelayDecoder {
+    pub const fn new(threshold: Duration) -> Self {
+        Self::new_with_tolerance(threshold, Duration::ZERO)
+    }
+
+    /// Like `new`, allocating room for `expected_bits` up front.
+    pub fn with_capacity(threshold: Duration, expected_bits: usize) -> Self {
+        Self {
+            bits: BitVec::with_capacity(expected_bits),
+            ..Self::new(threshold)
+        }
+    }
+
+    pub const fn new_with_tolerance(threshold: Duration, tolerance: Duration) -> Self {
+        Self {
+            threshold,
+            tolerance,
+            previous_bit: false,
+            bits: BitVec::EMPTY,
+        }
+    }
+
+    pub const fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    pub const fn tolerance(&self) -> Duration {
+        self.tolerance
+    }
+}
+
 impl DelayDecoder for ThresholdDelayDecoder {
+    type Output = BitVec;
+
     fn push_duration(&mut self, duration: Duration) {
-        self.bits.push(duration >= self.threshold);
+        let bit = if duration.saturating_sub(self.threshold) > self.tolerance {
+            true
+        } else if self.threshold.saturating_sub(duration) > self.tolerance {
+            false
+        } else if self.tolerance.is_zero() {
+            duration >= self.threshold
+        } else {
+            self.previous_bit
+        };
+        self.previous_bit = bit;
+        self.bits.push(bit);
     }
 
     fn close(self) -> BitVec {
         self.bits
     }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.bits.clone())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        Some(self.threshold)
+    }
 }
 
-#[derive(Default, Debug)]
-pub struct AverageDelayDecoder {
-    durations: Vec<Duration>,
+impl<M: ?Sized> MetaDelayDecoder<M> for ThresholdDelayDecoder {}
+
+impl ReusableDelayDecoder for ThresholdDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        self.previous_bit = false;
+        take(&mut self.bits)
+    }
 }
 
-impl AverageDelayDecoder {
-    pub const fn new() -> Self {
+impl StreamingDelayDecoder for ThresholdDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        take(&mut self.bits)
+    }
+}
+
+/// Classifies each duration against `factor` times an exponential moving
+/// average of the durations before it, so the threshold follows a drifting
+/// baseline delay, e.g. of a mobile client switching networks.
+///
+/// The first `warmup` durations are buffered, and classified against their
+/// mean once all of them are in, which also seeds the average. Each later
+/// duration is classified before it updates the average with weight `alpha`.
+#[derive(Debug)]
+pub struct EmaThresholdDelayDecoder {
+    alpha: f64,
+    factor: f64,
+    warmup: usize,
+    /// The average in seconds, once warmed up.
+    ema: Option<f64>,
+    warmup_durations: Vec<Duration>,
+    bits: BitVec,
+}
+
+impl EmaThresholdDelayDecoder {
+    /// # Panics
+    ///
+    /// Panics if `alpha` is not in `(0, 1]`, `factor` is not finite and
+    /// positive, or `warmup` is zero.
+    pub fn new(alpha: f64, factor: f64, warmup: usize) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "alpha must be in (0, 1]");
+        assert!(
+            factor.is_finite() && factor > 0.0,
+            "threshold factor must be finite and positive"
+        );
+        assert!(warmup > 0, "warmup must be at least one duration");
+
         Self {
-            durations: Vec::new(),
+            alpha,
+            factor,
+            warmup,
+            ema: None,
+            warmup_durations: Vec::new(),
+            bits: BitVec::new(),
+        }
+    }
+
+    /// Classifies the buffered warmup durations against their mean, returning
+    /// the bits and the mean in seconds.
+    fn classify_warmup(&self) -> (BitVec, f64) {
+        let mean = mean_duration(&self.warmup_durations).as_secs_f64();
+        let bits = self
+            .warmup_durations
+            .iter()
+            .map(|duration| duration.as_secs_f64() >= mean * self.factor)
+            .collect();
+        (bits, mean)
+    }
+
+    fn decode(&self) -> BitVec {
+        if self.ema.is_some() {
+            self.bits.clone()
+        } else {
+            self.classify_warmup().0
         }
     }
 }
 
-impl DelayDecoder for AverageDelayDecoder {
+impl DelayDecoder for EmaThresholdDelayDecoder {
+    type Output = BitVec;
+
     fn push_duration(&mut self, duration: Duration) {
-        self.durations.push(duration);
+        let secs = duration.as_secs_f64();
+        match self.ema {
+            Some(ema) => {
+                self.bits.push(secs >= ema * self.factor);
+                self.ema = Some(ema + self.alpha * (secs - ema));
+            }
+            None => {
+                self.warmup_durations.push(duration);
+                if self.warmup_durations.len() == self.warmup {
+                    let (bits, mean) = self.classify_warmup();
+                    self.bits = bits;
+                    self.ema = Some(mean);
+                    self.warmup_durations.clear();
+                }
+            }
+        }
+    }
+
+    fn close(mut self) -> BitVec {
+        self.close_and_reset()
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.decode())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        Duration::try_from_secs_f64(self.ema? * self.factor).ok()
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for EmaThresholdDelayDecoder {}
+
+impl ReusableDelayDecoder for EmaThresholdDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        let bits = match self.ema.take() {
+            Some(_) => take(&mut self.bits),
+            None => self.classify_warmup().0,
+        };
+        self.warmup_durations.clear();
+        bits
+    }
+}
+
+impl StreamingDelayDecoder for EmaThresholdDelayDecoder {
+    /// Nothing is decided before the warmup durations are all in.
+    fn take_decided(&mut self) -> BitVec {
+        take(&mut self.bits)
+    }
+}
+
+/// What [`HysteresisDelayDecoder`] does with a duration between its
+/// thresholds, and [`DifferentialDelayDecoder`] with a duration within its
+/// tolerance of the previous one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum DeadBandPolicy {
+    /// Repeats the previous bit, or emits `false` if there is none yet.
+    #[default]
+    RepeatPrevious,
+    /// Emits no bit for the duration.
+    Drop,
+}
+
+/// Emits `true` for durations at or above `high` and `false` for durations
+/// at or below `low`, so durations hovering around a single threshold do not
+/// produce alternating bits. Durations in between are handled as the
+/// [`DeadBandPolicy`] says.
+#[derive(Debug)]
+pub struct HysteresisDelayDecoder {
+    low: Duration,
+    high: Duration,
+    policy: DeadBandPolicy,
+    previous_bit: bool,
+    bits: BitVec,
+}
+
+impl HysteresisDelayDecoder {
+    /// # Panics
+    ///
+    /// Panics if `low` is greater than `high`.
+    pub fn new(low: Duration, high: Duration) -> Self {
+        assert!(low <= high, "low threshold must not exceed high threshold");
+        Self {
+            low,
+            high,
+            policy: DeadBandPolicy::default(),
+            previous_bit: false,
+            bits: BitVec::new(),
+        }
+    }
+
+    pub const fn dead_band_policy(mut self, policy: DeadBandPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl DelayDecoder for HysteresisDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        let bit = if duration >= self.high {
+            true
+        } else if duration <= self.low {
+            false
+        } else {
+            match self.policy {
+                DeadBandPolicy::RepeatPrevious => self.previous_bit,
+                DeadBandPolicy::Drop => return,
+            }
+        };
+        self.previous_bit = bit;
+        self.bits.push(bit);
     }
 
     fn close(self) -> BitVec {
-        if self.durations.len() < 2 {
-            BitVec::EMPTY
+        self.bits
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.bits.clone())
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for HysteresisDelayDecoder {}
+
+impl ReusableDelayDecoder for HysteresisDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        self.previous_bit = false;
+        take(&mut self.bits)
+    }
+}
+
+impl StreamingDelayDecoder for HysteresisDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        take(&mut self.bits)
+    }
+}
+
+/// Emits `true` for a duration longer than the previous one by more than
+/// `tolerance` and `false` for one shorter by more than `tolerance`, so bits
+/// are carried by transitions rather than absolute delays, and a baseline
+/// drifting slowly, e.g. under congestion, does not flip them. Durations
+/// within the tolerance are handled as the [`DeadBandPolicy`] says. The
+/// first duration only sets the reference, emitting no bit.
+#[derive(Debug)]
+pub struct DifferentialDelayDecoder {
+    tolerance: Duration,
+    policy: DeadBandPolicy,
+    previous: Option<Duration>,
+    previous_bit: bool,
+    bits: BitVec,
+}
+
+impl DifferentialDelayDecoder {
+    pub fn new(tolerance: Duration) -> Self {
+        Self {
+            tolerance,
+            policy: DeadBandPolicy::default(),
+            previous: None,
+            previous_bit: false,
+            bits: BitVec::new(),
+        }
+    }
+
+    pub const fn dead_band_policy(mut self, policy: DeadBandPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl DelayDecoder for DifferentialDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        let Some(previous) = self.previous.replace(duration) else {
+            return;
+        };
+
+        let bit = if duration.saturating_sub(previous) > self.tolerance {
+            true
+        } else if previous.saturating_sub(duration) > self.tolerance {
+            false
         } else {
-            let duration_sum: Duration = self.durations.iter().sum();
-            let average_duration = duration_sum / self.durations.len() as u32;
+            match self.policy {
+                DeadBandPolicy::RepeatPrevious => self.previous_bit,
+                DeadBandPolicy::Drop => return,
+            }
+        };
+        self.previous_bit = bit;
+        self.bits.push(bit);
+    }
+
+    fn close(self) -> BitVec {
+        self.bits
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.bits.clone())
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for DifferentialDelayDecoder {}
+
+impl ReusableDelayDecoder for DifferentialDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        self.previous = None;
+        self.previous_bit = false;
+        take(&mut self.bits)
+    }
+}
+
+impl StreamingDelayDecoder for DifferentialDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        take(&mut self.bits)
+    }
+}
+
+/// Decodes NRZI: a duration at or above `threshold` toggles the current level,
+/// a shorter one keeps it, and every duration emits the level after it. The
+/// level starts at `initial`.
+#[derive(Debug)]
+pub struct NrziDelayDecoder {
+    threshold: Duration,
+    initial: bool,
+    level: bool,
+    bits: BitVec,
+}
+
+impl NrziDelayDecoder {
+    pub const fn new(threshold: Duration, initial: bool) -> Self {
+        Self {
+            threshold,
+            initial,
+            level: initial,
+            bits: BitVec::EMPTY,
+        }
+    }
+}
+
+impl DelayDecoder for NrziDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.level ^= duration >= self.threshold;
+        self.bits.push(self.level);
+    }
+
+    fn close(self) -> BitVec {
+        self.bits
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.bits.clone())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        Some(self.threshold)
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for NrziDelayDecoder {}
+
+impl ReusableDelayDecoder for NrziDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        self.level = self.initial;
+        take(&mut self.bits)
+    }
+}
+
+impl StreamingDelayDecoder for NrziDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        take(&mut self.bits)
+    }
+}
+
+/// Decodes NRZI like [`NrziDelayDecoder`], taking the bits of `decoder` as
+/// whether each duration toggles the level, so any classifier can be used.
+#[derive(Debug)]
+pub struct NrziDecoder<D> {
+    decoder: D,
+    initial: bool,
+}
+
+impl<D> NrziDecoder<D> {
+    pub const fn new(decoder: D, initial: bool) -> Self {
+        Self { decoder, initial }
+    }
+}
+
+impl<D: DelayDecoder<Output = BitVec>> DelayDecoder for NrziDecoder<D> {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.decoder.push_duration(duration);
+    }
+
+    fn close(self) -> BitVec {
+        nrzi_levels(&self.decoder.close(), self.initial)
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        self.decoder
+            .snapshot()
+            .map(|toggles| nrzi_levels(&toggles, self.initial))
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        self.decoder.decision_threshold()
+    }
+}
 
-            self.durations
-                .into_iter()
-                .map(|duration| duration >= average_duration)
-                .collect()
+impl<D: MetaDelayDecoder<M, Output = BitVec>, M: ?Sized> MetaDelayDecoder<M> for NrziDecoder<D> {
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.decoder.push_duration_with(duration, meta);
+    }
+}
+
+impl<D> ReusableDelayDecoder for NrziDecoder<D>
+where
+    D: ReusableDelayDecoder<Output = BitVec>,
+{
+    fn close_and_reset(&mut self) -> BitVec {
+        nrzi_levels(&self.decoder.close_and_reset(), self.initial)
+    }
+}
+
+/// Turns NRZI `toggles` into the levels after each of them, starting from
+/// `initial`.
+pub fn nrzi_levels(toggles: &BitSlice, initial: bool) -> BitVec {
+    toggles
+        .iter()
+        .by_vals()
+        .scan(initial, |level, toggle| {
+            *level ^= toggle;
+            Some(*level)
+        })
+        .collect()
+}
+
+/// Classifies durations against a baseline measured at the start of the
+/// session, e.g. the path latency of a noisy WAN, plus `offset`.
+///
+/// The first `calibration_samples` durations emit no bits; their median
+/// becomes the baseline. Each later duration emits `true` if it is at least
+/// the baseline plus `offset`. A baseline measured earlier, e.g. in a
+/// previous session of the same key, can be set with
+/// [`preset_baseline`](Self::preset_baseline) to skip calibration.
+#[derive(Debug)]
+pub struct CalibratedDelayDecoder {
+    calibration_samples: usize,
+    offset: Duration,
+    preset: Option<Duration>,
+    baseline: Option<Duration>,
+    samples: Vec<Duration>,
+    bits: BitVec,
+}
+
+impl CalibratedDelayDecoder {
+    /// # Panics
+    ///
+    /// Panics if `calibration_samples` is zero.
+    pub fn new(calibration_samples: usize, offset: Duration) -> Self {
+        assert!(
+            calibration_samples > 0,
+            "calibration needs at least one duration"
+        );
+
+        Self {
+            calibration_samples,
+            offset,
+            preset: None,
+            baseline: None,
+            samples: Vec::with_capacity(calibration_samples),
+            bits: BitVec::new(),
+        }
+    }
+
+    /// Uses `baseline` instead of calibrating, also after a reset.
+    pub fn preset_baseline(mut self, baseline: Duration) -> Self {
+        self.preset = Some(baseline);
+        self.baseline = Some(baseline);
+        self
+    }
+
+    /// The baseline, once calibrated or preset.
+    pub const fn baseline(&self) -> Option<Duration> {
+        self.baseline
+    }
+}
+
+impl DelayDecoder for CalibratedDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        match self.baseline {
+            Some(baseline) => self
+                .bits
+                .push(duration >= baseline.saturating_add(self.offset)),
+            None => {
+                self.samples.push(duration);
+                if self.samples.len() == self.calibration_samples {
+                    self.baseline = Some(median_duration(&self.samples));
+                    self.samples.clear();
+                }
+            }
         }
     }
-}
\ No newline at end of file
+
+    fn close(self) -> BitVec {
+        self.bits
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.bits.clone())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        Some(self.baseline?.saturating_add(self.offset))
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for CalibratedDelayDecoder {}
+
+impl ReusableDelayDecoder for CalibratedDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        self.baseline = self.preset;
+        self.samples.clear();
+        take(&mut self.bits)
+    }
+}
+
+impl StreamingDelayDecoder for CalibratedDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        take(&mut self.bits)
+    }
+}
+
+/// Emits `true` for every duration at or above the mean of all durations.
+///
+/// The mean is computed exactly over `u128` nanoseconds, so `close` never
+/// panics, even for durations near `Duration::MAX`. Absurd durations are not
+/// treated as outliers; they simply pull the mean up.
+#[derive(Clone, Default, Debug)]
+pub struct AverageDelayDecoder {
+    durations: Vec<Duration>,
+}
+
+impl AverageDelayDecoder {
+    pub const fn new() -> Self {
+        Self {
+            durations: Vec::new(),
+        }
+    }
+
+    /// Like `new`, allocating room for `expected_durations` up front.
+    pub fn with_capacity(expected_durations: usize) -> Self {
+        Self {
+            durations: Vec::with_capacity(expected_durations),
+        }
+    }
+
+    fn decode(&self) -> BitVec {
+        if self.durations.len() < 2 {
+            return BitVec::EMPTY;
+        }
+
+        classify_at_mean(&self.durations)
+    }
+}
+
+impl DelayDecoder for AverageDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.durations.push(duration);
+    }
+
+    fn close(mut self) -> BitVec {
+        self.close_and_reset()
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.decode())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        (self.durations.len() >= 2).then(|| mean_duration(&self.durations))
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for AverageDelayDecoder {}
+
+impl ReusableDelayDecoder for AverageDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        let bits = self.decode();
+        self.durations.clear();
+        bits
+    }
+}
+
+impl StreamingDelayDecoder for AverageDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        BitVec::new()
+    }
+}
+
+/// Like [`AverageDelayDecoder`], buffering at most `max_samples` durations
+/// rather than every one.
+///
+/// Once `max_samples` durations are buffered, they are classified against
+/// their mean, which is then kept for classifying every later duration as it
+/// arrives. On traffic whose delays do not drift, this decodes like
+/// `AverageDelayDecoder`.
+///
+/// The decoded bits are still kept until they are taken, a bit per duration
+/// past the cap: an eighth of a megabyte per million durations instead of the
+/// 16 MB `AverageDelayDecoder` buffers. To bound a session's memory, take
+/// them as they are decided, see [`StreamingDelayDecoder`], or cap the
+/// session's durations.
+#[derive(Debug)]
+pub struct BoundedAverageDelayDecoder {
+    max_samples: usize,
+    mean: Option<Duration>,
+    durations: Vec<Duration>,
+    bits: BitVec,
+}
+
+impl BoundedAverageDelayDecoder {
+    /// # Panics
+    ///
+    /// Panics if `max_samples` is less than two.
+    pub fn new(max_samples: usize) -> Self {
+        assert!(
+            max_samples >= 2,
+            "at least two samples are needed for a mean"
+        );
+        Self {
+            max_samples,
+            mean: None,
+            durations: Vec::new(),
+            bits: BitVec::new(),
+        }
+    }
+
+    /// The mean later durations are classified against, once `max_samples`
+    /// durations were pushed.
+    pub const fn mean(&self) -> Option<Duration> {
+        self.mean
+    }
+
+    fn decode(&self) -> BitVec {
+        match self.mean {
+            Some(_) => self.bits.clone(),
+            None if self.durations.len() < 2 => BitVec::EMPTY,
+            None => classify_at_mean(&self.durations),
+        }
+    }
+}
+
+impl DelayDecoder for BoundedAverageDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        match self.mean {
+            Some(mean) => self.bits.push(duration >= mean),
+            None => {
+                self.durations.push(duration);
+                if self.durations.len() == self.max_samples {
+                    self.bits = classify_at_mean(&self.durations);
+                    self.mean = Some(mean_duration(&self.durations));
+                    self.durations = Vec::new();
+                }
+            }
+        }
+    }
+
+    fn close(mut self) -> BitVec {
+        self.close_and_reset()
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.decode())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        self.mean
+            .or_else(|| (self.durations.len() >= 2).then(|| mean_duration(&self.durations)))
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for BoundedAverageDelayDecoder {}
+
+impl ReusableDelayDecoder for BoundedAverageDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        let bits = match self.mean.take() {
+            Some(_) => take(&mut self.bits),
+            None => self.decode(),
+        };
+        self.durations.clear();
+        bits
+    }
+}
+
+impl StreamingDelayDecoder for BoundedAverageDelayDecoder {
+    /// Nothing is decided before `max_samples` durations are pushed.
+    fn take_decided(&mut self) -> BitVec {
+        take(&mut self.bits)
+    }
+}
+
+/// Classifies `durations` against their mean, as [`AverageDelayDecoder`]
+/// does.
+fn classify_at_mean(durations: &[Duration]) -> BitVec {
+    let average_duration = mean_duration(durations);
+    durations
+        .iter()
+        .map(|duration| *duration >= average_duration)
+        .collect()
+}
+
+/// Emits `true` for every duration at or above the median of all durations.
+///
+/// Unlike the mean of [`AverageDelayDecoder`], the median is not dragged up
+/// by a single outlier, such as a retransmit inflating one gap to seconds.
+/// For an even number of durations the median is the mean of the two middle
+/// ones.
+#[derive(Default, Debug)]
+pub struct MedianDelayDecoder {
+    durations: Vec<Duration>,
+}
+
+impl MedianDelayDecoder {
+    pub const fn new() -> Self {
+        Self {
+            durations: Vec::new(),
+        }
+    }
+
+    fn decode(&self) -> BitVec {
+        if self.durations.len() < 2 {
+            return BitVec::EMPTY;
+        }
+
+        let median_duration = median_duration(&self.durations);
+        self.durations
+            .iter()
+            .map(|duration| *duration >= median_duration)
+            .collect()
+    }
+}
+
+impl DelayDecoder for MedianDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.durations.push(duration);
+    }
+
+    fn close(mut self) -> BitVec {
+        self.close_and_reset()
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.decode())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        (self.durations.len() >= 2).then(|| median_duration(&self.durations))
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for MedianDelayDecoder {}
+
+impl ReusableDelayDecoder for MedianDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        let bits = self.decode();
+        self.durations.clear();
+        bits
+    }
+}
+
+impl StreamingDelayDecoder for MedianDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        BitVec::new()
+    }
+}
+
+/// Emits `true` for every duration at or above the `quantile` of all
+/// durations, for encodings where long delays are not half of the symbols:
+/// with a `quantile` of `0.8`, about the longest fifth of the durations
+/// decode to `true`.
+///
+/// The threshold is the duration at index `quantile * n`, rounded down, of
+/// the `n` durations sorted.
+#[derive(Debug)]
+pub struct QuantileDelayDecoder {
+    quantile: f64,
+    durations: Vec<Duration>,
+}
+
+impl QuantileDelayDecoder {
+    /// # Panics
+    ///
+    /// Panics if `quantile` is not in `(0, 1)`.
+    pub fn new(quantile: f64) -> Self {
+        assert!(
+            quantile > 0.0 && quantile < 1.0,
+            "quantile must be in (0, 1)"
+        );
+        Self {
+            quantile,
+            durations: Vec::new(),
+        }
+    }
+
+    pub const fn quantile(&self) -> f64 {
+        self.quantile
+    }
+
+    /// The threshold of the durations so far, or `None` for fewer than two.
+    pub fn threshold(&self) -> Option<Duration> {
+        if self.durations.len() < 2 {
+            return None;
+        }
+
+        let mut sorted = self.durations.clone();
+        sorted.sort_unstable();
+        let index = (self.quantile * sorted.len() as f64) as usize;
+        Some(sorted[index.min(sorted.len() - 1)])
+    }
+
+    fn decode(&self) -> BitVec {
+        let Some(threshold) = self.threshold() else {
+            return BitVec::EMPTY;
+        };
+
+        self.durations
+            .iter()
+            .map(|duration| *duration >= threshold)
+            .collect()
+    }
+}
+
+impl DelayDecoder for QuantileDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.durations.push(duration);
+    }
+
+    fn close(mut self) -> BitVec {
+        self.close_and_reset()
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.decode())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        self.threshold()
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for QuantileDelayDecoder {}
+
+impl ReusableDelayDecoder for QuantileDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        let bits = self.decode();
+        self.durations.clear();
+        bits
+    }
+}
+
+impl StreamingDelayDecoder for QuantileDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        BitVec::new()
+    }
+}
+
+/// Lloyd iterations [`KMeansDelayDecoder`] runs at most.
+const KMEANS_MAX_ITERATIONS: usize = 64;
+/// [`KMeansDelayDecoder`] stops iterating once no centroid moves further.
+const KMEANS_EPSILON: Duration = Duration::from_micros(1);
+
+/// The centroids of the short and long durations found by
+/// [`KMeansDelayDecoder`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct DelayClusters {
+    pub short: Duration,
+    pub long: Duration,
+}
+
+impl DelayClusters {
+    /// The inferred gap between short and long delays.
+    pub fn gap(&self) -> Duration {
+        self.long.saturating_sub(self.short)
+    }
+
+    /// The midpoint between the centroids, at or above which a duration is
+    /// nearer the long one.
+    fn boundary(&self) -> Duration {
+        mean_duration(&[self.short, self.long])
+    }
+}
+
+/// Clusters durations into short and long ones with 1-D 2-means, and emits
+/// `true` for the long ones. This adapts to the jitter of each session
+/// without a fixed threshold, and is less sensitive to a skewed split of
+/// short and long durations than the mean of [`AverageDelayDecoder`].
+///
+/// The centroids start at the shortest and longest durations. If all
+/// durations are equal, every bit is `false`.
+#[derive(Default, Debug)]
+pub struct KMeansDelayDecoder {
+    durations: Vec<Duration>,
+}
+
+impl KMeansDelayDecoder {
+    pub const fn new() -> Self {
+        Self {
+            durations: Vec::new(),
+        }
+    }
+
+    /// Clusters the durations pushed so far, or returns `None` for fewer than
+    /// two of them, e.g. to log the inferred gap before closing.
+    pub fn clusters(&self) -> Option<DelayClusters> {
+        if self.durations.len() < 2 {
+            return None;
+        }
+
+        let min = self.durations.iter().min().copied()?;
+        let max = self.durations.iter().max().copied()?;
+        let mut clusters = DelayClusters {
+            short: min,
+            long: max,
+        };
+        if min == max {
+            return Some(clusters);
+        }
+
+        for _ in 0..KMEANS_MAX_ITERATIONS {
+            let boundary = clusters.boundary();
+            let (mut short_sum, mut short_len) = (0u128, 0u128);
+            let (mut long_sum, mut long_len) = (0u128, 0u128);
+            for duration in &self.durations {
+                if *duration >= boundary {
+                    long_sum += duration.as_nanos();
+                    long_len += 1;
+                } else {
+                    short_sum += duration.as_nanos();
+                    short_len += 1;
+                }
+            }
+            if short_len == 0 || long_len == 0 {
+                break;
+            }
+
+            let next = DelayClusters {
+                short: nanos_to_duration(short_sum / short_len),
+                long: nanos_to_duration(long_sum / long_len),
+            };
+            let moved = next
+                .short
+                .abs_diff(clusters.short)
+                .max(next.long.abs_diff(clusters.long));
+            clusters = next;
+            if moved < KMEANS_EPSILON {
+                break;
+            }
+        }
+
+        Some(clusters)
+    }
+
+    fn decode(&self) -> BitVec {
+        let Some(clusters) = self.clusters() else {
+            return BitVec::EMPTY;
+        };
+        if clusters.short == clusters.long {
+            return BitVec::repeat(false, self.durations.len());
+        }
+
+        let boundary = clusters.boundary();
+        self.durations
+            .iter()
+            .map(|duration| *duration >= boundary)
+            .collect()
+    }
+}
+
+impl DelayDecoder for KMeansDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.durations.push(duration);
+    }
+
+    fn close(mut self) -> BitVec {
+        self.close_and_reset()
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.decode())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        self.clusters()
+            .filter(|clusters| clusters.short != clusters.long)
+            .map(|clusters| clusters.boundary())
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for KMeansDelayDecoder {}
+
+impl ReusableDelayDecoder for KMeansDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        let bits = self.decode();
+        self.durations.clear();
+        bits
+    }
+}
+
+impl StreamingDelayDecoder for KMeansDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        BitVec::new()
+    }
+}
+
+/// Histogram buckets [`OtsuDelayDecoder`] spreads durations over, unless a
+/// bucket width is set.
+const OTSU_DEFAULT_BUCKETS: u128 = 64;
+/// Caps the buckets of a set bucket width, bounding the histogram's size.
+const OTSU_MAX_BUCKETS: u128 = 4096;
+
+/// Picks the threshold that maximizes the between-class variance of a
+/// histogram of the durations, as Otsu's method does for image binarization,
+/// and emits `true` for durations at or above it.
+///
+/// Unless [`bucket_width`](Self::bucket_width) is set, the histogram spreads
+/// the durations over 64 buckets. If they all fall into one bucket, every bit
+/// is `false`.
+#[derive(Default, Debug)]
+pub struct OtsuDelayDecoder {
+    bucket_width: Option<Duration>,
+    durations: Vec<Duration>,
+}
+
+impl OtsuDelayDecoder {
+    pub const fn new() -> Self {
+        Self {
+            bucket_width: None,
+            durations: Vec::new(),
+        }
+    }
+
+    /// Sets the width of the histogram's buckets. Widths so narrow that the
+    /// durations would span more than 4096 buckets are widened.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero.
+    pub fn bucket_width(mut self, width: Duration) -> Self {
+        assert!(!width.is_zero(), "bucket width must be non-zero");
+        self.bucket_width = Some(width);
+        self
+    }
+
+    /// The threshold chosen for the durations pushed so far, or `None` if
+    /// there are fewer than two or they all fall into one bucket.
+    pub fn threshold(&self) -> Option<Duration> {
+        if self.durations.len() < 2 {
+            return None;
+        }
+
+        let min = self.durations.iter().min()?.as_nanos();
+        let spread = self.durations.iter().max()?.as_nanos() - min;
+        let width = match self.bucket_width {
+            Some(width) => width.as_nanos().max(spread.div_ceil(OTSU_MAX_BUCKETS)),
+            None => spread.div_ceil(OTSU_DEFAULT_BUCKETS),
+        };
+        if width == 0 {
+            return None;
+        }
+        let buckets = usize::try_from(spread / width + 1).ok()?;
+        if buckets < 2 {
+            return None;
+        }
+
+        // The count and the sum of the nanoseconds of each bucket's durations.
+        let mut histogram = vec![(0u64, 0f64); buckets];
+        for duration in &self.durations {
+            let nanos = duration.as_nanos() - min;
+            let bucket = &mut histogram[(nanos / width) as usize];
+            bucket.0 += 1;
+            bucket.1 += nanos as f64;
+        }
+
+        let total = self.durations.len() as f64;
+        let total_sum: f64 = histogram.iter().map(|(_, sum)| sum).sum();
+        let (mut below, mut below_sum) = (0f64, 0f64);
+        let mut best = None;
+        for split in 1..buckets {
+            let (count, sum) = histogram[split - 1];
+            below += count as f64;
+            below_sum += sum;
+            let above = total - below;
+            if below == 0.0 || above == 0.0 {
+                continue;
+            }
+
+            let mean_gap = below_sum / below - (total_sum - below_sum) / above;
+            let variance = below * above * mean_gap * mean_gap;
+            match &mut best {
+                Some((_, last, best)) if variance == *best => *last = split,
+                Some((_, _, best)) if variance < *best => {}
+                _ => best = Some((split, split, variance)),
+            }
+        }
+
+        // Splits between the same buckets tie, so the threshold is centered
+        // in the gap between them.
+        let (first, last, _) = best?;
+        Some(nanos_to_duration(min + (first + last) as u128 * width / 2))
+    }
+
+    fn decode(&self) -> BitVec {
+        if self.durations.len() < 2 {
+            return BitVec::EMPTY;
+        }
+
+        match self.threshold() {
+            Some(threshold) => self
+                .durations
+                .iter()
+                .map(|duration| *duration >= threshold)
+                .collect(),
+            None => BitVec::repeat(false, self.durations.len()),
+        }
+    }
+}
+
+impl DelayDecoder for OtsuDelayDecoder {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.durations.push(duration);
+    }
+
+    fn close(mut self) -> BitVec {
+        self.close_and_reset()
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        Some(self.decode())
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        self.threshold()
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for OtsuDelayDecoder {}
+
+impl ReusableDelayDecoder for OtsuDelayDecoder {
+    fn close_and_reset(&mut self) -> BitVec {
+        let bits = self.decode();
+        self.durations.clear();
+        bits
+    }
+}
+
+impl StreamingDelayDecoder for OtsuDelayDecoder {
+    fn take_decided(&mut self) -> BitVec {
+        BitVec::new()
+    }
+}
+
+/// A decoder that can tell how marginal each of its decisions was.
+pub trait SoftDelayDecoder: DelayDecoder {
+    /// Like `close`, pairing every bit with a confidence in `[0.5, 1]`: `0.5`
+    /// for a duration right at the decision boundary, approaching `1` the
+    /// further it is from the boundary, relative to the mean absolute
+    /// deviation of the session's durations.
+    fn close_soft(self) -> Vec<(bool, f32)>;
+}
+
+impl SoftDelayDecoder for AverageDelayDecoder {
+    fn close_soft(self) -> Vec<(bool, f32)> {
+        if self.durations.len() < 2 {
+            return Vec::new();
+        }
+        soft_bits(&self.durations, mean_duration(&self.durations))
+    }
+}
+
+impl SoftDelayDecoder for MedianDelayDecoder {
+    fn close_soft(self) -> Vec<(bool, f32)> {
+        if self.durations.len() < 2 {
+            return Vec::new();
+        }
+        soft_bits(&self.durations, median_duration(&self.durations))
+    }
+}
+
+impl SoftDelayDecoder for KMeansDelayDecoder {
+    fn close_soft(self) -> Vec<(bool, f32)> {
+        match self.clusters() {
+            None => Vec::new(),
+            Some(clusters) if clusters.short == clusters.long => {
+                vec![(false, 0.5); self.durations.len()]
+            }
+            Some(clusters) => soft_bits(&self.durations, clusters.boundary()),
+        }
+    }
+}
+
+impl SoftDelayDecoder for OtsuDelayDecoder {
+    fn close_soft(self) -> Vec<(bool, f32)> {
+        if self.durations.len() < 2 {
+            return Vec::new();
+        }
+        match self.threshold() {
+            Some(threshold) => soft_bits(&self.durations, threshold),
+            None => vec![(false, 0.5); self.durations.len()],
+        }
+    }
+}
+
+/// Outputs the soft bits of `decoder`, so sessions and stores can carry
+/// them. Snapshots are not supported.
+#[derive(Debug)]
+pub struct SoftDecoder<D> {
+    decoder: D,
+}
+
+impl<D> SoftDecoder<D> {
+    pub const fn new(decoder: D) -> Self {
+        Self { decoder }
+    }
+}
+
+impl<D: SoftDelayDecoder> DelayDecoder for SoftDecoder<D> {
+    type Output = Vec<(bool, f32)>;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.decoder.push_duration(duration);
+    }
+
+    fn close(self) -> Vec<(bool, f32)> {
+        self.decoder.close_soft()
+    }
+}
+
+impl<D: SoftDelayDecoder + MetaDelayDecoder<M>, M: ?Sized> MetaDelayDecoder<M> for SoftDecoder<D> {
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.decoder.push_duration_with(duration, meta);
+    }
+}
+
+/// Classifies `durations` against `boundary` as the buffering decoders do,
+/// with the confidences of [`SoftDelayDecoder::close_soft`].
+fn soft_bits(durations: &[Duration], boundary: Duration) -> Vec<(bool, f32)> {
+    let boundary = boundary.as_secs_f64();
+    let distances: Vec<_> = durations
+        .iter()
+        .map(|duration| duration.as_secs_f64() - boundary)
+        .collect();
+    let mean = distances.iter().sum::<f64>() / distances.len() as f64;
+    let spread = distances
+        .iter()
+        .map(|distance| (distance - mean).abs())
+        .sum::<f64>()
+        / distances.len() as f64;
+
+    distances
+        .iter()
+        .map(|distance| {
+            let confidence = match distance.abs() {
+                0.0 => 0.5,
+                _ if spread == 0.0 => 1.0,
+                distance => {
+                    let relative = distance / spread;
+                    0.5 + 0.5 * relative / (1.0 + relative)
+                }
+            };
+            (*distance >= 0.0, confidence as f32)
+        })
+        .collect()
+}
+
+/// Decodes each duration into the index of the band it falls in, for
+/// encodings of several bits per duration. With boundaries `[b0, b1, b2]`,
+/// durations below `b0` decode to `0`, durations from `b0` up to `b1` to
+/// `1`, and so on up to `3` for durations from `b2` on: two bits each.
+#[derive(Debug)]
+pub struct MultiLevelDelayDecoder {
+    boundaries: Vec<Duration>,
+    symbols: Vec<u8>,
+}
+
+impl MultiLevelDelayDecoder {
+    /// # Panics
+    ///
+    /// Panics if `boundaries` is not strictly increasing, or has more than
+    /// 255 boundaries, whose symbols would not fit in a `u8`.
+    pub fn new(boundaries: impl Into<Vec<Duration>>) -> Self {
+        let boundaries = boundaries.into();
+        assert!(
+            boundaries.windows(2).all(|pair| pair[0] < pair[1]),
+            "band boundaries must be strictly increasing"
+        );
+        assert!(
+            boundaries.len() <= usize::from(u8::MAX),
+            "at most 255 band boundaries are supported"
+        );
+
+        Self {
+            boundaries,
+            symbols: Vec::new(),
+        }
+    }
+
+    /// The number of symbols, one more than the number of boundaries.
+    pub fn levels(&self) -> usize {
+        self.boundaries.len() + 1
+    }
+}
+
+impl DelayDecoder for MultiLevelDelayDecoder {
+    type Output = Vec<u8>;
+
+    fn push_duration(&mut self, duration: Duration) {
+        let band = self
+            .boundaries
+            .partition_point(|boundary| *boundary <= duration);
+        self.symbols.push(band as u8);
+    }
+
+    fn close(self) -> Vec<u8> {
+        self.symbols
+    }
+
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        Some(self.symbols.clone())
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for MultiLevelDelayDecoder {}
+
+impl ReusableDelayDecoder for MultiLevelDelayDecoder {
+    fn close_and_reset(&mut self) -> Vec<u8> {
+        take(&mut self.symbols)
+    }
+}
+
+impl StreamingDelayDecoder for MultiLevelDelayDecoder {
+    fn take_decided(&mut self) -> Vec<u8> {
+        take(&mut self.symbols)
+    }
+}
+
+/// Decodes pulse-position signals: time is divided into slots of
+/// `slot_duration`, each split into `slots_per_symbol` equal positions, and
+/// each signal decodes to the index of the position it falls in within its
+/// slot. The first signal of a session fixes the slots' phase, so offsets are
+/// accumulated from the pushed durations modulo `slot_duration`.
+///
+/// Senders are expected to aim at the start of a position. Jitter can make a
+/// signal early, just before the start of its position, so signals up to the
+/// [`guard_interval`](Self::guard_interval) before a position's start are
+/// decoded as that position, wrapping around to position `0` at the end of
+/// the slot.
+#[derive(Debug)]
+pub struct SlotDelayDecoder {
+    slot_nanos: u128,
+    positions: u8,
+    guard_nanos: u128,
+    /// The current signal's offset into its slot.
+    offset_nanos: u128,
+    symbols: Vec<u8>,
+}
+
+impl SlotDelayDecoder {
+    /// # Panics
+    ///
+    /// Panics if `slot_duration` is zero, if `slots_per_symbol` is not
+    /// between 2 and 256, or if a position would be shorter than a
+    /// nanosecond.
+    pub fn new(slot_duration: Duration, slots_per_symbol: usize) -> Self {
+        assert!(
+            (2..=256).contains(&slots_per_symbol),
+            "slots per symbol must be between 2 and 256"
+        );
+        let slot_nanos = slot_duration.as_nanos();
+        assert!(
+            slot_nanos >= slots_per_symbol as u128,
+            "slot positions must be at least a nanosecond long"
+        );
+
+        Self {
+            slot_nanos,
+            positions: (slots_per_symbol - 1) as u8,
+            guard_nanos: 0,
+            offset_nanos: 0,
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Sets how early a signal may be and still decode as the position it
+    /// was aimed at. Zero unless set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `guard` is not shorter than a position.
+    pub fn guard_interval(mut self, guard: Duration) -> Self {
+        assert!(
+            guard.as_nanos() < self.position_nanos(),
+            "guard interval must be shorter than a slot position"
+        );
+        self.guard_nanos = guard.as_nanos();
+        self
+    }
+
+    /// The number of positions, the symbols being their indices.
+    pub fn levels(&self) -> usize {
+        usize::from(self.positions) + 1
+    }
+
+    fn position_nanos(&self) -> u128 {
+        self.slot_nanos / (u128::from(self.positions) + 1)
+    }
+}
+
+impl DelayDecoder for SlotDelayDecoder {
+    type Output = Vec<u8>;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.offset_nanos = (self.offset_nanos + duration.as_nanos()) % self.slot_nanos;
+        let guarded = (self.offset_nanos + self.guard_nanos) % self.slot_nanos;
+        // The last position also takes the remainder of an uneven split.
+        let position = (guarded / self.position_nanos()).min(u128::from(self.positions));
+        self.symbols.push(position as u8);
+    }
+
+    fn close(self) -> Vec<u8> {
+        self.symbols
+    }
+
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        Some(self.symbols.clone())
+    }
+}
+
+impl<M: ?Sized> MetaDelayDecoder<M> for SlotDelayDecoder {}
+
+impl ReusableDelayDecoder for SlotDelayDecoder {
+    fn close_and_reset(&mut self) -> Vec<u8> {
+        self.offset_nanos = 0;
+        take(&mut self.symbols)
+    }
+}
+
+impl StreamingDelayDecoder for SlotDelayDecoder {
+    fn take_decided(&mut self) -> Vec<u8> {
+        take(&mut self.symbols)
+    }
+}
+
+/// Multiplies every duration by `factor` before passing it on, so a decoder
+/// tuned for real-time durations can decode a time-dilated replay.
+#[derive(Debug)]
+pub struct ScaledDelayDecoder<D> {
+    decoder: D,
+    factor: f64,
+}
+
+impl<D> ScaledDelayDecoder<D> {
+    /// # Panics
+    ///
+    /// Panics if `factor` is not finite and positive.
+    pub fn new(decoder: D, factor: f64) -> Self {
+        assert!(
+            factor.is_finite() && factor > 0.0,
+            "scale factor must be finite and positive"
+        );
+        Self { decoder, factor }
+    }
+
+    fn scale(&self, duration: Duration) -> Duration {
+        Duration::try_from_secs_f64(duration.as_secs_f64() * self.factor).unwrap_or(Duration::MAX)
+    }
+}
+
+impl<D: DelayDecoder> DelayDecoder for ScaledDelayDecoder<D> {
+    type Output = D::Output;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.decoder.push_duration(self.scale(duration));
+    }
+
+    fn close(self) -> D::Output {
+        self.decoder.close()
+    }
+
+    fn snapshot(&self) -> Option<D::Output> {
+        self.decoder.snapshot()
+    }
+}
+
+impl<D: MetaDelayDecoder<M>, M: ?Sized> MetaDelayDecoder<M> for ScaledDelayDecoder<D> {
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.decoder.push_duration_with(self.scale(duration), meta);
+    }
+}
+
+impl<D: ReusableDelayDecoder> ReusableDelayDecoder for ScaledDelayDecoder<D> {
+    fn close_and_reset(&mut self) -> D::Output {
+        self.decoder.close_and_reset()
+    }
+}
+
+impl<D: StreamingDelayDecoder> StreamingDelayDecoder for ScaledDelayDecoder<D> {
+    fn take_decided(&mut self) -> D::Output {
+        self.decoder.take_decided()
+    }
+}
+
+/// Statistics of the durations a session pushed to its decoder.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct DecodeStats {
+    pub count: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    /// The population standard deviation.
+    pub std_dev: Duration,
+    /// The decoder's [`decision_threshold`](DelayDecoder::decision_threshold)
+    /// when it was closed.
+    pub threshold: Option<Duration>,
+}
+
+impl<O: DecoderOutput> DecoderOutput for (O, DecodeStats) {
+    fn symbol_count(&self) -> usize {
+        self.0.symbol_count()
+    }
+}
+
+/// Records [`DecodeStats`] of the durations passed on to `decoder`, and
+/// outputs them along with what it decodes, e.g. for tuning. A store of
+/// these decoders emits `(key, (bits, stats))`.
+#[derive(Debug)]
+pub struct StatsDecoder<D> {
+    decoder: D,
+    count: usize,
+    min: Duration,
+    max: Duration,
+    sum_nanos: u128,
+    sum_squared_nanos: u128,
+}
+
+impl<D> StatsDecoder<D> {
+    pub const fn new(decoder: D) -> Self {
+        Self {
+            decoder,
+            count: 0,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            sum_nanos: 0,
+            sum_squared_nanos: 0,
+        }
+    }
+
+    /// The statistics of the durations so far.
+    pub fn stats(&self) -> DecodeStats
+    where
+        D: DelayDecoder,
+    {
+        if self.count == 0 {
+            return DecodeStats::default();
+        }
+
+        let count = self.count as u128;
+        let mean = self.sum_nanos / count;
+        let variance = (self.sum_squared_nanos / count).saturating_sub(mean * mean);
+        DecodeStats {
+            count: self.count,
+            min: self.min,
+            max: self.max,
+            mean: nanos_to_duration(mean),
+            std_dev: nanos_to_duration(variance.isqrt()),
+            threshold: self.decoder.decision_threshold(),
+        }
+    }
+
+    fn record(&mut self, duration: Duration) {
+        let nanos = duration.as_nanos();
+        self.count += 1;
+        self.min = self.min.min(duration);
+        self.max = self.max.max(duration);
+        self.sum_nanos = self.sum_nanos.saturating_add(nanos);
+        self.sum_squared_nanos = self
+            .sum_squared_nanos
+            .saturating_add(nanos.saturating_mul(nanos));
+    }
+
+    fn reset_stats(&mut self) {
+        self.count = 0;
+        self.min = Duration::MAX;
+        self.max = Duration::ZERO;
+        self.sum_nanos = 0;
+        self.sum_squared_nanos = 0;
+    }
+}
+
+impl<D: DelayDecoder> DelayDecoder for StatsDecoder<D> {
+    type Output = (D::Output, DecodeStats);
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.record(duration);
+        self.decoder.push_duration(duration);
+    }
+
+    fn close(self) -> (D::Output, DecodeStats) {
+        let stats = self.stats();
+        (self.decoder.close(), stats)
+    }
+
+    fn snapshot(&self) -> Option<(D::Output, DecodeStats)> {
+        Some((self.decoder.snapshot()?, self.stats()))
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        self.decoder.decision_threshold()
+    }
+}
+
+impl<D: MetaDelayDecoder<M>, M: ?Sized> MetaDelayDecoder<M> for StatsDecoder<D> {
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.record(duration);
+        self.decoder.push_duration_with(duration, meta);
+    }
+}
+
+impl<D: ReusableDelayDecoder> ReusableDelayDecoder for StatsDecoder<D> {
+    fn close_and_reset(&mut self) -> (D::Output, DecodeStats) {
+        let stats = self.stats();
+        self.reset_stats();
+        (self.decoder.close_and_reset(), stats)
+    }
+}
+
+/// Merges durations shorter than `min_gap`, e.g. between a request and its
+/// retransmit, into the next duration, so the next gap is measured from the
+/// original signal and duplicates decode like the traffic without them. A
+/// short duration at the end of a session is dropped.
+#[derive(Debug)]
+pub struct DedupDelayDecoder<D> {
+    decoder: D,
+    min_gap: Duration,
+    pending: Duration,
+}
+
+impl<D> DedupDelayDecoder<D> {
+    pub const fn new(decoder: D, min_gap: Duration) -> Self {
+        Self {
+            decoder,
+            min_gap,
+            pending: Duration::ZERO,
+        }
+    }
+
+    /// Returns the duration to push, or `None` if it was merged into the
+    /// next one.
+    fn merge(&mut self, duration: Duration) -> Option<Duration> {
+        if duration < self.min_gap {
+            self.pending = self.pending.saturating_add(duration);
+            None
+        } else {
+            Some(take(&mut self.pending).saturating_add(duration))
+        }
+    }
+}
+
+impl<D: DelayDecoder> DelayDecoder for DedupDelayDecoder<D> {
+    type Output = D::Output;
+
+    fn push_duration(&mut self, duration: Duration) {
+        if let Some(duration) = self.merge(duration) {
+            self.decoder.push_duration(duration);
+        }
+    }
+
+    fn close(self) -> D::Output {
+        self.decoder.close()
+    }
+
+    fn snapshot(&self) -> Option<D::Output> {
+        self.decoder.snapshot()
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        self.decoder.decision_threshold()
+    }
+}
+
+impl<D: MetaDelayDecoder<M>, M: ?Sized> MetaDelayDecoder<M> for DedupDelayDecoder<D> {
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        if let Some(duration) = self.merge(duration) {
+            self.decoder.push_duration_with(duration, meta);
+        }
+    }
+}
+
+impl<D: ReusableDelayDecoder> ReusableDelayDecoder for DedupDelayDecoder<D> {
+    fn close_and_reset(&mut self) -> D::Output {
+        self.pending = Duration::ZERO;
+        self.decoder.close_and_reset()
+    }
+}
+
+impl<D: StreamingDelayDecoder> StreamingDelayDecoder for DedupDelayDecoder<D> {
+    fn take_decided(&mut self) -> D::Output {
+        self.decoder.take_decided()
+    }
+}
+
+/// Only decodes the durations ended by a signal whose metadata passes
+/// `filter`, e.g. to ignore requests for static assets. The duration ended
+/// by a filtered-out signal is merged into the next one, so gaps are measured
+/// between the signals that pass. Durations pushed without metadata are
+/// kept.
+pub struct MetadataFilterDecoder<D, M, F> {
+    decoder: D,
+    filter: F,
+    pending: Duration,
+    _meta: PhantomData<fn(&M)>,
+}
+
+impl<D, M, F> MetadataFilterDecoder<D, M, F>
+where
+    F: FnMut(&M) -> bool,
+{
+    pub const fn new(decoder: D, filter: F) -> Self {
+        Self {
+            decoder,
+            filter,
+            pending: Duration::ZERO,
+            _meta: PhantomData,
+        }
+    }
+}
+
+impl<D: core::fmt::Debug, M, F> core::fmt::Debug for MetadataFilterDecoder<D, M, F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MetadataFilterDecoder")
+            .field("decoder", &self.decoder)
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<D, M, F> DelayDecoder for MetadataFilterDecoder<D, M, F>
+where
+    D: DelayDecoder,
+    F: FnMut(&M) -> bool,
+{
+    type Output = D::Output;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.decoder
+            .push_duration(take(&mut self.pending).saturating_add(duration));
+    }
+
+    fn close(self) -> D::Output {
+        self.decoder.close()
+    }
+
+    fn snapshot(&self) -> Option<D::Output> {
+        self.decoder.snapshot()
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        self.decoder.decision_threshold()
+    }
+}
+
+impl<D, M, F> MetaDelayDecoder<M> for MetadataFilterDecoder<D, M, F>
+where
+    D: MetaDelayDecoder<M>,
+    F: FnMut(&M) -> bool,
+{
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        let duration = take(&mut self.pending).saturating_add(duration);
+        if (self.filter)(meta) {
+            self.decoder.push_duration_with(duration, meta);
+        } else {
+            self.pending = duration;
+        }
+    }
+}
+
+impl<D, M, F> ReusableDelayDecoder for MetadataFilterDecoder<D, M, F>
+where
+    D: ReusableDelayDecoder,
+    F: FnMut(&M) -> bool,
+{
+    fn close_and_reset(&mut self) -> D::Output {
+        self.pending = Duration::ZERO;
+        self.decoder.close_and_reset()
+    }
+}
+
+impl<D, M, F> StreamingDelayDecoder for MetadataFilterDecoder<D, M, F>
+where
+    D: StreamingDelayDecoder,
+    F: FnMut(&M) -> bool,
+{
+    fn take_decided(&mut self) -> D::Output {
+        self.decoder.take_decided()
+    }
+}
+
+/// What [`OutlierFilterDecoder`] does with an outlier.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum OutlierPolicy {
+    /// Clamps the outlier to the nearest fence, keeping one symbol per
+    /// duration.
+    #[default]
+    ClampToMax,
+    /// Drops the outlier, shifting the positions of every later symbol.
+    Drop,
+}
+
+/// Buffers durations and filters outliers out of them at close, before
+/// replaying the rest into `decoder`, so e.g. a few retransmits cannot skew
+/// the mean of an [`AverageDelayDecoder`].
+///
+/// Outliers are the durations outside Tukey's fences: more than `k` times
+/// the interquartile range below the first or above the third quartile, by
+/// nearest rank. Fewer than four durations are passed on unfiltered.
+///
+/// Metadata is buffered along with its duration and replayed with it.
+#[derive(Debug)]
+pub struct OutlierFilterDecoder<D, M = ()> {
+    decoder: D,
+    k: f64,
+    policy: OutlierPolicy,
+    durations: Vec<(Duration, Option<M>)>,
+    /// Copies the decoder for a snapshot to replay into, if enabled.
+    fork: Option<fn(&D) -> D>,
+}
+
+impl<D, M> OutlierFilterDecoder<D, M> {
+    /// # Panics
+    ///
+    /// Panics if `k` is not finite and positive.
+    pub fn new(decoder: D, k: f64) -> Self {
+        assert!(
+            k.is_finite() && k > 0.0,
+            "outlier factor must be finite and positive"
+        );
+        Self {
+            decoder,
+            k,
+            policy: OutlierPolicy::default(),
+            durations: Vec::new(),
+            fork: None,
+        }
+    }
+
+    pub const fn outlier_policy(mut self, policy: OutlierPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Supports snapshots, which filter the durations so far into a clone
+    /// of the decoder and close it.
+    pub fn snapshots(mut self) -> Self
+    where
+        D: Clone,
+    {
+        self.fork = Some(D::clone);
+        self
+    }
+
+    /// The fences outside of which durations are outliers, or `None` if
+    /// there are fewer than four durations.
+    pub fn fences(&self) -> Option<(Duration, Duration)> {
+        if self.durations.len() < 4 {
+            return None;
+        }
+
+        let mut sorted: Vec<_> = self
+            .durations
+            .iter()
+            .map(|(duration, _)| *duration)
+            .collect();
+        sorted.sort_unstable();
+        let last = sorted.len() - 1;
+        let (q1, q3) = (sorted[last / 4], sorted[last * 3 / 4]);
+        let reach =
+            Duration::try_from_secs_f64((q3 - q1).as_secs_f64() * self.k).unwrap_or(Duration::MAX);
+        Some((q1.saturating_sub(reach), q3.saturating_add(reach)))
+    }
+
+    /// Replays the filtered durations into the inner decoder.
+    fn replay(&mut self)
+    where
+        D: MetaDelayDecoder<M>,
+    {
+        let fences = self.fences();
+        replay_filtered(&mut self.decoder, &self.durations, fences, self.policy);
+        self.durations.clear();
+    }
+}
+
+impl<D, M> DelayDecoder for OutlierFilterDecoder<D, M>
+where
+    D: MetaDelayDecoder<M>,
+    M: Clone,
+{
+    type Output = D::Output;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.durations.push((duration, None));
+    }
+
+    fn close(mut self) -> D::Output {
+        self.replay();
+        self.decoder.close()
+    }
+
+    fn snapshot(&self) -> Option<D::Output> {
+        let mut decoder = (self.fork?)(&self.decoder);
+        replay_filtered(&mut decoder, &self.durations, self.fences(), self.policy);
+        Some(decoder.close())
+    }
+}
+
+impl<D, M> MetaDelayDecoder<M> for OutlierFilterDecoder<D, M>
+where
+    D: MetaDelayDecoder<M>,
+    M: Clone,
+{
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.durations.push((duration, Some(meta.clone())));
+    }
+}
+
+impl<D, M> ReusableDelayDecoder for OutlierFilterDecoder<D, M>
+where
+    D: ReusableDelayDecoder + MetaDelayDecoder<M>,
+    M: Clone,
+{
+    fn close_and_reset(&mut self) -> D::Output {
+        self.replay();
+        self.decoder.close_and_reset()
+    }
+}
+
+/// Pushes `durations` into `decoder` with their metadata, treating those
+/// outside `fences` as `policy` says.
+fn replay_filtered<D: MetaDelayDecoder<M>, M>(
+    decoder: &mut D,
+    durations: &[(Duration, Option<M>)],
+    fences: Option<(Duration, Duration)>,
+    policy: OutlierPolicy,
+) {
+    for (duration, meta) in durations {
+        let duration = match fences {
+            Some((low, high)) if *duration < low || *duration > high => match policy {
+                OutlierPolicy::ClampToMax => (*duration).clamp(low, high),
+                OutlierPolicy::Drop => continue,
+            },
+            _ => *duration,
+        };
+        match meta {
+            Some(meta) => decoder.push_duration_with(duration, meta),
+            None => decoder.push_duration(duration),
+        }
+    }
+}
+
+/// How often a decoder of an [`EnsembleDelayDecoder`] agreed with the vote.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct DecoderAgreement {
+    /// Voted positions the decoder had a bit for.
+    pub voted: usize,
+    /// Those of them where its bit won the vote.
+    pub agreed: usize,
+}
+
+impl DecoderAgreement {
+    /// The fraction of voted positions the decoder agreed on, or `None` if it
+    /// had a bit for none.
+    pub fn rate(&self) -> Option<f64> {
+        (self.voted > 0).then(|| self.agreed as f64 / self.voted as f64)
+    }
+}
+
+/// Forwards every duration to each of its decoders and takes a majority vote
+/// over their bits at every position.
+///
+/// Decoders may return different numbers of bits, so only positions that at
+/// least a quorum of them has a bit for are voted on, a majority of the
+/// decoders unless [`quorum`](Self::quorum) is set. Tied votes go to the
+/// [`tie_break`](Self::tie_break) bit, `false` unless set.
+///
+/// Its decoders must be reusable, so the ensemble is too. Each takes in the
+/// metadata `M` pushed to the ensemble.
+pub struct EnsembleDelayDecoder<M = ()> {
+    decoders: Vec<Box<dyn DynReusableDelayDecoder<BitVec, M> + Send>>,
+    quorum: Option<usize>,
+    tie_break: bool,
+}
+
+impl EnsembleDelayDecoder {
+    pub const fn new() -> Self {
+        Self::with_metadata()
+    }
+}
+
+impl<M> EnsembleDelayDecoder<M> {
+    /// An ensemble taking in metadata `M`, for decoders that look at it.
+    pub const fn with_metadata() -> Self {
+        Self {
+            decoders: Vec::new(),
+            quorum: None,
+            tie_break: false,
+        }
+    }
+
+    /// Adds a decoder to the vote.
+    pub fn decoder(
+        mut self,
+        decoder: impl ReusableDelayDecoder<Output = BitVec> + MetaDelayDecoder<M> + Send + 'static,
+    ) -> Self {
+        self.decoders.push(Box::new(decoder));
+        self
+    }
+
+    /// Sets how many decoders must have a bit at a position for it to be
+    /// voted on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `quorum` is zero.
+    pub fn quorum(mut self, quorum: usize) -> Self {
+        assert!(quorum > 0, "quorum must be non-zero");
+        self.quorum = Some(quorum);
+        self
+    }
+
+    pub const fn tie_break(mut self, bit: bool) -> Self {
+        self.tie_break = bit;
+        self
+    }
+
+    /// How often each decoder, in the order they were added, agreed with the
+    /// vote so far, or `None` if any of them does not support snapshots.
+    pub fn agreement(&self) -> Option<Vec<DecoderAgreement>> {
+        let bits = self
+            .decoders
+            .iter()
+            .map(|decoder| decoder.dyn_snapshot())
+            .collect::<Option<Vec<_>>>()?;
+        Some(majority_vote(&bits, self.quorum, self.tie_break).1)
+    }
+
+    /// Like `close`, also returning how often each decoder agreed with the
+    /// vote.
+    pub fn close_with_agreement(self) -> (BitVec, Vec<DecoderAgreement>) {
+        let bits: Vec<_> = self
+            .decoders
+            .into_iter()
+            .map(|decoder| decoder.dyn_close())
+            .collect();
+        majority_vote(&bits, self.quorum, self.tie_break)
+    }
+}
+
+/// Votes on every position that at least `quorum` of `bits` have, a majority
+/// of them if `None`.
+fn majority_vote(
+    bits: &[BitVec],
+    quorum: Option<usize>,
+    tie_break: bool,
+) -> (BitVec, Vec<DecoderAgreement>) {
+    let mut agreement = vec![DecoderAgreement::default(); bits.len()];
+    let quorum = quorum.unwrap_or(bits.len() / 2 + 1);
+
+    let mut lengths: Vec<_> = bits.iter().map(BitVec::len).collect();
+    lengths.sort_unstable_by(|a, b| b.cmp(a));
+    let voted = lengths.get(quorum - 1).copied().unwrap_or(0);
+
+    let mut result = BitVec::with_capacity(voted);
+    for position in 0..voted {
+        let (ones, zeros) =
+            bits.iter()
+                .filter_map(|bits| bits.get(position))
+                .fold((0, 0), |(ones, zeros), bit| {
+                    if *bit {
+                        (ones + 1, zeros)
+                    } else {
+                        (ones, zeros + 1)
+                    }
+                });
+        let winner = match ones.cmp(&zeros) {
+            Ordering::Greater => true,
+            Ordering::Less => false,
+            Ordering::Equal => tie_break,
+        };
+        result.push(winner);
+
+        for (bits, agreement) in bits.iter().zip(&mut agreement) {
+            if let Some(bit) = bits.get(position) {
+                agreement.voted += 1;
+                agreement.agreed += usize::from(*bit == winner);
+            }
+        }
+    }
+
+    (result, agreement)
+}
+
+impl<M> Default for EnsembleDelayDecoder<M> {
+    fn default() -> Self {
+        Self::with_metadata()
+    }
+}
+
+impl<M> core::fmt::Debug for EnsembleDelayDecoder<M> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EnsembleDelayDecoder")
+            .field("decoders", &self.decoders.len())
+            .field("quorum", &self.quorum)
+            .field("tie_break", &self.tie_break)
+            .finish()
+    }
+}
+
+impl<M> DelayDecoder for EnsembleDelayDecoder<M> {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        for decoder in &mut self.decoders {
+            decoder.dyn_push_duration(duration);
+        }
+    }
+
+    fn close(self) -> BitVec {
+        self.close_with_agreement().0
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        let bits = self
+            .decoders
+            .iter()
+            .map(|decoder| decoder.dyn_snapshot())
+            .collect::<Option<Vec<_>>>()?;
+        Some(majority_vote(&bits, self.quorum, self.tie_break).0)
+    }
+}
+
+impl<M> MetaDelayDecoder<M> for EnsembleDelayDecoder<M> {
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        for decoder in &mut self.decoders {
+            decoder.dyn_push_duration_with(duration, meta);
+        }
+    }
+}
+
+impl<M> ReusableDelayDecoder for EnsembleDelayDecoder<M> {
+    fn close_and_reset(&mut self) -> BitVec {
+        let bits: Vec<_> = self
+            .decoders
+            .iter_mut()
+            .map(|decoder| decoder.dyn_close_and_reset())
+            .collect();
+        majority_vote(&bits, self.quorum, self.tie_break).0
+    }
+}
+
+/// Debiases the bits of `decoder` with the von Neumann extractor at close:
+/// of every non-overlapping pair of bits, `(1, 0)` becomes `1`, `(0, 1)`
+/// becomes `0`, and equal pairs are discarded, along with a trailing odd bit.
+///
+/// The output is unbiased if the input bits are independent with a constant
+/// bias, but it is at most half as long as the input, and a quarter as long
+/// for unbiased input. The stronger the bias, the fewer bits survive.
+#[derive(Debug)]
+pub struct VonNeumannDecoder<D> {
+    decoder: D,
+}
+
+impl<D> VonNeumannDecoder<D> {
+    pub const fn new(decoder: D) -> Self {
+        Self { decoder }
+    }
+}
+
+impl<D: DelayDecoder<Output = BitVec>> VonNeumannDecoder<D> {
+    /// The fraction of complete pairs decoded so far that were discarded, if
+    /// the inner decoder supports snapshots and has decoded a pair.
+    pub fn discard_rate(&self) -> Option<f64> {
+        let bits = self.decoder.snapshot()?;
+        let pairs = bits.len() / 2;
+        if pairs == 0 {
+            return None;
+        }
+
+        let (extracted, _) = von_neumann_extract(&bits);
+        Some((pairs - extracted.len()) as f64 / pairs as f64)
+    }
+}
+
+impl<D: DelayDecoder<Output = BitVec>> DelayDecoder for VonNeumannDecoder<D> {
+    type Output = BitVec;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.decoder.push_duration(duration);
+    }
+
+    fn close(self) -> BitVec {
+        von_neumann_extract(&self.decoder.close()).0
+    }
+
+    fn snapshot(&self) -> Option<BitVec> {
+        self.decoder
+            .snapshot()
+            .map(|bits| von_neumann_extract(&bits).0)
+    }
+}
+
+impl<D: MetaDelayDecoder<M, Output = BitVec>, M: ?Sized> MetaDelayDecoder<M>
+    for VonNeumannDecoder<D>
+{
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.decoder.push_duration_with(duration, meta);
+    }
+}
+
+impl<D> ReusableDelayDecoder for VonNeumannDecoder<D>
+where
+    D: ReusableDelayDecoder<Output = BitVec>,
+{
+    fn close_and_reset(&mut self) -> BitVec {
+        von_neumann_extract(&self.decoder.close_and_reset()).0
+    }
+}
+
+/// Applies the von Neumann extractor of [`VonNeumannDecoder`] to `bits`,
+/// returning the extracted bits and the number of discarded pairs.
+pub fn von_neumann_extract(bits: &BitSlice) -> (BitVec, usize) {
+    let mut extracted = BitVec::with_capacity(bits.len() / 4);
+    let mut discarded = 0;
+
+    for pair in bits.chunks_exact(2) {
+        if pair[0] == pair[1] {
+            discarded += 1;
+        } else {
+            extracted.push(pair[0]);
+        }
+    }
+
+    (extracted, discarded)
+}
+
+/// Computes the exact mean of `durations` in `u128` nanoseconds, which cannot
+/// overflow for any realistic number of durations, even near `Duration::MAX`.
+pub(crate) fn mean_duration(durations: &[Duration]) -> Duration {
+    if durations.is_empty() {
+        return Duration::ZERO;
+    }
+
+    let nanos_sum: u128 = durations.iter().map(Duration::as_nanos).sum();
+    nanos_to_duration(nanos_sum / durations.len() as u128)
+}
+
+/// Computes the median of `durations`, the mean of the two middle ones for an
+/// even number of them.
+fn median_duration(durations: &[Duration]) -> Duration {
+    let mut sorted = durations.to_vec();
+    sorted.sort_unstable();
+
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => Duration::ZERO,
+        len if len % 2 == 1 => sorted[middle],
+        _ => mean_duration(&sorted[middle - 1..=middle]),
+    }
+}
+
+/// Converts nanoseconds into a `Duration`, saturating at `Duration::MAX`.
+pub(crate) fn nanos_to_duration(nanos: u128) -> Duration {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+    match u64::try_from(nanos / NANOS_PER_SEC) {
+        Ok(secs) => Duration::new(secs, (nanos % NANOS_PER_SEC) as u32),
+        Err(_) => Duration::MAX,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec::Vec};
+    use core::time::Duration;
+
+    use bitvec::prelude::*;
+
+    use super::{
+        von_neumann_extract, AverageDelayDecoder, BoundedAverageDelayDecoder, BoxedDelayDecoder,
+        CalibratedDelayDecoder, DeadBandPolicy, DecodeStats, DedupDelayDecoder, DelayDecoder,
+        DifferentialDelayDecoder, EmaThresholdDelayDecoder, EnsembleDelayDecoder,
+        HysteresisDelayDecoder, KMeansDelayDecoder, MedianDelayDecoder, MetaDelayDecoder,
+        MetadataFilterDecoder, NrziDecoder, NrziDelayDecoder, OtsuDelayDecoder,
+        OutlierFilterDecoder, OutlierPolicy, QuantileDelayDecoder, ReusableDelayDecoder,
+        SlotDelayDecoder, SoftDelayDecoder, StatsDecoder, StreamingDelayDecoder,
+        ThresholdDelayDecoder, VonNeumannDecoder,
+    };
+    use crate::encoder::{DelayEncoder, ThresholdDelayEncoder};
+    use crate::rng::SplitMix64;
+
+    const SHORT: Duration = Duration::from_millis(10);
+    const LONG: Duration = Duration::from_millis(30);
+
+    fn threshold_decoder() -> ThresholdDelayDecoder {
+        ThresholdDelayDecoder::new(Duration::from_millis(20))
+    }
+
+    fn delay(bit: bool) -> Duration {
+        if bit {
+            LONG
+        } else {
+            SHORT
+        }
+    }
+
+    #[test]
+    fn near_max_durations_decode_without_overflowing() {
+        fn decode(mut decoder: impl DelayDecoder<Output = BitVec>) -> BitVec {
+            for duration in [Duration::MAX, SHORT, Duration::MAX - SHORT, SHORT] {
+                decoder.push_duration(duration);
+            }
+            decoder.close()
+        }
+
+        let expected = bitvec![1, 0, 1, 0];
+        assert_eq!(decode(AverageDelayDecoder::new()), expected);
+        assert_eq!(decode(BoundedAverageDelayDecoder::new(8)), expected);
+        assert_eq!(decode(threshold_decoder()), expected);
+        assert_eq!(decode(KMeansDelayDecoder::new()), expected);
+        assert_eq!(decode(OtsuDelayDecoder::new()), expected);
+        assert_eq!(decode(MedianDelayDecoder::new()).len(), 4);
+        assert_eq!(decode(DifferentialDelayDecoder::new(SHORT)).len(), 3);
+    }
+
+    #[test]
+    fn jittered_encoder_delays_decode_back_into_the_message() {
+        let message = bitvec![1, 0, 0, 1, 1, 1, 0, 1, 0, 0, 0, 1, 1, 0, 1, 0];
+        let mut encoder =
+            ThresholdDelayEncoder::new(SHORT, LONG).jitter(Duration::from_millis(9), 3);
+
+        let mut decoder = encoder.decoder();
+        for delay in encoder.encode(&message) {
+            decoder.push_duration(delay);
+        }
+        assert_eq!(decoder.close(), message);
+    }
+
+    #[test]
+    fn von_neumann_pairing_drops_an_odd_last_bit() {
+        assert_eq!(
+            von_neumann_extract(bits![1, 0, 0, 1, 1]),
+            (bitvec![1, 0], 0)
+        );
+        assert_eq!(von_neumann_extract(bits![1, 1, 0]), (bitvec![], 1));
+        assert_eq!(von_neumann_extract(bits![1]), (bitvec![], 0));
+
+        let mut decoder = VonNeumannDecoder::new(threshold_decoder());
+        for bit in [true, false, true, true, false] {
+            decoder.push_duration(delay(bit));
+        }
+        assert_eq!(decoder.discard_rate(), Some(0.5));
+        assert_eq!(decoder.close(), bitvec![1]);
+    }
+
+    #[test]
+    fn von_neumann_output_of_a_biased_input_is_unbiased() {
+        const DURATIONS: usize = 100_000;
+
+        let mut rng = SplitMix64::new(3);
+        let mut decoder = VonNeumannDecoder::new(threshold_decoder());
+        for _ in 0..DURATIONS {
+            decoder.push_duration(delay(rng.next_f64() < 0.8));
+        }
+
+        // Pairs differ with probability 2 * 0.8 * 0.2.
+        let discard_rate = decoder.discard_rate().unwrap();
+        assert!((discard_rate - 0.68).abs() < 0.01, "{discard_rate}");
+        let bits = decoder.close();
+        let expected = DURATIONS as f64 / 2.0 * 0.32;
+        assert!((bits.len() as f64 - expected).abs() < expected * 0.05);
+        let ones = bits.count_ones() as f64 / bits.len() as f64;
+        // Five standard deviations of a fair coin over ~16000 flips.
+        assert!((ones - 0.5).abs() < 0.02, "{ones} of the bits are ones");
+    }
+
+    fn decode<D: DelayDecoder>(mut decoder: D, durations: &[Duration]) -> D::Output {
+        for duration in durations {
+            decoder.push_duration(*duration);
+        }
+        decoder.close()
+    }
+
+    #[test]
+    fn the_median_survives_an_outlier_that_flips_the_mean() {
+        let message = bitvec![1, 0, 1, 1, 0, 0, 1, 0];
+        let mut durations: Vec<_> = message.iter().by_vals().map(delay).collect();
+        // A retransmit inflates one long gap to two seconds.
+        durations[2] = Duration::from_secs(2);
+
+        assert_eq!(decode(MedianDelayDecoder::new(), &durations), message);
+        let mean = decode(AverageDelayDecoder::new(), &durations);
+        assert_eq!(mean, bitvec![0, 0, 1, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn the_median_of_an_even_count_is_between_the_middle_durations() {
+        let durations = [SHORT, LONG, SHORT, LONG];
+        let mut decoder = MedianDelayDecoder::new();
+        for duration in durations {
+            decoder.push_duration(duration);
+        }
+        assert_eq!(
+            decoder.decision_threshold(),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(decoder.close(), bitvec![0, 1, 0, 1]);
+
+        assert_eq!(decode(MedianDelayDecoder::new(), &[]), bitvec![]);
+        assert_eq!(decode(MedianDelayDecoder::new(), &[LONG]), bitvec![]);
+        assert_eq!(decode(AverageDelayDecoder::new(), &[LONG]), bitvec![]);
+    }
+
+    #[test]
+    fn filtering_outliers_recovers_a_mean_decoded_message() {
+        let message = bitvec![1, 0, 0, 1, 1, 0, 1, 0, 0, 1, 1, 1, 0, 0, 1, 0];
+        let mut durations: Vec<_> = message.iter().by_vals().map(delay).collect();
+        durations[3] = Duration::from_secs(5);
+        durations[10] = Duration::from_secs(5);
+
+        assert_ne!(decode(AverageDelayDecoder::new(), &durations), message);
+        for policy in [OutlierPolicy::ClampToMax, OutlierPolicy::Drop] {
+            let mut decoder = OutlierFilterDecoder::<_>::new(AverageDelayDecoder::new(), 1.5)
+                .outlier_policy(policy)
+                .snapshots();
+            for duration in &durations {
+                decoder.push_duration(*duration);
+            }
+            let snapshot = decoder.snapshot().unwrap();
+            let bits = decoder.close();
+            assert_eq!(snapshot, bits);
+            match policy {
+                OutlierPolicy::ClampToMax => assert_eq!(bits, message),
+                OutlierPolicy::Drop => {
+                    let mut kept = message.clone();
+                    kept.remove(10);
+                    kept.remove(3);
+                    assert_eq!(bits, kept);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn filtered_durations_are_replayed_with_their_metadata() {
+        let pushes = [
+            (SHORT, "/a"),
+            (LONG, "/b"),
+            (Duration::from_millis(5), "/style.css"),
+            (Duration::from_millis(5), "/c"),
+            (LONG, "/d"),
+            (SHORT, "/e"),
+        ];
+
+        let mut plain = MetadataFilterDecoder::new(ThresholdDelayDecoder::new(SHORT * 2), skip_css);
+        let mut filtered = OutlierFilterDecoder::<_, &str>::new(
+            MetadataFilterDecoder::new(ThresholdDelayDecoder::new(SHORT * 2), skip_css),
+            10.0,
+        );
+        for (duration, path) in pushes {
+            plain.push_duration_with(duration, &path);
+            filtered.push_duration_with(duration, &path);
+        }
+        assert_eq!(filtered.snapshot(), None);
+        // The stylesheet's gap is merged into the next one's.
+        assert_eq!(plain.close(), bitvec![0, 1, 0, 1, 0]);
+        assert_eq!(filtered.close(), bitvec![0, 1, 0, 1, 0]);
+    }
+
+    fn skip_css(path: &&str) -> bool {
+        !path.ends_with(".css")
+    }
+
+    fn ensemble() -> EnsembleDelayDecoder<&'static str> {
+        EnsembleDelayDecoder::with_metadata()
+            .decoder(MetadataFilterDecoder::new(threshold_decoder(), skip_css))
+            .decoder(MetadataFilterDecoder::new(
+                AverageDelayDecoder::new(),
+                skip_css,
+            ))
+            .decoder(MetadataFilterDecoder::new(
+                MedianDelayDecoder::new(),
+                skip_css,
+            ))
+    }
+
+    #[test]
+    fn ensembles_forward_metadata_to_every_decoder() {
+        let mut decoder = ensemble();
+        for (duration, path) in [
+            (LONG, "/a"),
+            (Duration::from_millis(2), "/style.css"),
+            (SHORT, "/b"),
+            (LONG, "/c"),
+            (SHORT, "/d"),
+        ] {
+            decoder.push_duration_with(duration, &path);
+        }
+
+        // The stylesheet's gap is merged into the next one's by every decoder.
+        let (bits, agreement) = decoder.close_with_agreement();
+        assert_eq!(bits, bitvec![1, 0, 1, 0]);
+        assert!(agreement
+            .iter()
+            .all(|agreement| agreement.rate() == Some(1.0)));
+    }
+
+    #[test]
+    fn reset_ensembles_decode_like_fresh_ones() {
+        let first = bitvec![1, 1, 0, 1, 0, 0];
+        let second = bitvec![0, 1, 0, 0, 1, 1, 0, 1];
+
+        let mut reused = ensemble();
+        for bit in first.iter().by_vals() {
+            reused.push_duration(delay(bit));
+        }
+        assert_eq!(reused.close_and_reset(), first);
+        for bit in second.iter().by_vals() {
+            reused.push_duration(delay(bit));
+        }
+        let durations: Vec<_> = second.iter().by_vals().map(delay).collect();
+        assert_eq!(reused.close(), decode(ensemble(), &durations));
+    }
+
+    #[test]
+    fn soft_bits_are_marginal_at_the_threshold_and_sure_at_the_extremes() {
+        let mut decoder = AverageDelayDecoder::new();
+        for duration in [SHORT, LONG, Duration::from_millis(20)] {
+            decoder.push_duration(duration);
+        }
+        let soft = decoder.close_soft();
+        assert_eq!(soft[2], (true, 0.5));
+        assert!(!soft[0].0 && soft[0].1 > 0.5);
+
+        // 500 short and 500 long durations around a median of 20 ms, and two
+        // far from it.
+        let mut decoder = MedianDelayDecoder::new();
+        let durations = [SHORT, LONG].repeat(500).into_iter().chain([
+            Duration::from_millis(20),
+            Duration::ZERO,
+            Duration::from_secs(60),
+        ]);
+        for duration in durations {
+            decoder.push_duration(duration);
+        }
+        let soft = decoder.close_soft();
+        let (bit, at_threshold) = soft[1000];
+        assert!(bit);
+        assert!((at_threshold - 0.5).abs() < 1e-6, "{at_threshold}");
+        let (bit, extreme) = soft[1002];
+        assert!(bit);
+        assert!(extreme > 0.99, "{extreme}");
+        for (bit, confidence) in &soft[..1000] {
+            assert!(
+                *confidence > 0.5 && *confidence < extreme,
+                "{bit}: {confidence}"
+            );
+        }
+    }
+
+    /// Asserts that taking decided bits every `every` durations and then
+    /// closing adds up to closing alone.
+    fn assert_streams_like_closing<D>(
+        mut decoder_factory: impl FnMut() -> D,
+        durations: &[Duration],
+    ) where
+        D: StreamingDelayDecoder<Output = BitVec>,
+    {
+        let closed = decode(decoder_factory(), durations);
+        for every in [1, 3, durations.len()] {
+            let mut decoder = decoder_factory();
+            let mut streamed = BitVec::<usize, Lsb0>::new();
+            for (n, duration) in durations.iter().enumerate() {
+                decoder.push_duration(*duration);
+                if (n + 1) % every == 0 {
+                    streamed.extend_from_bitslice(&decoder.take_decided());
+                }
+            }
+            streamed.extend_from_bitslice(&decoder.close());
+            assert_eq!(streamed, closed, "{}", core::any::type_name::<D>());
+        }
+    }
+
+    #[test]
+    fn streamed_bits_and_the_close_add_up_to_the_close_alone() {
+        let mut rng = SplitMix64::new(5);
+        let durations: Vec<_> = (0..64)
+            .map(|_| delay(rng.next_bool()) + Duration::from_millis(4).mul_f64(rng.next_f64()))
+            .collect();
+        let threshold = Duration::from_millis(22);
+
+        let tolerance = Duration::from_millis(3);
+        assert_streams_like_closing(
+            || ThresholdDelayDecoder::new_with_tolerance(threshold, tolerance),
+            &durations,
+        );
+        assert_streams_like_closing(|| EmaThresholdDelayDecoder::new(0.2, 1.2, 4), &durations);
+        assert_streams_like_closing(
+            || HysteresisDelayDecoder::new(Duration::from_millis(18), Duration::from_millis(26)),
+            &durations,
+        );
+        assert_streams_like_closing(
+            || DifferentialDelayDecoder::new(Duration::from_millis(5)),
+            &durations,
+        );
+        assert_streams_like_closing(|| NrziDelayDecoder::new(threshold, false), &durations);
+        assert_streams_like_closing(
+            || CalibratedDelayDecoder::new(4, Duration::from_millis(2)),
+            &durations,
+        );
+        assert_streams_like_closing(AverageDelayDecoder::new, &durations);
+        assert_streams_like_closing(|| BoundedAverageDelayDecoder::new(8), &durations);
+        assert_streams_like_closing(MedianDelayDecoder::new, &durations);
+        assert_streams_like_closing(|| QuantileDelayDecoder::new(0.5), &durations);
+        assert_streams_like_closing(KMeansDelayDecoder::new, &durations);
+        assert_streams_like_closing(OtsuDelayDecoder::new, &durations);
+        assert_streams_like_closing(
+            || DedupDelayDecoder::new(ThresholdDelayDecoder::new(threshold), SHORT),
+            &durations,
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn bounded_averages_of_a_million_durations_stay_small() {
+        use crate::test_alloc;
+
+        const DURATIONS: usize = 1_000_000;
+        const CAP: usize = 1024;
+
+        let mut rng = SplitMix64::new(9);
+        let durations: Vec<_> = (0..DURATIONS)
+            .map(|_| delay(rng.next_bool()) + Duration::from_millis(4).mul_f64(rng.next_f64()))
+            .collect();
+        let buffered = (CAP * core::mem::size_of::<Duration>()) as isize;
+
+        // Decided bits grow by a bit per duration, the buffer not at all.
+        let before = test_alloc::live_bytes();
+        let mut decoder = BoundedAverageDelayDecoder::new(CAP);
+        let mut peak = 0;
+        for duration in &durations {
+            decoder.push_duration(*duration);
+            peak = peak.max(test_alloc::live_bytes() - before);
+        }
+        assert!(
+            peak < 2 * buffered + (DURATIONS / 8) as isize,
+            "{peak} bytes"
+        );
+        assert_eq!(
+            decoder.close(),
+            decode(AverageDelayDecoder::new(), &durations)
+        );
+
+        // Taking the decided bits keeps the decoder within its buffer.
+        let before = test_alloc::live_bytes();
+        let mut decoder = BoundedAverageDelayDecoder::new(CAP);
+        let mut peak = 0;
+        let mut ones = 0;
+        for (n, duration) in durations.iter().enumerate() {
+            decoder.push_duration(*duration);
+            if n % CAP == 0 {
+                ones += decoder.take_decided().count_ones();
+            }
+            peak = peak.max(test_alloc::live_bytes() - before);
+        }
+        ones += decoder.close().count_ones();
+        assert!(peak < 2 * buffered, "{peak} bytes");
+        let expected = decode(AverageDelayDecoder::new(), &durations);
+        assert_eq!(ones, expected.count_ones());
+
+        for len in [2, 3, CAP / 2, CAP - 1, CAP] {
+            assert_eq!(
+                decode(BoundedAverageDelayDecoder::new(CAP), &durations[..len]),
+                decode(AverageDelayDecoder::new(), &durations[..len]),
+            );
+        }
+    }
+
+    #[test]
+    fn k_means_splits_a_skewed_session_between_its_clusters() {
+        // Mostly jittered long delays pull the mean above some of them.
+        let millis = [10, 30, 31, 29, 30, 32, 24, 30, 31, 29, 11, 30];
+        let mut decoder = KMeansDelayDecoder::new();
+        let mut average = AverageDelayDecoder::new();
+        for millis in millis {
+            decoder.push_duration(Duration::from_millis(millis));
+            average.push_duration(Duration::from_millis(millis));
+        }
+
+        let clusters = decoder.clusters().unwrap();
+        assert_eq!(clusters.short, Duration::from_micros(10_500));
+        assert_eq!(clusters.long, Duration::from_micros(29_600));
+        assert_eq!(
+            decoder.decision_threshold(),
+            Some(Duration::from_micros(20_050))
+        );
+
+        let expected: BitVec = millis.iter().map(|millis| *millis > 20).collect();
+        assert_ne!(average.close(), expected);
+        assert_eq!(decoder.close(), expected);
+    }
+
+    #[test]
+    fn k_means_of_equal_or_too_few_durations_is_all_false() {
+        let mut decoder = KMeansDelayDecoder::new();
+        decoder.push_duration(LONG);
+        assert_eq!(decoder.clusters(), None);
+
+        decoder.push_duration(LONG);
+        decoder.push_duration(LONG);
+        assert_eq!(decoder.decision_threshold(), None);
+        assert_eq!(decoder.close(), bitvec![0, 0, 0]);
+    }
+
+    #[test]
+    fn durations_hovering_around_the_threshold_keep_their_bit() {
+        let millis = [10, 19, 21, 19, 30, 21, 19, 21, 10];
+        let decode = |policy| {
+            let mut decoder =
+                HysteresisDelayDecoder::new(SHORT * 3 / 2, LONG * 5 / 6).dead_band_policy(policy);
+            for millis in millis {
+                decoder.push_duration(Duration::from_millis(millis));
+            }
+            decoder.close()
+        };
+
+        assert_eq!(
+            decode(DeadBandPolicy::RepeatPrevious),
+            bitvec![0, 0, 0, 0, 1, 1, 1, 1, 0]
+        );
+        assert_eq!(decode(DeadBandPolicy::Drop), bitvec![0, 1, 0]);
+    }
+
+    #[test]
+    fn hysteresis_bounds_are_inclusive_and_reset_forgets_the_last_bit() {
+        let mut decoder = HysteresisDelayDecoder::new(SHORT, LONG);
+        for duration in [SHORT, LONG, SHORT + LONG / 2] {
+            decoder.push_duration(duration);
+        }
+        assert_eq!(decoder.close_and_reset(), bitvec![0, 1, 1]);
+
+        decoder.push_duration(Duration::from_millis(20));
+        assert_eq!(decoder.close(), bitvec![0]);
+    }
+
+    #[test]
+    fn otsu_centers_the_threshold_in_the_gap_between_the_classes() {
+        let mut decoder = OtsuDelayDecoder::new().bucket_width(Duration::from_millis(1));
+        for bit in [false, true, true, false, false, true, false] {
+            decoder.push_duration(delay(bit));
+        }
+        assert_eq!(decoder.threshold(), Some(Duration::from_micros(20_500)));
+        assert_eq!(decoder.close(), bitvec![0, 1, 1, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn otsu_splits_three_clusters_between_short_and_long() {
+        // One short cluster and two long ones: separating the short one
+        // leaves the most variance between the classes.
+        let millis = [10, 11, 10, 30, 31, 40, 41, 40, 41, 40];
+        let mut decoder = OtsuDelayDecoder::new();
+        for millis in millis {
+            decoder.push_duration(Duration::from_millis(millis));
+        }
+        let threshold = decoder.threshold().unwrap();
+        assert!(threshold > Duration::from_millis(11) && threshold <= Duration::from_millis(30));
+        assert_eq!(decoder.close(), bitvec![0, 0, 0, 1, 1, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn otsu_of_one_bucket_is_all_false() {
+        let mut decoder = OtsuDelayDecoder::new().bucket_width(Duration::from_millis(5));
+        decoder.push_duration(SHORT);
+        assert_eq!(decoder.threshold(), None);
+
+        decoder.push_duration(SHORT + Duration::from_millis(1));
+        assert_eq!(decoder.threshold(), None);
+        assert_eq!(decoder.close(), bitvec![0, 0]);
+    }
+
+    /// The gaps between signals aimed at `symbols`' positions in consecutive
+    /// slots of 100 ms split into 4 positions, after a first signal at the
+    /// start of a slot, each shifted by `jitter` milliseconds.
+    fn slot_gaps(symbols: &[u64], jitter: &[i64]) -> Vec<Duration> {
+        let mut last = 0;
+        symbols
+            .iter()
+            .zip(jitter)
+            .enumerate()
+            .map(|(slot, (symbol, jitter))| {
+                let at = ((slot as u64 + 1) * 100 + symbol * 25).saturating_add_signed(*jitter);
+                let gap = Duration::from_millis(at - last);
+                last = at;
+                gap
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pulse_positions_decode_to_their_index_in_the_slot() {
+        let mut decoder = SlotDelayDecoder::new(Duration::from_millis(100), 4);
+        assert_eq!(decoder.levels(), 4);
+        for gap in slot_gaps(&[1, 3, 0, 2, 2], &[0, 4, 0, 24, 0]) {
+            decoder.push_duration(gap);
+        }
+        assert_eq!(decoder.close(), [1, 3, 0, 2, 2]);
+    }
+
+    #[test]
+    fn early_signals_within_the_guard_decode_as_their_position() {
+        let gaps = slot_gaps(&[1, 0, 3, 2], &[-2, -1, -2, 0]);
+        let decode = |decoder: SlotDelayDecoder| {
+            let mut decoder = decoder;
+            for gap in &gaps {
+                decoder.push_duration(*gap);
+            }
+            decoder.close()
+        };
+
+        let slots = || SlotDelayDecoder::new(Duration::from_millis(100), 4);
+        assert_eq!(decode(slots()), [0, 3, 2, 2]);
+        // Position 0 wraps around from the end of the previous slot.
+        assert_eq!(
+            decode(slots().guard_interval(Duration::from_millis(3))),
+            [1, 0, 3, 2]
+        );
+    }
+
+    #[test]
+    fn transitions_decode_regardless_of_a_drifting_baseline() {
+        // Each delay steps 20 ms up or down from the last while the
+        // baseline creeps up by 5 ms, so no fixed threshold would do.
+        let millis = [50, 75, 60, 85, 110, 95, 80, 105, 90];
+        let decode = |policy| {
+            let mut decoder =
+                DifferentialDelayDecoder::new(Duration::from_millis(5)).dead_band_policy(policy);
+            for millis in millis {
+                decoder.push_duration(Duration::from_millis(millis));
+            }
+            decoder.close()
+        };
+
+        let expected = bitvec![1, 0, 1, 1, 0, 0, 1, 0];
+        assert_eq!(decode(DeadBandPolicy::RepeatPrevious), expected);
+        assert_eq!(decode(DeadBandPolicy::Drop), expected);
+    }
+
+    #[test]
+    fn steady_durations_repeat_or_drop_the_last_transition() {
+        let millis = [50, 70, 72, 68, 40, 40];
+        let decode = |policy| {
+            let mut decoder =
+                DifferentialDelayDecoder::new(Duration::from_millis(5)).dead_band_policy(policy);
+            for millis in millis {
+                decoder.push_duration(Duration::from_millis(millis));
+            }
+            decoder.close()
+        };
+
+        assert_eq!(
+            decode(DeadBandPolicy::RepeatPrevious),
+            bitvec![1, 1, 1, 0, 0]
+        );
+        assert_eq!(decode(DeadBandPolicy::Drop), bitvec![1, 0]);
+    }
+
+    #[test]
+    fn calibration_durations_set_the_baseline_and_emit_no_bits() {
+        // A 100 ms path latency with one outlier among the calibration.
+        let mut decoder = CalibratedDelayDecoder::new(5, Duration::from_millis(15));
+        for millis in [101, 99, 180, 100, 102] {
+            decoder.push_duration(Duration::from_millis(millis));
+        }
+        assert_eq!(decoder.baseline(), Some(Duration::from_millis(101)));
+        assert_eq!(
+            decoder.decision_threshold(),
+            Some(Duration::from_millis(116))
+        );
+
+        for millis in [100, 120, 116, 115] {
+            decoder.push_duration(Duration::from_millis(millis));
+        }
+        assert_eq!(decoder.close_and_reset(), bitvec![0, 1, 1, 0]);
+        assert_eq!(decoder.baseline(), None);
+    }
+
+    #[test]
+    fn a_preset_baseline_skips_calibration_across_resets() {
+        let mut decoder = CalibratedDelayDecoder::new(5, Duration::from_millis(15))
+            .preset_baseline(Duration::from_millis(100));
+        for _ in 0..2 {
+            decoder.push_duration(Duration::from_millis(120));
+            decoder.push_duration(Duration::from_millis(100));
+            assert_eq!(decoder.close_and_reset(), bitvec![1, 0]);
+            assert_eq!(decoder.baseline(), Some(Duration::from_millis(100)));
+        }
+    }
+
+    #[test]
+    fn the_quantile_sets_the_share_of_long_durations() {
+        // Two long delays in ten, where the median would make half long.
+        let millis = [10, 12, 30, 11, 13, 10, 31, 12, 11, 10];
+        let mut decoder = QuantileDelayDecoder::new(0.8);
+        for millis in millis {
+            decoder.push_duration(Duration::from_millis(millis));
+        }
+        assert_eq!(decoder.threshold(), Some(Duration::from_millis(30)));
+        assert_eq!(decoder.close(), bitvec![0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+
+        let mut decoder = QuantileDelayDecoder::new(0.5);
+        for millis in millis {
+            decoder.push_duration(Duration::from_millis(millis));
+        }
+        assert_eq!(decoder.threshold(), Some(Duration::from_millis(12)));
+    }
+
+    #[test]
+    fn quantiles_of_fewer_than_two_durations_are_undefined() {
+        let mut decoder = QuantileDelayDecoder::new(0.99);
+        decoder.push_duration(LONG);
+        assert_eq!(decoder.threshold(), None);
+
+        // Index 0.99 * 2, rounded down, is the longer of the two.
+        decoder.push_duration(SHORT);
+        assert_eq!(decoder.threshold(), Some(LONG));
+        assert_eq!(decoder.close(), bitvec![1, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "quantile must be in (0, 1)")]
+    fn a_quantile_of_one_is_rejected() {
+        QuantileDelayDecoder::new(1.0);
+    }
+
+    #[test]
+    fn boxed_decoders_chosen_at_runtime_decode_like_their_own_type() {
+        let choose = |name: &str| -> BoxedDelayDecoder {
+            match name {
+                "threshold" => Box::new(threshold_decoder()),
+                _ => Box::new(KMeansDelayDecoder::new()),
+            }
+        };
+
+        for name in ["threshold", "k-means"] {
+            let mut decoder = choose(name);
+            for bit in [true, false, false, true] {
+                decoder.push_duration(delay(bit));
+            }
+            assert_eq!(decoder.snapshot(), Some(bitvec![1, 0, 0, 1]), "{name}");
+            assert_eq!(
+                decoder.decision_threshold(),
+                Some(Duration::from_millis(20)),
+                "{name}"
+            );
+            assert_eq!(decoder.close(), bitvec![1, 0, 0, 1], "{name}");
+        }
+    }
+
+    #[test]
+    fn a_tolerance_keeps_the_previous_bit_around_the_threshold() {
+        let threshold = Duration::from_millis(20);
+        let mut decoder =
+            ThresholdDelayDecoder::new_with_tolerance(threshold, Duration::from_millis(3));
+        assert_eq!(decoder.tolerance(), Duration::from_millis(3));
+        for millis in [19, 30, 18, 21, 17, 10, 23, 24] {
+            decoder.push_duration(Duration::from_millis(millis));
+        }
+        assert_eq!(decoder.close_and_reset(), bitvec![0, 1, 1, 1, 1, 0, 0, 1]);
+
+        // Without a tolerance the threshold itself is long.
+        let mut decoder = ThresholdDelayDecoder::with_capacity(threshold, 64);
+        assert_eq!(decoder.threshold(), threshold);
+        for millis in [19, 20, 21] {
+            decoder.push_duration(Duration::from_millis(millis));
+        }
+        assert_eq!(decoder.close(), bitvec![0, 1, 1]);
+    }
+
+    #[test]
+    fn stats_describe_the_durations_alongside_the_bits() {
+        let mut decoder = StatsDecoder::new(threshold_decoder());
+        assert_eq!(decoder.stats(), DecodeStats::default());
+        for bit in [true, false, true, false] {
+            decoder.push_duration(delay(bit));
+        }
+
+        let expected = DecodeStats {
+            count: 4,
+            min: SHORT,
+            max: LONG,
+            mean: Duration::from_millis(20),
+            std_dev: Duration::from_millis(10),
+            threshold: Some(Duration::from_millis(20)),
+        };
+        assert_eq!(decoder.snapshot(), Some((bitvec![1, 0, 1, 0], expected)));
+        assert_eq!(decoder.close_and_reset(), (bitvec![1, 0, 1, 0], expected));
+
+        decoder.push_duration(SHORT);
+        let (bits, stats) = decoder.close();
+        assert_eq!(bits, bitvec![0]);
+        assert_eq!((stats.count, stats.min, stats.max), (1, SHORT, SHORT));
+        assert_eq!(stats.std_dev, Duration::ZERO);
+    }
+
+    #[test]
+    fn long_delays_toggle_the_nrzi_level() {
+        let toggles = [true, false, false, true, true, false];
+        for initial in [false, true] {
+            let mut decoder = NrziDelayDecoder::new(Duration::from_millis(20), initial);
+            let mut wrapped = NrziDecoder::new(threshold_decoder(), initial);
+            for toggle in toggles {
+                decoder.push_duration(delay(toggle));
+                wrapped.push_duration(delay(toggle));
+            }
+
+            let expected: BitVec = [1, 1, 1, 0, 1, 1]
+                .iter()
+                .map(|level| (*level == 1) != initial)
+                .collect();
+            assert_eq!(decoder.close_and_reset(), expected, "initial {initial}");
+            assert_eq!(wrapped.close(), expected, "initial {initial}");
+
+            // A reset starts over from the initial level.
+            decoder.push_duration(SHORT);
+            let bits = decoder.close();
+            assert_eq!((bits.len(), bits[0]), (1, initial));
+        }
+    }
+
+    #[test]
+    fn retransmit_gaps_merge_into_the_next_duration() {
+        let min_gap = Duration::from_millis(5);
+        let mut decoder = DedupDelayDecoder::new(threshold_decoder(), min_gap);
+        // A retransmit 2 ms after the first signal, then 8 ms to the next,
+        // decodes like the 10 ms gap without it.
+        for millis in [30, 2, 8, 10, 1, 1, 28, 3] {
+            decoder.push_duration(Duration::from_millis(millis));
+        }
+        assert_eq!(decoder.close_and_reset(), bitvec![1, 0, 0, 1]);
+
+        // The short duration left over at the reset is forgotten.
+        decoder.push_duration(Duration::from_millis(18));
+        assert_eq!(decoder.close(), bitvec![0]);
+    }
+}
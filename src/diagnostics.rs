@@ -0,0 +1,149 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::Stream;
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
+
+use crate::clock::Clock;
+
+/// Capacity of each subscriber's channel.
+const DIAGNOSTICS_CAPACITY: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum DiagnosticReason {
+    /// A session's result was lost because the result stream was closed.
+    ResultSendFailed,
+    /// A session's result was dropped by the store's `ResultOverflow` policy.
+    ResultOverflow,
+    /// `try_push_signal` dropped a signal because the session queue was full.
+    SessionQueueFull,
+    /// `try_push_signal` dropped a signal because a lock was busy.
+    LockBusy,
+    /// A signal was rejected by the store's `InstantPolicy`.
+    ImplausibleInstant,
+    /// A signal was rejected by the store's `InstantPolicy`, closing its
+    /// session.
+    SessionClosedImplausible,
+    /// A session was closed early, with its partial result, because the
+    /// store's cancellation token was cancelled.
+    SessionCancelled,
+    /// A session was closed, with its result, because it reached the store's
+    /// `max_durations`.
+    SessionDurationLimit,
+    /// A signal for a new key was rejected because the store had its
+    /// `max_sessions`.
+    SessionLimitReached,
+    /// A session was closed, with its partial result, to make room for a new
+    /// key's under the store's `max_sessions`.
+    SessionEvicted,
+}
+
+/// Something that went wrong in a store.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Diagnostic<K> {
+    pub key: Option<K>,
+    pub reason: DiagnosticReason,
+    /// When the store noticed, by its clock.
+    pub instant: Instant,
+}
+
+struct Subscriber<K> {
+    sender: Sender<Diagnostic<K>>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Fans diagnostics out to a store's subscribers.
+pub(crate) struct DiagnosticHub<K> {
+    clock: Arc<dyn Clock>,
+    subscribers: Mutex<Vec<Subscriber<K>>>,
+}
+
+impl<K> DiagnosticHub<K> {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> DiagnosticStream<K> {
+        let (sender, receiver) = channel(DIAGNOSTICS_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Subscriber {
+                sender,
+                dropped: dropped.clone(),
+            });
+
+        DiagnosticStream { receiver, dropped }
+    }
+
+    /// Sends a diagnostic to every subscriber with room for it. `key` is only
+    /// called if there is a subscriber.
+    pub(crate) fn report(&self, reason: DiagnosticReason, key: impl FnOnce() -> Option<K>)
+    where
+        K: Clone,
+    {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let diagnostic = Diagnostic {
+            key: key(),
+            reason,
+            instant: self.clock.now(),
+        };
+        subscribers.retain(
+            |subscriber| match subscriber.sender.try_send(diagnostic.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+        );
+    }
+}
+
+/// Diagnostics of a store, from [`DelaySessionStore::subscribe_diagnostics`].
+///
+/// Delivery is best effort: diagnostics arriving while the stream's buffer is
+/// full are dropped and counted in [`DiagnosticStream::dropped`]. The stream
+/// ends once the store and all of its sessions are gone.
+///
+/// [`DelaySessionStore::subscribe_diagnostics`]: crate::session_store::DelaySessionStore::subscribe_diagnostics
+#[derive(Debug)]
+pub struct DiagnosticStream<K> {
+    receiver: Receiver<Diagnostic<K>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<K> DiagnosticStream<K> {
+    /// Diagnostics dropped because this stream's buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<K> Stream for DiagnosticStream<K> {
+    type Item = Diagnostic<K>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
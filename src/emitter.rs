@@ -0,0 +1,376 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    hash::Hash,
+    sync::{Arc, Mutex as StdMutex, PoisonError, Weak},
+    time::Duration,
+};
+
+use tokio::sync::{
+    mpsc::{
+        error::{SendError, TrySendError},
+        Receiver, Sender,
+    },
+    Notify,
+};
+
+use crate::{
+    cancel::Cancellation,
+    clock::Clock,
+    dead_letter::{DeadLetterHub, DeadLetterReason, DeadResult, ResultOverflow},
+    diagnostics::{DiagnosticHub, DiagnosticReason},
+    fairness::FairSender,
+    instrument::{self, KeyRedactor},
+    metrics::StoreMetrics,
+    session::SessionSummary,
+    task,
+    waiter::ResultWaiters,
+};
+
+/// Names a store's tasks unless `DelaySessionStoreBuilder::name` is set.
+pub(crate) const DEFAULT_STORE_NAME: &str = "delay-session-store";
+
+pub(crate) type SharedResultReceiver<K, T> = StdMutex<Receiver<(K, T)>>;
+
+/// Maps a session's result, given the summary of its session unless the
+/// result is a snapshot of an open one.
+pub(crate) type ResultMapper<K, T, O> =
+    Arc<dyn Fn(&K, O, Option<&SessionSummary>) -> Option<T> + Send + Sync>;
+
+/// The result channel, optionally behind fair emission.
+pub(crate) enum ResultSender<K, T> {
+    Direct(Sender<(K, T)>),
+    Fair(Arc<FairSender<K, T>>),
+}
+
+impl<K, T> Clone for ResultSender<K, T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Direct(sender) => Self::Direct(sender.clone()),
+            Self::Fair(sender) => Self::Fair(sender.clone()),
+        }
+    }
+}
+
+impl<K, T> Debug for ResultSender<K, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Direct(sender) => Debug::fmt(sender, f),
+            Self::Fair(_) => f.debug_struct("FairSender").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<K, T> ResultSender<K, T>
+where
+    K: Clone + Eq + Hash,
+{
+    fn is_closed(&self) -> bool {
+        match self {
+            Self::Direct(sender) => sender.is_closed(),
+            Self::Fair(sender) => sender.is_closed(),
+        }
+    }
+
+    async fn send(&self, result: (K, T)) -> Result<(), SendError<(K, T)>> {
+        match self {
+            Self::Direct(sender) => sender.send(result).await,
+            Self::Fair(sender) => sender.send(result).await,
+        }
+    }
+
+    fn try_send(&self, result: (K, T)) -> Result<(), TrySendError<(K, T)>> {
+        match self {
+            Self::Direct(sender) => sender.try_send(result),
+            Self::Fair(sender) => sender.try_send(result),
+        }
+    }
+
+    /// Waits until a result could be sent without waiting, and returns
+    /// `false` if the channel closes first.
+    #[cfg(feature = "tower")]
+    pub(crate) async fn has_room(&self) -> bool {
+        match self {
+            // The permit is given back right away: it only probes for room.
+            Self::Direct(sender) => sender.reserve().await.is_ok(),
+            Self::Fair(sender) => sender.has_room().await,
+        }
+    }
+}
+
+/// Maps what closed sessions decoded and sends it on the result channel.
+pub(crate) struct ResultEmitter<K, T, O> {
+    pub(crate) result_mapper: ResultMapper<K, T, O>,
+    pub(crate) result_sender: ResultSender<K, T>,
+    pub(crate) redactor: KeyRedactor<K>,
+    /// The emit interval and the mapper of open sessions' snapshots.
+    pub(crate) intermediate: Option<(Duration, ResultMapper<K, T, O>)>,
+    pub(crate) diagnostics: Arc<DiagnosticHub<K>>,
+    pub(crate) overflow: ResultOverflow,
+    /// The result stream's receiver, to evict from with
+    /// `ResultOverflow::DropOldest`.
+    pub(crate) oldest: Option<Weak<SharedResultReceiver<K, T>>>,
+    pub(crate) dead_letters: Arc<DeadLetterHub<K, T, O>>,
+    pub(crate) waiters: Arc<ResultWaiters<K, T>>,
+    pub(crate) cancellation: Cancellation,
+    /// The store's name, for task names.
+    pub(crate) name: Arc<str>,
+    /// The store's metrics, counting sends that wait for room.
+    pub(crate) metrics: Arc<StoreMetrics>,
+    /// Notified as sessions close, for readiness waiting for room under
+    /// `SessionLimitPolicy::Reject`.
+    pub(crate) closed: Arc<Notify>,
+}
+
+impl<K, T, O> Clone for ResultEmitter<K, T, O> {
+    fn clone(&self) -> Self {
+        Self {
+            result_mapper: self.result_mapper.clone(),
+            result_sender: self.result_sender.clone(),
+            redactor: self.redactor.clone(),
+            intermediate: self.intermediate.clone(),
+            diagnostics: self.diagnostics.clone(),
+            overflow: self.overflow,
+            oldest: self.oldest.clone(),
+            dead_letters: self.dead_letters.clone(),
+            waiters: self.waiters.clone(),
+            cancellation: self.cancellation.clone(),
+            name: self.name.clone(),
+            metrics: self.metrics.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+impl<K, T, O> ResultEmitter<K, T, O> {
+    pub(crate) fn new(
+        result_mapper: ResultMapper<K, T, O>,
+        result_sender: ResultSender<K, T>,
+        redactor: KeyRedactor<K>,
+        intermediate: Option<(Duration, ResultMapper<K, T, O>)>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            result_mapper,
+            result_sender,
+            redactor,
+            intermediate,
+            diagnostics: Arc::new(DiagnosticHub::new(clock)),
+            overflow: ResultOverflow::Wait,
+            oldest: None,
+            dead_letters: Arc::new(DeadLetterHub::new(false)),
+            waiters: Arc::new(ResultWaiters::new()),
+            cancellation: Cancellation::default(),
+            name: Arc::from(DEFAULT_STORE_NAME),
+            metrics: Default::default(),
+            closed: Default::default(),
+        }
+    }
+}
+
+impl<K, T, O> ResultEmitter<K, T, O>
+where
+    K: Clone + Eq + Hash,
+    O: Clone,
+{
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) const fn redactor(&self) -> &KeyRedactor<K> {
+        &self.redactor
+    }
+
+    pub(crate) const fn cancellation(&self) -> &Cancellation {
+        &self.cancellation
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.result_sender.is_closed()
+    }
+
+    pub(crate) fn emit_interval(&self) -> Option<Duration> {
+        self.intermediate.as_ref().map(|(interval, _)| *interval)
+    }
+
+    /// Emits a session's result, outside of any lock.
+    pub(crate) async fn emit(&self, key: K, bits: O, summary: SessionSummary) {
+        // The timer wheel has already let go of the session.
+        self.closed.notify_waiters();
+        if let Some(result) = self.map_final(key, bits, summary) {
+            self.send(result).await;
+        }
+    }
+
+    /// Emits a snapshot of an open session, if the store has an emit interval.
+    pub(crate) async fn emit_intermediate(&self, key: K, bits: O) {
+        if let Some((_, mapper)) = &self.intermediate {
+            if let Some(result) = self.map(mapper, key, bits, None) {
+                self.send(result).await;
+            }
+        }
+    }
+
+    async fn send(&self, result: (K, T)) {
+        match self.overflow {
+            ResultOverflow::Wait => {
+                let result = match self.result_sender.try_send(result) {
+                    Ok(()) => return,
+                    Err(TrySendError::Full(result)) => result,
+                    Err(TrySendError::Closed((key, result))) => {
+                        return self.send_failed(key, result)
+                    }
+                };
+                self.send_blocked(&result.0);
+                if let Err(SendError((key, result))) = self.result_sender.send(result).await {
+                    self.send_failed(key, result);
+                }
+            }
+            ResultOverflow::DropNewest | ResultOverflow::DropOldest => self.send_or_drop(result),
+        }
+    }
+
+    /// Emits a session's result without awaiting, falling back to a spawned
+    /// send if the result channel is full and the store waits for room.
+    pub(crate) fn emit_now(&self, key: K, bits: O, summary: SessionSummary)
+    where
+        K: Send + 'static,
+        T: Send + 'static,
+        O: Send + 'static,
+    {
+        let Some(result) = self.map_final(key, bits, summary) else {
+            return;
+        };
+
+        if self.overflow != ResultOverflow::Wait {
+            return self.send_or_drop(result);
+        }
+        match self.result_sender.try_send(result) {
+            Ok(()) => {}
+            Err(TrySendError::Full(result)) => {
+                self.send_blocked(&result.0);
+                let emitter = self.clone();
+                let send = async move {
+                    if let Err(SendError((key, result))) = emitter.result_sender.send(result).await
+                    {
+                        emitter.send_failed(key, result);
+                    }
+                };
+                task::spawn(
+                    || format!("{} result send", self.name),
+                    self.cancellation.track(send),
+                );
+            }
+            Err(TrySendError::Closed((key, result))) => self.send_failed(key, result),
+        }
+    }
+
+    /// Records a result that waits for room in the full result stream.
+    fn send_blocked(&self, key: &K) {
+        instrument::result_send_blocked(&self.redactor, key);
+        self.metrics.record_result_send_blocked();
+    }
+
+    /// Maps what a closed session decoded, handing the result to the key's
+    /// waiters too.
+    fn map_final(&self, key: K, bits: O, summary: SessionSummary) -> Option<(K, T)> {
+        let result = self.map(&self.result_mapper, key, bits, Some(&summary))?;
+        self.waiters.fulfill(&result.0, &result.1);
+        Some(result)
+    }
+
+    /// Maps `bits`, routing them to the dead letters if they are suppressed.
+    fn map(
+        &self,
+        mapper: &ResultMapper<K, T, O>,
+        key: K,
+        bits: O,
+        summary: Option<&SessionSummary>,
+    ) -> Option<(K, T)> {
+        let unmapped = self.dead_letters.wants_suppressed().then(|| bits.clone());
+        match mapper(&key, bits, summary) {
+            Some(result) => Some((key, result)),
+            None => {
+                instrument::result_suppressed(&self.redactor, &key);
+                if let Some(bits) = unmapped {
+                    self.dead_letters.route(
+                        key,
+                        DeadResult::Unmapped(bits),
+                        DeadLetterReason::Suppressed,
+                    );
+                }
+                None
+            }
+        }
+    }
+
+    /// Sends `result` if the result channel has room, and otherwise applies
+    /// the store's dropping `ResultOverflow` policy.
+    fn send_or_drop(&self, result: (K, T)) {
+        let result = match self.result_sender.try_send(result) {
+            Ok(()) => return,
+            Err(TrySendError::Full(result)) => result,
+            Err(TrySendError::Closed((key, result))) => return self.send_failed(key, result),
+        };
+
+        let evicted = self
+            .oldest
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .and_then(|receiver| {
+                receiver
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .try_recv()
+                    .ok()
+            });
+        let result = match evicted {
+            Some((key, evicted)) => {
+                self.overflowed(key, evicted, DeadLetterReason::OverflowOldest);
+                match self.result_sender.try_send(result) {
+                    Ok(()) => return,
+                    Err(TrySendError::Full(result)) => result,
+                    Err(TrySendError::Closed((key, result))) => {
+                        return self.send_failed(key, result)
+                    }
+                }
+            }
+            None => result,
+        };
+
+        let (key, result) = result;
+        self.overflowed(key, result, DeadLetterReason::OverflowNewest);
+    }
+
+    fn overflowed(&self, key: K, result: T, reason: DeadLetterReason) {
+        instrument::result_overflowed(&self.redactor, &key);
+        self.diagnostics
+            .report(DiagnosticReason::ResultOverflow, || Some(key.clone()));
+        self.dead_letters
+            .route(key, DeadResult::Mapped(result), reason);
+    }
+
+    fn send_failed(&self, key: K, result: T) {
+        instrument::result_send_failed(&self.redactor, &key);
+        self.diagnostics
+            .report(DiagnosticReason::ResultSendFailed, || Some(key.clone()));
+        self.dead_letters.route(
+            key,
+            DeadResult::Mapped(result),
+            DeadLetterReason::StreamClosed,
+        );
+    }
+
+    /// Waits until every result queued for fair emission has been forwarded.
+    #[cfg(feature = "tokio-util")]
+    pub(crate) async fn flushed(&self) {
+        if let ResultSender::Fair(sender) = &self.result_sender {
+            sender.flushed().await;
+        }
+    }
+
+    /// Reports a dropped or rejected signal. `key` is only called if someone
+    /// is subscribed to diagnostics.
+    pub(crate) fn report(&self, reason: DiagnosticReason, key: impl FnOnce() -> K) {
+        self.diagnostics.report(reason, || Some(key()));
+    }
+}
@@ -0,0 +1,411 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use bitvec::slice::BitSlice;
+
+use crate::{decoder::ThresholdDelayDecoder, rng::SplitMix64};
+
+/// The sending counterpart of a [`DelayDecoder`], turning bits into the
+/// delays between consecutive requests. A message of `n` bits takes `n + 1`
+/// requests, the first one starting the session.
+///
+/// [`DelayDecoder`]: crate::decoder::DelayDecoder
+pub trait DelayEncoder {
+    /// The delay before the request carrying `bit`.
+    fn next_delay(&mut self, bit: bool) -> Duration;
+
+    /// The delays of every bit of `bits`, in order.
+    fn encode(&mut self, bits: &BitSlice) -> Vec<Duration> {
+        bits.iter()
+            .by_vals()
+            .map(|bit| self.next_delay(bit))
+            .collect()
+    }
+}
+
+/// Sends `long_delay` for `true` and `short_delay` for `false`, decodable by
+/// the [`decoder`](Self::decoder) thresholding halfway between them.
+///
+/// With [`jitter`](Self::jitter), each delay is moved by up to the jitter in
+/// either direction, uniformly, so traffic looks less mechanical.
+#[derive(Clone, Debug)]
+pub struct ThresholdDelayEncoder {
+    short_delay: Duration,
+    long_delay: Duration,
+    jitter: Duration,
+    rng: SplitMix64,
+}
+
+impl ThresholdDelayEncoder {
+    /// # Panics
+    ///
+    /// Panics if `short_delay` is not shorter than `long_delay`.
+    pub fn new(short_delay: Duration, long_delay: Duration) -> Self {
+        assert!(
+            short_delay < long_delay,
+            "short delay must be shorter than long delay"
+        );
+        Self {
+            short_delay,
+            long_delay,
+            jitter: Duration::ZERO,
+            rng: SplitMix64::new(0),
+        }
+    }
+
+    /// Moves every delay by a uniformly random amount up to `jitter`, drawn
+    /// from a generator seeded with `seed`. The generator is not meant for
+    /// anything security relevant.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `jitter` is not less than half the separation of the
+    /// delays, which would let a delay cross the decoder's threshold even
+    /// without network jitter.
+    pub fn jitter(mut self, jitter: Duration, seed: u64) -> Self {
+        assert!(
+            jitter < (self.long_delay - self.short_delay) / 2,
+            "jitter must be less than half the separation of the delays"
+        );
+        self.jitter = jitter;
+        self.rng = SplitMix64::new(seed);
+        self
+    }
+
+    /// The duration halfway between the delays.
+    pub fn threshold(&self) -> Duration {
+        self.short_delay + (self.long_delay - self.short_delay) / 2
+    }
+
+    /// A decoder of this encoder's delays.
+    pub fn decoder(&self) -> ThresholdDelayDecoder {
+        ThresholdDelayDecoder::new(self.threshold())
+    }
+}
+
+impl DelayEncoder for ThresholdDelayEncoder {
+    fn next_delay(&mut self, bit: bool) -> Duration {
+        let delay = if bit {
+            self.long_delay
+        } else {
+            self.short_delay
+        };
+        if self.jitter.is_zero() {
+            return delay;
+        }
+
+        let offset = self.jitter.mul_f64(self.rng.next_f64());
+        if self.rng.next_bool() {
+            delay.saturating_add(offset)
+        } else {
+            delay.saturating_sub(offset)
+        }
+    }
+}
+
+/// Limits on when a [`DelaySchedule`] sends its requests. The default limits
+/// nothing.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct RateControl {
+    /// Most bits sent per second: requests carrying a bit are at least its
+    /// inverse apart.
+    pub max_bits_per_second: Option<f64>,
+    /// Shortest gap between any two requests, whatever bit they carry.
+    pub min_gap: Duration,
+    /// Windows to send in, repeating from the first request.
+    pub duty_cycle: Option<DutyCycle>,
+}
+
+/// Sending for `active`, then idling for `idle`, over and over.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct DutyCycle {
+    pub active: Duration,
+    pub idle: Duration,
+}
+
+impl DutyCycle {
+    /// How far `offset` is into its period, and whether that is in the
+    /// active window.
+    fn phase(&self, offset: Duration) -> (Duration, bool) {
+        let period = (self.active + self.idle).as_nanos();
+        let phase = Duration::from_nanos((offset.as_nanos() % period) as u64);
+        (phase, phase < self.active)
+    }
+
+    /// The earliest instant from `offset` on at which a request can be sent
+    /// with another one `span` later in the same active window.
+    fn fit(&self, offset: Duration, span: Duration) -> Duration {
+        let (phase, active) = self.phase(offset);
+        if active && phase + span < self.active {
+            offset
+        } else {
+            offset - phase + self.active + self.idle
+        }
+    }
+}
+
+/// A request of a [`DelaySchedule`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct ScheduledRequest {
+    /// When the request is sent, after the first one.
+    pub offset: Duration,
+    /// Whether the request carries no bit and only keeps the session open.
+    pub keepalive: bool,
+}
+
+/// Times the requests carrying a message encoded by `E` within the limits of
+/// a [`RateControl`].
+///
+/// Every bit is sent its encoder's delay after the previous request. When
+/// that would break a limit, the schedule first holds back, sending a
+/// keepalive once the bit can follow it by its delay. Decode with
+/// `KeepalivePolicy::RestartDuration`, so that durations are measured from
+/// keepalives, and a session timeout longer than the longest gap between
+/// requests, which spans the duty cycle's idle window.
+#[derive(Clone, Debug)]
+pub struct DelaySchedule<E> {
+    encoder: E,
+    rate: RateControl,
+    /// Least time between requests carrying bits.
+    bit_spacing: Duration,
+}
+
+impl<E: DelayEncoder> DelaySchedule<E> {
+    /// # Panics
+    ///
+    /// Panics if the bit budget is not positive and finite, or if the duty
+    /// cycle's active window is empty.
+    pub fn new(encoder: E, rate: RateControl) -> Self {
+        let bit_spacing = rate.max_bits_per_second.map_or(Duration::ZERO, |bits| {
+            assert!(
+                bits > 0.0 && bits.is_finite(),
+                "bit budget must be positive and finite"
+            );
+            Duration::from_secs_f64(bits.recip())
+        });
+        if let Some(cycle) = rate.duty_cycle {
+            assert!(!cycle.active.is_zero(), "active window must be non-empty");
+        }
+        Self {
+            encoder,
+            rate,
+            bit_spacing,
+        }
+    }
+
+    /// The requests carrying `bits`, starting with the one at offset zero
+    /// that starts the session.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the encoder gives a delay shorter than the minimum gap, or
+    /// one not fitting in the duty cycle's active window.
+    pub fn schedule(&mut self, bits: &BitSlice) -> Vec<ScheduledRequest> {
+        let mut requests = Vec::with_capacity(bits.len() + 1);
+        requests.push(ScheduledRequest {
+            offset: Duration::ZERO,
+            keepalive: false,
+        });
+        let (mut last, mut last_bit) = (Duration::ZERO, Duration::ZERO);
+
+        for bit in bits.iter().by_vals() {
+            let delay = self.encoder.next_delay(bit);
+            assert!(
+                delay >= self.rate.min_gap,
+                "delay {delay:?} is shorter than the minimum gap"
+            );
+            if let Some(cycle) = self.rate.duty_cycle {
+                assert!(
+                    delay < cycle.active,
+                    "delay {delay:?} does not fit in the active window"
+                );
+            }
+
+            let earliest = last_bit + self.bit_spacing;
+            let at = last + delay;
+            let active = self.rate.duty_cycle.is_none_or(|cycle| cycle.phase(at).1);
+            if at < earliest || !active {
+                let hold = (last + self.rate.min_gap).max(earliest.saturating_sub(delay));
+                last = self
+                    .rate
+                    .duty_cycle
+                    .map_or(hold, |cycle| cycle.fit(hold, delay));
+                requests.push(ScheduledRequest {
+                    offset: last,
+                    keepalive: true,
+                });
+            }
+
+            last += delay;
+            last_bit = last;
+            requests.push(ScheduledRequest {
+                offset: last,
+                keepalive: false,
+            });
+        }
+        requests
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::time::Duration;
+
+    use bitvec::vec::BitVec;
+    use futures::StreamExt;
+    use tokio::time::sleep_until;
+
+    use super::{
+        DelayEncoder, DelaySchedule, DutyCycle, RateControl, ScheduledRequest,
+        ThresholdDelayEncoder,
+    };
+    use crate::{
+        decoder::{DelayDecoder, ThresholdDelayDecoder},
+        rng::SplitMix64,
+        session::KeepalivePolicy,
+        session_store::DelaySessionStoreBuilder,
+    };
+
+    const RATE: RateControl = RateControl {
+        max_bits_per_second: Some(2.0),
+        min_gap: Duration::from_millis(50),
+        duty_cycle: Some(DutyCycle {
+            active: Duration::from_secs(3),
+            idle: Duration::from_secs(9),
+        }),
+    };
+
+    fn encoder() -> ThresholdDelayEncoder {
+        ThresholdDelayEncoder::new(Duration::from_millis(100), Duration::from_millis(300))
+            .jitter(Duration::from_millis(40), 7)
+    }
+
+    fn message(bits: usize) -> BitVec {
+        let mut rng = SplitMix64::new(11);
+        (0..bits).map(|_| rng.next_bool()).collect()
+    }
+
+    fn longest_gap(requests: &[ScheduledRequest]) -> Duration {
+        requests
+            .windows(2)
+            .map(|pair| pair[1].offset - pair[0].offset)
+            .max()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn schedules_keep_within_the_rate_control() {
+        let message = message(64);
+        let requests = DelaySchedule::new(encoder(), RATE).schedule(&message);
+        let cycle = RATE.duty_cycle.unwrap();
+
+        let bits: Vec<_> = requests
+            .iter()
+            .filter(|request| !request.keepalive)
+            .collect();
+        assert_eq!(bits.len(), message.len() + 1);
+        assert!(bits.len() < requests.len(), "nothing was held back");
+        for pair in requests.windows(2) {
+            assert!(pair[1].offset - pair[0].offset >= RATE.min_gap);
+        }
+        for pair in bits.windows(2) {
+            assert!(pair[1].offset - pair[0].offset >= Duration::from_millis(500));
+        }
+        for request in &requests {
+            assert!(cycle.phase(request.offset).1, "{request:?} is sent idling");
+        }
+        assert!(longest_gap(&requests) > cycle.idle);
+
+        // Durations are measured from the previous request of either kind.
+        let mut decoder = encoder().decoder();
+        for pair in requests.windows(2).filter(|pair| !pair[1].keepalive) {
+            decoder.push_duration(pair[1].offset - pair[0].offset);
+        }
+        assert_eq!(decoder.close(), message);
+    }
+
+    #[test]
+    fn an_unlimited_schedule_sends_the_encoder_delays() {
+        let message = message(32);
+        let requests = DelaySchedule::new(encoder(), RateControl::default()).schedule(&message);
+        let delays = encoder().encode(&message);
+
+        assert!(requests.iter().all(|request| !request.keepalive));
+        let gaps: Vec<_> = requests
+            .windows(2)
+            .map(|pair| pair[1].offset - pair[0].offset)
+            .collect();
+        assert_eq!(gaps, delays);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_controlled_schedules_round_trip_through_a_store() {
+        let message = message(24);
+        let requests = DelaySchedule::new(encoder(), RATE).schedule(&message);
+        let threshold = encoder().threshold();
+        let (store, mut results) =
+            DelaySessionStoreBuilder::<u32>::new(longest_gap(&requests) + RATE.min_gap)
+                .keepalive_policy(KeepalivePolicy::RestartDuration)
+                .build();
+
+        let start = tokio::time::Instant::now();
+        for request in requests {
+            sleep_until(start + request.offset).await;
+            if request.keepalive {
+                assert!(store.push_keepalive(&1).await.unwrap());
+            } else {
+                store
+                    .push_signal_now(1, move || ThresholdDelayDecoder::new(threshold))
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let (key, bits) = results.next().await.unwrap();
+        assert_eq!(key, 1);
+        assert_eq!(bits, message);
+    }
+
+    #[test]
+    fn jittered_delays_stay_within_the_jitter_and_repeat_by_seed() {
+        let bits = message(200);
+        let delays = encoder().encode(&bits);
+        assert_eq!(delays, encoder().encode(&bits));
+        assert_ne!(
+            delays,
+            ThresholdDelayEncoder::new(Duration::from_millis(100), Duration::from_millis(300))
+                .jitter(Duration::from_millis(40), 8)
+                .encode(&bits)
+        );
+
+        for (bit, delay) in bits.iter().by_vals().zip(&delays) {
+            let aimed = if bit { 300 } else { 100 };
+            assert!(
+                delay.abs_diff(Duration::from_millis(aimed)) <= Duration::from_millis(40),
+                "{delay:?} for {bit}"
+            );
+        }
+    }
+
+    #[test]
+    fn unjittered_delays_are_exact_and_threshold_halfway() {
+        let mut encoder =
+            ThresholdDelayEncoder::new(Duration::from_millis(100), Duration::from_millis(300));
+        assert_eq!(encoder.threshold(), Duration::from_millis(200));
+        assert_eq!(encoder.decoder().threshold(), Duration::from_millis(200));
+        assert_eq!(
+            encoder.encode(&message(4)),
+            message(4)
+                .iter()
+                .by_vals()
+                .map(|bit| Duration::from_millis(if bit { 300 } else { 100 }))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "jitter must be less than half the separation")]
+    fn jitter_reaching_the_threshold_is_rejected() {
+        encoder().jitter(Duration::from_millis(100), 0);
+    }
+}
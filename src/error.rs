@@ -0,0 +1,89 @@
+use core::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+/// Why a push to a store, of a signal, keepalive, pause or resume, failed.
+/// `DelaySessionStore::try_push_signal` reports its outcome as a
+/// `PushOutcome` instead, which `DelaySessionService` turns into these.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum PushError {
+    /// The session for the key stopped receiving signals before the push
+    /// could be delivered, while the store itself kept running.
+    SessionClosed,
+    /// The session's signal channel was full, and the push does not wait for
+    /// room in it.
+    ChannelFull,
+    /// The store was cancelled while the push was being delivered, closing
+    /// the session it was for.
+    StoreShutdown,
+    /// The timestamp could not be converted into an `Instant`.
+    InvalidTimestamp,
+    /// The store's result stream was closed or dropped, so no result could
+    /// ever be delivered.
+    ResultStreamClosed,
+    /// The signal's instant violated the store's `InstantPolicy`.
+    ImplausibleInstant,
+    /// The store was paused with `PausedPushes::Reject`.
+    Paused,
+    /// The store's cancellation token was cancelled.
+    Cancelled,
+    /// The signal would start a session, but the store already has its
+    /// `max_sessions` and rejects new keys.
+    SessionLimitReached,
+}
+
+impl Display for PushError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SessionClosed => f.write_str("session closed before the signal was delivered"),
+            Self::ChannelFull => f.write_str("session signal channel is full"),
+            Self::StoreShutdown => f.write_str("store shut down before the signal was delivered"),
+            Self::InvalidTimestamp => f.write_str("timestamp is not representable as an instant"),
+            Self::ResultStreamClosed => f.write_str("result stream closed"),
+            Self::ImplausibleInstant => f.write_str("signal instant is implausible"),
+            Self::Paused => f.write_str("store is paused"),
+            Self::Cancelled => f.write_str("store was cancelled"),
+            Self::SessionLimitReached => f.write_str("store is at its session limit"),
+        }
+    }
+}
+
+impl Error for PushError {}
+
+/// Why a session could not be closed on request.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum CloseError {
+    /// The key has no open session.
+    NoSession,
+}
+
+impl Display for CloseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSession => f.write_str("key has no open session"),
+        }
+    }
+}
+
+impl Error for CloseError {}
+
+/// Why a string is not an address block, see `IpCidr`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum ParseCidrError {
+    /// The part before the `/` is not an IP address.
+    InvalidAddress,
+    /// The prefix length is not a number or longer than the address.
+    InvalidPrefix,
+}
+
+impl Display for ParseCidrError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress => f.write_str("invalid IP address"),
+            Self::InvalidPrefix => f.write_str("invalid prefix length"),
+        }
+    }
+}
+
+impl Error for ParseCidrError {}
@@ -0,0 +1,126 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::decoder::{mean_duration, nanos_to_duration};
+
+/// Separation below which a suggestion is flagged as low confidence: the
+/// cluster centers are less than four combined standard deviations apart.
+const MIN_CONFIDENT_SEPARATION: f64 = 16.0;
+
+const MAX_ITERATIONS: usize = 64;
+
+/// The bundled decoder likely to decode a trace best.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum RecommendedDecoder {
+    /// The mean of the trace falls between its clusters, so
+    /// `AverageDelayDecoder` splits it like the suggested threshold would,
+    /// without needing calibration.
+    Average,
+    /// The clusters are unbalanced enough that the mean falls inside one of
+    /// them, so a `ThresholdDelayDecoder` at the suggested threshold is needed.
+    Threshold,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ThresholdSuggestion {
+    /// Durations at or above the threshold decode as `1`, as in the decoders.
+    pub threshold: Duration,
+    pub low_center: Duration,
+    pub high_center: Duration,
+    /// Fisher's criterion: the squared distance between the centers over the
+    /// sum of the clusters' variances. Higher is better separated.
+    pub separation: f64,
+    /// Set when `separation` suggests the trace is not bimodal at all, e.g.
+    /// because it holds no covert signal or more than two symbols.
+    pub low_confidence: bool,
+    pub recommended: RecommendedDecoder,
+}
+
+/// Suggests decoder parameters for a trace of recorded durations.
+///
+/// Starting from the mean, which is where `AverageDelayDecoder` splits, the
+/// threshold is refined by two-means clustering until the split is stable,
+/// using the decoders' `>=` comparison to assign durations to clusters.
+pub fn suggest_threshold(durations: &[Duration]) -> ThresholdSuggestion {
+    let mean = mean_duration(durations);
+    let mut threshold = mean;
+    let (mut low, mut high) = split(durations, threshold);
+
+    for _ in 0..MAX_ITERATIONS {
+        if low.is_empty() || high.is_empty() {
+            break;
+        }
+
+        let midpoint =
+            (mean_duration(&low).as_nanos() + mean_duration(&high).as_nanos()).div_ceil(2);
+        let next = nanos_to_duration(midpoint);
+        if next == threshold {
+            break;
+        }
+
+        threshold = next;
+        (low, high) = split(durations, threshold);
+    }
+
+    let low_center = mean_duration(&low);
+    let high_center = mean_duration(&high);
+    let separation = separation(&low, low_center, &high, high_center);
+
+    let agrees_with_mean = durations
+        .iter()
+        .all(|duration| (*duration >= mean) == (*duration >= threshold));
+
+    ThresholdSuggestion {
+        threshold,
+        low_center,
+        high_center,
+        separation,
+        low_confidence: low.is_empty() || high.is_empty() || separation < MIN_CONFIDENT_SEPARATION,
+        recommended: if agrees_with_mean {
+            RecommendedDecoder::Average
+        } else {
+            RecommendedDecoder::Threshold
+        },
+    }
+}
+
+fn split(durations: &[Duration], threshold: Duration) -> (Vec<Duration>, Vec<Duration>) {
+    durations
+        .iter()
+        .partition(|duration| **duration < threshold)
+}
+
+fn separation(
+    low: &[Duration],
+    low_center: Duration,
+    high: &[Duration],
+    high_center: Duration,
+) -> f64 {
+    let distance = high_center.as_secs_f64() - low_center.as_secs_f64();
+    let spread = variance(low, low_center) + variance(high, high_center);
+
+    if spread > 0.0 {
+        distance * distance / spread
+    } else if distance > 0.0 {
+        f64::INFINITY
+    } else {
+        0.0
+    }
+}
+
+fn variance(durations: &[Duration], center: Duration) -> f64 {
+    if durations.is_empty() {
+        return 0.0;
+    }
+
+    let center = center.as_secs_f64();
+    let sum: f64 = durations
+        .iter()
+        .map(|duration| {
+            let deviation = duration.as_secs_f64() - center;
+            deviation * deviation
+        })
+        .sum();
+
+    sum / durations.len() as f64
+}
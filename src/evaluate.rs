@@ -0,0 +1,235 @@
+use std::{cmp::Ordering, time::Duration};
+
+use bitvec::vec::BitVec;
+
+use crate::{decoder::DelayDecoder, rng::SplitMix64, testing::ArrivalModel};
+
+/// z-score of the 95% confidence intervals in [`EvalReport`].
+const Z_95: f64 = 1.96;
+
+/// How the network perturbs encoded signals on their way to the decoder.
+///
+/// The default leaves signals untouched.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct NoiseModel {
+    /// Delay added to every signal independently, so gaps can both shrink and
+    /// grow, and signals can be reordered.
+    pub jitter: Option<ArrivalModel>,
+    /// Probability in `[0, 1]` that a signal is lost, merging the gaps on
+    /// either side of it.
+    pub loss: f64,
+}
+
+/// Results of encode, perturb and decode trials.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Hash)]
+pub struct EvalReport {
+    pub trials: usize,
+    /// Trials whose decoded message differed from the sent one in any way.
+    pub failed_trials: usize,
+    /// Bits compared over all trials: per trial, the longer of the sent and
+    /// decoded messages.
+    pub compared_bits: usize,
+    /// Compared bits that differ, counting every bit one message has past the
+    /// end of the other as an error.
+    pub bit_errors: usize,
+}
+
+impl EvalReport {
+    /// Bit error rate.
+    pub fn ber(&self) -> f64 {
+        ratio(self.bit_errors, self.compared_bits)
+    }
+
+    /// 95% confidence interval of the bit error rate.
+    pub fn ber_interval(&self) -> (f64, f64) {
+        wilson_interval(self.bit_errors, self.compared_bits)
+    }
+
+    /// Fraction of trials that did not decode their whole message exactly.
+    pub fn failure_rate(&self) -> f64 {
+        ratio(self.failed_trials, self.trials)
+    }
+
+    /// 95% confidence interval of the failure rate.
+    pub fn failure_interval(&self) -> (f64, f64) {
+        wilson_interval(self.failed_trials, self.trials)
+    }
+
+    /// Accounts for a trial that sent `sent` and decoded `decoded`.
+    pub fn record(&mut self, sent: &BitVec, decoded: &BitVec) {
+        let common = sent.len().min(decoded.len());
+        let mismatches = sent
+            .iter()
+            .by_vals()
+            .zip(decoded.iter().by_vals())
+            .filter(|(sent, decoded)| sent != decoded)
+            .count();
+        let errors = mismatches + sent.len().max(decoded.len()) - common;
+
+        self.trials += 1;
+        self.failed_trials += usize::from(errors > 0);
+        self.compared_bits += sent.len().max(decoded.len());
+        self.bit_errors += errors;
+    }
+}
+
+/// Decodes a trial's durations with a fresh decoder.
+type Decode<'a> = Box<dyn FnMut(&[Duration]) -> BitVec + 'a>;
+
+/// A named decoder factory to evaluate with [`compare`].
+pub struct Candidate<'a> {
+    name: String,
+    decode: Decode<'a>,
+}
+
+impl<'a> Candidate<'a> {
+    pub fn new<D, F>(name: impl Into<String>, mut decoder_factory: F) -> Self
+    where
+        D: DelayDecoder<Output = BitVec>,
+        F: FnMut() -> D + 'a,
+    {
+        Self {
+            name: name.into(),
+            decode: Box::new(move |durations| {
+                let mut decoder = decoder_factory();
+                for duration in durations {
+                    decoder.push_duration(*duration);
+                }
+                decoder.close()
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Debug for Candidate<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Candidate")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runs `trials` trials, each encoding a random message of `message_len`
+/// bits with `encoder`, perturbing its signals with `noise` and decoding
+/// them with a fresh decoder.
+///
+/// The same arguments and seed always produce the same report.
+pub fn run<D, F, E>(
+    decoder_factory: F,
+    encoder: E,
+    noise: NoiseModel,
+    message_len: usize,
+    trials: usize,
+    seed: u64,
+) -> EvalReport
+where
+    D: DelayDecoder<Output = BitVec>,
+    F: FnMut() -> D,
+    E: FnMut(bool) -> Duration,
+{
+    let mut candidates = [Candidate::new("", decoder_factory)];
+    compare(&mut candidates, encoder, noise, message_len, trials, seed)
+        .pop()
+        .map(|(_, report)| report)
+        .unwrap_or_default()
+}
+
+/// Like [`run`] for each candidate, on the same perturbed signals, returning
+/// the reports ranked from the lowest bit error rate, then failure rate.
+pub fn compare<E>(
+    candidates: &mut [Candidate<'_>],
+    mut encoder: E,
+    noise: NoiseModel,
+    message_len: usize,
+    trials: usize,
+    seed: u64,
+) -> Vec<(String, EvalReport)>
+where
+    E: FnMut(bool) -> Duration,
+{
+    let mut rng = SplitMix64::new(seed);
+    let mut reports = vec![EvalReport::default(); candidates.len()];
+
+    for _ in 0..trials {
+        let message: BitVec = (0..message_len).map(|_| rng.next_bool()).collect();
+        let durations = transmit(&message, &mut encoder, &noise, &mut rng);
+
+        for (candidate, report) in candidates.iter_mut().zip(&mut reports) {
+            report.record(&message, &(candidate.decode)(&durations));
+        }
+    }
+
+    let mut ranked: Vec<_> = candidates
+        .iter()
+        .map(|candidate| candidate.name.clone())
+        .zip(reports)
+        .collect();
+    ranked.sort_by(|(_, a), (_, b)| {
+        a.ber()
+            .partial_cmp(&b.ber())
+            .unwrap_or(Ordering::Equal)
+            .then(
+                a.failure_rate()
+                    .partial_cmp(&b.failure_rate())
+                    .unwrap_or(Ordering::Equal),
+            )
+    });
+    ranked
+}
+
+/// Encodes `message` as an anchoring signal followed by one signal per bit,
+/// like `TrafficGenerator`'s covert keys, and returns the durations between
+/// the signals that arrive.
+fn transmit<E>(
+    message: &BitVec,
+    encoder: &mut E,
+    noise: &NoiseModel,
+    rng: &mut SplitMix64,
+) -> Vec<Duration>
+where
+    E: FnMut(bool) -> Duration,
+{
+    let mut sent = Duration::ZERO;
+    let mut arrivals = Vec::with_capacity(message.len() + 1);
+    for gap in core::iter::once(Duration::ZERO).chain(message.iter().by_vals().map(&mut *encoder)) {
+        sent = sent.saturating_add(gap);
+        if rng.next_f64() < noise.loss {
+            continue;
+        }
+
+        let jitter = noise
+            .jitter
+            .map_or(Duration::ZERO, |jitter| jitter.sample(rng));
+        arrivals.push(sent.saturating_add(jitter));
+    }
+
+    arrivals.sort_unstable();
+    arrivals.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+/// Wilson score interval, which stays within `[0, 1]` and is meaningful even
+/// when no or all trials succeed.
+fn wilson_interval(count: usize, total: usize) -> (f64, f64) {
+    if total == 0 {
+        return (0.0, 1.0);
+    }
+
+    let n = total as f64;
+    let p = count as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let margin = Z_95 / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    ((center - margin).max(0.0), (center + margin).min(1.0))
+}
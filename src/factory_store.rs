@@ -0,0 +1,159 @@
+//! Stores owning the decoder factory of their sessions.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    hash::Hash,
+    sync::{Arc, Mutex as StdMutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use bitvec::vec::BitVec;
+
+use crate::{
+    decoder::{BoxedDelayDecoder, DecoderOutput, MetaDelayDecoder},
+    error::PushError,
+    session_sink::DelaySessionSink,
+    session_store::{DelaySessionStore, PushOutcome},
+};
+
+type PlainDecoderFactory<O> = Arc<StdMutex<dyn FnMut() -> BoxedDelayDecoder<O> + Send>>;
+type KeyedDecoderFactory<K, O> = Arc<StdMutex<dyn FnMut(&K) -> BoxedDelayDecoder<O> + Send>>;
+
+/// The decoder factory a [`FactoryDelaySessionStore`] owns.
+enum SharedDecoderFactory<K, O> {
+    Plain(PlainDecoderFactory<O>),
+    /// Takes the session's key, so each push clones it.
+    Keyed(KeyedDecoderFactory<K, O>),
+}
+
+/// The owned factory handed to one push, with its key if it takes one.
+enum PushDecoderFactory<K, O> {
+    Plain(PlainDecoderFactory<O>),
+    Keyed(KeyedDecoderFactory<K, O>, K),
+}
+
+/// A store that owns the decoder factory of its sessions, so pushes only
+/// take a key and an instant. This is the usual way to use a store: it names
+/// no closure type, so it fits in shared application state as e.g.
+/// `Arc<FactoryDelaySessionStore<String>>`. The wrapped
+/// [`store`](Self::store) still takes a factory per push, for keys that need
+/// a decoder of their own, and
+/// [`delay_session_store_with_calibration`](crate::session_store::delay_session_store_with_calibration)
+/// creates one whose factory also gets the key and a shared calibration.
+pub struct FactoryDelaySessionStore<K, T = BitVec, O = BitVec> {
+    store: DelaySessionStore<K, T, O>,
+    decoder_factory: SharedDecoderFactory<K, O>,
+}
+
+impl<K, T, O> FactoryDelaySessionStore<K, T, O>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    T: Send + 'static,
+    O: DecoderOutput + Clone + Send + 'static,
+{
+    pub(crate) fn new<D>(
+        store: DelaySessionStore<K, T, O>,
+        mut decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Self
+    where
+        D: MetaDelayDecoder<(), Output = O> + Send + 'static,
+    {
+        Self {
+            store,
+            decoder_factory: SharedDecoderFactory::Plain(Arc::new(StdMutex::new(move || {
+                Box::new(decoder_factory()) as BoxedDelayDecoder<O>
+            }))),
+        }
+    }
+
+    /// Like [`new`](Self::new), with a factory creating the decoder of each
+    /// session from its key.
+    pub(crate) fn keyed<D>(
+        store: DelaySessionStore<K, T, O>,
+        mut decoder_factory: impl FnMut(&K) -> D + Send + 'static,
+    ) -> Self
+    where
+        D: MetaDelayDecoder<(), Output = O> + Send + 'static,
+    {
+        Self {
+            store,
+            decoder_factory: SharedDecoderFactory::Keyed(Arc::new(StdMutex::new(
+                move |key: &K| Box::new(decoder_factory(key)) as BoxedDelayDecoder<O>,
+            ))),
+        }
+    }
+
+    /// A handle on the owned factory for one push to `key`.
+    fn decoder_factory(&self, key: &K) -> impl FnMut() -> BoxedDelayDecoder<O> + Send + 'static {
+        let decoder_factory = match &self.decoder_factory {
+            SharedDecoderFactory::Plain(factory) => PushDecoderFactory::Plain(factory.clone()),
+            SharedDecoderFactory::Keyed(factory) => {
+                PushDecoderFactory::Keyed(factory.clone(), key.clone())
+            }
+        };
+        move || match &decoder_factory {
+            PushDecoderFactory::Plain(factory) => {
+                factory.lock().unwrap_or_else(PoisonError::into_inner)()
+            }
+            PushDecoderFactory::Keyed(factory, key) => {
+                factory.lock().unwrap_or_else(PoisonError::into_inner)(key)
+            }
+        }
+    }
+
+    /// See [`DelaySessionStore::push_signal`].
+    pub async fn push_signal(&self, key: K, instant: Instant) -> Result<(), PushError> {
+        let decoder_factory = self.decoder_factory(&key);
+        self.store.push_signal(key, instant, decoder_factory).await
+    }
+
+    /// See [`DelaySessionStore::push_signal_now`].
+    pub async fn push_signal_now(&self, key: K) -> Result<(), PushError> {
+        let decoder_factory = self.decoder_factory(&key);
+        self.store.push_signal_now(key, decoder_factory).await
+    }
+
+    /// See [`DelaySessionStore::push_signal_with_timeout`].
+    pub async fn push_signal_with_timeout(
+        &self,
+        key: K,
+        instant: Instant,
+        timeout: Duration,
+    ) -> Result<(), PushError> {
+        let decoder_factory = self.decoder_factory(&key);
+        self.store
+            .push_signal_with_timeout(key, instant, timeout, decoder_factory)
+            .await
+    }
+
+    /// See [`DelaySessionStore::try_push_signal`].
+    pub fn try_push_signal(&self, key: K, instant: Instant) -> PushOutcome {
+        let decoder_factory = self.decoder_factory(&key);
+        self.store.try_push_signal(key, instant, decoder_factory)
+    }
+
+    pub fn sink(self: &Arc<Self>) -> DelaySessionSink<K> {
+        let store = self.clone();
+        DelaySessionSink::new(move |key, instant| {
+            let store = store.clone();
+            Box::pin(async move { store.push_signal(key, instant).await })
+        })
+    }
+}
+
+impl<K, T, O> FactoryDelaySessionStore<K, T, O> {
+    pub const fn store(&self) -> &DelaySessionStore<K, T, O> {
+        &self.store
+    }
+}
+
+impl<K, T, O> Debug for FactoryDelaySessionStore<K, T, O>
+where
+    DelaySessionStore<K, T, O>: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FactoryDelaySessionStore")
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use tokio::sync::{
+    mpsc::{
+        error::{SendError, TrySendError},
+        Sender,
+    },
+    Notify,
+};
+
+use crate::task;
+
+/// Configuration of fair result emission, see
+/// `DelaySessionStoreBuilder::fair_emission`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct FairnessConfig {
+    /// Results queued per key; past it, the key's sessions wait to emit.
+    pub per_key: usize,
+    /// Results queued over all keys.
+    pub total: usize,
+}
+
+impl Default for FairnessConfig {
+    fn default() -> Self {
+        Self {
+            per_key: 4,
+            total: 1024,
+        }
+    }
+}
+
+struct Queues<K, T> {
+    queues: HashMap<K, VecDeque<T>>,
+    /// Keys with queued results, in the order they are served.
+    order: VecDeque<K>,
+    /// Results queued, including the one being forwarded.
+    len: usize,
+    senders_gone: bool,
+}
+
+struct Shared<K, T> {
+    config: FairnessConfig,
+    sender: Sender<(K, T)>,
+    queues: Mutex<Queues<K, T>>,
+    queued: Notify,
+    dequeued: Notify,
+}
+
+/// Queues results per key in front of the result channel, and forwards them
+/// round robin, one result per key at a time, so a key with many results
+/// cannot hold up the others.
+pub(crate) struct FairSender<K, T> {
+    shared: Arc<Shared<K, T>>,
+}
+
+impl<K, T> FairSender<K, T>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    T: Send + 'static,
+{
+    /// Spawns the task forwarding queued results to `sender`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either bound in `config` is zero.
+    pub(crate) fn spawn(config: FairnessConfig, sender: Sender<(K, T)>, store_name: &str) -> Self {
+        assert!(
+            config.per_key > 0 && config.total > 0,
+            "fair emission bounds must be non-zero"
+        );
+
+        let shared = Arc::new(Shared {
+            config,
+            sender,
+            queues: Mutex::new(Queues {
+                queues: HashMap::new(),
+                order: VecDeque::new(),
+                len: 0,
+                senders_gone: false,
+            }),
+            queued: Notify::new(),
+            dequeued: Notify::new(),
+        });
+        task::spawn(
+            || format!("{store_name} fair forwarder"),
+            forward(shared.clone()),
+        );
+
+        Self { shared }
+    }
+}
+
+impl<K, T> FairSender<K, T>
+where
+    K: Clone + Eq + Hash,
+{
+    pub(crate) fn is_closed(&self) -> bool {
+        self.shared.sender.is_closed()
+    }
+
+    pub(crate) async fn send(&self, result: (K, T)) -> Result<(), SendError<(K, T)>> {
+        let mut result = result;
+        loop {
+            let dequeued = self.shared.dequeued.notified();
+            result = match self.try_send(result) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(result)) => result,
+                Err(TrySendError::Closed(result)) => return Err(SendError(result)),
+            };
+            dequeued.await;
+        }
+    }
+
+    pub(crate) fn try_send(&self, (key, result): (K, T)) -> Result<(), TrySendError<(K, T)>> {
+        if self.is_closed() {
+            return Err(TrySendError::Closed((key, result)));
+        }
+
+        let mut queues = self
+            .shared
+            .queues
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if queues.len >= self.shared.config.total {
+            return Err(TrySendError::Full((key, result)));
+        }
+
+        let Queues {
+            queues: per_key,
+            order,
+            len,
+            ..
+        } = &mut *queues;
+        match per_key.get_mut(&key) {
+            Some(queue) if queue.len() >= self.shared.config.per_key => {
+                return Err(TrySendError::Full((key, result)));
+            }
+            Some(queue) => queue.push_back(result),
+            None => {
+                order.push_back(key.clone());
+                per_key.insert(key, VecDeque::from([result]));
+            }
+        }
+        *len += 1;
+        drop(queues);
+
+        self.shared.queued.notify_one();
+        Ok(())
+    }
+
+    /// Waits until the queues have room for a result of a key with none
+    /// queued, and returns `false` if the result channel closes first.
+    #[cfg(feature = "tower")]
+    pub(crate) async fn has_room(&self) -> bool {
+        loop {
+            let dequeued = self.shared.dequeued.notified();
+            if self.is_closed() {
+                return false;
+            }
+            let len = self
+                .shared
+                .queues
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .len;
+            if len < self.shared.config.total {
+                return true;
+            }
+            dequeued.await;
+        }
+    }
+
+    /// Waits until every queued result has been forwarded, or the result
+    /// channel closes.
+    #[cfg(feature = "tokio-util")]
+    pub(crate) async fn flushed(&self) {
+        loop {
+            let dequeued = self.shared.dequeued.notified();
+            let len = self
+                .shared
+                .queues
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .len;
+            if len == 0 || self.is_closed() {
+                return;
+            }
+            dequeued.await;
+        }
+    }
+}
+
+impl<K, T> Drop for FairSender<K, T> {
+    fn drop(&mut self) {
+        self.shared
+            .queues
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .senders_gone = true;
+        self.shared.queued.notify_one();
+    }
+}
+
+/// Forwards queued results until the senders are gone and the queues are
+/// drained, or the result channel closes.
+async fn forward<K, T>(shared: Arc<Shared<K, T>>)
+where
+    K: Clone + Eq + Hash,
+{
+    loop {
+        let next = {
+            let mut queues = shared.queues.lock().unwrap_or_else(PoisonError::into_inner);
+            let Queues {
+                queues: per_key,
+                order,
+                senders_gone,
+                ..
+            } = &mut *queues;
+
+            match order.pop_front() {
+                Some(key) => {
+                    let queue = per_key.get_mut(&key).expect("ordered key has a queue");
+                    let result = queue.pop_front().expect("queues are never left empty");
+                    if queue.is_empty() {
+                        per_key.remove(&key);
+                    } else {
+                        order.push_back(key.clone());
+                    }
+                    Some((key, result))
+                }
+                None if *senders_gone => return,
+                None => None,
+            }
+        };
+
+        match next {
+            Some(result) => {
+                shared.dequeued.notify_waiters();
+                if shared.sender.send(result).await.is_err() {
+                    // The stream was closed: results still queued can never
+                    // be delivered, and senders see the closed channel.
+                    shared.dequeued.notify_waiters();
+                    return;
+                }
+                shared
+                    .queues
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .len -= 1;
+                shared.dequeued.notify_waiters();
+            }
+            None => shared.queued.notified().await,
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    mem::swap,
+    path::Path,
+    time::Duration,
+};
+
+use futures::StreamExt;
+use tokio::time::{timeout_at, Instant};
+
+use crate::session_store::DelaySessionStream;
+
+const HEADER_LEN: usize = 8;
+
+/// What scanning a result file found.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Recovery {
+    /// Number of complete records in the file.
+    pub records: u64,
+    /// Length of the file up to the end of the last complete record.
+    pub committed_len: u64,
+    /// Payload of the last complete record, from which upstream replay can
+    /// find where to resume.
+    pub last_record: Option<Vec<u8>>,
+    /// Bytes past the last complete record, left by a crash mid-record.
+    /// [`FileResultSink::open`] truncates them.
+    pub truncated: u64,
+}
+
+/// An append-only file of results that survives process crashes.
+///
+/// Each result is encoded into a payload by the caller's `encode` function
+/// and framed as a little-endian `u32` length, a `u32` FNV-1a checksum of the
+/// payload, and the payload itself. Records are fsynced at least every
+/// `sync_interval` and when the stream ends; a crash may lose records written
+/// since the last sync, and may leave a partial record at the end of the file,
+/// which [`FileResultSink::open`] detects and truncates.
+pub struct FileResultSink<E> {
+    writer: BufWriter<File>,
+    encode: E,
+    sync_interval: Duration,
+    payload: Vec<u8>,
+}
+
+impl<E> FileResultSink<E> {
+    /// Opens or creates the file at `path`, truncating any partial trailing
+    /// record, and reports what it already holds.
+    pub fn open(
+        path: impl AsRef<Path>,
+        sync_interval: Duration,
+        encode: E,
+    ) -> io::Result<(Self, Recovery)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let recovery = scan(&mut file)?;
+        if recovery.truncated > 0 {
+            file.set_len(recovery.committed_len)?;
+            file.sync_data()?;
+        }
+        file.seek(SeekFrom::Start(recovery.committed_len))?;
+
+        Ok((
+            Self {
+                writer: BufWriter::new(file),
+                encode,
+                sync_interval,
+                payload: Vec::new(),
+            },
+            recovery,
+        ))
+    }
+
+    /// Appends every result of `stream` until it ends, returning the number
+    /// of records written. Fsyncs run on the blocking thread pool.
+    pub async fn run<K, T>(mut self, mut stream: DelaySessionStream<K, T>) -> io::Result<u64>
+    where
+        E: FnMut(&K, &T, &mut Vec<u8>),
+    {
+        let mut written = 0;
+        let mut dirty = false;
+        let mut next_sync = Instant::now() + self.sync_interval;
+
+        loop {
+            match timeout_at(next_sync, stream.next()).await {
+                Ok(Some((key, result))) => {
+                    self.append(&key, &result)?;
+                    written += 1;
+                    dirty = true;
+                }
+                Ok(None) => break,
+                Err(_) => {}
+            }
+
+            if Instant::now() >= next_sync {
+                if dirty {
+                    self.sync().await?;
+                    dirty = false;
+                }
+                next_sync = Instant::now() + self.sync_interval;
+            }
+        }
+
+        if dirty {
+            self.sync().await?;
+        }
+
+        Ok(written)
+    }
+
+    fn append<K, T>(&mut self, key: &K, result: &T) -> io::Result<()>
+    where
+        E: FnMut(&K, &T, &mut Vec<u8>),
+    {
+        self.payload.clear();
+        (self.encode)(key, result, &mut self.payload);
+
+        let len = u32::try_from(self.payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too large"))?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer
+            .write_all(&checksum(&self.payload).to_le_bytes())?;
+        self.writer.write_all(&self.payload)
+    }
+
+    async fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let file = self.writer.get_ref().try_clone()?;
+        tokio::task::spawn_blocking(move || file.sync_data())
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+impl<E> Debug for FileResultSink<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileResultSink")
+            .field("writer", &self.writer)
+            .field("sync_interval", &self.sync_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Scans the file at `path` without modifying it.
+pub fn recover(path: impl AsRef<Path>) -> io::Result<Recovery> {
+    scan(&mut File::open(path)?)
+}
+
+fn scan(file: &mut File) -> io::Result<Recovery> {
+    let len = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(file);
+
+    let mut recovery = Recovery {
+        records: 0,
+        committed_len: 0,
+        last_record: None,
+        truncated: 0,
+    };
+    let mut header = [0; HEADER_LEN];
+    let mut payload = Vec::new();
+    let mut last_record = Vec::new();
+
+    loop {
+        let remaining = len - recovery.committed_len;
+        if remaining < HEADER_LEN as u64 {
+            break;
+        }
+        reader.read_exact(&mut header)?;
+
+        let [l0, l1, l2, l3, c0, c1, c2, c3] = header;
+        let payload_len = u32::from_le_bytes([l0, l1, l2, l3]);
+        let expected = u32::from_le_bytes([c0, c1, c2, c3]);
+        if remaining - (HEADER_LEN as u64) < u64::from(payload_len) {
+            break;
+        }
+
+        payload.resize(payload_len as usize, 0);
+        reader.read_exact(&mut payload)?;
+        if checksum(&payload) != expected {
+            break;
+        }
+
+        recovery.records += 1;
+        recovery.committed_len += (HEADER_LEN as u64) + u64::from(payload_len);
+        swap(&mut payload, &mut last_record);
+    }
+
+    recovery.last_record = (recovery.records > 0).then_some(last_record);
+    recovery.truncated = len - recovery.committed_len;
+    Ok(recovery)
+}
+
+/// FNV-1a, enough to tell a torn write from a complete record.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use bitvec::vec::BitVec;
+    use tokio::time::sleep;
+
+    use super::{recover, FileResultSink, Recovery, HEADER_LEN};
+    use crate::{decoder::AverageDelayDecoder, session_store::DelaySessionStoreBuilder};
+
+    fn encode(key: &u32, _: &BitVec, payload: &mut Vec<u8>) {
+        payload.extend(key.to_le_bytes());
+    }
+
+    /// Writes a result for each of `keys`, in order, to the sink at `path`.
+    async fn write(path: &std::path::Path, keys: impl IntoIterator<Item = u32>) -> Recovery {
+        let (sink, recovery) = FileResultSink::open(path, Duration::from_secs(1), encode).unwrap();
+        let (store, results) =
+            DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60)).build();
+        let start = store.clock().now();
+        for key in keys {
+            store
+                .push_signal(key, start, AverageDelayDecoder::new)
+                .await
+                .unwrap();
+            assert!(store.flush(&key).await);
+            // Lets the session emit before the next one, keeping their order.
+            sleep(Duration::from_millis(1)).await;
+        }
+        drop(store);
+        sink.run(results).await.unwrap();
+        recovery
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recovery_stops_at_a_record_torn_by_a_crash() {
+        const RECORD_LEN: u64 = HEADER_LEN as u64 + 4;
+
+        let path = std::env::temp_dir().join(format!("delay-file-sink-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let empty = write(&path, 1..=3).await;
+        assert_eq!(empty.records, 0);
+        assert_eq!(recover(&path).unwrap().records, 3);
+
+        // A crash while appending the third record.
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(2 * RECORD_LEN + 5).unwrap();
+        drop(file);
+
+        let torn = Recovery {
+            records: 2,
+            committed_len: 2 * RECORD_LEN,
+            last_record: Some(2u32.to_le_bytes().to_vec()),
+            truncated: 5,
+        };
+        assert_eq!(recover(&path).unwrap(), torn);
+
+        // Reopening truncates the torn record and appends after the second.
+        let reopened = write(&path, [4]).await;
+        assert_eq!(reopened, torn);
+        assert_eq!(
+            recover(&path).unwrap(),
+            Recovery {
+                records: 3,
+                committed_len: 3 * RECORD_LEN,
+                last_record: Some(4u32.to_le_bytes().to_vec()),
+                truncated: 0,
+            }
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
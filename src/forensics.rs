@@ -0,0 +1,149 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap, VecDeque},
+    hash::Hash,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Configuration of a store's forensic buffer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct ForensicConfig {
+    /// Samples kept per key; older ones are overwritten.
+    pub per_key: usize,
+    /// Samples kept over all keys. Past it, the least recently pushed keys
+    /// are evicted whole.
+    pub max_entries: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct ForensicSample {
+    pub instant: Instant,
+    /// Time since the key's previous signal, if it is still remembered.
+    pub since_previous: Option<Duration>,
+}
+
+/// The latest raw signals pushed for a key, oldest first.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct ForensicTrace {
+    pub samples: Vec<ForensicSample>,
+}
+
+#[derive(Debug)]
+struct KeyRing {
+    samples: VecDeque<ForensicSample>,
+    last_instant: Instant,
+    touched: u64,
+}
+
+#[derive(Debug)]
+struct Rings<K> {
+    rings: HashMap<K, KeyRing>,
+    by_touch: BTreeMap<u64, K>,
+    touches: u64,
+    entries: usize,
+}
+
+/// Per-key rings of raw timing, kept by the store independently of sessions,
+/// so they survive session restarts and closes.
+#[derive(Debug)]
+pub(crate) struct ForensicBuffer<K> {
+    config: ForensicConfig,
+    rings: Mutex<Rings<K>>,
+}
+
+impl<K> ForensicBuffer<K> {
+    pub(crate) fn new(config: ForensicConfig) -> Self {
+        Self {
+            config,
+            rings: Mutex::new(Rings {
+                rings: HashMap::new(),
+                by_touch: BTreeMap::new(),
+                touches: 0,
+                entries: 0,
+            }),
+        }
+    }
+}
+
+impl<K> ForensicBuffer<K>
+where
+    K: Clone + Eq + Hash,
+{
+    pub(crate) fn record<Q>(&self, key: &Q, to_owned: impl Fn(&Q) -> K, instant: Instant)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let per_key = self.config.per_key.min(self.config.max_entries);
+        if per_key == 0 {
+            return;
+        }
+
+        let mut rings = self.rings.lock().unwrap_or_else(PoisonError::into_inner);
+        let Rings {
+            rings,
+            by_touch,
+            touches,
+            entries,
+        } = &mut *rings;
+        *touches += 1;
+
+        match rings.get_mut(key) {
+            Some(ring) => {
+                let owned = by_touch
+                    .remove(&ring.touched)
+                    .unwrap_or_else(|| to_owned(key));
+                by_touch.insert(*touches, owned);
+                ring.touched = *touches;
+
+                if ring.samples.len() == per_key {
+                    ring.samples.pop_front();
+                } else {
+                    *entries += 1;
+                }
+                ring.samples.push_back(ForensicSample {
+                    instant,
+                    since_previous: Some(instant.saturating_duration_since(ring.last_instant)),
+                });
+                ring.last_instant = instant;
+            }
+            None => {
+                let key = to_owned(key);
+                by_touch.insert(*touches, key.clone());
+                rings.insert(
+                    key,
+                    KeyRing {
+                        samples: VecDeque::from([ForensicSample {
+                            instant,
+                            since_previous: None,
+                        }]),
+                        last_instant: instant,
+                        touched: *touches,
+                    },
+                );
+                *entries += 1;
+            }
+        }
+
+        while *entries > self.config.max_entries {
+            let Some((_, key)) = by_touch.pop_first() else {
+                break;
+            };
+            if let Some(ring) = rings.remove::<K>(&key) {
+                *entries -= ring.samples.len();
+            }
+        }
+    }
+
+    pub(crate) fn export<Q>(&self, key: &Q) -> Option<ForensicTrace>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let rings = self.rings.lock().unwrap_or_else(PoisonError::into_inner);
+        rings.rings.get(key).map(|ring| ForensicTrace {
+            samples: ring.samples.iter().copied().collect(),
+        })
+    }
+}
@@ -0,0 +1,358 @@
+use alloc::vec::Vec;
+use core::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use crate::error::ParseCidrError;
+
+/// A block of IP addresses, e.g. `10.0.0.0/8` or `2001:db8::/32`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct IpCidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    /// The block of the addresses sharing the first `prefix_len` bits of
+    /// `addr`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `prefix_len` is longer than `addr`.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, ParseCidrError> {
+        let network = match addr {
+            IpAddr::V4(addr) if prefix_len <= 32 => {
+                IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask_u32(prefix_len)))
+            }
+            IpAddr::V6(addr) if prefix_len <= 128 => {
+                IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask_u128(prefix_len)))
+            }
+            _ => return Err(ParseCidrError::InvalidPrefix),
+        };
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+
+    /// Whether `addr` is in the block. IPv4-mapped IPv6 addresses count as
+    /// their IPv4 address.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.network, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                u32::from(addr) & mask_u32(self.prefix_len) == u32::from(network)
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                u128::from(addr) & mask_u128(self.prefix_len) == u128::from(network)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses `addr/prefix_len`, or a bare address as the block of just itself.
+impl FromStr for IpCidr {
+    type Err = ParseCidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = s
+            .split_once('/')
+            .map_or((s, None), |(addr, prefix_len)| (addr, Some(prefix_len)));
+        let addr = IpAddr::from_str(addr).map_err(|_| ParseCidrError::InvalidAddress)?;
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .map_err(|_| ParseCidrError::InvalidPrefix)?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(addr, prefix_len)
+    }
+}
+
+fn mask_u32(prefix_len: u8) -> u32 {
+    u32::MAX
+        .checked_shl(32 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
+
+fn mask_u128(prefix_len: u8) -> u128 {
+    u128::MAX
+        .checked_shl(128 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
+
+/// Which header a [`ForwardedKeyExtractor`] reads the proxy chain from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For: client, proxy1, proxy2`.
+    #[default]
+    XForwardedFor,
+    /// The standard `Forwarded: for=client, for=proxy1` of RFC 7239, whose
+    /// `for` parameters make up the chain.
+    Forwarded,
+}
+
+/// Keys requests that came through proxies by the address of the client
+/// that sent them, rather than by the peer of the connection, which behind a
+/// load balancer is the same for every client.
+///
+/// The chain of addresses in the forwarding header is walked from the right,
+/// the hop closest to the server, skipping trusted proxies, and the first
+/// untrusted address is the client. Anyone can send the header, so it is
+/// only read when the peer itself is a trusted proxy; otherwise, and when
+/// the header is absent or malformed, the key is the peer. With no trusted
+/// proxies configured the key is always the peer.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ForwardedKeyExtractor {
+    trusted: Vec<IpCidr>,
+    header: ForwardedHeader,
+}
+
+impl ForwardedKeyExtractor {
+    /// Trusts the proxies in `trusted` to forward client addresses in
+    /// `X-Forwarded-For`.
+    pub fn new(trusted: impl IntoIterator<Item = IpCidr>) -> Self {
+        Self {
+            trusted: trusted.into_iter().collect(),
+            header: ForwardedHeader::default(),
+        }
+    }
+
+    /// Reads the chain from `header` instead of `X-Forwarded-For`.
+    pub fn header(mut self, header: ForwardedHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// The header the chain is read from.
+    pub fn header_name(&self) -> &'static str {
+        match self.header {
+            ForwardedHeader::XForwardedFor => "x-forwarded-for",
+            ForwardedHeader::Forwarded => "forwarded",
+        }
+    }
+
+    /// Whether `addr` is a trusted proxy.
+    pub fn is_trusted(&self, addr: IpAddr) -> bool {
+        self.trusted.iter().any(|cidr| cidr.contains(addr))
+    }
+
+    /// The client address of a request from `peer`, given the values of
+    /// every [`header_name`](Self::header_name) field of the request in the
+    /// order they came, which make up one chain.
+    pub fn client<'a>(&self, peer: IpAddr, values: impl IntoIterator<Item = &'a str>) -> IpAddr {
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+
+        let mut chain = Vec::new();
+        for element in values.into_iter().flat_map(|value| value.split(',')) {
+            let node = match self.header {
+                ForwardedHeader::XForwardedFor => Some(element),
+                ForwardedHeader::Forwarded => forwarded_for(element),
+            };
+            match node.and_then(parse_node) {
+                Some(addr) => chain.push(addr),
+                None => return peer,
+            }
+        }
+
+        chain
+            .iter()
+            .rev()
+            .find(|addr| !self.is_trusted(**addr))
+            // Every hop is trusted, so the leftmost is as far as is known.
+            .or_else(|| chain.first())
+            .copied()
+            .unwrap_or(peer)
+    }
+}
+
+/// The `for` parameter of a `Forwarded` element, if it has exactly one.
+fn forwarded_for(element: &str) -> Option<&str> {
+    let mut nodes = element.split(';').filter_map(|pair| {
+        let (name, value) = pair.trim().split_once('=')?;
+        name.eq_ignore_ascii_case("for").then_some(value)
+    });
+    let node = nodes.next()?;
+    nodes.next().is_none().then_some(node)
+}
+
+/// Parses a node of a chain: an address, optionally quoted, and with a port
+/// after an IPv4 address or a bracketed IPv6 one. Obfuscated and `unknown`
+/// nodes are not addresses.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    let node = node
+        .strip_prefix('"')
+        .and_then(|node| node.strip_suffix('"'))
+        .unwrap_or(node);
+
+    if let Some(node) = node.strip_prefix('[') {
+        let (addr, port) = node.split_once(']')?;
+        if !port.is_empty() && !is_port(port.strip_prefix(':')?) {
+            return None;
+        }
+        return Ipv6Addr::from_str(addr).ok().map(IpAddr::V6);
+    }
+    if let Ok(addr) = IpAddr::from_str(node) {
+        return Some(addr);
+    }
+    let (addr, port) = node.split_once(':')?;
+    if !is_port(port) {
+        return None;
+    }
+    Ipv4Addr::from_str(addr).ok().map(IpAddr::V4)
+}
+
+fn is_port(port: &str) -> bool {
+    port.parse::<u16>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::IpAddr;
+
+    use super::{ForwardedHeader, ForwardedKeyExtractor, IpCidr};
+    use crate::error::ParseCidrError;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn extractor() -> ForwardedKeyExtractor {
+        ForwardedKeyExtractor::new(
+            ["10.0.0.0/8", "192.168.1.1", "2001:db8:ffff::/48"].map(|cidr| cidr.parse().unwrap()),
+        )
+    }
+
+    #[test]
+    fn cidrs_parse_and_match() {
+        let cidr: IpCidr = "10.1.2.3/8".parse().unwrap();
+        assert!(cidr.contains(ip("10.255.0.1")));
+        assert!(cidr.contains(ip("::ffff:10.0.0.1")));
+        assert!(!cidr.contains(ip("11.0.0.1")));
+        assert!("0.0.0.0/0"
+            .parse::<IpCidr>()
+            .unwrap()
+            .contains(ip("8.8.8.8")));
+        assert!(!"0.0.0.0/0".parse::<IpCidr>().unwrap().contains(ip("::1")));
+
+        assert_eq!(
+            "10.0.0.0/33".parse::<IpCidr>(),
+            Err(ParseCidrError::InvalidPrefix)
+        );
+        assert_eq!("::/x".parse::<IpCidr>(), Err(ParseCidrError::InvalidPrefix));
+        assert_eq!(
+            "10.0.0/8".parse::<IpCidr>(),
+            Err(ParseCidrError::InvalidAddress)
+        );
+    }
+
+    #[test]
+    fn multi_hop_chains_skip_trusted_proxies_from_the_right() {
+        let extractor = extractor();
+        let peer = ip("10.0.0.1");
+
+        let chain = ["198.51.100.7, 203.0.113.9, 10.2.3.4", "192.168.1.1"];
+        assert_eq!(extractor.client(peer, chain), ip("203.0.113.9"));
+        assert_eq!(extractor.client(peer, ["198.51.100.7"]), ip("198.51.100.7"));
+        // A chain of trusted proxies only ends at its leftmost hop.
+        assert_eq!(
+            extractor.client(peer, ["10.9.9.9, 10.0.0.2"]),
+            ip("10.9.9.9")
+        );
+        assert_eq!(
+            extractor.client(peer, ["198.51.100.7:5000"]),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn absent_or_malformed_headers_fall_back_to_the_peer() {
+        let extractor = extractor();
+        let peer = ip("10.0.0.1");
+
+        assert_eq!(extractor.client(peer, []), peer);
+        for value in [
+            "",
+            "198.51.100.7,",
+            "not-an-ip",
+            "198.51.100.7, 300.1.1.1",
+            "1.2.3.4:99999",
+        ] {
+            assert_eq!(extractor.client(peer, [value]), peer, "{value:?}");
+        }
+
+        let forwarded = extractor.clone().header(ForwardedHeader::Forwarded);
+        for value in [
+            "for=unknown",
+            "for=_hidden",
+            "proto=https",
+            "for=1.2.3.4;for=5.6.7.8",
+        ] {
+            assert_eq!(forwarded.client(peer, [value]), peer, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn ipv6_chains_are_walked_like_ipv4_ones() {
+        let extractor = extractor();
+        let peer = ip("2001:db8:ffff::1");
+
+        let chain = ["2001:db8::7, [2001:db8:ffff::2]:443"];
+        assert_eq!(extractor.client(peer, chain), ip("2001:db8::7"));
+
+        let forwarded = extractor.header(ForwardedHeader::Forwarded);
+        let chain = [r#"for="[2001:db8::7]:4711";proto=https, For=192.0.2.60;by=10.0.0.1"#];
+        assert_eq!(forwarded.client(peer, chain), ip("192.0.2.60"));
+        assert_eq!(forwarded.header_name(), "forwarded");
+    }
+
+    #[test]
+    fn headers_from_untrusted_peers_are_ignored() {
+        let peer = ip("203.0.113.50");
+        assert_eq!(extractor().client(peer, ["198.51.100.7"]), peer);
+        // With nothing trusted, nobody can forward client addresses.
+        let extractor = ForwardedKeyExtractor::default();
+        assert_eq!(
+            extractor.client(ip("10.0.0.1"), ["198.51.100.7"]),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[tokio::test(start_paused = true)]
+    async fn sessions_are_keyed_by_the_client_behind_the_proxy() {
+        use std::time::Duration;
+
+        use futures::StreamExt;
+
+        use crate::{decoder::AverageDelayDecoder, session_store::DelaySessionStoreBuilder};
+
+        let extractor = extractor();
+        let balancer = ip("10.0.0.1");
+        let (store, mut results) =
+            DelaySessionStoreBuilder::<IpAddr>::new(Duration::from_secs(1)).build();
+        for (client, pushes) in [("198.51.100.7", 3), ("203.0.113.9", 2)] {
+            let value = format!("{client}, 10.0.0.2");
+            for _ in 0..pushes {
+                let key = extractor.client(balancer, [value.as_str()]);
+                store
+                    .push_signal_now(key, AverageDelayDecoder::new)
+                    .await
+                    .unwrap();
+            }
+        }
+        assert_eq!(store.session_count().await, 2);
+
+        let mut keys = Vec::new();
+        for _ in 0..2 {
+            keys.push(results.next().await.unwrap().0);
+        }
+        keys.sort_unstable();
+        assert_eq!(keys, [ip("198.51.100.7"), ip("203.0.113.9")]);
+    }
+}
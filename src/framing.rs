@@ -0,0 +1,134 @@
+use alloc::{
+    string::{FromUtf8Error, String},
+    vec::Vec,
+};
+
+use bitvec::{order, slice::BitSlice, vec::BitVec, view::BitView};
+
+/// Which bit of a byte is sent first.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum BitOrder {
+    /// The most significant bit first, as [`pack_bytes`] assembles bytes.
+    ///
+    /// [`pack_bytes`]: crate::transform::pack_bytes
+    #[default]
+    Msb0,
+    Lsb0,
+}
+
+/// How [`bits_to_string`] handles bytes that are not valid UTF-8.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum Utf8Mode {
+    /// Replaces invalid sequences with `U+FFFD`.
+    #[default]
+    Lossy,
+    /// Fails on any invalid sequence.
+    Strict,
+}
+
+/// Assembles `bits` into bytes, returning them and the number of bits in the
+/// trailing partial byte, or zero if there is none. A partial byte is padded
+/// with `false` bits in the positions sent last.
+pub fn bits_to_bytes(bits: &BitSlice, order: BitOrder) -> (Vec<u8>, usize) {
+    let bytes = bits
+        .chunks(8)
+        .map(|byte| {
+            byte.iter()
+                .by_vals()
+                .enumerate()
+                .fold(0u8, |acc, (i, bit)| {
+                    let shift = match order {
+                        BitOrder::Msb0 => 7 - i,
+                        BitOrder::Lsb0 => i,
+                    };
+                    acc | u8::from(bit) << shift
+                })
+        })
+        .collect();
+
+    (bytes, bits.len() % 8)
+}
+
+/// Splits `bytes` into bits, the inverse of [`bits_to_bytes`] for whole
+/// bytes.
+pub fn bytes_to_bits(bytes: &[u8], order: BitOrder) -> BitVec {
+    match order {
+        BitOrder::Msb0 => bytes.view_bits::<order::Msb0>().iter().by_vals().collect(),
+        BitOrder::Lsb0 => bytes.view_bits::<order::Lsb0>().iter().by_vals().collect(),
+    }
+}
+
+/// Assembles `bits` into bytes and decodes them as UTF-8. A trailing partial
+/// byte is dropped.
+pub fn bits_to_string(
+    bits: &BitSlice,
+    order: BitOrder,
+    mode: Utf8Mode,
+) -> Result<String, FromUtf8Error> {
+    let (mut bytes, trailing_bits) = bits_to_bytes(bits, order);
+    if trailing_bits > 0 {
+        bytes.pop();
+    }
+
+    match mode {
+        Utf8Mode::Lossy => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Utf8Mode::Strict => String::from_utf8(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use bitvec::{bitvec, order::Lsb0};
+
+    use super::{bits_to_bytes, bits_to_string, bytes_to_bits, BitOrder, Utf8Mode};
+
+    #[test]
+    fn bytes_round_trip_in_either_bit_order() {
+        for order in [BitOrder::Msb0, BitOrder::Lsb0] {
+            let bits = bytes_to_bits(b"Hi!", order);
+            assert_eq!(bits.len(), 24);
+            assert_eq!(bits_to_bytes(&bits, order), (b"Hi!".to_vec(), 0));
+        }
+
+        assert_eq!(
+            &bytes_to_bits(&[0x81, 0x02], BitOrder::Msb0)[..8],
+            bitvec![1, 0, 0, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(
+            &bytes_to_bits(&[0x02], BitOrder::Lsb0)[..],
+            bitvec![0, 1, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn a_partial_byte_is_padded_in_the_positions_sent_last() {
+        let bits = bitvec![0, 1, 0, 0, 0, 0, 0, 1, 1, 0, 1];
+        assert_eq!(
+            bits_to_bytes(&bits, BitOrder::Msb0),
+            (vec![0x41, 0b1010_0000], 3)
+        );
+        assert_eq!(
+            bits_to_bytes(&bits, BitOrder::Lsb0),
+            (vec![0x82, 0b0000_0101], 3)
+        );
+    }
+
+    #[test]
+    fn strings_drop_a_partial_byte_and_handle_invalid_utf8_by_mode() {
+        let mut bits = bytes_to_bits("é!".as_bytes(), BitOrder::Msb0);
+        bits.extend([true, false]);
+        assert_eq!(
+            bits_to_string(&bits, BitOrder::Msb0, Utf8Mode::Strict).unwrap(),
+            "é!"
+        );
+
+        let invalid = bytes_to_bits(&[b'a', 0xff, b'b'], BitOrder::Msb0);
+        assert_eq!(
+            bits_to_string(&invalid, BitOrder::Msb0, Utf8Mode::Lossy).unwrap(),
+            "a\u{fffd}b"
+        );
+        assert!(bits_to_string(&invalid, BitOrder::Msb0, Utf8Mode::Strict).is_err());
+    }
+}
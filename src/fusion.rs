@@ -0,0 +1,286 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    hash::Hash,
+    pin::pin,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{select, Either},
+    Stream, StreamExt,
+};
+use tokio::time::sleep_until;
+
+use crate::{decoder::MetaDelayDecoder, session_store::DelaySessionStore};
+
+/// Matched pairs an offset needs before it replaces the wide `max_offset`
+/// matching window.
+const MIN_OFFSET_SAMPLES: usize = 3;
+
+/// Configuration of a [`SignalFuser`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct FusionConfig<O> {
+    /// The observer whose clock and observations are preferred.
+    pub primary: O,
+    /// Observations of a key at most this far apart, after clock alignment,
+    /// are taken to be the same request.
+    pub tolerance: Duration,
+    /// The largest clock offset expected between an observer and the
+    /// primary. Until an observer's offset is learned, its observations are
+    /// matched within this window. Every signal is held back this long, plus
+    /// `tolerance`, for late duplicates.
+    pub max_offset: Duration,
+    /// Matched pairs the offsets are the median of.
+    pub offset_samples: usize,
+}
+
+#[derive(Debug)]
+struct Pending<O> {
+    seq: u64,
+    /// The observation emitted, by the primary's clock.
+    instant: Instant,
+    observer: O,
+    /// Every observation of the request, by its observer's clock.
+    observations: Vec<(O, Instant)>,
+}
+
+#[derive(Debug, Default)]
+struct Offset {
+    /// Offsets of matched pairs in nanoseconds, positive if the observer's
+    /// clock is ahead of the primary's.
+    samples: VecDeque<i64>,
+    median: Option<i64>,
+}
+
+/// Fuses the signals of several observers of the same keys, e.g. an edge
+/// proxy and an origin, into one stream.
+///
+/// Each observer's clock is aligned to the primary's by the median offset of
+/// observations matched between them. Observations of a key by different
+/// observers within `tolerance` of each other are emitted once, with the
+/// primary's instant if it saw the request, or else the earliest. Signals
+/// seen by any observer are emitted, so the stream only loses requests every
+/// observer missed.
+#[derive(Debug)]
+pub struct SignalFuser<K, O> {
+    config: FusionConfig<O>,
+    pending: HashMap<K, VecDeque<Pending<O>>>,
+    /// Pending signals by release deadline.
+    deadlines: BTreeMap<(Instant, u64), K>,
+    offsets: HashMap<O, Offset>,
+    next_seq: u64,
+}
+
+impl<K, O> SignalFuser<K, O>
+where
+    K: Clone + Eq + Hash,
+    O: Clone + Eq + Hash,
+{
+    pub fn new(config: FusionConfig<O>) -> Self {
+        Self {
+            config,
+            pending: HashMap::new(),
+            deadlines: BTreeMap::new(),
+            offsets: HashMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    /// The learned clock offset of `observer` from the primary in
+    /// nanoseconds, positive if its clock is ahead, or `None` until enough
+    /// of its observations have been matched.
+    pub fn offset_nanos(&self, observer: &O) -> Option<i64> {
+        if *observer == self.config.primary {
+            return Some(0);
+        }
+        self.offsets.get(observer)?.median
+    }
+
+    /// Adds an observation, by `observer`'s clock.
+    pub fn push(&mut self, observer: O, key: K, instant: Instant) {
+        let offset = self.offset_nanos(&observer);
+        let aligned = shift(instant, -offset.unwrap_or(0));
+        let window = match offset {
+            Some(_) => self.config.tolerance,
+            None => self.config.max_offset + self.config.tolerance,
+        };
+
+        let queue = self.pending.entry(key.clone()).or_default();
+        let matched = queue
+            .iter_mut()
+            .filter(|pending| {
+                !pending
+                    .observations
+                    .iter()
+                    .any(|(seen_by, _)| *seen_by == observer)
+            })
+            .map(|pending| (abs_diff(pending.instant, aligned), pending))
+            .filter(|(distance, _)| *distance <= window)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, pending)| pending);
+
+        if let Some(pending) = matched {
+            let primary = &self.config.primary;
+            let mut samples = Vec::new();
+            for (seen_by, seen_at) in &pending.observations {
+                if *seen_by == *primary {
+                    samples.push((observer.clone(), nanos_between(*seen_at, instant)));
+                } else if observer == *primary {
+                    samples.push((seen_by.clone(), nanos_between(instant, *seen_at)));
+                }
+            }
+
+            pending.observations.push((observer.clone(), instant));
+            if observer == *primary || (pending.observer != *primary && aligned < pending.instant) {
+                pending.instant = aligned;
+                pending.observer = observer;
+            }
+
+            for (observer, sample) in samples {
+                self.learn_offset(observer, sample);
+            }
+            return;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let position = queue.partition_point(|pending| pending.instant <= aligned);
+        queue.insert(
+            position,
+            Pending {
+                seq,
+                instant: aligned,
+                observer: observer.clone(),
+                observations: vec![(observer, instant)],
+            },
+        );
+        self.deadlines.insert((aligned + self.hold(), seq), key);
+    }
+
+    /// Pops the next signal whose hold time has passed by `now`, by the
+    /// primary's clock.
+    pub fn pop_ready(&mut self, now: Instant) -> Option<(K, Instant)> {
+        let entry = self.deadlines.first_entry()?;
+        if entry.key().0 > now {
+            return None;
+        }
+
+        let ((_, seq), key) = entry.remove_entry();
+        self.take(key, seq)
+    }
+
+    /// Pops the next signal regardless of its hold time, e.g. once the
+    /// observers are done.
+    pub fn pop_any(&mut self) -> Option<(K, Instant)> {
+        let ((_, seq), key) = self.deadlines.pop_first()?;
+        self.take(key, seq)
+    }
+
+    /// When the next signal becomes ready.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines
+            .first_key_value()
+            .map(|((deadline, _), _)| *deadline)
+    }
+
+    fn hold(&self) -> Duration {
+        self.config.max_offset + self.config.tolerance
+    }
+
+    fn take(&mut self, key: K, seq: u64) -> Option<(K, Instant)> {
+        let queue = self.pending.get_mut(&key)?;
+        let position = queue.iter().position(|pending| pending.seq == seq)?;
+        let pending = queue.remove(position)?;
+        if queue.is_empty() {
+            self.pending.remove(&key);
+        }
+
+        Some((key, pending.instant))
+    }
+
+    fn learn_offset(&mut self, observer: O, sample: i64) {
+        let capacity = self.config.offset_samples.max(1);
+        let offset = self.offsets.entry(observer).or_default();
+        if offset.samples.len() == capacity {
+            offset.samples.pop_front();
+        }
+        offset.samples.push_back(sample);
+
+        if offset.samples.len() >= MIN_OFFSET_SAMPLES.min(capacity) {
+            let mut sorted: Vec<_> = offset.samples.iter().copied().collect();
+            sorted.sort_unstable();
+            offset.median = Some(sorted[sorted.len() / 2]);
+        }
+    }
+}
+
+/// Fuses `observations` and pushes the fused signals into `store`, creating
+/// decoders for new sessions with `decoder_factory`. Signals are released
+/// by the store's clock; once `observations` ends, the ones still held are
+/// pushed right away.
+pub async fn fuse_into<K, O, T, D>(
+    observations: impl Stream<Item = (O, K, Instant)>,
+    mut fuser: SignalFuser<K, O>,
+    store: &DelaySessionStore<K, T, D::Output>,
+    decoder_factory: impl FnMut() -> D + Clone + Send + 'static,
+) where
+    K: Clone + Eq + Hash + Send + 'static,
+    O: Clone + Eq + Hash,
+    T: Send + 'static,
+    D: MetaDelayDecoder<()> + Send + 'static,
+    D::Output: Clone + Send + 'static,
+{
+    let mut observations = pin!(observations);
+
+    loop {
+        while let Some((key, instant)) = fuser.pop_ready(store.clock().now()) {
+            let _ = store
+                .push_signal(key, instant, decoder_factory.clone())
+                .await;
+        }
+
+        let next = match fuser.next_deadline() {
+            Some(deadline) => {
+                match select(observations.next(), pin!(sleep_until(deadline.into()))).await {
+                    Either::Left((next, _)) => next,
+                    Either::Right(_) => continue,
+                }
+            }
+            None => observations.next().await,
+        };
+        match next {
+            Some((observer, key, instant)) => fuser.push(observer, key, instant),
+            None => break,
+        }
+    }
+
+    while let Some((key, instant)) = fuser.pop_any() {
+        let _ = store
+            .push_signal(key, instant, decoder_factory.clone())
+            .await;
+    }
+}
+
+fn abs_diff(a: Instant, b: Instant) -> Duration {
+    a.saturating_duration_since(b)
+        .max(b.saturating_duration_since(a))
+}
+
+/// `to - from` in nanoseconds, saturating.
+fn nanos_between(from: Instant, to: Instant) -> i64 {
+    let nanos = |duration: Duration| i64::try_from(duration.as_nanos()).unwrap_or(i64::MAX);
+    if to >= from {
+        nanos(to - from)
+    } else {
+        -nanos(from - to)
+    }
+}
+
+fn shift(instant: Instant, nanos: i64) -> Instant {
+    let by = Duration::from_nanos(nanos.unsigned_abs());
+    if nanos >= 0 {
+        instant.checked_add(by).unwrap_or(instant)
+    } else {
+        instant.checked_sub(by).unwrap_or(instant)
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::Clock,
+    decoder::MetaDelayDecoder,
+    metrics::StoreMetrics,
+    session::{timeout_instant, PauseGapPolicy},
+};
+
+/// What a signal's instant is compared against.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum SkewReference {
+    /// The instant of the previous signal of the same session. The first
+    /// signal of a session is always accepted.
+    PreviousSignal,
+    /// The store's clock at the time of the push.
+    AtPush,
+}
+
+/// What happens to a signal whose instant is further than `max_skew` from
+/// its reference.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ImplausibleAction {
+    /// The signal is dropped and the push fails with
+    /// `PushError::ImplausibleInstant`.
+    Reject,
+    /// The instant is moved to the nearest plausible one and the signal is
+    /// pushed as usual.
+    Clamp,
+    /// The key's session is closed, emitting its result, and the signal is
+    /// dropped as with `Reject`.
+    CloseSession,
+}
+
+/// Bounds how far a signal's instant may stray from a reference, guarding
+/// against instants hours in the future arming absurd deadlines, or long
+/// past instants from a replay.
+///
+/// The default has no `max_skew`, so every instant is accepted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct InstantPolicy {
+    pub max_skew: Option<Duration>,
+    pub reference: SkewReference,
+    pub action: ImplausibleAction,
+}
+
+impl Default for InstantPolicy {
+    fn default() -> Self {
+        Self {
+            max_skew: None,
+            reference: SkewReference::PreviousSignal,
+            action: ImplausibleAction::Reject,
+        }
+    }
+}
+
+impl InstantPolicy {
+    /// Captures what checking one push needs, reading `clock` only if the
+    /// policy compares against it.
+    pub(crate) fn screen<'a>(
+        &self,
+        clock: &dyn Clock,
+        metrics: &'a StoreMetrics,
+    ) -> InstantScreen<'a> {
+        let now = (self.max_skew.is_some() && self.reference == SkewReference::AtPush)
+            .then(|| clock.now());
+
+        InstantScreen {
+            policy: *self,
+            now,
+            metrics,
+            resumed_at: None,
+        }
+    }
+}
+
+/// The verdict on a single signal's instant.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub(crate) enum Screened {
+    Accept(Instant),
+    Reject,
+    CloseSession,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct InstantScreen<'a> {
+    policy: InstantPolicy,
+    now: Option<Instant>,
+    metrics: &'a StoreMetrics,
+    /// When the store resumed, for a push buffered while it was paused.
+    resumed_at: Option<Instant>,
+}
+
+impl InstantScreen<'_> {
+    /// Screens a push buffered while the store was paused, made at
+    /// `pushed_at` and replayed once the store resumed at `resumed_at`.
+    pub(crate) fn replay(self, pushed_at: Instant, resumed_at: Instant) -> Self {
+        Self {
+            now: self.now.map(|_| pushed_at),
+            resumed_at: Some(resumed_at),
+            ..self
+        }
+    }
+
+    /// How much of the store's pause was left after a replayed signal at
+    /// `instant`, which its timeout does not count.
+    pub(crate) fn pause_left(&self, instant: Instant) -> Duration {
+        self.resumed_at.map_or(Duration::ZERO, |resumed_at| {
+            resumed_at.saturating_duration_since(instant)
+        })
+    }
+
+    /// Checks `instant` against the policy, counting every violation.
+    pub(crate) fn check(&self, previous: Option<Instant>, instant: Instant) -> Screened {
+        let reference = match self.policy.reference {
+            SkewReference::PreviousSignal => previous,
+            SkewReference::AtPush => self.now,
+        };
+        let (Some(max_skew), Some(reference)) = (self.policy.max_skew, reference) else {
+            return Screened::Accept(instant);
+        };
+
+        let bound = match (
+            reference.checked_sub(max_skew),
+            reference.checked_add(max_skew),
+        ) {
+            (Some(earliest), _) if instant < earliest => earliest,
+            (_, Some(latest)) if instant > latest => latest,
+            _ => return Screened::Accept(instant),
+        };
+
+        self.metrics.record_implausible(self.policy.action);
+        match self.policy.action {
+            ImplausibleAction::Reject => Screened::Reject,
+            ImplausibleAction::Clamp => Screened::Accept(bound),
+            ImplausibleAction::CloseSession => Screened::CloseSession,
+        }
+    }
+}
+
+/// What a session does with a signal whose instant is earlier than that of
+/// a signal it already took in, e.g. when two request handlers race to push.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum OutOfOrderPolicy {
+    /// The signal ends a zero duration, and the next duration is measured
+    /// from it.
+    #[default]
+    SaturateZero,
+    /// The signal is dropped.
+    Drop,
+    /// The last `window` signals are held back and pushed to the decoder in
+    /// order of their instants once later ones arrive, or when the session
+    /// closes. A signal earlier than one already pushed is dropped, so
+    /// `Reorder { window: 0 }` is the same as `Drop`.
+    Reorder { window: usize },
+}
+
+/// Orders a session's signals into durations as its `OutOfOrderPolicy`
+/// says.
+#[derive(Debug)]
+pub(crate) struct SignalSequencer<M> {
+    policy: OutOfOrderPolicy,
+    /// The instant of the last signal pushed, which the next duration is
+    /// measured from.
+    last: Instant,
+    /// Signals held back by `Reorder`, by instant.
+    held: VecDeque<(Instant, M)>,
+    /// Signals closer than this to the latest one are dropped.
+    min_gap: Duration,
+    /// When a session was paused, under `PauseGapPolicy::Clamp`, until the
+    /// duration spanning the pause is measured.
+    clamp_at: Option<Instant>,
+}
+
+impl<M> SignalSequencer<M> {
+    pub(crate) const fn new(policy: OutOfOrderPolicy, start: Instant) -> Self {
+        Self {
+            policy,
+            last: start,
+            held: VecDeque::new(),
+            min_gap: Duration::ZERO,
+            clamp_at: None,
+        }
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: OutOfOrderPolicy) {
+        self.policy = policy;
+    }
+
+    pub(crate) fn set_min_gap(&mut self, min_gap: Duration) {
+        self.min_gap = min_gap;
+    }
+
+    /// The instant of the latest signal taken in, which the session's
+    /// timeout runs from.
+    pub(crate) fn latest(&self) -> Instant {
+        self.held.back().map_or(self.last, |(instant, _)| *instant)
+    }
+
+    /// Whether a signal at `instant` is taken in rather than dropped.
+    pub(crate) fn admits(&self, instant: Instant) -> bool {
+        self.policy == OutOfOrderPolicy::SaturateZero || instant >= self.last
+    }
+
+    /// Whether a signal at `instant` is within the minimum gap of the latest
+    /// signal, and so dropped before it ends a duration or extends the
+    /// timeout.
+    pub(crate) fn debounces(&self, instant: Instant) -> bool {
+        within(instant, self.latest(), self.min_gap)
+    }
+
+    /// Takes in an admitted signal, returning the duration and metadata to
+    /// push to the decoder, if any is due.
+    pub(crate) fn accept(&mut self, instant: Instant, meta: M) -> Option<(Duration, M)> {
+        let OutOfOrderPolicy::Reorder { window } = self.policy else {
+            return Some(self.advance(instant, meta));
+        };
+
+        let at = self.held.partition_point(|(held, _)| *held <= instant);
+        self.held.insert(at, (instant, meta));
+        if self.held.len() > window {
+            let (instant, meta) = self.held.pop_front()?;
+            Some(self.advance(instant, meta))
+        } else {
+            None
+        }
+    }
+
+    /// Pushes every held signal to `decoder`, for a closing session, and
+    /// then the duration from the latest signal to the `terminal` instant,
+    /// with the metadata of the signal at it, if there is one.
+    pub(crate) fn release<D>(&mut self, decoder: &mut D, terminal: Option<(Instant, Option<&M>)>)
+    where
+        D: MetaDelayDecoder<M>,
+    {
+        while let Some((instant, meta)) = self.held.pop_front() {
+            let (duration, meta) = self.advance(instant, meta);
+            decoder.push_duration_with(duration, &meta);
+        }
+        match terminal {
+            Some((instant, Some(meta))) => decoder.push_duration_with(self.gap_to(instant), meta),
+            Some((instant, None)) => decoder.push_duration(self.gap_to(instant)),
+            None => {}
+        }
+    }
+
+    /// Measures the next duration from a keepalive at `instant`, once the
+    /// signals held before it are pushed to `decoder`. A keepalive earlier
+    /// than a held signal changes nothing. Returns how many held signals it
+    /// pushed.
+    pub(crate) fn mark<D>(&mut self, decoder: &mut D, instant: Instant) -> usize
+    where
+        D: MetaDelayDecoder<M>,
+    {
+        let mut pushed = 0;
+        while self.held.front().is_some_and(|(held, _)| *held <= instant) {
+            if let Some((held, meta)) = self.held.pop_front() {
+                let (duration, meta) = self.advance(held, meta);
+                decoder.push_duration_with(duration, &meta);
+                pushed += 1;
+            }
+        }
+        if self.held.is_empty() && instant > self.last {
+            self.last = instant;
+            self.clamp_at = None;
+        }
+        pushed
+    }
+
+    /// Measures the duration spanning a pause from `paused_at` to
+    /// `resumed_at` as `policy` says.
+    pub(crate) fn resume(
+        &mut self,
+        policy: PauseGapPolicy,
+        paused_at: Instant,
+        resumed_at: Instant,
+    ) {
+        match policy {
+            PauseGapPolicy::Exclude => self.shift(resumed_at.saturating_duration_since(paused_at)),
+            PauseGapPolicy::Clamp => self.clamp_at = Some(paused_at),
+        }
+    }
+
+    /// Starts over from a new session's first signal, dropping held ones.
+    pub(crate) fn restart(&mut self, start: Instant) {
+        self.held.clear();
+        self.last = start;
+        self.clamp_at = None;
+    }
+
+    /// Moves every instant `by` later, as `DelaySession::shift` does.
+    pub(crate) fn shift(&mut self, by: Duration) {
+        self.last = timeout_instant(self.last, by);
+        for (instant, _) in &mut self.held {
+            *instant = timeout_instant(*instant, by);
+        }
+        self.clamp_at = self.clamp_at.map(|at| timeout_instant(at, by));
+    }
+
+    fn advance(&mut self, instant: Instant, meta: M) -> (Duration, M) {
+        let duration = self.gap_to(instant);
+        self.last = instant;
+        (duration, meta)
+    }
+
+    /// The duration from the last signal to `instant`, clamped to its part
+    /// before a pause it spans under `PauseGapPolicy::Clamp`.
+    fn gap_to(&mut self, instant: Instant) -> Duration {
+        let duration = instant.saturating_duration_since(self.last);
+        match self.clamp_at {
+            Some(paused_at) if instant >= paused_at => {
+                self.clamp_at = None;
+                duration.min(paused_at.saturating_duration_since(self.last))
+            }
+            _ => duration,
+        }
+    }
+}
+
+/// Whether `a` and `b` are less than `gap` apart.
+pub(crate) fn within(a: Instant, b: Instant, gap: Duration) -> bool {
+    a.saturating_duration_since(b)
+        .max(b.saturating_duration_since(a))
+        < gap
+}
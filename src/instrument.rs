@@ -0,0 +1,379 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+    time::Instant,
+};
+
+use crate::clock::Clock;
+
+type KeyFormatter<K> = Arc<dyn Fn(&K, &mut Formatter<'_>) -> fmt::Result + Send + Sync>;
+
+/// Decides how keys appear in log output. Keys may be sensitive, so unless a
+/// formatter is configured they are rendered as `<redacted>`.
+pub(crate) struct KeyRedactor<K> {
+    formatter: Option<KeyFormatter<K>>,
+}
+
+impl<K> KeyRedactor<K> {
+    pub(crate) const fn redacted() -> Self {
+        Self { formatter: None }
+    }
+
+    pub(crate) fn new(
+        formatter: impl Fn(&K, &mut Formatter<'_>) -> fmt::Result + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            formatter: Some(Arc::new(formatter)),
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "log", feature = "tracing")), allow(dead_code))]
+    pub(crate) const fn key<'a>(&'a self, key: &'a K) -> RedactedKey<'a, K> {
+        RedactedKey {
+            redactor: self,
+            key,
+        }
+    }
+}
+
+impl<K> Clone for KeyRedactor<K> {
+    fn clone(&self) -> Self {
+        Self {
+            formatter: self.formatter.clone(),
+        }
+    }
+}
+
+impl<K> Debug for KeyRedactor<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyRedactor")
+            .field("redacted", &self.formatter.is_none())
+            .finish()
+    }
+}
+
+pub(crate) struct RedactedKey<'a, K> {
+    redactor: &'a KeyRedactor<K>,
+    key: &'a K,
+}
+
+impl<K> Debug for RedactedKey<'_, K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.redactor.formatter {
+            Some(formatter) => formatter(self.key, f),
+            None => f.write_str("<redacted>"),
+        }
+    }
+}
+
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+pub(crate) fn session_created<K>(redactor: &KeyRedactor<K>, key: &K) {
+    #[cfg(feature = "log")]
+    log::debug!("session created for key {:?}", redactor.key(key));
+    #[cfg(feature = "tracing")]
+    tracing::debug!(key = ?redactor.key(key), "session created");
+}
+
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+pub(crate) fn session_closed<K>(redactor: &KeyRedactor<K>, key: &K, reason: &str, symbols: usize) {
+    #[cfg(feature = "log")]
+    log::debug!(
+        "session closed for key {:?} ({reason}) with {symbols} symbols",
+        redactor.key(key)
+    );
+    #[cfg(feature = "tracing")]
+    tracing::debug!(key = ?redactor.key(key), reason, symbols, "session closed");
+}
+
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+pub(crate) fn signal_dropped<K>(redactor: &KeyRedactor<K>, key: &K, reason: &str) {
+    #[cfg(feature = "log")]
+    log::warn!("signal dropped for key {:?}: {reason}", redactor.key(key));
+    #[cfg(feature = "tracing")]
+    tracing::warn!(key = ?redactor.key(key), reason, "signal dropped");
+}
+
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+pub(crate) fn result_suppressed<K>(redactor: &KeyRedactor<K>, key: &K) {
+    #[cfg(feature = "log")]
+    log::debug!("result suppressed for key {:?}", redactor.key(key));
+    #[cfg(feature = "tracing")]
+    tracing::debug!(key = ?redactor.key(key), "result suppressed");
+}
+
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+pub(crate) fn result_send_failed<K>(redactor: &KeyRedactor<K>, key: &K) {
+    #[cfg(feature = "log")]
+    log::warn!(
+        "result for key {:?} dropped: result stream closed",
+        redactor.key(key)
+    );
+    #[cfg(feature = "tracing")]
+    tracing::warn!(key = ?redactor.key(key), "result dropped: result stream closed");
+}
+
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+pub(crate) fn result_overflowed<K>(redactor: &KeyRedactor<K>, key: &K) {
+    #[cfg(feature = "log")]
+    log::warn!(
+        "result for key {:?} dropped: result stream full",
+        redactor.key(key)
+    );
+    #[cfg(feature = "tracing")]
+    tracing::warn!(key = ?redactor.key(key), "result dropped: result stream full");
+}
+
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+pub(crate) fn result_send_blocked<K>(redactor: &KeyRedactor<K>, key: &K) {
+    #[cfg(feature = "log")]
+    log::debug!(
+        "result for key {:?} waits for room in the result stream",
+        redactor.key(key)
+    );
+    #[cfg(feature = "tracing")]
+    tracing::debug!(key = ?redactor.key(key), "result send blocked");
+}
+
+/// A session took in `count` signals in one poll. Sessions do not know their
+/// key, so this is only traced, within the span of the session's task.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn signals_received(count: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(count, "signals received");
+}
+
+/// A session's timeout moved to `deadline`, traced like `signals_received`.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn timeout_reset(clock: &dyn Clock, deadline: Instant) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        remaining = ?deadline.saturating_duration_since(clock.now()),
+        "timeout reset"
+    );
+}
+
+#[cfg(all(test, any(feature = "log", feature = "tracing")))]
+mod tests {
+    #[cfg(feature = "log")]
+    use std::{
+        sync::Once,
+        thread::{self, ThreadId},
+    };
+    use std::{
+        sync::{Mutex, PoisonError},
+        time::Duration,
+    };
+
+    use futures::StreamExt;
+    #[cfg(feature = "log")]
+    use log::{Level, Log, Metadata, Record};
+    #[cfg(feature = "log")]
+    use tokio::{task::yield_now, time::timeout};
+
+    use crate::{decoder::AverageDelayDecoder, session_store::DelaySessionStoreBuilder};
+
+    /// Keeps every record with the thread that logged it, as tests running
+    /// in parallel share the logger.
+    #[cfg(feature = "log")]
+    struct CapturingLogger {
+        records: Mutex<Vec<(ThreadId, Level, String)>>,
+    }
+
+    #[cfg(feature = "log")]
+    impl Log for CapturingLogger {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            self.records
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push((
+                    thread::current().id(),
+                    record.level(),
+                    record.args().to_string(),
+                ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[cfg(feature = "log")]
+    static LOGGER: CapturingLogger = CapturingLogger {
+        records: Mutex::new(Vec::new()),
+    };
+
+    /// The records this thread logged so far.
+    #[cfg(feature = "log")]
+    fn records() -> Vec<(Level, String)> {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+        let thread = thread::current().id();
+        LOGGER
+            .records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|(id, ..)| *id == thread)
+            .map(|(_, level, message)| (*level, message.clone()))
+            .collect()
+    }
+
+    #[cfg(feature = "log")]
+    async fn logged(level: Level, message: &str) {
+        timeout(Duration::from_secs(5), async {
+            while !records().contains(&(level, message.to_owned())) {
+                yield_now().await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("no {level} record {message:?} in {:#?}", records()));
+    }
+
+    #[cfg(feature = "log")]
+    #[tokio::test]
+    async fn session_lifecycle_is_logged_with_redacted_keys() {
+        records();
+        let (store, mut results) = DelaySessionStoreBuilder::<String>::new(Duration::from_secs(60))
+            .result_mapper(|key: &String, bits| (key != "quiet").then_some(bits))
+            .debug_keys()
+            .signal_capacity(1)
+            .build();
+        let start = store.clock().now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        store
+            .push_signal("alpha".to_owned(), at(0), AverageDelayDecoder::new)
+            .await
+            .unwrap();
+        store.try_push_signal("alpha".to_owned(), at(10), AverageDelayDecoder::new);
+        store.try_push_signal("alpha".to_owned(), at(20), AverageDelayDecoder::new);
+        assert!(store.flush("alpha").await);
+        results.next().await.unwrap();
+
+        store
+            .push_signal("quiet".to_owned(), at(0), AverageDelayDecoder::new)
+            .await
+            .unwrap();
+        assert!(store.flush("quiet").await);
+
+        logged(Level::Debug, r#"session created for key "alpha""#).await;
+        logged(
+            Level::Warn,
+            r#"signal dropped for key "alpha": session queue full"#,
+        )
+        .await;
+        logged(
+            Level::Debug,
+            r#"session closed for key "alpha" (flushed) with 0 symbols"#,
+        )
+        .await;
+        logged(Level::Debug, r#"result suppressed for key "quiet""#).await;
+
+        let (redacted, _results) =
+            DelaySessionStoreBuilder::<String>::new(Duration::from_secs(60)).build();
+        redacted
+            .push_signal("secret".to_owned(), at(0), AverageDelayDecoder::new)
+            .await
+            .unwrap();
+        logged(Level::Debug, "session created for key <redacted>").await;
+        assert!(!records()
+            .iter()
+            .any(|(_, message)| message.contains("secret")));
+    }
+
+    /// A writer appending to a shared buffer, for capturing traces.
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<Mutex<Vec<u8>>>);
+
+    #[cfg(feature = "tracing")]
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test(start_paused = true)]
+    async fn session_lifecycle_is_traced_within_a_span_per_key() {
+        let buffer = SharedBuffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .without_time()
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (store, mut results) = DelaySessionStoreBuilder::<String>::new(Duration::from_secs(1))
+            .debug_keys()
+            .result_capacity(1)
+            .build();
+        let start = store.clock().now();
+        for key in ["alpha", "beta"] {
+            for millis in [0, 10, 40] {
+                store
+                    .push_signal(
+                        key.to_owned(),
+                        start + Duration::from_millis(millis),
+                        AverageDelayDecoder::new,
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        for _ in 0..2 {
+            results.next().await.unwrap();
+        }
+
+        let traces = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let span = r#"session{store="delay-session-store" key="alpha"}"#;
+        for expected in [
+            r#"DEBUG delay_data_rs::instrument: session created key="alpha""#.to_owned(),
+            format!("TRACE {span}: delay_data_rs::instrument: signals received count=2"),
+            format!("TRACE {span}: delay_data_rs::instrument: timeout reset remaining=1.04s"),
+            format!(
+                r#"DEBUG {span}: delay_data_rs::instrument: session closed key="alpha" reason="timeout" symbols=2"#
+            ),
+            r#"delay_data_rs::instrument: result send blocked key="beta""#.to_owned(),
+        ] {
+            assert!(traces.contains(&expected), "no {expected:?} in\n{traces}");
+        }
+    }
+}
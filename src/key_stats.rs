@@ -0,0 +1,210 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap, VecDeque},
+    hash::Hash,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Buckets the window of `KeyStats::count_in_window` is counted in, which
+/// bounds its error to one bucket's width.
+const WINDOW_BUCKETS: usize = 16;
+
+/// Configuration of a store's per-key statistics.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct KeyStatsConfig {
+    /// Span of `KeyStats::count_in_window`.
+    pub window: Duration,
+    /// Latest inter-arrival durations kept per key for percentiles.
+    pub durations: usize,
+    /// Keys tracked at once. Past it, the least recently pushed keys are
+    /// evicted.
+    pub max_keys: usize,
+}
+
+impl Default for KeyStatsConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            durations: 128,
+            max_keys: 10_000,
+        }
+    }
+}
+
+/// Rolling timing statistics of a key's recent signals.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct KeyStats {
+    /// Signals over the key's lifetime in the store.
+    pub count: u64,
+    /// Signals within the configured window before now, accurate to
+    /// `1/16` of the window at its far edge.
+    pub count_in_window: u64,
+    /// Percentiles of the latest inter-arrival durations, exact over the
+    /// `KeyStatsConfig::durations` kept, or `None` before the key's second
+    /// signal.
+    pub p50: Option<Duration>,
+    pub p95: Option<Duration>,
+    pub p99: Option<Duration>,
+    /// The latest instant pushed for the key.
+    pub last_seen: Instant,
+}
+
+#[derive(Debug)]
+struct KeyTiming {
+    durations: VecDeque<Duration>,
+    /// `(bucket index, signals)` per slot, indexed from `anchor`.
+    buckets: [(u64, u64); WINDOW_BUCKETS],
+    anchor: Instant,
+    count: u64,
+    last_seen: Instant,
+    touched: u64,
+}
+
+#[derive(Debug)]
+struct Timings<K> {
+    timings: HashMap<K, KeyTiming>,
+    by_touch: BTreeMap<u64, K>,
+    touches: u64,
+}
+
+/// Per-key timing statistics, kept by the store independently of sessions,
+/// so they survive session restarts and closes.
+#[derive(Debug)]
+pub(crate) struct KeyStatsTracker<K> {
+    config: KeyStatsConfig,
+    bucket_width: Duration,
+    timings: Mutex<Timings<K>>,
+}
+
+impl<K> KeyStatsTracker<K> {
+    pub(crate) fn new(config: KeyStatsConfig) -> Self {
+        Self {
+            config,
+            bucket_width: (config.window / WINDOW_BUCKETS as u32).max(Duration::from_nanos(1)),
+            timings: Mutex::new(Timings {
+                timings: HashMap::new(),
+                by_touch: BTreeMap::new(),
+                touches: 0,
+            }),
+        }
+    }
+
+    fn bucket(&self, anchor: Instant, instant: Instant) -> u64 {
+        (instant.saturating_duration_since(anchor).as_nanos() / self.bucket_width.as_nanos()) as u64
+    }
+}
+
+impl<K> KeyStatsTracker<K>
+where
+    K: Clone + Eq + Hash,
+{
+    pub(crate) fn record<Q>(&self, key: &Q, to_owned: impl Fn(&Q) -> K, instant: Instant)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.config.max_keys == 0 {
+            return;
+        }
+
+        let mut timings = self.timings.lock().unwrap_or_else(PoisonError::into_inner);
+        let Timings {
+            timings,
+            by_touch,
+            touches,
+        } = &mut *timings;
+        *touches += 1;
+
+        match timings.get_mut(key) {
+            Some(timing) => {
+                let owned = by_touch
+                    .remove(&timing.touched)
+                    .unwrap_or_else(|| to_owned(key));
+                by_touch.insert(*touches, owned);
+                timing.touched = *touches;
+
+                if self.config.durations > 0 {
+                    if timing.durations.len() == self.config.durations {
+                        timing.durations.pop_front();
+                    }
+                    timing
+                        .durations
+                        .push_back(instant.saturating_duration_since(timing.last_seen));
+                }
+                timing.count += 1;
+                timing.last_seen = timing.last_seen.max(instant);
+
+                let bucket = self.bucket(timing.anchor, instant);
+                let slot = &mut timing.buckets[bucket as usize % WINDOW_BUCKETS];
+                if slot.0 == bucket {
+                    slot.1 += 1;
+                } else if slot.0 < bucket {
+                    *slot = (bucket, 1);
+                }
+            }
+            None => {
+                let key = to_owned(key);
+                by_touch.insert(*touches, key.clone());
+
+                let mut buckets = [(0, 0); WINDOW_BUCKETS];
+                buckets[0] = (0, 1);
+                timings.insert(
+                    key,
+                    KeyTiming {
+                        durations: VecDeque::with_capacity(self.config.durations),
+                        buckets,
+                        anchor: instant,
+                        count: 1,
+                        last_seen: instant,
+                        touched: *touches,
+                    },
+                );
+            }
+        }
+
+        while timings.len() > self.config.max_keys {
+            let Some((_, key)) = by_touch.pop_first() else {
+                break;
+            };
+            timings.remove::<K>(&key);
+        }
+    }
+
+    pub(crate) fn stats<Q>(&self, key: &Q, now: Instant) -> Option<KeyStats>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let timings = self.timings.lock().unwrap_or_else(PoisonError::into_inner);
+        let timing = timings.timings.get(key)?;
+
+        let now_bucket = self.bucket(timing.anchor, now);
+        let count_in_window = timing
+            .buckets
+            .iter()
+            .filter(|(bucket, _)| {
+                *bucket <= now_bucket && now_bucket - bucket < WINDOW_BUCKETS as u64
+            })
+            .map(|(_, count)| count)
+            .sum();
+
+        let mut durations: Vec<_> = timing.durations.iter().copied().collect();
+        durations.sort_unstable();
+
+        Some(KeyStats {
+            count: timing.count,
+            count_in_window,
+            p50: percentile(&durations, 50),
+            p95: percentile(&durations, 95),
+            p99: percentile(&durations, 99),
+            last_seen: timing.last_seen,
+        })
+    }
+}
+
+/// Nearest-rank percentile of sorted durations.
+fn percentile(sorted: &[Duration], percent: usize) -> Option<Duration> {
+    let rank = (sorted.len() * percent).div_ceil(100);
+    sorted.get(rank.max(1) - 1).copied()
+}
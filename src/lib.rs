@@ -1,5 +1,134 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "testing")]
+pub mod adaptive;
+
+pub mod align;
+
+#[cfg(feature = "std")]
+pub mod analyzer;
+
+#[cfg(feature = "std")]
+mod cancel;
+
+#[cfg(feature = "std")]
+pub mod clock;
+
+pub mod codec;
+
+#[cfg(feature = "std")]
+pub mod dead_letter;
+
 pub mod decoder;
 
+#[cfg(feature = "std")]
+pub mod diagnostics;
+
+#[cfg(feature = "std")]
+mod emitter;
+
+pub mod encoder;
+
+pub mod error;
+
+pub mod estimate;
+
+#[cfg(feature = "testing")]
+pub mod evaluate;
+
+#[cfg(feature = "std")]
+pub mod factory_store;
+
+#[cfg(feature = "std")]
+pub mod fairness;
+
+#[cfg(feature = "std")]
+pub mod file_sink;
+
+#[cfg(feature = "std")]
+pub mod forensics;
+
+pub mod forwarded;
+
+pub mod framing;
+
+#[cfg(feature = "std")]
+pub mod fusion;
+
+#[cfg(feature = "std")]
+pub mod instant_policy;
+
+#[cfg(feature = "std")]
+mod instrument;
+
+#[cfg(feature = "std")]
+pub mod key_stats;
+
+#[cfg(feature = "std")]
+pub mod message;
+
+#[cfg(feature = "std")]
+pub mod metrics;
+
+#[cfg(feature = "std")]
+pub mod pause;
+
+#[cfg(feature = "std")]
+pub mod pool;
+
+#[cfg(feature = "std")]
+pub mod record;
+
+#[cfg(feature = "std")]
+pub mod sampling;
+
+#[cfg(feature = "std")]
 pub mod session;
 
-pub mod session_store;
\ No newline at end of file
+#[cfg(feature = "std")]
+mod session_config;
+
+#[cfg(feature = "tower")]
+pub mod session_service;
+
+#[cfg(feature = "std")]
+pub mod session_sink;
+
+#[cfg(feature = "std")]
+pub mod session_store;
+
+#[cfg(feature = "std")]
+mod task;
+
+#[cfg(feature = "std")]
+mod task_backend;
+
+#[cfg(all(test, feature = "std"))]
+mod test_alloc;
+
+#[cfg(feature = "std")]
+pub mod time_anchor;
+
+#[cfg(feature = "std")]
+pub mod timeout_advisor;
+
+#[cfg(feature = "std")]
+pub mod timeout_policy;
+
+#[cfg(feature = "std")]
+pub mod timer_wheel;
+
+pub mod transform;
+
+#[cfg(feature = "std")]
+mod waiter;
+
+#[cfg(feature = "std")]
+pub mod watchdog;
+
+mod rng;
+
+#[cfg(feature = "testing")]
+pub mod testing;
@@ -0,0 +1,193 @@
+use std::{
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bitvec::{slice::BitSlice, vec::BitVec};
+use futures::Stream;
+use pin_project::pin_project;
+
+use crate::{
+    align::find_offset,
+    session_store::DelaySessionStream,
+    transform::{pack_bytes, TransformRegistry},
+};
+
+/// A session's payload, framed by a [`MessageStream`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct FramedMessage {
+    /// Bits decoded before the preamble, e.g. noise preceding the message.
+    pub offset: usize,
+    /// The bytes following the preamble, decoded with the key's transform if
+    /// it has one. A trailing partial byte is dropped.
+    pub payload: Vec<u8>,
+}
+
+/// Frames the bit results of a session store into messages.
+///
+/// Each result is realigned after its best match of a preamble, as by
+/// [`realign`], assembled into bytes and decoded with the transform
+/// registered for its key. Results without a match within `max_errors`
+/// yield `None`, so consumers still learn which keys failed to frame.
+///
+/// [`realign`]: crate::align::realign
+#[derive(Debug)]
+#[pin_project]
+pub struct MessageStream<K, S = DelaySessionStream<K>> {
+    #[pin]
+    results: S,
+    preamble: BitVec,
+    max_errors: usize,
+    transforms: Option<TransformRegistry<K>>,
+}
+
+impl<K, S> MessageStream<K, S> {
+    /// Frames the results of `results` after an exact match of `preamble`.
+    pub fn new(results: S, preamble: &BitSlice) -> Self {
+        Self {
+            results,
+            preamble: preamble.to_bitvec(),
+            max_errors: 0,
+            transforms: None,
+        }
+    }
+
+    /// Tolerates up to `max_errors` flipped bits in the preamble.
+    pub fn max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Decodes each payload with the transform `transforms` holds for its
+    /// key. Transforms registered later apply to the results framed after.
+    pub fn transforms(mut self, transforms: TransformRegistry<K>) -> Self {
+        self.transforms = Some(transforms);
+        self
+    }
+
+    pub fn into_inner(self) -> S {
+        self.results
+    }
+}
+
+impl<K, S> Stream for MessageStream<K, S>
+where
+    K: Eq + Hash,
+    S: Stream<Item = (K, BitVec)>,
+{
+    type Item = (K, Option<FramedMessage>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let Some((key, bits)) = futures::ready!(this.results.poll_next(cx)) else {
+            return Poll::Ready(None);
+        };
+
+        let message = find_offset(&bits, this.preamble, *this.max_errors).map(|offset| {
+            let payload = &bits[offset + this.preamble.len()..];
+            FramedMessage {
+                offset,
+                payload: match this.transforms {
+                    Some(transforms) => transforms.decode(&key, payload),
+                    None => pack_bytes(payload),
+                },
+            }
+        });
+
+        Poll::Ready(Some((key, message)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.results.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bitvec::{bitvec, order::Lsb0, vec::BitVec};
+    use futures::{stream, StreamExt};
+    use tokio::time::sleep;
+
+    use super::{FramedMessage, MessageStream};
+    use crate::{
+        decoder::ThresholdDelayDecoder,
+        encoder::{DelayEncoder, ThresholdDelayEncoder},
+        session_store::delay_session_store,
+        transform::{encode_payload, unpack_bits, RepeatingXor, TransformRegistry},
+    };
+
+    fn preamble() -> BitVec {
+        bitvec![1, 1, 0, 1, 0, 0, 1, 0]
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sessions_are_framed_and_decoded_with_their_key_transform() {
+        let (store, results) = delay_session_store::<u32, BitVec>(Duration::from_secs(1));
+        let transforms = TransformRegistry::new();
+        transforms.register(2, RepeatingXor::new(*b"k"));
+        let mut messages = MessageStream::new(results, &preamble()).transforms(transforms);
+
+        let mut encoder =
+            ThresholdDelayEncoder::new(Duration::from_millis(10), Duration::from_millis(30));
+        let threshold = encoder.threshold();
+        for key in [1, 2] {
+            // Two bits of noise, then the preamble and the payload.
+            let mut bits = bitvec![0, 1];
+            bits.extend_from_bitslice(&preamble());
+            if key == 2 {
+                bits.extend_from_bitslice(&encode_payload(&RepeatingXor::new(*b"k"), b"hi"));
+            } else {
+                bits.extend_from_bitslice(&unpack_bits(b"hi"));
+            }
+
+            let mut instant = store.clock().now();
+            for delay in std::iter::once(Duration::ZERO)
+                .chain(bits.iter().by_vals().map(|bit| encoder.next_delay(bit)))
+            {
+                instant += delay;
+                store
+                    .push_signal(key, instant, move || ThresholdDelayDecoder::new(threshold))
+                    .await
+                    .unwrap();
+            }
+        }
+        sleep(Duration::from_secs(5)).await;
+
+        let mut framed = Vec::new();
+        for _ in 0..2 {
+            framed.push(messages.next().await.unwrap());
+        }
+        framed.sort_unstable_by_key(|(key, _)| *key);
+        let expected = FramedMessage {
+            offset: 2,
+            payload: b"hi".to_vec(),
+        };
+        assert_eq!(framed, [(1, Some(expected.clone())), (2, Some(expected))]);
+    }
+
+    #[tokio::test]
+    async fn results_without_the_preamble_frame_to_none() {
+        let mut flipped = preamble();
+        flipped.set(3, false);
+        let mut bits = flipped.clone();
+        bits.extend_from_bitslice(&bitvec![0, 1, 0, 0, 0, 0, 0, 1]);
+        let results = stream::iter([(1, bits.clone()), (2, bitvec![1, 0, 1])]);
+
+        let framed: Vec<_> = MessageStream::new(results, &preamble()).collect().await;
+        assert_eq!(framed, [(1, None), (2, None)]);
+
+        let results = stream::iter([(1, bits)]);
+        let framed: Vec<_> = MessageStream::new(results, &preamble())
+            .max_errors(1)
+            .collect()
+            .await;
+        let message = FramedMessage {
+            offset: 0,
+            payload: b"A".to_vec(),
+        };
+        assert_eq!(framed, [(1, Some(message))]);
+    }
+}
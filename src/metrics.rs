@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{instant_policy::ImplausibleAction, session_store::PushOutcome};
+
+/// Counters a store maintains about its own operation.
+#[derive(Default, Debug)]
+pub struct StoreMetrics {
+    signals_dropped_full: AtomicU64,
+    signals_dropped_lock_busy: AtomicU64,
+    signals_session_closed: AtomicU64,
+    signals_rejected_implausible: AtomicU64,
+    signals_clamped: AtomicU64,
+    sessions_closed_implausible: AtomicU64,
+    signals_sampled_out: AtomicU64,
+    results_send_blocked: AtomicU64,
+}
+
+impl StoreMetrics {
+    /// Signals dropped by `try_push_signal` because the session channel was full.
+    pub fn signals_dropped_full(&self) -> u64 {
+        self.signals_dropped_full.load(Ordering::Relaxed)
+    }
+
+    /// Signals dropped by `try_push_signal` because the map lock was busy.
+    pub fn signals_dropped_lock_busy(&self) -> u64 {
+        self.signals_dropped_lock_busy.load(Ordering::Relaxed)
+    }
+
+    /// Signals rejected by `try_push_signal` because the session had closed.
+    pub fn signals_session_closed(&self) -> u64 {
+        self.signals_session_closed.load(Ordering::Relaxed)
+    }
+
+    /// Signals dropped because their instant violated the store's
+    /// `InstantPolicy`, including those that closed their session.
+    pub fn signals_rejected_implausible(&self) -> u64 {
+        self.signals_rejected_implausible.load(Ordering::Relaxed)
+    }
+
+    /// Signals whose instant was clamped by the store's `InstantPolicy`.
+    pub fn signals_clamped(&self) -> u64 {
+        self.signals_clamped.load(Ordering::Relaxed)
+    }
+
+    /// Sessions closed because of a signal violating the store's
+    /// `InstantPolicy`.
+    pub fn sessions_closed_implausible(&self) -> u64 {
+        self.sessions_closed_implausible.load(Ordering::Relaxed)
+    }
+
+    /// Signals of sampled keys that did not reach their sessions.
+    pub fn signals_sampled_out(&self) -> u64 {
+        self.signals_sampled_out.load(Ordering::Relaxed)
+    }
+
+    /// Results that had to wait for room in the full result stream, whose
+    /// sessions were held up meanwhile. Only counted with
+    /// `ResultOverflow::Wait`.
+    pub fn results_send_blocked(&self) -> u64 {
+        self.results_send_blocked.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_implausible(&self, action: ImplausibleAction) {
+        match action {
+            ImplausibleAction::Reject => {
+                self.signals_rejected_implausible
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            ImplausibleAction::Clamp => {
+                self.signals_clamped.fetch_add(1, Ordering::Relaxed);
+            }
+            ImplausibleAction::CloseSession => {
+                self.signals_rejected_implausible
+                    .fetch_add(1, Ordering::Relaxed);
+                self.sessions_closed_implausible
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn record_result_send_blocked(&self) {
+        self.results_send_blocked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_push_outcome(&self, outcome: PushOutcome) {
+        let counter = match outcome {
+            PushOutcome::Delivered
+            | PushOutcome::ResultStreamClosed
+            | PushOutcome::RejectedImplausible
+            | PushOutcome::RejectedPaused
+            | PushOutcome::Buffered
+            | PushOutcome::Cancelled
+            | PushOutcome::RejectedSessionLimit => return,
+            PushOutcome::DroppedFull => &self.signals_dropped_full,
+            PushOutcome::DroppedLockBusy => &self.signals_dropped_lock_busy,
+            PushOutcome::SessionClosed => &self.signals_session_closed,
+            PushOutcome::SampledOut => &self.signals_sampled_out,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,24 @@
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch::Receiver;
+
+/// What a paused store does with pushed signals, see
+/// `DelaySessionStore::pause`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum PausedPushes {
+    /// Pushes fail with `PushError::Paused`.
+    Reject,
+    /// Pushes are held until the store resumes, and then delivered at their
+    /// own instants, with the rest of the pause left out of their timeouts.
+    Buffer,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub(crate) struct PauseState {
+    /// When the store was paused, by the store's clock, if it is.
+    pub(crate) paused: Option<(Instant, PausedPushes)>,
+    /// Time the store has spent paused, over all pauses that ended.
+    pub(crate) total: Duration,
+}
+
+pub(crate) type PauseReceiver = Receiver<PauseState>;
@@ -0,0 +1,176 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    hash::{BuildHasher, RandomState},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::decoder::{DelayDecoder, MetaDelayDecoder, ReusableDelayDecoder};
+
+/// A bounded pool of reset decoders, reused across session churn so their
+/// buffers are not reallocated for every new session.
+///
+/// Free decoders are kept in per-shard lists, picked by the current thread,
+/// so the pool does not become a single point of contention. Pooling never
+/// changes decode results: a pooled decoder is reset exactly like a new one.
+pub struct DecoderPool<D> {
+    shards: Box<[Mutex<Vec<D>>]>,
+    shard_capacity: usize,
+    hasher: RandomState,
+    make_decoder: Box<dyn Fn() -> D + Send + Sync>,
+    created: AtomicU64,
+    reused: AtomicU64,
+    discarded: AtomicU64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct PoolStats {
+    pub created: u64,
+    pub reused: u64,
+    pub discarded: u64,
+}
+
+impl<D> DecoderPool<D>
+where
+    D: ReusableDelayDecoder + Send,
+{
+    /// Creates a pool holding at most `capacity` free decoders spread over
+    /// `shards` free lists, making new decoders with `make_decoder`.
+    pub fn new(
+        shards: usize,
+        capacity: usize,
+        make_decoder: impl Fn() -> D + Send + Sync + 'static,
+    ) -> Arc<Self> {
+        let shards = shards.max(1);
+
+        Arc::new(Self {
+            shards: (0..shards).map(|_| Mutex::new(Vec::new())).collect(),
+            shard_capacity: capacity.div_ceil(shards),
+            hasher: RandomState::new(),
+            make_decoder: Box::new(make_decoder),
+            created: AtomicU64::new(0),
+            reused: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns a decoder factory for use with a store.
+    pub fn factory(self: &Arc<Self>) -> impl FnMut() -> PooledDecoder<D> + Clone + Send + Sync {
+        let pool = self.clone();
+        move || pool.get()
+    }
+
+    pub fn get(self: &Arc<Self>) -> PooledDecoder<D> {
+        let decoder = self
+            .shard()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+
+        let decoder = match decoder {
+            Some(decoder) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                decoder
+            }
+            None => {
+                self.created.fetch_add(1, Ordering::Relaxed);
+                (self.make_decoder)()
+            }
+        };
+
+        PooledDecoder {
+            decoder,
+            pool: self.clone(),
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            created: self.created.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+        }
+    }
+
+    fn put(&self, decoder: D) {
+        let mut free = self.shard().lock().unwrap_or_else(PoisonError::into_inner);
+        if free.len() < self.shard_capacity {
+            free.push(decoder);
+        } else {
+            self.discarded.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn shard(&self) -> &Mutex<Vec<D>> {
+        let index = self.hasher.hash_one(thread::current().id()) as usize % self.shards.len();
+        &self.shards[index]
+    }
+}
+
+impl<D> Debug for DecoderPool<D> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecoderPool")
+            .field("shards", &self.shards.len())
+            .field("shard_capacity", &self.shard_capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A decoder borrowed from a [`DecoderPool`], returned to it on close.
+pub struct PooledDecoder<D>
+where
+    D: ReusableDelayDecoder + Send,
+{
+    decoder: D,
+    pool: Arc<DecoderPool<D>>,
+}
+
+impl<D> Debug for PooledDecoder<D>
+where
+    D: ReusableDelayDecoder + Send + Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledDecoder")
+            .field("decoder", &self.decoder)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<D> DelayDecoder for PooledDecoder<D>
+where
+    D: ReusableDelayDecoder + Send,
+{
+    type Output = D::Output;
+
+    fn push_duration(&mut self, duration: Duration) {
+        self.decoder.push_duration(duration);
+    }
+
+    fn close(mut self) -> D::Output {
+        let bits = self.decoder.close_and_reset();
+        self.pool.put(self.decoder);
+        bits
+    }
+
+    fn snapshot(&self) -> Option<D::Output> {
+        self.decoder.snapshot()
+    }
+
+    fn decision_threshold(&self) -> Option<Duration> {
+        self.decoder.decision_threshold()
+    }
+}
+
+impl<D, M> MetaDelayDecoder<M> for PooledDecoder<D>
+where
+    D: ReusableDelayDecoder + MetaDelayDecoder<M> + Send,
+    M: ?Sized,
+{
+    fn push_duration_with(&mut self, duration: Duration, meta: &M) {
+        self.decoder.push_duration_with(duration, meta);
+    }
+}
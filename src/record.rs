@@ -0,0 +1,333 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    hash::Hash,
+    io, mem,
+    time::{Duration, Instant},
+};
+
+use futures::{future::join, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, StreamExt};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    decoder::{DelayDecoder, MetaDelayDecoder, ScaledDelayDecoder},
+    session_store::{delay_session_store, DelaySessionStore},
+};
+
+/// Copies every signal pushed into a store to a [`SignalRecorder`].
+///
+/// The tap never waits on the recorder: its queue is unbounded, so a recorder
+/// that cannot keep up grows memory rather than slowing pushes down.
+#[derive(Debug)]
+pub struct SignalTap<K> {
+    sender: UnboundedSender<(K, Instant)>,
+}
+
+impl<K> SignalTap<K> {
+    pub(crate) fn record(&self, key: K, instant: Instant) {
+        let _ = self.sender.send((key, instant));
+    }
+}
+
+/// Writes the signals copied by a [`SignalTap`] as records of a little-endian
+/// `u32` length, an `i64` offset in nanoseconds from `anchor`, and the
+/// encoded key.
+#[derive(Debug)]
+pub struct SignalRecorder<K> {
+    receiver: UnboundedReceiver<(K, Instant)>,
+    anchor: Instant,
+}
+
+/// Creates a tap to install on a store with `DelaySessionStoreBuilder::tap`
+/// and the recorder writing what it copies, with offsets from `anchor`.
+pub fn signal_recorder<K>(anchor: Instant) -> (SignalTap<K>, SignalRecorder<K>) {
+    let (sender, receiver) = unbounded_channel();
+    (SignalTap { sender }, SignalRecorder { receiver, anchor })
+}
+
+impl<K> SignalRecorder<K> {
+    /// Writes records until the store holding the tap is dropped, returning
+    /// the number of records written.
+    pub async fn run<W>(
+        mut self,
+        mut writer: W,
+        mut encode_key: impl FnMut(&K, &mut Vec<u8>),
+    ) -> io::Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut record = Vec::new();
+        let mut written = 0;
+
+        while let Some((key, instant)) = self.receiver.recv().await {
+            record.clear();
+            record.extend_from_slice(&[0; 4]);
+            record.extend_from_slice(&offset_nanos(self.anchor, instant).to_le_bytes());
+            encode_key(&key, &mut record);
+
+            let len = u32::try_from(record.len() - 4)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "key too large"))?;
+            record[..4].copy_from_slice(&len.to_le_bytes());
+
+            writer.write_all(&record).await?;
+            written += 1;
+        }
+
+        writer.flush().await?;
+        Ok(written)
+    }
+}
+
+/// How fast recorded signals are replayed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReplaySpeed {
+    /// Every signal is pushed as soon as the previous push completes.
+    AsFastAsPossible,
+    /// Signals are pushed with their recorded spacing.
+    RealTime,
+    /// Signals are pushed with their recorded spacing divided by the factor.
+    Scaled(f64),
+}
+
+/// Reads records written by a [`SignalRecorder`] and calls `push` with each
+/// key and its reconstructed instant, paced by `speed`.
+///
+/// Instants are reconstructed relative to the current Tokio time, keeping
+/// their recorded spacing whatever the speed, so decoders see the original
+/// durations. Only the pacing of the pushes changes. Returns the number of
+/// records replayed.
+pub async fn replay_with<R, K>(
+    mut reader: R,
+    mut decode_key: impl FnMut(&[u8]) -> io::Result<K>,
+    speed: ReplaySpeed,
+    mut push: impl FnMut(K, Instant),
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut replayer = Replayer::new(speed);
+    while let Some((offset, key)) = read_record(&mut reader, &mut decode_key).await? {
+        let (instant, _) = replayer.pace(offset).await;
+        push(key, instant);
+    }
+
+    Ok(replayer.replayed)
+}
+
+/// Like [`replay_with`], pushing every signal into `store`.
+///
+/// The store times sessions out on Tokio time, so instants are reconstructed
+/// from the pacing instead of the recorded spacing: at `Scaled` speeds the
+/// store sees every duration divided by the factor, and its timeout should be
+/// divided to match. `RealTime` reproduces the recording exactly. For
+/// `AsFastAsPossible`, use [`replay_offline`].
+pub async fn replay_into<R, K, T, D>(
+    mut reader: R,
+    mut decode_key: impl FnMut(&[u8]) -> io::Result<K>,
+    speed: ReplaySpeed,
+    store: &DelaySessionStore<K, T, D::Output>,
+    decoder_factory: impl FnMut() -> D + Clone + Send + 'static,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    K: Clone + Eq + Hash + Send + 'static,
+    T: Send + 'static,
+    D: MetaDelayDecoder<()> + Send + 'static,
+    D::Output: Clone + Send + 'static,
+{
+    let mut replayer = Replayer::new(speed);
+    while let Some((offset, key)) = read_record(&mut reader, &mut decode_key).await? {
+        let (_, instant) = replayer.pace(offset).await;
+        store
+            .push_signal(key, instant, decoder_factory.clone())
+            .await
+            .map_err(io::Error::other)?;
+    }
+
+    Ok(replayer.replayed)
+}
+
+/// Replays a recording `factor` times faster than real time into a store of
+/// its own and returns the store's results.
+///
+/// The store times out after `timeout_duration` divided by `factor`, and its
+/// decoders see durations multiplied back by `factor`, so they decode as they
+/// would have in real time.
+///
+/// # Panics
+///
+/// Panics if `factor` is not finite and positive.
+pub async fn replay_dilated<R, K, D>(
+    reader: R,
+    decode_key: impl FnMut(&[u8]) -> io::Result<K>,
+    factor: f64,
+    timeout_duration: Duration,
+    mut decoder_factory: impl FnMut() -> D + Clone + Send + 'static,
+) -> io::Result<Vec<(K, D::Output)>>
+where
+    R: AsyncRead + Unpin,
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    D: MetaDelayDecoder<()> + Send + 'static,
+    D::Output: Clone + Send + 'static,
+{
+    assert!(
+        factor.is_finite() && factor > 0.0,
+        "scale factor must be finite and positive"
+    );
+
+    let (store, stream) = delay_session_store(timeout_duration.div_f64(factor));
+    let replay = async {
+        let replayed = replay_into(
+            reader,
+            decode_key,
+            ReplaySpeed::Scaled(factor),
+            &store,
+            move || ScaledDelayDecoder::new(decoder_factory(), factor),
+        )
+        .await;
+        drop(store);
+        replayed
+    };
+
+    let (replayed, results) = join(replay, stream.collect::<Vec<_>>()).await;
+    replayed.map(|_| results)
+}
+
+/// Decodes a recording as fast as possible without a store, splitting
+/// sessions wherever a key goes `timeout_duration` without a signal, as a
+/// store with that timeout would have in real time.
+///
+/// Results are returned in the order sessions close; sessions still open at
+/// the end of the recording close last.
+pub async fn replay_offline<R, K, D>(
+    mut reader: R,
+    mut decode_key: impl FnMut(&[u8]) -> io::Result<K>,
+    timeout_duration: Duration,
+    mut decoder_factory: impl FnMut() -> D,
+) -> io::Result<Vec<(K, D::Output)>>
+where
+    R: AsyncRead + Unpin,
+    K: Clone + Eq + Hash,
+    D: DelayDecoder,
+{
+    let mut sessions: HashMap<K, (D, i64)> = HashMap::new();
+    let mut results = Vec::new();
+
+    while let Some((offset, key)) = read_record(&mut reader, &mut decode_key).await? {
+        match sessions.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let (decoder, last_offset) = entry.get_mut();
+                let since_last = nanos_between(*last_offset, offset);
+                *last_offset = offset;
+
+                if since_last >= timeout_duration {
+                    let decoder = mem::replace(decoder, decoder_factory());
+                    results.push((entry.key().clone(), decoder.close()));
+                } else {
+                    decoder.push_duration(since_last);
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((decoder_factory(), offset));
+            }
+        }
+    }
+
+    results.extend(
+        sessions
+            .into_iter()
+            .map(|(key, (decoder, _))| (key, decoder.close())),
+    );
+    Ok(results)
+}
+
+struct Replayer {
+    speed: ReplaySpeed,
+    start: Option<(i64, tokio::time::Instant)>,
+    replayed: u64,
+}
+
+impl Replayer {
+    const fn new(speed: ReplaySpeed) -> Self {
+        Self {
+            speed,
+            start: None,
+            replayed: 0,
+        }
+    }
+
+    /// Waits until the record at `offset` is due, returning its instant with
+    /// the recorded spacing and the instant it was due at.
+    async fn pace(&mut self, offset: i64) -> (Instant, Instant) {
+        let (first, start) = *self
+            .start
+            .get_or_insert_with(|| (offset, tokio::time::Instant::now()));
+        let since_first = i128::from(offset) - i128::from(first);
+        self.replayed += 1;
+
+        let delay = Duration::from_nanos(u64::try_from(since_first).unwrap_or(0));
+        let due = match self.speed {
+            ReplaySpeed::AsFastAsPossible => Duration::ZERO,
+            ReplaySpeed::RealTime => delay,
+            ReplaySpeed::Scaled(factor) if factor > 0.0 => {
+                Duration::from_secs_f64(delay.as_secs_f64() / factor)
+            }
+            ReplaySpeed::Scaled(_) => Duration::ZERO,
+        };
+        tokio::time::sleep_until(start + due).await;
+
+        let start = start.into_std();
+        let magnitude = Duration::from_nanos(since_first.unsigned_abs() as u64);
+        let recorded = if since_first >= 0 {
+            start.checked_add(magnitude)
+        } else {
+            start.checked_sub(magnitude)
+        }
+        .unwrap_or(start);
+        (recorded, start + due)
+    }
+}
+
+async fn read_record<R, K>(
+    reader: &mut R,
+    decode_key: &mut impl FnMut(&[u8]) -> io::Result<K>,
+) -> io::Result<Option<(i64, K)>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len < 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "record shorter than its offset",
+        ));
+    }
+
+    let mut record = vec![0; len];
+    reader.read_exact(&mut record).await?;
+    let (offset, key) = record.split_at(8);
+    let offset = i64::from_le_bytes(offset.try_into().map_err(io::Error::other)?);
+
+    Ok(Some((offset, decode_key(key)?)))
+}
+
+/// Duration from offset `from` to offset `to`, zero if `to` is earlier.
+fn nanos_between(from: i64, to: i64) -> Duration {
+    let nanos = i128::from(to) - i128::from(from);
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(0))
+}
+
+/// Nanoseconds from `anchor` to `instant`, saturating at the `i64` range.
+fn offset_nanos(anchor: Instant, instant: Instant) -> i64 {
+    match instant.checked_duration_since(anchor) {
+        Some(after) => i64::try_from(after.as_nanos()).unwrap_or(i64::MAX),
+        None => i64::try_from(anchor.duration_since(instant).as_nanos())
+            .map_or(i64::MIN, |before| -before),
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Span over which a key's rate is compared to `SamplingConfig::rate_threshold`.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Configuration of a store's per-key sampling, see
+/// `DelaySessionStoreBuilder::sampling`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct SamplingConfig {
+    /// Signals per second a key may send before it is sampled.
+    pub rate_threshold: u64,
+    /// Past the threshold, only every `factor`-th signal reaches the key's
+    /// session.
+    pub factor: u32,
+    /// Keys tracked at once. Past it, the least recently pushed keys are
+    /// evicted, and start over unsampled.
+    pub max_keys: usize,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            rate_threshold: 1_000,
+            factor: 10,
+            max_keys: 10_000,
+        }
+    }
+}
+
+/// A result of a store that samples chatty keys, see
+/// `DelaySessionStoreBuilder::sampling`.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Sampled<T> {
+    pub result: T,
+    /// The sampling factor the key's latest signal was subject to, or `1` if
+    /// it was below the rate threshold. Durations decoded from sampled
+    /// signals span `factor` signals each.
+    pub factor: u32,
+}
+
+#[derive(Debug)]
+struct KeyRate {
+    window_start: Instant,
+    in_window: u64,
+    /// Signals past the threshold, which the sampling counts off.
+    over_threshold: u64,
+    sampling: bool,
+    touched: u64,
+}
+
+#[derive(Debug)]
+struct Rates<K> {
+    rates: HashMap<K, KeyRate>,
+    by_touch: BTreeMap<u64, K>,
+    touches: u64,
+}
+
+/// Decides which signals of chatty keys reach their sessions. A key is only
+/// sampled once it has sent more than `rate_threshold` signals since the
+/// start of its current one-second window, so keys below the threshold are
+/// never sampled.
+#[derive(Debug)]
+pub(crate) struct Sampler<K> {
+    config: SamplingConfig,
+    rates: Mutex<Rates<K>>,
+}
+
+impl<K> Sampler<K> {
+    /// # Panics
+    ///
+    /// Panics if `config.factor` is zero.
+    pub(crate) fn new(config: SamplingConfig) -> Self {
+        assert!(config.factor > 0, "sampling factor must be non-zero");
+
+        Self {
+            config,
+            rates: Mutex::new(Rates {
+                rates: HashMap::new(),
+                by_touch: BTreeMap::new(),
+                touches: 0,
+            }),
+        }
+    }
+}
+
+impl<K> Sampler<K>
+where
+    K: Eq + Hash,
+{
+    /// Counts a signal of `key` and returns whether it should reach the
+    /// key's session.
+    pub(crate) fn admit<Q>(&self, key: &Q, to_owned: impl Fn(&Q) -> K, instant: Instant) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.config.max_keys == 0 {
+            return true;
+        }
+
+        let mut rates = self.rates.lock().unwrap_or_else(PoisonError::into_inner);
+        let Rates {
+            rates,
+            by_touch,
+            touches,
+        } = &mut *rates;
+        *touches += 1;
+
+        let Some(rate) = rates.get_mut(key) else {
+            if rates.len() == self.config.max_keys {
+                if let Some((_, evicted)) = by_touch.pop_first() {
+                    rates.remove::<K>(&evicted);
+                }
+            }
+            by_touch.insert(*touches, to_owned(key));
+            rates.insert(
+                to_owned(key),
+                KeyRate {
+                    window_start: instant,
+                    in_window: 1,
+                    over_threshold: 0,
+                    sampling: false,
+                    touched: *touches,
+                },
+            );
+            return true;
+        };
+
+        let owned = by_touch
+            .remove(&rate.touched)
+            .unwrap_or_else(|| to_owned(key));
+        by_touch.insert(*touches, owned);
+        rate.touched = *touches;
+
+        if instant.saturating_duration_since(rate.window_start) >= RATE_WINDOW {
+            rate.window_start = instant;
+            rate.in_window = 0;
+        }
+        rate.in_window += 1;
+
+        rate.sampling = rate.in_window > self.config.rate_threshold;
+        if !rate.sampling {
+            return true;
+        }
+        let admitted = rate.over_threshold % u64::from(self.config.factor) == 0;
+        rate.over_threshold += 1;
+        admitted
+    }
+
+    /// The factor `key`'s latest signal was sampled with, or `1`.
+    pub(crate) fn factor(&self, key: &K) -> u32 {
+        let rates = self.rates.lock().unwrap_or_else(PoisonError::into_inner);
+        match rates.rates.get(key) {
+            Some(rate) if rate.sampling => self.config.factor,
+            _ => 1,
+        }
+    }
+}
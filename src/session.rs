@@ -1,43 +1,255 @@
 use std::{
+    fmt::{self, Debug, Formatter},
     future::Future,
-    pin::Pin,
+    pin::{pin, Pin},
+    sync::Arc,
     task::{Context, Poll},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use bitvec::vec::BitVec;
+use futures::{
+    future::{select, Either, FusedFuture},
+    Stream,
+};
 use pin_project::pin_project;
-use tokio::{
-    sync::mpsc::{channel, Receiver, Sender},
-    time::{sleep_until, Sleep},
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+#[cfg(feature = "tokio-util")]
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    clock::{Clock, ClockSleep, TokioClock},
+    decoder::{DelayDecoder, MetaDelayDecoder, StreamingDelayDecoder},
+    instant_policy::{OutOfOrderPolicy, SignalSequencer},
+    instrument,
 };
 
-use crate::decoder::DelayDecoder;
+pub type SignalSender<M = ()> = Sender<Signal<M>>;
+pub type SignalReceiver<M = ()> = Receiver<Signal<M>>;
+
+/// Capacity of the channel `signal_channel` creates.
+pub(crate) const DEFAULT_SIGNAL_CAPACITY: usize = 8;
+
+pub fn signal_channel<M>() -> (SignalSender<M>, SignalReceiver<M>) {
+    signal_channel_with_capacity(DEFAULT_SIGNAL_CAPACITY)
+}
+
+/// Like `signal_channel`, holding up to `capacity` signals not yet taken in
+/// before sends wait.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn signal_channel_with_capacity<M>(capacity: usize) -> (SignalSender<M>, SignalReceiver<M>) {
+    assert!(capacity >= 1, "signal channel capacity must be positive");
+    channel(capacity)
+}
 
-pub type SignalSender = Sender<Signal>;
-pub type SignalReceiver = Receiver<Signal>;
+/// Returns `instant + timeout_duration`, falling back to a deadline roughly
+/// thirty years out when the sum is not representable, instead of panicking.
+pub fn timeout_instant(instant: Instant, timeout_duration: Duration) -> Instant {
+    const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
 
-pub fn signal_channel() -> (SignalSender, SignalReceiver) {
-    channel(8)
+    instant
+        .checked_add(timeout_duration)
+        .or_else(|| instant.checked_add(FAR_FUTURE))
+        .unwrap_or(instant)
 }
 
+/// A signal to a session, carrying metadata `M`, e.g. the method or path of
+/// the HTTP request, which is pushed to the decoder along with the duration
+/// the signal ends.
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
-pub struct Signal {
+pub struct Signal<M = ()> {
     pub instant: Instant,
     pub timeout_instant: Instant,
+    pub meta: M,
+    pub kind: SignalKind,
+}
+
+impl<M> Signal<M> {
+    /// A data signal.
+    pub const fn new(instant: Instant, timeout_instant: Instant, meta: M) -> Self {
+        Self {
+            instant,
+            timeout_instant,
+            meta,
+            kind: SignalKind::Data,
+        }
+    }
+
+    /// A keepalive signal, whose metadata no decoder sees.
+    pub fn keepalive(instant: Instant, timeout_instant: Instant) -> Self
+    where
+        M: Default,
+    {
+        Self {
+            instant,
+            timeout_instant,
+            meta: M::default(),
+            kind: SignalKind::Keepalive,
+        }
+    }
+
+    /// A signal pausing its session at `instant`. Its `timeout_instant` is
+    /// unused, and its metadata no decoder sees.
+    pub fn pause(instant: Instant) -> Self
+    where
+        M: Default,
+    {
+        Self {
+            instant,
+            timeout_instant: instant,
+            meta: M::default(),
+            kind: SignalKind::Pause,
+        }
+    }
+
+    /// A signal resuming its paused session at `instant`, like
+    /// [`pause`](Self::pause).
+    pub fn resume(instant: Instant) -> Self
+    where
+        M: Default,
+    {
+        Self {
+            instant,
+            timeout_instant: instant,
+            meta: M::default(),
+            kind: SignalKind::Resume,
+        }
+    }
+}
+
+/// Whether a signal carries data or only keeps its session open.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum SignalKind {
+    /// The signal ends a duration pushed to the decoder.
+    #[default]
+    Data,
+    /// The signal extends the session's timeout, e.g. for a heartbeat
+    /// request, without ending a duration. It never starts a session, and
+    /// one at or after the deadline closes the session as timed out.
+    Keepalive,
+    /// The signal pauses the session, e.g. for a maintenance window: its
+    /// timeout stops until a `Resume`, and data signals arriving meanwhile
+    /// are held, up to the session's `pause_buffer`, to be taken in on
+    /// resume, without moving the re-armed deadline. They keep the gaps
+    /// between them, moved so the latest lands on the resume, and the
+    /// duration spanning the pause ends at the earliest. Keepalives are
+    /// ignored meanwhile.
+    /// One at or after the deadline closes the session as timed out.
+    Pause,
+    /// The signal resumes a paused session, re-arming its timeout with the
+    /// time that was left, and measuring the duration spanning the pause as
+    /// the session's `PauseGapPolicy` says. Sessions that are not paused
+    /// ignore it.
+    Resume,
+}
+
+/// What a keepalive does to the durations of its session.
+///
+/// By default nothing: the next data signal's duration still spans back to
+/// the previous data signal, so keepalives never change the decoded output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum KeepalivePolicy {
+    /// The next data signal's duration is measured from the previous data
+    /// signal, as if the keepalive was never sent.
+    #[default]
+    Transparent,
+    /// The next data signal's duration is measured from the keepalive
+    /// instead.
+    RestartDuration,
+}
+
+/// How a session measures the duration spanning a pause, from the last
+/// data signal before the pause to the first one after it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum PauseGapPolicy {
+    /// The pause is left out: the duration is its part before the pause
+    /// plus its part after the resume, as if the pause never happened.
+    #[default]
+    Exclude,
+    /// The duration is clamped to its part before the pause, so the time
+    /// between the resume and the next data signal is not counted either.
+    Clamp,
+}
+
+/// Why a session closed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum CloseReason {
+    /// No signal arrived before the deadline, that of the timeout or of the
+    /// `max_lifetime`.
+    Timeout,
+    /// A signal arrived at or after the deadline.
+    TerminatingSignal,
+    /// Every sender of the session's channel was dropped, e.g. with its
+    /// store.
+    SenderDropped,
+    /// The session was closed early on request, with `DelaySession::close`
+    /// or `DelaySessionStore::flush`, or by its store for an implausible
+    /// instant or a conflicting migration.
+    Flushed,
+    /// The session was cancelled.
+    Cancelled,
+    /// The session took in its `max_durations`.
+    LimitReached,
+    /// The session was closed to make room for a new key's, see
+    /// `DelaySessionStoreBuilder::max_sessions`.
+    Evicted,
+}
+
+/// How a session went, once it closed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct SessionSummary {
+    pub close_reason: CloseReason,
+    /// The instant of the signal that started the session.
+    pub started_at: Instant,
+    /// When the session closed, by its clock.
+    pub closed_at: Instant,
+    /// Signals the session took in, including the one that started it.
+    pub signal_count: u64,
+}
+
+/// Whether a session pushes the gap that ended it to its decoder as a final
+/// duration, e.g. for a protocol ending on a long terminating delay.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum TerminalDuration {
+    /// The gap is dropped.
+    #[default]
+    Discard,
+    /// A signal at or after the deadline pushes the duration from the latest
+    /// signal taken in to its own instant, with its metadata. Timeouts push
+    /// nothing.
+    Signal,
+    /// Like `Signal`, and a timeout pushes the duration from the latest
+    /// signal taken in to the deadline, that of the timeout or of the
+    /// `max_lifetime`, whichever came first, with `()` as metadata. It does
+    /// not depend on how late the timer fired.
+    SignalOrTimeout,
+}
+
+impl TerminalDuration {
+    pub(crate) const fn on_signal(self) -> bool {
+        matches!(self, Self::Signal | Self::SignalOrTimeout)
+    }
+
+    pub(crate) const fn on_timeout(self) -> bool {
+        matches!(self, Self::SignalOrTimeout)
+    }
 }
 
 #[derive(Debug)]
 #[pin_project]
-pub struct DelaySession<D> {
+pub struct DelaySession<D, M = ()> {
     #[pin]
-    inner: DelaySessionInner<D>,
+    inner: DelaySessionInner<D, M>,
 }
 
-impl<D> DelaySession<D> {
+impl<D, M> DelaySession<D, M> {
     pub fn new(
         decoder: D,
-        receiver: SignalReceiver,
+        receiver: SignalReceiver<M>,
         start_instant: Instant,
         timeout_instant: Instant,
     ) -> Self {
@@ -45,85 +257,741 @@ impl<D> DelaySession<D> {
             inner: DelaySessionInner::Open {
                 decoder,
                 receiver,
-                last_signal_instant: start_instant,
-                timeout_sleep: sleep_until(timeout_instant.into()),
+                sequencer: SignalSequencer::new(OutOfOrderPolicy::default(), start_instant),
+                timeout: Timeout::new(timeout_instant),
+                started: start_instant,
+                signals: 1,
+                durations_left: None,
+                lifetime_deadline: None,
+                terminal: TerminalDuration::Discard,
+                keepalive: KeepalivePolicy::Transparent,
+                pause_gap: PauseGapPolicy::Exclude,
+                pause_buffer: DEFAULT_SIGNAL_CAPACITY,
+                paused: None,
+                cancel: CancelSignal(None),
             },
         }
     }
 
-    pub fn start_with_receiver(decoder: D, mut receiver: SignalReceiver) -> Self {
-        match receiver.try_recv() {
-            Ok(signal) => Self {
-                inner: DelaySessionInner::Open {
-                    decoder,
-                    receiver,
-                    last_signal_instant: signal.instant,
-                    timeout_sleep: sleep_until(signal.timeout_instant.into()),
-                },
-            },
+    /// Starts a session with the first data signal queued in `receiver`,
+    /// skipping keepalives, pauses and resumes before it, or returns a closed
+    /// session if there is none.
+    pub fn start_with_receiver(decoder: D, mut receiver: SignalReceiver<M>) -> Self {
+        let first = loop {
+            match receiver.try_recv() {
+                Ok(signal) if signal.kind != SignalKind::Data => {}
+                first => break first,
+            }
+        };
+        match first {
+            Ok(signal) => Self::new(decoder, receiver, signal.instant, signal.timeout_instant),
             Err(_) => Self {
-                inner: DelaySessionInner::Closed,
+                inner: DelaySessionInner::Closed { summary: None },
+            },
+        }
+    }
+
+    /// Like [`start_with_receiver`](Self::start_with_receiver), but waits up
+    /// to `grace` on `clock` for the first data signal if none is queued
+    /// yet, so one sent while the previous session on `receiver` was
+    /// wrapping up still starts a session. A signal queued by the time the
+    /// grace runs out, even exactly then, starts one, with a decoder from
+    /// `decoder_factory` created only then. Returns a closed session right
+    /// away once every sender is gone.
+    pub async fn start_with_receiver_graceful(
+        decoder_factory: impl FnOnce() -> D,
+        mut receiver: SignalReceiver<M>,
+        grace: Duration,
+        clock: &dyn Clock,
+    ) -> Self {
+        let deadline = clock.sleep_until(timeout_instant(clock.now(), grace));
+        let first = {
+            let first = pin!(async {
+                loop {
+                    match receiver.recv().await {
+                        Some(signal) if signal.kind != SignalKind::Data => {}
+                        first => return first,
+                    }
+                }
+            });
+            // Polls the receiver first, so a signal queued as the grace runs
+            // out is still taken.
+            match select(first, deadline).await {
+                Either::Left((first, _)) => first,
+                Either::Right(((), _)) => None,
+            }
+        };
+        match first {
+            Some(signal) => Self::new(
+                decoder_factory(),
+                receiver,
+                signal.instant,
+                signal.timeout_instant,
+            ),
+            None => Self {
+                inner: DelaySessionInner::Closed { summary: None },
             },
         }
     }
 
+    /// Closes the session, as if it timed out, once it took in
+    /// `max_durations` durations, so a client cannot keep it open
+    /// forever by signalling just before every timeout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_durations` is zero.
+    pub fn max_durations(mut self, max_durations: usize) -> Self {
+        assert!(max_durations > 0, "duration limit must be positive");
+        if let DelaySessionInner::Open { durations_left, .. } = &mut self.inner {
+            *durations_left = Some(max_durations);
+        }
+        self
+    }
+
+    /// Closes the session `max_lifetime` after it started, however recently
+    /// it was signalled. Timeouts are cut short to this deadline, and a
+    /// signal at or after it closes the session like a signal after a
+    /// timeout does.
+    pub fn max_lifetime(mut self, max_lifetime: Duration) -> Self {
+        if let DelaySessionInner::Open {
+            sequencer,
+            timeout,
+            lifetime_deadline,
+            ..
+        } = &mut self.inner
+        {
+            let deadline = timeout_instant(sequencer.latest(), max_lifetime);
+            *lifetime_deadline = Some(deadline);
+            if deadline < timeout.deadline {
+                timeout.reset(deadline);
+            }
+        }
+        self
+    }
+
+    /// Times the session out by `clock` instead of Tokio's clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        if let DelaySessionInner::Open { timeout, .. } = &mut self.inner {
+            timeout.clock = clock;
+            timeout.sleep = None;
+        }
+        self
+    }
+
+    /// Handles keepalive signals as `policy` says, instead of leaving
+    /// durations untouched.
+    pub fn keepalive_policy(mut self, policy: KeepalivePolicy) -> Self {
+        if let DelaySessionInner::Open { keepalive, .. } = &mut self.inner {
+            *keepalive = policy;
+        }
+        self
+    }
+
+    /// Measures the duration spanning a pause as `policy` says, instead of
+    /// leaving the pause out.
+    pub fn pause_gap_policy(mut self, policy: PauseGapPolicy) -> Self {
+        if let DelaySessionInner::Open { pause_gap, .. } = &mut self.inner {
+            *pause_gap = policy;
+        }
+        self
+    }
+
+    /// Holds up to `capacity` data signals arriving while the session is
+    /// paused, instead of as many as a default signal channel. Later ones
+    /// are dropped.
+    pub fn pause_buffer(mut self, capacity: usize) -> Self {
+        if let DelaySessionInner::Open { pause_buffer, .. } = &mut self.inner {
+            *pause_buffer = capacity;
+        }
+        self
+    }
+
+    /// Pushes the gap that ends the session to its decoder as `terminal`
+    /// says. Sessions closed any other way, e.g. by `max_durations` or
+    /// `close`, push nothing.
+    pub fn terminal_duration(mut self, terminal: TerminalDuration) -> Self {
+        if let DelaySessionInner::Open {
+            terminal: current, ..
+        } = &mut self.inner
+        {
+            *current = terminal;
+        }
+        self
+    }
+
+    /// Closes the session as soon as `token` is cancelled, without taking in
+    /// the signals still queued, so it returns what was decoded so far.
+    #[cfg(feature = "tokio-util")]
+    pub fn cancel_on(self, token: CancellationToken) -> Self {
+        self.cancel_when(token.cancelled_owned())
+    }
+
+    /// Like `cancel_on`, closing the session once `cancelled` completes.
+    pub(crate) fn cancel_when(
+        mut self,
+        cancelled: impl Future<Output = ()> + Send + 'static,
+    ) -> Self {
+        if let DelaySessionInner::Open { cancel, .. } = &mut self.inner {
+            cancel.0 = Some(Box::pin(cancelled));
+        }
+        self
+    }
+
+    /// Handles signals arriving out of order as `policy` says, instead of
+    /// saturating their durations to zero.
+    pub fn out_of_order(mut self, policy: OutOfOrderPolicy) -> Self {
+        if let DelaySessionInner::Open { sequencer, .. } = &mut self.inner {
+            sequencer.set_policy(policy);
+        }
+        self
+    }
+
+    /// Drops data signals within `gap` of the latest signal, such as a
+    /// prefetch racing its request, before they end a duration or extend
+    /// the timeout. Signals held during a pause are taken in regardless.
+    pub fn min_signal_gap(mut self, gap: Duration) -> Self {
+        if let DelaySessionInner::Open { sequencer, .. } = &mut self.inner {
+            sequencer.set_min_gap(gap);
+        }
+        self
+    }
+
     pub const fn is_open(&self) -> bool {
         matches!(self.inner, DelaySessionInner::Open { .. })
     }
+
+    /// Whether the session is paused by a `SignalKind::Pause`.
+    pub const fn is_paused(&self) -> bool {
+        matches!(
+            self.inner,
+            DelaySessionInner::Open {
+                paused: Some(_),
+                ..
+            }
+        )
+    }
+
+    /// Signals the session has taken in, including the one that started it,
+    /// or `None` if it closed.
+    pub const fn signal_count(&self) -> Option<u64> {
+        match &self.inner {
+            DelaySessionInner::Open { signals, .. } => Some(*signals),
+            DelaySessionInner::Closed { .. } => None,
+        }
+    }
+
+    /// When the latest signal the session took in arrived, or `None` if it
+    /// closed.
+    pub fn last_signal_instant(&self) -> Option<Instant> {
+        match &self.inner {
+            DelaySessionInner::Open { sequencer, .. } => Some(sequencer.latest()),
+            DelaySessionInner::Closed { .. } => None,
+        }
+    }
+
+    /// When the session times out unless another signal arrives, or `None`
+    /// if it closed.
+    pub fn deadline(&self) -> Option<Instant> {
+        match &self.inner {
+            DelaySessionInner::Open { timeout, .. } => Some(timeout.deadline),
+            DelaySessionInner::Closed { .. } => None,
+        }
+    }
+
+    /// How the session went, once it closed.
+    pub const fn summary(&self) -> Option<SessionSummary> {
+        match self.inner {
+            DelaySessionInner::Closed { summary } => summary,
+            DelaySessionInner::Open { .. } => None,
+        }
+    }
+
+    /// Whether the session closed because it reached its `max_durations`.
+    pub const fn reached_duration_limit(&self) -> bool {
+        matches!(
+            self.summary(),
+            Some(SessionSummary {
+                close_reason: CloseReason::LimitReached,
+                ..
+            })
+        )
+    }
+
+    /// Whether the session closed because it was cancelled.
+    pub const fn was_cancelled(&self) -> bool {
+        matches!(
+            self.summary(),
+            Some(SessionSummary {
+                close_reason: CloseReason::Cancelled,
+                ..
+            })
+        )
+    }
+
+    /// Extends the session's deadlines `by`, as if it had been suspended
+    /// that long: the suspended time does not count toward its timeout, but
+    /// is still part of the next duration pushed to its decoder.
+    pub fn shift(self: Pin<&mut Self>, by: Duration) {
+        if let DelaySessionInnerProj::Open {
+            timeout,
+            lifetime_deadline,
+            paused,
+            ..
+        } = self.project().inner.project()
+        {
+            if let Some(paused) = paused {
+                paused.at = timeout_instant(paused.at, by);
+            }
+            *lifetime_deadline = lifetime_deadline.map(|deadline| timeout_instant(deadline, by));
+            timeout.reset(timeout_instant(timeout.deadline, by));
+        }
+    }
+
+    /// Closes the session before its timeout as `CloseReason::Flushed`,
+    /// returning what was decoded so far and the receiver, or `None` if it
+    /// already closed. Signals still queued in the receiver are not taken in.
+    pub fn close(self: Pin<&mut Self>) -> Option<(D::Output, SignalReceiver<M>)>
+    where
+        D: MetaDelayDecoder<M>,
+    {
+        self.project().inner.close_as(CloseReason::Flushed, None)
+    }
+
+    /// Takes what the decoder decided since the last call, or `None` if the
+    /// session already closed. The session's output then only holds what
+    /// was decided after it.
+    pub fn take_decided(self: Pin<&mut Self>) -> Option<D::Output>
+    where
+        D: StreamingDelayDecoder,
+    {
+        match self.project().inner.project() {
+            DelaySessionInnerProj::Open { decoder, .. } => Some(decoder.take_decided()),
+            DelaySessionInnerProj::Closed { .. } => None,
+        }
+    }
+
+    /// Turns the session into a stream of its bits, yielding each as soon as
+    /// the decoder decides it and the rest once the session closes.
+    pub fn into_bit_stream(self) -> DelaySessionBitStream<D, M>
+    where
+        D: StreamingDelayDecoder<Output = BitVec>,
+    {
+        DelaySessionBitStream {
+            session: self,
+            bits: BitVec::new(),
+            next: 0,
+            closed: false,
+        }
+    }
+
+    /// Returns what was decoded so far, if the session is open and its
+    /// decoder supports snapshots.
+    pub fn snapshot(&self) -> Option<D::Output>
+    where
+        D: DelayDecoder,
+    {
+        match &self.inner {
+            DelaySessionInner::Open { decoder, .. } => decoder.snapshot(),
+            DelaySessionInner::Closed { .. } => None,
+        }
+    }
 }
 
-impl<D> Future for DelaySession<D>
+/// Resolves once the session closes, with what it decoded and its receiver.
+/// Polled again after resolving, it stays pending, and panics in debug
+/// builds. Check [`is_open`](DelaySession::is_open), or
+/// `FusedFuture::is_terminated`, before polling a session that may have
+/// closed.
+impl<D, M> Future for DelaySession<D, M>
 where
-    D: DelayDecoder,
+    D: MetaDelayDecoder<M>,
 {
-    type Output = (BitVec, SignalReceiver);
+    type Output = (D::Output, SignalReceiver<M>);
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        debug_assert!(
+            self.is_open(),
+            "DelaySession polled after it closed; check DelaySession::is_open first"
+        );
         self.project().inner.poll(cx)
     }
 }
 
-pub fn delay_session<D>(
+impl<D, M> FusedFuture for DelaySession<D, M>
+where
+    D: MetaDelayDecoder<M>,
+{
+    fn is_terminated(&self) -> bool {
+        !self.is_open()
+    }
+}
+
+/// A session's bits as a stream, from [`DelaySession::into_bit_stream`].
+///
+/// The stream yields the same bits the session would have returned, and
+/// ends once it closed.
+#[derive(Debug)]
+#[pin_project]
+pub struct DelaySessionBitStream<D, M = ()> {
+    #[pin]
+    session: DelaySession<D, M>,
+    /// Bits taken from the decoder, yielded from `next` on.
+    bits: BitVec,
+    next: usize,
+    closed: bool,
+}
+
+impl<D, M> Stream for DelaySessionBitStream<D, M>
+where
+    D: StreamingDelayDecoder<Output = BitVec> + MetaDelayDecoder<M>,
+{
+    type Item = bool;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(bit) = this.bits.get(*this.next) {
+                *this.next += 1;
+                return Poll::Ready(Some(*bit));
+            }
+            if *this.closed {
+                return Poll::Ready(None);
+            }
+
+            *this.next = 0;
+            match this.session.as_mut().poll(cx) {
+                Poll::Ready((rest, _)) => {
+                    *this.bits = rest;
+                    *this.closed = true;
+                }
+                Poll::Pending => {
+                    *this.bits = this
+                        .session
+                        .as_mut()
+                        .take_decided()
+                        .expect("pending sessions are open");
+                    if this.bits.is_empty() {
+                        return Poll::Pending;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Moves the instants of the data signals held during a pause from
+/// `paused_at` to `resumed_at` so the latest lands on the resume, keeping
+/// the gaps between them, and returns where the earliest landed, which the
+/// duration spanning the pause ends at.
+pub(crate) fn shift_held<M>(
+    held: &mut [(Instant, M)],
+    paused_at: Instant,
+    resumed_at: Instant,
+) -> Instant {
+    let Some(latest) = held.iter().map(|(instant, _)| *instant).max() else {
+        return resumed_at;
+    };
+    let mut earliest = resumed_at;
+    for (instant, _) in held {
+        *instant = resumed_at
+            .checked_sub(latest.saturating_duration_since(*instant))
+            .map_or(paused_at, |shifted| shifted.max(paused_at));
+        earliest = earliest.min(*instant);
+    }
+    earliest
+}
+
+/// Closes `decoder` once it took in the signals `sequencer` held back and
+/// the `terminal` duration, if any.
+fn close_decoder<D, M>(
+    mut decoder: D,
+    mut sequencer: SignalSequencer<M>,
+    terminal: Option<(Instant, Option<&M>)>,
+) -> D::Output
+where
+    D: MetaDelayDecoder<M>,
+{
+    sequencer.release(&mut decoder, terminal);
+    decoder.close()
+}
+
+pub fn delay_session<D, M>(
     decoder: D,
     start_instant: Instant,
     timeout_instant: Instant,
-) -> (SignalSender, DelaySession<D>) {
-    let (sender, receiver) = signal_channel();
+) -> (SignalSender<M>, DelaySession<D, M>) {
+    delay_session_with_capacity(
+        decoder,
+        start_instant,
+        timeout_instant,
+        DEFAULT_SIGNAL_CAPACITY,
+    )
+}
+
+/// Like `delay_session`, with a signal channel of `capacity`.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn delay_session_with_capacity<D, M>(
+    decoder: D,
+    start_instant: Instant,
+    timeout_instant: Instant,
+    capacity: usize,
+) -> (SignalSender<M>, DelaySession<D, M>) {
+    let (sender, receiver) = signal_channel_with_capacity(capacity);
     (
         sender,
         DelaySession::new(decoder, receiver, start_instant, timeout_instant),
     )
 }
 
+/// A session's timeout, slept out on its clock.
+struct Timeout {
+    clock: Arc<dyn Clock>,
+    deadline: Instant,
+    /// Armed on the next poll, so a burst of signals only sleeps once.
+    sleep: Option<ClockSleep>,
+}
+
+impl Timeout {
+    fn new(deadline: Instant) -> Self {
+        Self {
+            clock: Arc::new(TokioClock),
+            deadline,
+            sleep: None,
+        }
+    }
+
+    fn reset(&mut self, deadline: Instant) {
+        if deadline != self.deadline {
+            instrument::timeout_reset(&*self.clock, deadline);
+            self.deadline = deadline;
+            self.sleep = None;
+        }
+    }
+
+    /// Drops the armed sleep, re-armed on the next poll.
+    fn suspend(&mut self) {
+        self.sleep = None;
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let (clock, deadline) = (&self.clock, self.deadline);
+        self.sleep
+            .get_or_insert_with(|| clock.sleep_until(deadline))
+            .as_mut()
+            .poll(cx)
+    }
+}
+
+impl Debug for Timeout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timeout")
+            .field("clock", &self.clock)
+            .field("deadline", &self.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What ended a session, for its `TerminalDuration`.
+enum SessionEnd<M> {
+    /// A data signal at or after the session's deadline.
+    Signal(Instant, M),
+    Timeout,
+}
+
+impl<M> SessionEnd<M> {
+    /// The end by a `signal` at or after the deadline: any but a data
+    /// signal only finds the session timed out.
+    fn of(signal: Signal<M>) -> Option<Self> {
+        match signal.kind {
+            SignalKind::Data => Some(Self::Signal(signal.instant, signal.meta)),
+            SignalKind::Keepalive | SignalKind::Pause | SignalKind::Resume => Some(Self::Timeout),
+        }
+    }
+}
+
+/// What closes a session early once it completes, if anything.
+struct CancelSignal(Option<Pin<Box<dyn Future<Output = ()> + Send>>>);
+
+impl CancelSignal {
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.0 {
+            Some(cancelled) => cancelled.as_mut().poll(cx),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Debug for CancelSignal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CancelSignal")
+            .field(&self.0.is_some())
+            .finish()
+    }
+}
+
+/// A session paused by a `SignalKind::Pause`.
+#[derive(Debug)]
+struct Paused<M> {
+    at: Instant,
+    /// The time that was left before the deadline.
+    remaining: Duration,
+    /// The data signals that arrived since, with their instants.
+    buffered: Vec<(Instant, M)>,
+}
+
 #[derive(Debug)]
 #[pin_project(project = DelaySessionInnerProj, project_replace = DelaySessionInnerOwnedProj)]
-enum DelaySessionInner<D> {
+enum DelaySessionInner<D, M> {
     Open {
         decoder: D,
-        receiver: SignalReceiver,
-        last_signal_instant: Instant,
-        #[pin]
-        timeout_sleep: Sleep,
+        receiver: SignalReceiver<M>,
+        sequencer: SignalSequencer<M>,
+        timeout: Timeout,
+        /// The instant of the signal that started the session.
+        started: Instant,
+        /// Signals taken in, including the one that started the session.
+        signals: u64,
+        /// Durations the decoder takes before the session closes.
+        durations_left: Option<usize>,
+        /// When the session closes regardless of signals, if it has a
+        /// `max_lifetime`.
+        lifetime_deadline: Option<Instant>,
+        terminal: TerminalDuration,
+        keepalive: KeepalivePolicy,
+        pause_gap: PauseGapPolicy,
+        /// Data signals held while the session is paused.
+        pause_buffer: usize,
+        paused: Option<Box<Paused<M>>>,
+        cancel: CancelSignal,
     },
-    Closed,
+    /// Closed, with a summary unless the session never opened.
+    Closed { summary: Option<SessionSummary> },
+}
+
+impl<D, M> DelaySessionInner<D, M> {
+    /// Closes the session for `reason`, pushing the gap to `end` to the
+    /// decoder as its `TerminalDuration` says, or returns `None` if it
+    /// already closed.
+    fn close_as(
+        mut self: Pin<&mut Self>,
+        reason: CloseReason,
+        end: Option<SessionEnd<M>>,
+    ) -> Option<(D::Output, SignalReceiver<M>)>
+    where
+        D: MetaDelayDecoder<M>,
+    {
+        // Leaves the summary of a closed session in place.
+        if let DelaySessionInner::Closed { .. } = *self {
+            return None;
+        }
+        let DelaySessionInnerOwnedProj::Open {
+            decoder,
+            receiver,
+            sequencer,
+            timeout,
+            started,
+            signals,
+            terminal,
+            ..
+        } = self
+            .as_mut()
+            .project_replace(DelaySessionInner::Closed { summary: None })
+        else {
+            return None;
+        };
+
+        let terminal_at = match &end {
+            Some(SessionEnd::Signal(instant, meta)) if terminal.on_signal() => {
+                Some((*instant, Some(meta)))
+            }
+            Some(SessionEnd::Timeout) if terminal.on_timeout() => Some((timeout.deadline, None)),
+            _ => None,
+        };
+        let output = close_decoder(decoder, sequencer, terminal_at);
+        self.set(DelaySessionInner::Closed {
+            summary: Some(SessionSummary {
+                close_reason: reason,
+                started_at: started,
+                closed_at: timeout.clock.now(),
+                signal_count: signals,
+            }),
+        });
+        Some((output, receiver))
+    }
 }
 
-impl<D> Future for DelaySessionInner<D>
+impl<D, M> Future for DelaySessionInner<D, M>
 where
-    D: DelayDecoder,
+    D: MetaDelayDecoder<M>,
 {
-    type Output = (BitVec, SignalReceiver);
+    type Output = (D::Output, SignalReceiver<M>);
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        fn close_assert_open<D>(session: Pin<&mut DelaySessionInner<D>>) -> (BitVec, SignalReceiver)
+        fn close_assert_open<D, M>(
+            session: Pin<&mut DelaySessionInner<D, M>>,
+            end: Option<SessionEnd<M>>,
+        ) -> (D::Output, SignalReceiver<M>)
+        where
+            D: MetaDelayDecoder<M>,
+        {
+            let reason = match end {
+                Some(SessionEnd::Signal(..)) => CloseReason::TerminatingSignal,
+                Some(SessionEnd::Timeout) => CloseReason::Timeout,
+                None => CloseReason::SenderDropped,
+            };
+            close_open_as(session, reason, end)
+        }
+
+        fn close_open_as<D, M>(
+            session: Pin<&mut DelaySessionInner<D, M>>,
+            reason: CloseReason,
+            end: Option<SessionEnd<M>>,
+        ) -> (D::Output, SignalReceiver<M>)
+        where
+            D: MetaDelayDecoder<M>,
+        {
+            session
+                .close_as(reason, end)
+                .expect("only open sessions are polled")
+        }
+
+        /// Takes in a signal unless `sequencer` drops it, returning whether it
+        /// is the latest signal, which the timeout runs from, and whether it
+        /// pushed a duration, which a held back one does not.
+        fn take_in<D, M>(
+            decoder: &mut D,
+            sequencer: &mut SignalSequencer<M>,
+            instant: Instant,
+            meta: M,
+        ) -> Option<(bool, bool)>
         where
-            D: DelayDecoder,
+            D: MetaDelayDecoder<M>,
         {
-            match session.project_replace(DelaySessionInner::Closed) {
-                DelaySessionInnerOwnedProj::Open {
-                    decoder, receiver, ..
-                } => (decoder.close(), receiver),
-                DelaySessionInnerOwnedProj::Closed => unreachable!(),
+            if !sequencer.admits(instant) {
+                return None;
+            }
+            let pushed = match sequencer.accept(instant, meta) {
+                Some((duration, meta)) => {
+                    decoder.push_duration_with(duration, &meta);
+                    true
+                }
+                None => false,
+            };
+            Some((sequencer.latest() == instant, pushed))
+        }
+
+        /// Counts `pushed` durations, returning whether they reach the limit.
+        fn count_durations(durations_left: &mut Option<usize>, pushed: usize) -> bool {
+            match durations_left {
+                Some(left) if pushed > 0 => {
+                    *left = left.saturating_sub(pushed);
+                    *left == 0
+                }
+                _ => false,
             }
         }
 
@@ -131,48 +999,683 @@ where
             DelaySessionInnerProj::Open {
                 decoder,
                 receiver,
-                last_signal_instant,
-                mut timeout_sleep,
+                sequencer,
+                timeout,
+                signals,
+                durations_left,
+                lifetime_deadline,
+                keepalive,
+                pause_gap,
+                pause_buffer,
+                paused,
+                cancel,
+                ..
             } => {
-                if let Poll::Ready(signal_option) = receiver.poll_recv(cx) {
-                    if let Some(signal) = signal_option {
-                        if signal.instant >= timeout_sleep.deadline().into() {
-                            return Poll::Ready(close_assert_open(self));
-                        }
+                if cancel.poll(cx).is_ready() {
+                    return Poll::Ready(close_open_as(self, CloseReason::Cancelled, None));
+                }
 
-                        let mut new_timeout_instant = signal.timeout_instant;
-                        decoder.push_duration(signal.instant - *last_signal_instant);
-                        *last_signal_instant = signal.instant;
+                let clamp = |lifetime_deadline: Option<Instant>, timeout_instant: Instant| {
+                    match lifetime_deadline {
+                        Some(deadline) => timeout_instant.min(deadline),
+                        None => timeout_instant,
+                    }
+                };
+                let mut new_timeout_instant = timeout.deadline;
+                let mut received = 0;
+                while let Poll::Ready(signal_option) = receiver.poll_recv(cx) {
+                    let Some(signal) = signal_option else {
+                        return Poll::Ready(close_assert_open(self, None));
+                    };
+                    received += 1;
 
-                        while let Poll::Ready(signal_option) = receiver.poll_recv(cx) {
-                            if let Some(signal) = signal_option {
-                                if signal.instant >= new_timeout_instant {
-                                    return Poll::Ready(close_assert_open(self));
-                                }
+                    if let Some(state) = paused {
+                        if signal.kind != SignalKind::Resume {
+                            if signal.kind == SignalKind::Data
+                                && state.buffered.len() < *pause_buffer
+                            {
+                                state.buffered.push((signal.instant, signal.meta));
+                            }
+                            continue;
+                        }
 
-                                new_timeout_instant = signal.timeout_instant;
-                                decoder.push_duration(signal.instant - *last_signal_instant);
-                                *last_signal_instant = signal.instant;
-                            } else {
-                                return Poll::Ready(close_assert_open(self));
+                        let Some(state) = paused.take() else {
+                            continue;
+                        };
+                        let Paused {
+                            at,
+                            remaining,
+                            mut buffered,
+                        } = *state;
+                        let resumed_at = signal.instant.max(at);
+                        let gap = resumed_at.saturating_duration_since(at);
+                        let gap_end = shift_held(&mut buffered, at, resumed_at);
+                        sequencer.resume(*pause_gap, at, gap_end);
+                        *lifetime_deadline =
+                            lifetime_deadline.map(|deadline| timeout_instant(deadline, gap));
+                        new_timeout_instant =
+                            clamp(*lifetime_deadline, timeout_instant(resumed_at, remaining));
+                        for (instant, meta) in buffered {
+                            if let Some((_, pushed)) = take_in(decoder, sequencer, instant, meta) {
+                                *signals += 1;
+                                if count_durations(durations_left, usize::from(pushed)) {
+                                    return Poll::Ready(close_open_as(
+                                        self,
+                                        CloseReason::LimitReached,
+                                        None,
+                                    ));
+                                }
                             }
                         }
+                        continue;
+                    }
+
+                    if signal.instant >= new_timeout_instant {
+                        let end = SessionEnd::of(signal);
+                        return Poll::Ready(close_assert_open(self, end));
+                    }
 
-                        timeout_sleep.as_mut().reset(new_timeout_instant.into());
-                    } else {
-                        return Poll::Ready(close_assert_open(self));
+                    match signal.kind {
+                        SignalKind::Data if sequencer.debounces(signal.instant) => {}
+                        SignalKind::Data => {
+                            if let Some((latest, pushed)) =
+                                take_in(decoder, sequencer, signal.instant, signal.meta)
+                            {
+                                if latest {
+                                    new_timeout_instant =
+                                        clamp(*lifetime_deadline, signal.timeout_instant);
+                                }
+                                *signals += 1;
+                                if count_durations(durations_left, usize::from(pushed)) {
+                                    return Poll::Ready(close_open_as(
+                                        self,
+                                        CloseReason::LimitReached,
+                                        None,
+                                    ));
+                                }
+                            }
+                        }
+                        SignalKind::Keepalive => {
+                            if *keepalive == KeepalivePolicy::RestartDuration {
+                                let pushed = sequencer.mark(decoder, signal.instant);
+                                if count_durations(durations_left, pushed) {
+                                    return Poll::Ready(close_open_as(
+                                        self,
+                                        CloseReason::LimitReached,
+                                        None,
+                                    ));
+                                }
+                            }
+                            new_timeout_instant = new_timeout_instant
+                                .max(clamp(*lifetime_deadline, signal.timeout_instant));
+                        }
+                        SignalKind::Pause => {
+                            *paused = Some(Box::new(Paused {
+                                at: signal.instant,
+                                remaining: new_timeout_instant
+                                    .saturating_duration_since(signal.instant),
+                                buffered: Vec::new(),
+                            }));
+                        }
+                        SignalKind::Resume => {}
                     }
                 }
+                if received > 0 {
+                    instrument::signals_received(received);
+                }
+                timeout.reset(new_timeout_instant);
+                if paused.is_some() {
+                    // Frozen until resumed.
+                    timeout.suspend();
+                    return Poll::Pending;
+                }
 
-                if timeout_sleep.poll(cx).is_ready() {
-                    Poll::Ready(close_assert_open(self))
+                if timeout.poll(cx).is_ready() {
+                    Poll::Ready(close_assert_open(self, Some(SessionEnd::Timeout)))
                 } else {
                     Poll::Pending
                 }
             }
-            DelaySessionInnerProj::Closed => {
-                panic!("DelaySession polled after having returned Poll::Ready")
+            // Its output was taken when it closed.
+            DelaySessionInnerProj::Closed { .. } => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use bitvec::{bitvec, order::Lsb0, vec::BitVec};
+    use futures::{task::noop_waker_ref, StreamExt};
+
+    use super::{
+        delay_session, delay_session_with_capacity, timeout_instant, CloseReason, KeepalivePolicy,
+        OutOfOrderPolicy, PauseGapPolicy, Signal, TerminalDuration,
+    };
+    use crate::{
+        clock::ManualClock,
+        decoder::{AverageDelayDecoder, ThresholdDelayDecoder},
+    };
+
+    #[tokio::test(start_paused = true)]
+    #[cfg_attr(debug_assertions, should_panic(expected = "polled after it closed"))]
+    async fn a_closed_session_stays_pending() {
+        let start = tokio::time::Instant::now().into_std();
+        let (sender, session) = delay_session::<_, ()>(
+            AverageDelayDecoder::new(),
+            start,
+            timeout_instant(start, Duration::from_secs(1)),
+        );
+        drop(sender);
+        let mut session = pin!(session);
+        session.as_mut().await;
+        assert!(!session.is_open());
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(matches!(session.as_mut().poll(&mut cx), Poll::Pending));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unrepresentable_deadlines_and_huge_gaps_do_not_panic() {
+        const YEAR: Duration = Duration::from_secs(86400 * 365);
+
+        let start = tokio::time::Instant::now().into_std();
+        let never = timeout_instant(start, Duration::MAX);
+        assert!(never >= start + 29 * YEAR);
+
+        let (sender, session) = delay_session(AverageDelayDecoder::new(), start, never);
+        for instant in [start + 10 * YEAR, start, start + 20 * YEAR] {
+            let signal = Signal::new(instant, timeout_instant(instant, Duration::MAX), ());
+            sender.send(signal).await.unwrap();
+        }
+        drop(sender);
+
+        // The out-of-order signal ends a zero duration.
+        let (bits, _) = session.await;
+        assert_eq!(bits.len(), 3);
+        assert!(bits[0] && !bits[1] && bits[2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn signals_held_over_a_long_pause_keep_their_gaps() {
+        // Gaps of 100, 500, 500 and 300 ms; the middle two signals arrive
+        // during a pause over seven times longer than the timeout.
+        async fn decode(pause: Option<PauseGapPolicy>) -> BitVec {
+            let start = tokio::time::Instant::now().into_std();
+            let at = |millis| start + Duration::from_millis(millis);
+            let data = |millis| Signal::new(at(millis), at(millis + 1000), ());
+            let (sender, session) = delay_session_with_capacity(
+                ThresholdDelayDecoder::new(Duration::from_millis(200)),
+                start,
+                at(1000),
+                16,
+            );
+            let signals = match pause {
+                Some(_) => vec![
+                    data(100),
+                    Signal::pause(at(600)),
+                    data(4000),
+                    data(4500),
+                    Signal::resume(at(8000)),
+                    data(8300),
+                ],
+                None => vec![data(100), data(600), data(1100), data(1400)],
+            };
+            for signal in signals {
+                sender.send(signal).await.unwrap();
+            }
+            let session = match pause {
+                Some(policy) => session.pause_gap_policy(policy),
+                None => session,
+            };
+            session.await.0
+        }
+
+        let unpaused = decode(None).await;
+        assert_eq!(unpaused.len(), 4);
+        for policy in [PauseGapPolicy::Exclude, PauseGapPolicy::Clamp] {
+            assert_eq!(decode(Some(policy)).await, unpaused, "{policy:?}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_lifetime_closes_a_session_however_recently_signalled() {
+        let start = tokio::time::Instant::now().into_std();
+        let at = |millis| start + Duration::from_millis(millis);
+        let (sender, session) = delay_session::<_, ()>(
+            ThresholdDelayDecoder::new(Duration::from_millis(300)),
+            start,
+            at(1000),
+        );
+        for millis in (500..=2000).step_by(500) {
+            sender
+                .send(Signal::new(at(millis), at(millis + 1000), ()))
+                .await
+                .unwrap();
+        }
+
+        let mut session = pin!(session.max_lifetime(Duration::from_millis(2500)));
+        let (bits, _) = session.as_mut().await;
+        assert_eq!(bits.len(), 4);
+        assert_eq!(tokio::time::Instant::now().into_std(), at(2500));
+
+        let summary = session.summary().unwrap();
+        assert_eq!(summary.close_reason, CloseReason::Timeout);
+        assert_eq!(summary.closed_at, at(2500));
+        assert_eq!(summary.signal_count, 5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_signal_past_the_lifetime_terminates_the_session() {
+        let start = tokio::time::Instant::now().into_std();
+        let at = |millis| start + Duration::from_millis(millis);
+        let (sender, session) =
+            delay_session::<_, ()>(AverageDelayDecoder::new(), start, at(10_000));
+        for millis in [100, 400, 2000] {
+            sender
+                .send(Signal::new(at(millis), at(10_000), ()))
+                .await
+                .unwrap();
+        }
+
+        let mut session = pin!(session.max_lifetime(Duration::from_secs(1)));
+        let (bits, _) = session.as_mut().await;
+        assert_eq!(bits.len(), 2);
+        let summary = session.summary().unwrap();
+        assert_eq!(summary.close_reason, CloseReason::TerminatingSignal);
+        assert_eq!(summary.signal_count, 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn an_open_session_reports_its_signals_and_deadline() {
+        let start = tokio::time::Instant::now().into_std();
+        let at = |millis| start + Duration::from_millis(millis);
+        let (sender, session) = delay_session::<_, ()>(AverageDelayDecoder::new(), start, at(1000));
+        let mut session = pin!(session);
+        assert_eq!(session.signal_count(), Some(1));
+        assert_eq!(session.last_signal_instant(), Some(start));
+        assert_eq!(session.deadline(), Some(at(1000)));
+
+        for millis in [10, 40] {
+            sender
+                .send(Signal::new(at(millis), at(millis + 1000), ()))
+                .await
+                .unwrap();
+        }
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(session.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(session.signal_count(), Some(3));
+        assert_eq!(session.last_signal_instant(), Some(at(40)));
+        assert_eq!(session.deadline(), Some(at(1040)));
+        assert_eq!(session.summary(), None);
+
+        drop(sender);
+        session.as_mut().await;
+        assert!(!session.is_open());
+        assert_eq!(session.signal_count(), None);
+        assert_eq!(session.deadline(), None);
+        assert_eq!(
+            session.summary().unwrap().close_reason,
+            CloseReason::SenderDropped
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn late_signals_follow_the_out_of_order_policy() {
+        // The signal at 20 ms arrives after the one at 30 ms.
+        async fn decode(policy: OutOfOrderPolicy) -> BitVec {
+            let start = tokio::time::Instant::now().into_std();
+            let at = |millis| start + Duration::from_millis(millis);
+            let (sender, session) = delay_session(
+                ThresholdDelayDecoder::new(Duration::from_millis(15)),
+                start,
+                at(1000),
+            );
+            for millis in [10, 30, 20, 60] {
+                sender
+                    .send(Signal::new(at(millis), at(millis + 1000), ()))
+                    .await
+                    .unwrap();
             }
+            drop(sender);
+            session.out_of_order(policy).await.0
+        }
+
+        let saturated = decode(OutOfOrderPolicy::SaturateZero).await;
+        assert_eq!(saturated, bitvec![0, 1, 0, 1]);
+        assert_eq!(decode(OutOfOrderPolicy::Drop).await, bitvec![0, 1, 1]);
+        let reordered = decode(OutOfOrderPolicy::Reorder { window: 1 }).await;
+        assert_eq!(reordered, bitvec![0, 0, 0, 1]);
+        let unbuffered = decode(OutOfOrderPolicy::Reorder { window: 0 }).await;
+        assert_eq!(unbuffered, bitvec![0, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn a_manual_clock_decides_when_the_session_times_out() {
+        let start = std::time::Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let clock = ManualClock::new(start);
+        let (sender, session) = delay_session::<_, ()>(
+            ThresholdDelayDecoder::new(Duration::from_millis(300)),
+            start,
+            at(1000),
+        );
+        let mut session = pin!(session
+            .clock(Arc::new(clock.clone()))
+            .max_lifetime(Duration::from_millis(2000)));
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(session.as_mut().poll(&mut cx).is_pending());
+
+        // A signal resets the timeout to its own deadline.
+        clock.set(at(500));
+        sender
+            .send(Signal::new(at(500), at(1500), ()))
+            .await
+            .unwrap();
+        assert!(session.as_mut().poll(&mut cx).is_pending());
+        clock.set(at(1499));
+        assert!(session.as_mut().poll(&mut cx).is_pending());
+        clock.set(at(1500));
+        let Poll::Ready((bits, _)) = session.as_mut().poll(&mut cx) else {
+            panic!("the session outlived its timeout");
+        };
+        assert_eq!(bits, bitvec![1]);
+        let summary = session.summary().unwrap();
+        assert_eq!(summary.close_reason, CloseReason::Timeout);
+        assert_eq!(summary.closed_at, at(1500));
+
+        // The lifetime ends a session signalled well within its timeout.
+        let (sender, session) = delay_session::<_, ()>(
+            ThresholdDelayDecoder::new(Duration::from_millis(300)),
+            start,
+            at(1000),
+        );
+        let mut session = pin!(session
+            .clock(Arc::new(clock.clone()))
+            .max_lifetime(Duration::from_millis(2000)));
+        for millis in [800, 1600] {
+            sender
+                .send(Signal::new(at(millis), at(millis + 1000), ()))
+                .await
+                .unwrap();
+        }
+        assert!(session.as_mut().poll(&mut cx).is_pending());
+        clock.set(at(2000));
+        let Poll::Ready((bits, _)) = session.as_mut().poll(&mut cx) else {
+            panic!("the session outlived its lifetime");
+        };
+        assert_eq!(bits, bitvec![1, 1]);
+        assert_eq!(session.summary().unwrap().closed_at, at(2000));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_bit_stream_yields_bits_as_they_are_decided() {
+        const GAPS: [u64; 5] = [100, 400, 100, 500, 400];
+
+        fn send_spaced(sender: super::SignalSender, start: std::time::Instant) {
+            tokio::spawn(async move {
+                let mut millis = 0;
+                for gap in GAPS {
+                    millis += gap;
+                    let instant = start + Duration::from_millis(millis);
+                    tokio::time::sleep_until(instant.into()).await;
+                    let signal = Signal::new(instant, instant + Duration::from_secs(1), ());
+                    sender.send(signal).await.unwrap();
+                }
+            });
+        }
+
+        let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(200));
+        let start = tokio::time::Instant::now().into_std();
+        let (sender, session) = delay_session(decoder(), start, start + Duration::from_secs(1));
+        send_spaced(sender, start);
+        let (expected, _) = session.await;
+        assert_eq!(expected, bitvec![0, 1, 0, 1, 1]);
+
+        let start = tokio::time::Instant::now().into_std();
+        let (sender, session) = delay_session(decoder(), start, start + Duration::from_secs(1));
+        send_spaced(sender, start);
+        let mut bits = session.into_bit_stream();
+        let mut streamed: BitVec = BitVec::new();
+        let mut millis = 0;
+        for (gap, bit) in GAPS.into_iter().zip(&expected) {
+            millis += gap;
+            assert_eq!(bits.next().await, Some(*bit));
+            let elapsed = tokio::time::Instant::now().into_std() - start;
+            assert_eq!(elapsed, Duration::from_millis(millis));
+            streamed.push(*bit);
+        }
+        assert_eq!(bits.next().await, None);
+        assert_eq!(streamed, expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_buffering_decoder_streams_its_bits_on_close() {
+        let start = tokio::time::Instant::now().into_std();
+        let at = |millis| start + Duration::from_millis(millis);
+        let (sender, session) = delay_session(AverageDelayDecoder::new(), start, at(1000));
+        for millis in [100, 500, 600, 1000] {
+            sender
+                .send(Signal::new(at(millis), at(millis + 1000), ()))
+                .await
+                .unwrap();
+        }
+        drop(sender);
+
+        let bits: BitVec = session.into_bit_stream().collect().await;
+        assert_eq!(bits, bitvec![0, 1, 0, 1]);
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test(start_paused = true)]
+    async fn a_cancelled_session_closes_without_its_queued_signals() {
+        use tokio_util::sync::CancellationToken;
+
+        let start = tokio::time::Instant::now().into_std();
+        let at = |millis| start + Duration::from_millis(millis);
+        let token = CancellationToken::new();
+        let (sender, session) = delay_session::<_, ()>(
+            ThresholdDelayDecoder::new(Duration::from_millis(15)),
+            start,
+            at(1000),
+        );
+        let mut session = pin!(session.cancel_on(token.clone()));
+        for millis in [10, 40] {
+            sender
+                .send(Signal::new(at(millis), at(millis + 1000), ()))
+                .await
+                .unwrap();
+        }
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(session.as_mut().poll(&mut cx).is_pending());
+
+        sender
+            .send(Signal::new(at(50), at(1050), ()))
+            .await
+            .unwrap();
+        token.cancel();
+        let (bits, _) = session.as_mut().await;
+        assert_eq!(bits, bitvec![0, 1]);
+        let summary = session.summary().unwrap();
+        assert_eq!(summary.close_reason, CloseReason::Cancelled);
+        assert_eq!(summary.closed_at, start);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_terminal_gap_is_pushed_as_configured() {
+        // Gaps of 100 ms, then the timeout at 1200 ms or a signal at 1500 ms.
+        async fn decode(terminal: TerminalDuration, terminating_signal: bool) -> BitVec {
+            let start = tokio::time::Instant::now().into_std();
+            let at = |millis| start + Duration::from_millis(millis);
+            let (sender, session) = delay_session::<_, ()>(
+                ThresholdDelayDecoder::new(Duration::from_millis(150)),
+                start,
+                at(1000),
+            );
+            let mut signals = vec![100, 200];
+            if terminating_signal {
+                signals.push(1500);
+            }
+            for millis in signals {
+                sender
+                    .send(Signal::new(at(millis), at(millis + 1000), ()))
+                    .await
+                    .unwrap();
+            }
+            session.terminal_duration(terminal).await.0
+        }
+
+        for (terminal, on_signal, on_timeout) in [
+            (TerminalDuration::Discard, bitvec![0, 0], bitvec![0, 0]),
+            (TerminalDuration::Signal, bitvec![0, 0, 1], bitvec![0, 0]),
+            (
+                TerminalDuration::SignalOrTimeout,
+                bitvec![0, 0, 1],
+                bitvec![0, 0, 1],
+            ),
+        ] {
+            assert_eq!(decode(terminal, true).await, on_signal, "{terminal:?}");
+            assert_eq!(decode(terminal, false).await, on_timeout, "{terminal:?}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalives_extend_the_timeout_without_ending_a_duration() {
+        async fn decode(policy: KeepalivePolicy) -> BitVec {
+            let start = tokio::time::Instant::now().into_std();
+            let at = |millis| start + Duration::from_millis(millis);
+            let (sender, session) = delay_session::<_, ()>(
+                ThresholdDelayDecoder::new(Duration::from_millis(600)),
+                start,
+                at(1000),
+            );
+            // The data signal at 1700 ms is past the data deadline of 1200 ms.
+            for (millis, keepalive) in [(100, false), (200, false), (700, true), (1200, true)]
+                .into_iter()
+                .chain([(1700, false)])
+            {
+                let signal = if keepalive {
+                    Signal::keepalive(at(millis), at(millis + 1000))
+                } else {
+                    Signal::new(at(millis), at(millis + 1000), ())
+                };
+                sender.send(signal).await.unwrap();
+            }
+            drop(sender);
+            session.keepalive_policy(policy).await.0
+        }
+
+        assert_eq!(decode(KeepalivePolicy::Transparent).await, bitvec![0, 0, 1]);
+        assert_eq!(
+            decode(KeepalivePolicy::RestartDuration).await,
+            bitvec![0, 0, 0]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn summaries_tell_how_each_session_closed() {
+        let start = tokio::time::Instant::now().into_std();
+        let at = |millis| start + Duration::from_millis(millis);
+        let open = || {
+            delay_session::<_, ()>(
+                ThresholdDelayDecoder::new(Duration::from_millis(15)),
+                at(5),
+                at(1000),
+            )
+        };
+        let send = |sender: &super::SignalSender, millis| {
+            sender
+                .try_send(Signal::new(at(millis), at(millis + 1000), ()))
+                .unwrap();
+        };
+
+        let (sender, session) = open();
+        send(&sender, 10);
+        drop(sender);
+        let mut session = pin!(session);
+        assert_eq!(session.as_mut().await.0, bitvec![0]);
+        let summary = session.summary().unwrap();
+        assert_eq!(summary.close_reason, CloseReason::SenderDropped);
+        assert_eq!((summary.started_at, summary.closed_at), (at(5), start));
+        assert_eq!(summary.signal_count, 2);
+
+        let (sender, session) = open();
+        for millis in [10, 40, 50] {
+            send(&sender, millis);
+        }
+        let mut session = pin!(session.max_durations(2));
+        assert_eq!(session.as_mut().await.0, bitvec![0, 1]);
+        let summary = session.summary().unwrap();
+        assert_eq!(summary.close_reason, CloseReason::LimitReached);
+        assert_eq!(summary.signal_count, 3);
+
+        let (sender, session) = open();
+        send(&sender, 40);
+        let mut session = pin!(session);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(session.as_mut().poll(&mut cx).is_pending());
+        assert!(session.summary().is_none());
+        let (bits, _) = session.as_mut().close().unwrap();
+        assert_eq!(bits, bitvec![1]);
+        assert!(session.as_mut().close().is_none());
+        let summary = session.summary().unwrap();
+        assert_eq!(summary.close_reason, CloseReason::Flushed);
+        assert_eq!(summary.signal_count, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn signals_within_the_min_gap_are_ignored() {
+        for spaced in [false, true] {
+            let start = tokio::time::Instant::now().into_std();
+            let at = |micros| start + Duration::from_micros(micros);
+            let (sender, session) = delay_session::<_, ()>(
+                ThresholdDelayDecoder::new(Duration::from_millis(200)),
+                start,
+                at(1_000_000),
+            );
+            // Legitimate signals time out a second later, the spam within a
+            // millisecond of them five seconds later.
+            let signals = [
+                (100_000, 1_000_000),
+                (100_500, 5_000_000),
+                (400_000, 1_000_000),
+                (400_300, 5_000_000),
+                (400_900, 5_000_000),
+                (500_000, 1_000_000),
+                (900_000, 1_000_000),
+                (900_200, 5_000_000),
+            ];
+            let send = async move {
+                for (micros, timeout) in signals {
+                    if spaced {
+                        tokio::time::sleep_until(at(micros).into()).await;
+                    }
+                    sender
+                        .send(Signal::new(at(micros), at(micros + timeout), ()))
+                        .await
+                        .unwrap();
+                }
+                // Holds the session open until it times out.
+                std::future::pending::<()>().await;
+            };
+
+            let mut session = pin!(session.min_signal_gap(Duration::from_millis(1)));
+            let (bits, _) = tokio::select! {
+                output = session.as_mut() => output,
+                () = send => unreachable!(),
+            };
+            assert_eq!(bits, bitvec![0, 1, 0, 1], "spaced: {spaced}");
+            let summary = session.summary().unwrap();
+            assert_eq!(summary.close_reason, CloseReason::Timeout);
+            assert_eq!(summary.closed_at, at(1_900_000), "spaced: {spaced}");
+            assert_eq!(summary.signal_count, 5);
         }
     }
 }
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use crate::{
+    instant_policy::OutOfOrderPolicy,
+    session::{
+        DelaySession, KeepalivePolicy, PauseGapPolicy, TerminalDuration, DEFAULT_SIGNAL_CAPACITY,
+    },
+    session_store::{CancelBehavior, SessionLimitPolicy},
+    timeout_policy::TimeoutPolicyFactory,
+};
+
+/// How long a session task waits for a signal to start its next session with
+/// unless `DelaySessionStoreBuilder::restart_grace` is set.
+const DEFAULT_RESTART_GRACE: Duration = Duration::from_millis(10);
+
+/// How a store runs its sessions, on either backend, as set on its builder.
+#[derive(Clone, Debug)]
+pub(crate) struct SessionConfig {
+    pub(crate) timeout: TimeoutPolicyFactory,
+    pub(crate) max_durations: Option<usize>,
+    pub(crate) max_lifetime: Option<Duration>,
+    pub(crate) out_of_order: OutOfOrderPolicy,
+    pub(crate) min_signal_gap: Duration,
+    pub(crate) max_sessions: Option<(usize, SessionLimitPolicy)>,
+    pub(crate) terminal_duration: TerminalDuration,
+    pub(crate) keepalive_policy: KeepalivePolicy,
+    pub(crate) pause_gap: PauseGapPolicy,
+    /// Signals that wait for each session task, and that a paused session
+    /// holds, past which they are dropped.
+    pub(crate) signal_capacity: usize,
+    pub(crate) cancel_behavior: CancelBehavior,
+    pub(crate) restart_grace: Duration,
+}
+
+impl SessionConfig {
+    /// The defaults of a store whose sessions time out as `timeout` says.
+    pub(crate) fn new(timeout: TimeoutPolicyFactory) -> Self {
+        Self {
+            timeout,
+            max_durations: None,
+            max_lifetime: None,
+            out_of_order: OutOfOrderPolicy::default(),
+            min_signal_gap: Duration::ZERO,
+            max_sessions: None,
+            terminal_duration: TerminalDuration::default(),
+            keepalive_policy: KeepalivePolicy::default(),
+            pause_gap: PauseGapPolicy::default(),
+            signal_capacity: DEFAULT_SIGNAL_CAPACITY,
+            cancel_behavior: CancelBehavior::default(),
+            restart_grace: DEFAULT_RESTART_GRACE,
+        }
+    }
+
+    /// Applies the settings a session task's session runs with itself.
+    pub(crate) fn configure<D, M>(&self, mut session: DelaySession<D, M>) -> DelaySession<D, M> {
+        if let Some(max_durations) = self.max_durations {
+            session = session.max_durations(max_durations);
+        }
+        if let Some(max_lifetime) = self.max_lifetime {
+            session = session.max_lifetime(max_lifetime);
+        }
+        session
+            .out_of_order(self.out_of_order)
+            .min_signal_gap(self.min_signal_gap)
+            .terminal_duration(self.terminal_duration)
+            .keepalive_policy(self.keepalive_policy)
+            .pause_gap_policy(self.pause_gap)
+            .pause_buffer(self.signal_capacity)
+    }
+}
@@ -0,0 +1,308 @@
+//! A `tower::Service` feeding a store.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    hash::Hash,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use bitvec::vec::BitVec;
+use futures::{
+    future::{ready, BoxFuture, Ready},
+    ready,
+};
+
+use crate::{
+    decoder::{DecoderOutput, MetaDelayDecoder},
+    error::PushError,
+    session_store::{DelaySessionStore, PushOutcome},
+};
+
+/// A `tower::Service` pushing `(key, instant)` pairs into a store with
+/// [`DelaySessionStore::try_push_signal`].
+///
+/// `poll_ready` reflects what holds up the store as a whole: it is pending
+/// while the store is paused with `PausedPushes::Reject`, while it has its
+/// `max_sessions` with `SessionLimitPolicy::Reject`, even though pushes to
+/// open sessions would still go through, and while the result stream is
+/// full with `ResultOverflow::Wait`, since sessions closing then wait to
+/// emit. It fails with `PushError::Cancelled` once the store is
+/// cancelled and `PushError::ResultStreamClosed` once the stream is closed,
+/// and layers such as `load_shed` shed requests before they reach the store.
+///
+/// Readiness is still an approximation: it holds no reservation, and a
+/// single session's channel or the store's lock can only be probed by
+/// pushing. A full channel fails the call with `PushError::ChannelFull`, a
+/// busy lock is a `PushOutcome::DroppedLockBusy` response, and a store
+/// cancelled after `poll_ready` fails it with `PushError::StoreShutdown`.
+pub struct DelaySessionService<K, T, F, O = BitVec, M = ()> {
+    store: Arc<DelaySessionStore<K, T, O, M>>,
+    decoder_factory: F,
+    /// Waits for the store to take in pushes, while `poll_ready` is pending.
+    ready: Option<BoxFuture<'static, Result<(), PushError>>>,
+}
+
+impl<K, T, F, O, M> DelaySessionService<K, T, F, O, M> {
+    pub(crate) const fn new(store: Arc<DelaySessionStore<K, T, O, M>>, decoder_factory: F) -> Self {
+        Self {
+            store,
+            decoder_factory,
+            ready: None,
+        }
+    }
+}
+
+impl<K, T, F: Clone, O, M> Clone for DelaySessionService<K, T, F, O, M> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            decoder_factory: self.decoder_factory.clone(),
+            ready: None,
+        }
+    }
+}
+
+impl<K: Debug, T, F, O: Debug, M: Debug> Debug for DelaySessionService<K, T, F, O, M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DelaySessionService")
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, T, F, D, O, M> tower::Service<(K, Instant)> for DelaySessionService<K, T, F, O, M>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    T: Send + 'static,
+    O: DecoderOutput + Clone + Send + 'static,
+    M: Default + Send + 'static,
+    F: FnMut() -> D + Clone + Send + 'static,
+    D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+{
+    type Response = PushOutcome;
+    type Error = PushError;
+    type Future = Ready<Result<PushOutcome, PushError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let ready = self.ready.get_or_insert_with(|| {
+            let store = self.store.clone();
+            Box::pin(async move { store.push_ready().await })
+        });
+        let result = ready!(ready.as_mut().poll(cx));
+        self.ready = None;
+        Poll::Ready(result)
+    }
+
+    fn call(&mut self, (key, instant): (K, Instant)) -> Self::Future {
+        ready(
+            match self
+                .store
+                .try_push_signal(key, instant, self.decoder_factory.clone())
+            {
+                PushOutcome::SessionClosed => Err(PushError::SessionClosed),
+                PushOutcome::DroppedFull => Err(PushError::ChannelFull),
+                PushOutcome::Cancelled => Err(PushError::StoreShutdown),
+                PushOutcome::ResultStreamClosed => Err(PushError::ResultStreamClosed),
+                PushOutcome::RejectedImplausible => Err(PushError::ImplausibleInstant),
+                PushOutcome::RejectedSessionLimit => Err(PushError::SessionLimitReached),
+                outcome => Ok(outcome),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, future::poll_fn, sync::Arc, time::Duration};
+
+    use futures::StreamExt;
+    use tokio::time::sleep;
+    use tower::{
+        limit::{rate::Rate, RateLimit},
+        load_shed::{error::Overloaded, LoadShed},
+        Service,
+    };
+
+    use crate::{
+        decoder::AverageDelayDecoder,
+        pause::PausedPushes,
+        session_store::{DelaySessionStoreBuilder, PushOutcome, SessionLimitPolicy},
+        timer_wheel::TimerWheelConfig,
+    };
+
+    async fn call<S, R>(service: &mut S, request: R) -> Result<S::Response, S::Error>
+    where
+        S: Service<R>,
+    {
+        poll_fn(|cx| service.poll_ready(cx)).await?;
+        service.call(request).await
+    }
+
+    fn is_shed(error: &(dyn Error + Send + Sync + 'static)) -> bool {
+        error.is::<Overloaded>()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limited_requests_are_shed_before_the_store() {
+        let (store, _results) =
+            DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60)).build();
+        let store = Arc::new(store);
+        let mut service = LoadShed::new(RateLimit::new(
+            store.service(AverageDelayDecoder::new),
+            Rate::new(1, Duration::from_secs(1)),
+        ));
+        let now = store.clock().now();
+
+        let outcome = call(&mut service, (1, now)).await.unwrap();
+        assert_eq!(outcome, PushOutcome::Delivered);
+        assert!(is_shed(&*call(&mut service, (2, now)).await.unwrap_err()));
+        assert!(!store.contains_key(&2).await);
+
+        sleep(Duration::from_secs(1)).await;
+        let outcome = call(&mut service, (2, now)).await.unwrap();
+        assert_eq!(outcome, PushOutcome::Delivered);
+        assert!(store.contains_key(&2).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_store_rejecting_pushes_while_paused_is_not_ready() {
+        let (store, _results) =
+            DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60)).build();
+        let store = Arc::new(store);
+        let mut service = LoadShed::new(store.service(AverageDelayDecoder::new));
+        let now = store.clock().now();
+
+        store.pause(PausedPushes::Reject);
+        assert!(is_shed(&*call(&mut service, (1, now)).await.unwrap_err()));
+        assert!(!store.contains_key(&1).await);
+
+        store.pause(PausedPushes::Buffer);
+        let outcome = call(&mut service, (1, now)).await.unwrap();
+        assert_eq!(outcome, PushOutcome::Buffered);
+
+        store.resume().await;
+        let outcome = call(&mut service, (2, now)).await.unwrap();
+        assert_eq!(outcome, PushOutcome::Delivered);
+        assert!(store.contains_key(&1).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_full_result_stream_is_not_ready() {
+        let (store, mut results) = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60))
+            .result_capacity(1)
+            .build();
+        let store = Arc::new(store);
+        let mut service = LoadShed::new(store.service(AverageDelayDecoder::new));
+        let now = store.clock().now();
+
+        call(&mut service, (1, now)).await.unwrap();
+        assert!(store.flush(&1).await);
+        // Lets the session task emit its result into the stream.
+        sleep(Duration::from_millis(1)).await;
+        assert!(is_shed(&*call(&mut service, (2, now)).await.unwrap_err()));
+        assert!(!store.contains_key(&2).await);
+
+        assert_eq!(results.next().await.unwrap().0, 1);
+        let outcome = call(&mut service, (2, now)).await.unwrap();
+        assert_eq!(outcome, PushOutcome::Delivered);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_store_at_its_session_limit_is_not_ready() {
+        for timer_wheel in [false, true] {
+            let builder = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60))
+                .max_sessions(1, SessionLimitPolicy::Reject);
+            let builder = if timer_wheel {
+                builder.timer_wheel(TimerWheelConfig::default())
+            } else {
+                builder
+            };
+            let (store, _results) = builder.build();
+            let store = Arc::new(store);
+            let mut service = LoadShed::new(store.service(AverageDelayDecoder::new));
+            let now = store.clock().now();
+
+            call(&mut service, (1, now)).await.unwrap();
+            let shed = call(&mut service, (2, now)).await.unwrap_err();
+            assert!(is_shed(&*shed), "timer wheel: {timer_wheel}");
+            assert!(!store.contains_key(&2).await);
+
+            assert!(store.flush(&1).await);
+            // Lets the session task wind down and leave the store.
+            sleep(Duration::from_secs(1)).await;
+            let outcome = call(&mut service, (2, now)).await.unwrap();
+            assert_eq!(
+                outcome,
+                PushOutcome::Delivered,
+                "timer wheel: {timer_wheel}"
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn readiness_at_the_session_limit_resumes_once_a_session_closes() {
+        let (store, _results) = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60))
+            .max_sessions(1, SessionLimitPolicy::Reject)
+            .build();
+        let store = Arc::new(store);
+        let mut service = store.service(AverageDelayDecoder::new);
+        let now = store.clock().now();
+        call(&mut service, (1, now)).await.unwrap();
+
+        let ready =
+            tokio::spawn(
+                async move { poll_fn(|cx| service.poll_ready(cx)).await.map(|()| service) },
+            );
+        sleep(Duration::from_secs(30)).await;
+        assert!(!ready.is_finished());
+
+        // The session times out, making room.
+        let mut service = ready.await.unwrap().unwrap();
+        let outcome = service.call((2, store.clock().now())).await.unwrap();
+        assert_eq!(outcome, PushOutcome::Delivered);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_full_session_channel_fails_the_call() {
+        use crate::error::PushError;
+
+        let (store, _results) = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60))
+            .signal_capacity(1)
+            .build();
+        let store = Arc::new(store);
+        let mut service = store.service(AverageDelayDecoder::new);
+        let start = store.clock().now();
+
+        // The session has not run yet, so its second signal fills the channel.
+        for offset in [0, 10] {
+            let outcome = call(&mut service, (1, start + Duration::from_millis(offset))).await;
+            assert_eq!(outcome, Ok(PushOutcome::Delivered));
+        }
+        assert_eq!(
+            call(&mut service, (1, start + Duration::from_millis(20))).await,
+            Err(PushError::ChannelFull)
+        );
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test]
+    async fn a_cancelled_store_fails_readiness() {
+        use tokio_util::sync::CancellationToken;
+
+        use crate::error::PushError;
+
+        let token = CancellationToken::new();
+        let (store, _results) = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60))
+            .cancellation_token(token.clone())
+            .build();
+        let store = Arc::new(store);
+        let mut service = store.service(AverageDelayDecoder::new);
+
+        store.pause(PausedPushes::Reject);
+        let ready = tokio::spawn(async move { poll_fn(|cx| service.poll_ready(cx)).await });
+        token.cancel();
+        assert_eq!(ready.await.unwrap(), Err(PushError::Cancelled));
+    }
+}
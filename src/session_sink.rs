@@ -0,0 +1,151 @@
+//! A `Sink` feeding a store.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::{future::BoxFuture, ready, Sink};
+
+use crate::error::PushError;
+
+type PushFn<K> = Box<dyn Fn(K, Instant) -> BoxFuture<'static, Result<(), PushError>> + Send + Sync>;
+
+/// A `Sink` of `(key, instant)` pairs feeding a store.
+///
+/// At most one push is in flight at a time: `poll_ready` completes the previous
+/// push, so a full session channel applies backpressure to the sink's caller
+/// exactly as it would to a direct `push_signal` call: it waits for room in
+/// that one channel, while the store keeps taking pushes to other keys.
+pub struct DelaySessionSink<K> {
+    push: PushFn<K>,
+    in_flight: Option<BoxFuture<'static, Result<(), PushError>>>,
+}
+
+impl<K> DelaySessionSink<K> {
+    pub(crate) fn new(
+        push: impl Fn(K, Instant) -> BoxFuture<'static, Result<(), PushError>> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            push: Box::new(push),
+            in_flight: None,
+        }
+    }
+
+    fn poll_in_flight(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), PushError>> {
+        match self.in_flight.as_mut() {
+            Some(in_flight) => {
+                let result = ready!(in_flight.as_mut().poll(cx));
+                self.in_flight = None;
+                Poll::Ready(result)
+            }
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl<K> Debug for DelaySessionSink<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DelaySessionSink")
+            .field("in_flight", &self.in_flight.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K> Sink<(K, Instant)> for DelaySessionSink<K> {
+    type Error = PushError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_in_flight(cx)
+    }
+
+    fn start_send(
+        mut self: Pin<&mut Self>,
+        (key, instant): (K, Instant),
+    ) -> Result<(), Self::Error> {
+        self.in_flight = Some((self.push)(key, instant));
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_in_flight(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_in_flight(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use bitvec::vec::BitVec;
+    use futures::{stream, StreamExt};
+    use tokio::time::sleep;
+
+    use crate::{
+        decoder::ThresholdDelayDecoder,
+        session_store::{delay_session_store_with_factory, DelaySessionStream},
+    };
+
+    const KEYS: u32 = 50;
+
+    /// 1000 signals spread over the keys, each key's gaps alternating
+    /// between long and short in its own pattern.
+    fn signals(start: Instant) -> Vec<(u32, Instant)> {
+        let mut last = vec![start; KEYS as usize];
+        (0..1000u32)
+            .map(|n| {
+                let key = n % KEYS;
+                let gap = if (n / KEYS + key).is_multiple_of(3) {
+                    20
+                } else {
+                    10
+                };
+                last[key as usize] += Duration::from_millis(gap);
+                (key, last[key as usize])
+            })
+            .collect()
+    }
+
+    async fn results(mut results: DelaySessionStream<u32, BitVec>) -> Vec<(u32, BitVec)> {
+        let mut decoded = Vec::new();
+        for _ in 0..KEYS {
+            decoded.push(results.next().await.unwrap());
+        }
+        decoded.sort_unstable_by_key(|(key, _)| *key);
+        decoded
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_forwarded_stream_decodes_like_direct_pushes() {
+        let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(15));
+        let (sunk, sunk_results) =
+            delay_session_store_with_factory(Duration::from_secs(1), decoder);
+        let (direct, direct_results) =
+            delay_session_store_with_factory(Duration::from_secs(1), decoder);
+        let start = direct.store().clock().now();
+
+        let sunk = Arc::new(sunk);
+        stream::iter(signals(start))
+            .map(Ok)
+            .forward(sunk.sink())
+            .await
+            .unwrap();
+        for (key, instant) in signals(start) {
+            direct.push_signal(key, instant).await.unwrap();
+        }
+        sleep(Duration::from_secs(2)).await;
+
+        let decoded = results(sunk_results).await;
+        assert_eq!(decoded.len(), KEYS as usize);
+        assert!(decoded.iter().all(|(_, bits)| bits.len() == 19));
+        assert_eq!(decoded, results(direct_results).await);
+    }
+}
@@ -1,192 +1,4086 @@
 use std::{
+    borrow::Borrow,
     collections::{hash_map::Entry, HashMap},
+    fmt::{self, Debug, Formatter},
+    future::Future,
     hash::Hash,
-    mem::forget,
+    mem::take,
     pin::Pin,
-    sync::{Arc, Weak},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex as StdMutex, MutexGuard, PoisonError, RwLock, Weak,
+    },
     task::{Context, Poll},
     time::{Duration, Instant},
 };
 
 use bitvec::vec::BitVec;
-use futures::{join, Stream};
-use tokio::sync::{
-    mpsc::{channel, Receiver, Sender},
-    Mutex,
+use futures::{
+    future::{join_all, BoxFuture},
+    Stream, StreamExt,
+};
+use tokio::{
+    sync::{
+        mpsc::{channel, error::TrySendError, Receiver},
+        oneshot, watch,
+    },
+    time::timeout_at,
 };
 
 use crate::{
-    decoder::DelayDecoder,
-    session::{delay_session, DelaySession, Signal, SignalSender},
+    cancel::{Cancellation, StreamEnd},
+    clock::{Clock, TokioClock},
+    dead_letter::{DeadLetterHub, DeadLetterStream, ResultOverflow},
+    decoder::{DecoderOutput, MetaDelayDecoder},
+    diagnostics::{DiagnosticReason, DiagnosticStream},
+    emitter::{
+        ResultEmitter, ResultMapper, ResultSender, SharedResultReceiver, DEFAULT_STORE_NAME,
+    },
+    error::{CloseError, PushError},
+    factory_store::FactoryDelaySessionStore,
+    fairness::{FairSender, FairnessConfig},
+    forensics::{ForensicBuffer, ForensicConfig, ForensicTrace},
+    framing::{bits_to_bytes, BitOrder},
+    instant_policy::{InstantPolicy, InstantScreen, OutOfOrderPolicy, Screened},
+    instrument::{self, KeyRedactor},
+    key_stats::{KeyStats, KeyStatsConfig, KeyStatsTracker},
+    metrics::StoreMetrics,
+    pause::{PauseState, PausedPushes},
+    record::SignalTap,
+    sampling::{Sampled, Sampler, SamplingConfig},
+    session::{
+        delay_session_with_capacity, timeout_instant, CloseReason, KeepalivePolicy, PauseGapPolicy,
+        SessionSummary, Signal, TerminalDuration,
+    },
+    session_config::SessionConfig,
+    session_sink::DelaySessionSink,
+    task,
+    task_backend::{
+        finish_queued, LinkTarget, QueuedSignal, SessionEntry, SessionLink, SessionTask,
+        SharedSessionLink, SharedSignalSenderMap,
+    },
+    timeout_policy::{FixedTimeout, TimeoutPolicy, TimeoutPolicyFactory},
+    timer_wheel::{PushedSignal, ShardBusy, TimerWheelConfig, TimerWheelSessions},
+    watchdog::{spawn_watchdog, WatchdogConfig, WatchdogStream, WatchedSessions},
 };
 
-type SharedSignalSenderMap<K> = Mutex<HashMap<K, SignalSender>>;
+#[cfg(any(feature = "tower", feature = "tokio-util"))]
+use std::pin::pin;
+
+#[cfg(any(feature = "tower", feature = "tokio-util"))]
+use futures::future::{select, Either};
+
+#[cfg(feature = "tower")]
+use crate::session_service::DelaySessionService;
+#[cfg(feature = "tokio-util")]
+use crate::task_backend::resolve_link;
+#[cfg(feature = "libc")]
+use crate::time_anchor::TimeAnchor;
+#[cfg(feature = "tokio-util")]
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// How long `DelaySessionStore::snapshot_all` waits for session tasks to
+/// answer.
+const SNAPSHOT_WAIT: Duration = Duration::from_millis(250);
+
+const DEFAULT_RESULT_CAPACITY: usize = 8;
 
 #[derive(Debug)]
-pub struct DelaySessionStore<K> {
-    timeout_duration: Duration,
-    sender_map: Arc<SharedSignalSenderMap<K>>,
-    result_sender: Sender<(K, BitVec)>,
+enum StoreBackend<K, O, M> {
+    Task(Arc<SharedSignalSenderMap<K, O, M>>),
+    TimerWheel {
+        sessions: Arc<TimerWheelSessions<K, O, M>>,
+        _alive: Arc<()>,
+    },
+}
+
+impl<K, O, M> StoreBackend<K, O, M> {
+    fn watched(&self) -> WatchedSessions<K, O, M> {
+        match self {
+            Self::Task(sender_map) => WatchedSessions::Task(Arc::downgrade(sender_map)),
+            Self::TimerWheel {
+                sessions,
+                _alive: alive,
+            } => WatchedSessions::TimerWheel {
+                sessions: Arc::downgrade(sessions),
+                alive: Arc::downgrade(alive),
+            },
+        }
+    }
+}
+
+/// Once the store is cancelled, closes the timer wheel's sessions and waits
+/// for every session's result to reach the result channel before marking the
+/// store `drained`. Gives up if the store is `dropped` first.
+#[cfg(feature = "tokio-util")]
+async fn drain_cancelled<K, T, O, M>(
+    cancellation: Cancellation,
+    link: Weak<RwLock<SessionLink<K, T, O, M>>>,
+    sessions: WatchedSessions<K, O, M>,
+    cancel_behavior: CancelBehavior,
+    dropped: CancellationToken,
+    drained: CancellationToken,
+) where
+    K: Clone + Eq + Hash + Send + 'static,
+    T: Send + 'static,
+    O: DecoderOutput + Clone + Send + 'static,
+    M: Send + 'static,
+{
+    // Holding the emitter would keep the result stream open, so it is only
+    // taken once draining.
+    if let Either::Right(_) =
+        select(pin!(dropped.cancelled()), pin!(cancellation.cancelled())).await
+    {
+        let Some(emitter) = link.upgrade().map(|link| resolve_link(&link).emitter) else {
+            return;
+        };
+        if let WatchedSessions::TimerWheel { sessions, .. } = sessions {
+            if let Some(sessions) = sessions.upgrade() {
+                for (key, bits, summary) in sessions.close_all(CloseReason::Cancelled, "cancelled")
+                {
+                    emitter.report(DiagnosticReason::SessionCancelled, || key.clone());
+                    if cancel_behavior == CancelBehavior::Emit {
+                        emitter.emit(key, bits, summary).await;
+                    }
+                }
+            }
+        }
+        cancellation.drained().await;
+        emitter.flushed().await;
+        drained.cancel();
+    }
+}
+
+/// A result of a store with an emit interval, see
+/// [`DelaySessionStoreBuilder::emit_interval`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum SessionResult<T = BitVec> {
+    /// A snapshot of a session that is still open, decoded from its first
+    /// `bits` bits, or symbols for decoders of multi-bit symbols.
+    Intermediate { result: T, bits: usize },
+    /// The result of a closed session, decoded from all of its bits.
+    Final(T),
+}
+
+impl<T> SessionResult<T> {
+    pub const fn is_final(&self) -> bool {
+        matches!(self, Self::Final(_))
+    }
+
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Intermediate { result, .. } | Self::Final(result) => result,
+        }
+    }
+}
+
+/// A result along with how its session went, see
+/// [`DelaySessionStoreBuilder::summarized`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Summarized<T = BitVec> {
+    pub result: T,
+    /// `None` for snapshots of open sessions.
+    pub summary: Option<SessionSummary>,
+}
+
+/// An active session, from [`DelaySessionStore::snapshot_all`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct SessionSnapshot<K, O = BitVec> {
+    pub key: K,
+    /// What was decoded so far, if the session's decoder supports snapshots.
+    pub bits: Option<O>,
+    /// Whether the session gave its bits for the snapshot. A session task
+    /// too busy to answer in time is still listed, without bits.
+    pub answered: bool,
+    pub started_instant: Instant,
+    /// When the session times out unless another signal arrives.
+    pub deadline: Instant,
+    /// Signals the session has taken in, including the one that started it.
+    pub signals: u64,
+}
+
+/// The state of a key's session, from [`DelaySessionStore::session_info`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct SessionInfo {
+    pub started_instant: Instant,
+    pub last_signal_instant: Instant,
+    /// When the session times out unless another signal arrives.
+    pub deadline: Instant,
+    /// Signals the session has taken in, including the one that started it.
+    pub signals: u64,
+}
+
+/// What a push for a new key does when the store already has its
+/// `max_sessions`, see [`DelaySessionStoreBuilder::max_sessions`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum SessionLimitPolicy {
+    /// The push fails with `PushError::SessionLimitReached`.
+    #[default]
+    Reject,
+    /// The session whose latest signal is the oldest is closed, emitting its
+    /// partial result, to make room.
+    EvictLeastRecent,
+}
+
+/// What happens to the partial result of a cancelled session, see
+/// [`DelaySessionStoreBuilder::cancel_behavior`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum CancelBehavior {
+    /// The result is emitted like any other.
+    #[default]
+    Emit,
+    /// The result is dropped.
+    Discard,
+}
+
+/// The outcome of [`DelaySessionStore::try_push_signal`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum PushOutcome {
+    Delivered,
+    DroppedFull,
+    DroppedLockBusy,
+    SessionClosed,
+    ResultStreamClosed,
+    RejectedImplausible,
+    /// The store was paused with `PausedPushes::Reject`.
+    RejectedPaused,
+    /// The store was paused with `PausedPushes::Buffer`, and will deliver the
+    /// signal when it resumes.
+    Buffered,
+    /// The key is sampled and the signal was not among those kept, see
+    /// `DelaySessionStoreBuilder::sampling`.
+    SampledOut,
+    /// The store's cancellation token was cancelled, before the push or
+    /// while it was delivered.
+    Cancelled,
+    /// The signal would start a session, but the store already had its
+    /// `max_sessions`.
+    RejectedSessionLimit,
+}
+
+/// A store of delay sessions keyed by `K`, whose decoders output `O` and
+/// whose results are mapped to `T`.
+///
+/// `O` is `BitVec` for the decoders of this crate except
+/// `MultiLevelDelayDecoder`. Stores created before decoders had an `Output`
+/// type only need an annotation where nothing else pins `O` down, e.g.
+/// `DelaySessionStore<K>`.
+///
+/// Its pushes take the factory of the decoder a new session starts with.
+/// Most stores use one factory for every key, and are better built owning
+/// it, as a [`FactoryDelaySessionStore`].
+pub struct DelaySessionStore<K, T = BitVec, O = BitVec, M = ()> {
+    config: SessionConfig,
+    backend: StoreBackend<K, O, M>,
+    emitter: ResultEmitter<K, T, O>,
+    link: SharedSessionLink<K, T, O, M>,
+    metrics: Arc<StoreMetrics>,
+    clock: Arc<dyn Clock>,
+    instant_policy: InstantPolicy,
+    observers: SignalObservers<K>,
+    pause: watch::Sender<PauseState>,
+    /// Pushes held while paused with `PausedPushes::Buffer`.
+    buffered: StdMutex<Vec<BufferedPush<K, T, O, M>>>,
+    /// Stops the task draining the store once cancelled, when the store is
+    /// dropped first.
+    #[cfg(feature = "tokio-util")]
+    _drain: Option<DropGuard>,
+}
+
+/// A pushed signal, with the timeout it fixes for its key's session, if any.
+pub(crate) struct SignalPush<M> {
+    pub(crate) instant: Instant,
+    pub(crate) meta: M,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl<M> SignalPush<M> {
+    /// A signal at `instant` carrying `meta`, leaving the timeout to the
+    /// store's policy.
+    pub(crate) const fn new(instant: Instant, meta: M) -> Self {
+        Self {
+            instant,
+            meta,
+            timeout: None,
+        }
+    }
+}
+
+/// What [`DelaySessionStore::admit_push`] lets a push do.
+enum PushAdmission {
+    Deliver,
+    /// The store is paused with `PausedPushes::Buffer`.
+    Buffer,
+}
+
+/// Replays a buffered push at its own instant once the store resumed at the
+/// given instant.
+type BufferedPush<K, T, O, M> =
+    Box<dyn for<'a> FnOnce(&'a DelaySessionStore<K, T, O, M>, Instant) -> BoxFuture<'a, ()> + Send>;
+
+impl<K, T, O, M> Debug for DelaySessionStore<K, T, O, M>
+where
+    K: Debug,
+    O: Debug,
+    M: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DelaySessionStore")
+            .field("timeout", &self.config.timeout)
+            .field("backend", &self.backend)
+            .field("result_sender", &self.emitter.result_sender)
+            .field("clock", &self.clock)
+            .field("instant_policy", &self.instant_policy)
+            .field("forensics", &self.observers.forensics)
+            .field("key_stats", &self.observers.key_stats)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What every pushed signal is fed to besides its session.
+struct SignalObservers<K> {
+    forensics: Option<ForensicBuffer<K>>,
+    key_stats: Option<KeyStatsTracker<K>>,
+    tap: Option<SignalTap<K>>,
+    sampler: Option<Arc<Sampler<K>>>,
+}
+
+impl<K> Default for SignalObservers<K> {
+    fn default() -> Self {
+        Self {
+            forensics: None,
+            key_stats: None,
+            tap: None,
+            sampler: None,
+        }
+    }
+}
+
+impl<K> SignalObservers<K>
+where
+    K: Clone + Eq + Hash,
+{
+    fn observe<Q>(&self, key: &Q, to_owned: impl Fn(&Q) -> K, instant: Instant)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(forensics) = &self.forensics {
+            forensics.record(key, &to_owned, instant);
+        }
+        if let Some(key_stats) = &self.key_stats {
+            key_stats.record(key, &to_owned, instant);
+        }
+        if let Some(tap) = &self.tap {
+            tap.record(to_owned(key), instant);
+        }
+    }
+
+    /// Whether the signal should reach its session, rather than be sampled
+    /// out.
+    fn admit<Q>(&self, key: &Q, to_owned: impl Fn(&Q) -> K, instant: Instant) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.sampler
+            .as_ref()
+            .is_none_or(|sampler| sampler.admit(key, to_owned, instant))
+    }
+}
+
+impl<K, T, O, M> DelaySessionStore<K, T, O, M> {
+    fn new(
+        config: SessionConfig,
+        backend: StoreBackend<K, O, M>,
+        emitter: ResultEmitter<K, T, O>,
+        clock: Arc<dyn Clock>,
+        instant_policy: InstantPolicy,
+        observers: SignalObservers<K>,
+    ) -> Self {
+        let sender_map = match &backend {
+            StoreBackend::Task(sender_map) => Arc::downgrade(sender_map),
+            StoreBackend::TimerWheel { .. } => Weak::new(),
+        };
+        let pause = watch::Sender::new(PauseState {
+            paused: None,
+            total: Duration::ZERO,
+        });
+        let link = Arc::new(RwLock::new(SessionLink::Store(LinkTarget {
+            sender_map,
+            emitter: emitter.clone(),
+            pause: pause.subscribe(),
+        })));
+        let metrics = emitter.metrics.clone();
+
+        Self {
+            config,
+            backend,
+            emitter,
+            link,
+            metrics,
+            clock,
+            instant_policy,
+            observers,
+            pause,
+            buffered: StdMutex::new(Vec::new()),
+            #[cfg(feature = "tokio-util")]
+            _drain: None,
+        }
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    pub fn metrics(&self) -> &StoreMetrics {
+        &self.metrics
+    }
+
+    pub const fn instant_policy(&self) -> &InstantPolicy {
+        &self.instant_policy
+    }
+
+    fn screen(&self) -> InstantScreen<'_> {
+        self.instant_policy.screen(&*self.clock, &self.metrics)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused().is_some()
+    }
+
+    fn paused(&self) -> Option<PausedPushes> {
+        self.pause.borrow().paused.map(|(_, pushes)| pushes)
+    }
 }
 
-impl<K> DelaySessionStore<K>
+impl<K, T, O, M> DelaySessionStore<K, T, O, M>
 where
     K: Clone + Eq + Hash + Send + 'static,
+    T: Send + 'static,
+    O: DecoderOutput + Clone + Send + 'static,
+    M: Send + 'static,
 {
+    /// Pushes a signal with the store's default metadata.
     pub async fn push_signal<D>(
         &self,
-        mut key: K,
+        key: K,
         instant: Instant,
-        mut decoder_factory: impl FnMut() -> D + Send + 'static,
-    ) -> Result<(), ()>
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Result<(), PushError>
     where
-        D: DelayDecoder + Send + 'static,
+        M: Default,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
     {
-        match self.sender_map.lock().await.entry(key.clone()) {
-            Entry::Occupied(entry) => {
-                let sender = entry.get();
-                sender
-                    .send(Signal {
-                        instant,
-                        timeout_instant: instant + self.timeout_duration,
-                    })
-                    .await
-                    .map_err(|_| ())
+        self.push_signal_with(key, instant, M::default(), decoder_factory)
+            .await
+    }
+
+    /// Pushes a signal carrying `meta`, which the session's decoder receives
+    /// along with the duration the signal ends through
+    /// [`MetaDelayDecoder::push_duration_with`]. The metadata of a session's
+    /// first signal ends no duration, so no decoder sees it.
+    pub async fn push_signal_with<D>(
+        &self,
+        key: K,
+        instant: Instant,
+        meta: M,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Result<(), PushError>
+    where
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        self.push(
+            key,
+            SignalPush::new(instant, meta),
+            self.screen(),
+            decoder_factory,
+        )
+        .await
+    }
+
+    /// Like [`push_signal`](Self::push_signal), but times `key`'s session
+    /// out `timeout` after this signal and each later one, in place of the
+    /// store's policy, so keys of different cadence can wait differently.
+    /// The session keeps the timeout, also when a late signal restarts it,
+    /// until another push with a timeout changes it. A new session for the
+    /// key starts with the store's policy again.
+    pub async fn push_signal_with_timeout<D>(
+        &self,
+        key: K,
+        instant: Instant,
+        timeout: Duration,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Result<(), PushError>
+    where
+        M: Default,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        let push = SignalPush {
+            timeout: Some(timeout),
+            ..SignalPush::new(instant, M::default())
+        };
+        self.push(key, push, self.screen(), decoder_factory).await
+    }
+
+    /// Pushes a signal, fixing its session's timeout if the push has one.
+    async fn push<D>(
+        &self,
+        key: K,
+        push: SignalPush<M>,
+        screen: InstantScreen<'_>,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Result<(), PushError>
+    where
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        if let PushAdmission::Buffer = self.admit_push()? {
+            self.buffer_push(key, push, decoder_factory);
+            return Ok(());
+        }
+        if !self.admit_signal(&key, K::clone, push.instant) {
+            return Ok(());
+        }
+
+        match &self.backend {
+            StoreBackend::Task(sender_map) => {
+                let mut sender_map = sender_map.lock().await;
+                let queued = self
+                    .push_locked_task_signal(
+                        &mut sender_map,
+                        &screen,
+                        key,
+                        push,
+                        &mut Some(decoder_factory),
+                    )
+                    .await;
+                drop(sender_map);
+                self.delivered(finish_queued(queued).await)
             }
+            StoreBackend::TimerWheel { sessions, .. } => {
+                let pushed = sessions.push_signal(&key, K::clone, push, screen, decoder_factory);
+                self.finish_wheel_push(pushed, || key).await
+            }
+        }
+    }
 
-            Entry::Vacant(entry) => {
-                let (signal_sender, session) =
-                    delay_session(decoder_factory(), instant, instant + self.timeout_duration);
-                entry.insert(signal_sender);
+    /// Pushes a signal stamped with the store's clock.
+    pub async fn push_signal_now<D>(
+        &self,
+        key: K,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Result<(), PushError>
+    where
+        M: Default,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        self.push_signal(key, self.clock.now(), decoder_factory)
+            .await
+    }
 
-                let sender_map = Arc::downgrade(&self.sender_map);
-                let result_sender = self.result_sender.clone();
+    /// Pushes a keepalive stamped with the store's clock, extending `key`'s
+    /// timeout without ending a duration, see
+    /// [`SignalKind::Keepalive`](crate::session::SignalKind::Keepalive). By
+    /// default the next signal's duration still spans back to the previous
+    /// signal, see [`KeepalivePolicy`]. Unlike
+    /// [`push_signal`](Self::push_signal) it never starts a session, and
+    /// returns whether `key` had one that it kept open: a keepalive finding
+    /// the session past its deadline closes it as timed out. While the store
+    /// is paused, keepalives fail with `PushError::Paused` or, when pushes
+    /// are buffered, are dropped.
+    pub async fn push_keepalive<Q>(&self, key: &Q) -> Result<bool, PushError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        M: Default,
+    {
+        if let PushAdmission::Buffer = self.admit_push()? {
+            return Ok(false);
+        }
 
-                tokio::spawn(async move {
-                    struct UniqueSenderRemoveGuard<'a, K>
-                    where
-                        K: Clone + Eq + Hash + Send + 'static,
-                    {
-                        key: &'a mut K,
-                        sender_map: Weak<SharedSignalSenderMap<K>>,
-                    }
+        let instant = self.clock.now();
+        match &self.backend {
+            StoreBackend::Task(sender_map) => {
+                let mut sender_map = sender_map.lock().await;
+                let Some(entry) = sender_map.get_mut(key) else {
+                    return Ok(false);
+                };
+                if let Some(deadline) = entry.keep_alive(instant, self.config.max_lifetime) {
+                    let queued = QueuedSignal::send(
+                        &entry.sender,
+                        &entry.queued,
+                        Signal::keepalive(instant, deadline),
+                    )
+                    .await;
+                    drop(sender_map);
+                    return self.delivered(finish_queued(queued).await).map(|()| true);
+                }
+                // The keepalive closes the session if its timer has not yet.
+                if let Some(entry) = sender_map.remove(key) {
+                    drop(sender_map);
+                    let keepalive = Signal::keepalive(instant, entry.timeout.deadline());
+                    let _ = entry.sender.send(keepalive).await;
+                }
+                Ok(false)
+            }
+            StoreBackend::TimerWheel { sessions, .. } => match sessions.keep_alive(key, instant) {
+                None => Ok(false),
+                Some(None) => Ok(true),
+                Some(Some((key, bits, summary))) => {
+                    self.emitter.emit(key, bits, summary).await;
+                    Ok(false)
+                }
+            },
+        }
+    }
 
-                    impl<K> Drop for UniqueSenderRemoveGuard<'_, K>
-                    where
-                        K: Clone + Eq + Hash + Send + 'static,
-                    {
-                        fn drop(&mut self) {
-                            if let Some(map) = self.sender_map.upgrade() {
-                                let key = self.key.clone();
-                                tokio::spawn(async move {
-                                    map.lock().await.remove(&key);
-                                });
-                            }
-                        }
-                    }
+    /// Pauses `key`'s session, e.g. while its sender is under maintenance:
+    /// its timeout stops until [`resume_key`](Self::resume_key), and signals
+    /// pushed meanwhile, up to the store's signal capacity, are held and
+    /// taken in on resume as
+    /// [`SignalKind::Pause`](crate::session::SignalKind::Pause) says: they
+    /// keep the gaps between them, moved so the latest lands on the resume.
+    /// The duration spanning the pause, up to the earliest, is measured as
+    /// the store's [`PauseGapPolicy`] says. Returns whether `key` had a session that is
+    /// now paused: pausing finds a session past its deadline closed as timed
+    /// out. Stamped and affected by a pause of the whole store like
+    /// [`push_keepalive`](Self::push_keepalive).
+    pub async fn pause_key<Q>(&self, key: &Q) -> Result<bool, PushError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        M: Default,
+    {
+        if let PushAdmission::Buffer = self.admit_push()? {
+            return Ok(false);
+        }
+
+        let instant = self.clock.now();
+        match &self.backend {
+            StoreBackend::Task(sender_map) => {
+                let mut sender_map = sender_map.lock().await;
+                let Some(entry) = sender_map.get_mut(key) else {
+                    return Ok(false);
+                };
+                if entry.paused.is_some() {
+                    return Ok(true);
+                }
+                if !entry.timed_out(instant, self.config.max_lifetime) {
+                    entry.paused = Some((instant, 0));
+                    let queued =
+                        QueuedSignal::send(&entry.sender, &entry.queued, Signal::pause(instant))
+                            .await;
+                    drop(sender_map);
+                    return self.delivered(finish_queued(queued).await).map(|()| true);
+                }
+                // The pause closes the session if its timer has not yet.
+                if let Some(entry) = sender_map.remove(key) {
+                    drop(sender_map);
+                    let _ = entry.sender.send(Signal::pause(instant)).await;
+                }
+                Ok(false)
+            }
+            StoreBackend::TimerWheel { sessions, .. } => match sessions.pause(key, instant) {
+                None => Ok(false),
+                Some(None) => Ok(true),
+                Some(Some((key, bits, summary))) => {
+                    self.emitter.emit(key, bits, summary).await;
+                    Ok(false)
+                }
+            },
+        }
+    }
+
+    /// Resumes `key`'s session paused with [`pause_key`](Self::pause_key),
+    /// re-arming its timeout with the time that was left, and returns
+    /// whether it was paused.
+    pub async fn resume_key<Q>(&self, key: &Q) -> Result<bool, PushError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        M: Default,
+    {
+        if let PushAdmission::Buffer = self.admit_push()? {
+            return Ok(false);
+        }
+
+        let instant = self.clock.now();
+        match &self.backend {
+            StoreBackend::Task(sender_map) => {
+                let mut sender_map = sender_map.lock().await;
+                let Some(entry) = sender_map.get_mut(key) else {
+                    return Ok(false);
+                };
+                if !entry.resume(instant, self.config.pause_gap, self.config.signal_capacity) {
+                    return Ok(false);
+                }
+                let queued =
+                    QueuedSignal::send(&entry.sender, &entry.queued, Signal::resume(instant)).await;
+                drop(sender_map);
+                self.delivered(finish_queued(queued).await).map(|()| true)
+            }
+            StoreBackend::TimerWheel { sessions, .. } => match sessions.resume(key, instant) {
+                None => Ok(false),
+                Some(None) => Ok(true),
+                Some(Some((key, bits, summary))) => {
+                    self.emitter
+                        .report(DiagnosticReason::SessionDurationLimit, || key.clone());
+                    self.emitter.emit(key, bits, summary).await;
+                    Ok(true)
+                }
+            },
+        }
+    }
+
+    /// Pushes `instants` to `key`'s session in order, as one `push_signal`
+    /// each would, but locking the store once: the sender map with one task
+    /// per key, the key's shard with the timer wheel. Every session started
+    /// takes a clone of `decoder_factory`. A failing signal does not stop the
+    /// rest, and the first error is returned.
+    pub async fn push_signals<D, F>(
+        &self,
+        key: K,
+        instants: impl IntoIterator<Item = Instant>,
+        decoder_factory: F,
+    ) -> Result<(), PushError>
+    where
+        M: Default,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+        F: FnMut() -> D + Clone + Send + 'static,
+    {
+        if let PushAdmission::Buffer = self.admit_push()? {
+            for instant in instants {
+                let decoder_factory = decoder_factory.clone();
+                let push = SignalPush::new(instant, M::default());
+                self.buffer_push(key.clone(), push, decoder_factory);
+            }
+            return Ok(());
+        }
+        let instants: Vec<_> = instants
+            .into_iter()
+            .filter(|&instant| self.admit_signal(&key, K::clone, instant))
+            .collect();
 
-                    struct SharedSenderRemoveGuard<'a, K>
-                    where
-                        K: Clone + Eq + Hash + Send + 'static,
-                    {
-                        key: &'a K,
-                        sender_map: Weak<SharedSignalSenderMap<K>>,
+        let mut result = Ok(());
+        match &self.backend {
+            StoreBackend::Task(sender_map) => {
+                let screen = self.screen();
+                let mut sender_map = sender_map.lock().await;
+                let mut factory = None;
+                let mut queued = Vec::new();
+                for instant in instants {
+                    factory.get_or_insert_with(|| decoder_factory.clone());
+                    let pushed = self
+                        .push_locked_task_signal(
+                            &mut sender_map,
+                            &screen,
+                            key.clone(),
+                            SignalPush::new(instant, M::default()),
+                            &mut factory,
+                        )
+                        .await;
+                    match pushed {
+                        Ok(pushed) => queued.extend(pushed),
+                        Err(err) => result = result.and(Err(err)),
                     }
+                }
+                drop(sender_map);
+                for queued in queued {
+                    result = result.and(queued.finish().await);
+                }
+                result = self.delivered(result);
+            }
+            StoreBackend::TimerWheel { sessions, .. } => {
+                let pushed =
+                    sessions.push_signals(&key, K::clone, instants, self.screen(), decoder_factory);
+                for pushed in pushed {
+                    result = result.and(self.finish_wheel_push(pushed, || key.clone()).await);
+                }
+            }
+        }
+        result
+    }
 
-                    impl<K> Drop for SharedSenderRemoveGuard<'_, K>
-                    where
-                        K: Clone + Eq + Hash + Send + 'static,
-                    {
-                        fn drop(&mut self) {
-                            if let Some(map) = self.sender_map.upgrade() {
-                                let key = self.key.clone();
-                                tokio::spawn(async move {
-                                    map.lock().await.remove(&key);
-                                });
+    /// Like `push_signal`, but looks the key up by reference, so pushing to an
+    /// existing session never allocates an owned key. An owned `K` is only
+    /// created when a new session starts.
+    pub async fn push_signal_ref<Q, D>(
+        &self,
+        key: &Q,
+        instant: Instant,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Result<(), PushError>
+    where
+        K: Borrow<Q> + for<'a> From<&'a Q>,
+        Q: Hash + Eq + ?Sized,
+        M: Default,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        if let PushAdmission::Buffer = self.admit_push()? {
+            let push = SignalPush::new(instant, M::default());
+            self.buffer_push(K::from(key), push, decoder_factory);
+            return Ok(());
+        }
+        if !self.admit_signal(key, |key: &Q| K::from(key), instant) {
+            return Ok(());
+        }
+
+        match &self.backend {
+            StoreBackend::Task(sender_map) => {
+                let screen = self.screen();
+                let mut sender_map = sender_map.lock().await;
+                if let Some(entry) = sender_map.get_mut(key) {
+                    let instant = match entry.screen(&screen, instant, &self.config, None) {
+                        Screened::Accept(instant) => instant,
+                        Screened::Reject => {
+                            self.emitter
+                                .report(DiagnosticReason::ImplausibleInstant, || K::from(key));
+                            return Err(PushError::ImplausibleInstant);
+                        }
+                        Screened::CloseSession => {
+                            if let Some(entry) = sender_map.remove(key) {
+                                entry.close(CloseReason::Flushed);
                             }
+                            self.emitter
+                                .report(DiagnosticReason::SessionClosedImplausible, || {
+                                    K::from(key)
+                                });
+                            return Err(PushError::ImplausibleInstant);
                         }
-                    }
+                    };
+                    let signal = Signal::new(instant, entry.timeout.deadline(), M::default());
+                    let queued = QueuedSignal::send(&entry.sender, &entry.queued, signal).await;
+                    drop(sender_map);
+                    return self.delivered(finish_queued(queued).await);
+                }
 
-                    let mut session = session;
-                    loop {
-                        let guard = UniqueSenderRemoveGuard {
-                            key: &mut key,
-                            sender_map: sender_map.clone(),
-                        };
+                let Screened::Accept(instant) = screen.check(None, instant) else {
+                    self.emitter
+                        .report(DiagnosticReason::ImplausibleInstant, || K::from(key));
+                    return Err(PushError::ImplausibleInstant);
+                };
+                self.start_task_session(
+                    &mut sender_map,
+                    &screen,
+                    K::from(key),
+                    instant,
+                    None,
+                    decoder_factory,
+                )
+            }
+            StoreBackend::TimerWheel { sessions, .. } => {
+                let pushed = sessions.push_signal(
+                    key,
+                    |key: &Q| K::from(key),
+                    SignalPush::new(instant, M::default()),
+                    self.screen(),
+                    decoder_factory,
+                );
+                self.finish_wheel_push(pushed, || K::from(key)).await
+            }
+        }
+    }
+
+    /// Pushes a signal to the locked sender map, starting the key's session
+    /// with the factory taken from `decoder_factory` if it has none. A
+    /// push's timeout fixes the session's timeout from this signal on. A
+    /// signal for a full channel is returned queued, to be finished once the
+    /// map is unlocked.
+    async fn push_locked_task_signal<D, F>(
+        &self,
+        sender_map: &mut HashMap<K, SessionEntry<O, M>>,
+        screen: &InstantScreen<'_>,
+        key: K,
+        push: SignalPush<M>,
+        decoder_factory: &mut Option<F>,
+    ) -> Result<Option<QueuedSignal<M>>, PushError>
+    where
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+        F: FnMut() -> D + Send + 'static,
+    {
+        let SignalPush {
+            instant,
+            meta,
+            timeout,
+        } = push;
+        let Some(entry) = sender_map.get_mut(&key) else {
+            let Screened::Accept(instant) = screen.check(None, instant) else {
+                self.emitter
+                    .report(DiagnosticReason::ImplausibleInstant, || key);
+                return Err(PushError::ImplausibleInstant);
+            };
+            // The map stays locked, so the session cannot go away and need a
+            // second factory within one push.
+            let decoder_factory = decoder_factory
+                .take()
+                .expect("a push starts at most one session");
+            return self
+                .start_task_session(sender_map, screen, key, instant, timeout, decoder_factory)
+                .map(|()| None);
+        };
 
-                        let (result, signal_receiver) = session.await;
+        let instant = match entry.screen(screen, instant, &self.config, timeout) {
+            Screened::Accept(instant) => instant,
+            Screened::Reject => {
+                self.emitter
+                    .report(DiagnosticReason::ImplausibleInstant, || key);
+                return Err(PushError::ImplausibleInstant);
+            }
+            Screened::CloseSession => {
+                if let Some(entry) = sender_map.remove(&key) {
+                    entry.close(CloseReason::Flushed);
+                }
+                self.emitter
+                    .report(DiagnosticReason::SessionClosedImplausible, || key);
+                return Err(PushError::ImplausibleInstant);
+            }
+        };
+        QueuedSignal::send(
+            &entry.sender,
+            &entry.queued,
+            Signal::new(instant, entry.timeout.deadline(), meta),
+        )
+        .await
+    }
 
-                        forget(guard);
+    fn buffer_push<D>(
+        &self,
+        key: K,
+        push: SignalPush<M>,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) where
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        let pushed_at = self.clock.now();
+        self.buffered
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(move |store, resumed_at| {
+                Box::pin(async move {
+                    let screen = store.screen().replay(pushed_at, resumed_at);
+                    let _ = store.push(key, push, screen, decoder_factory).await;
+                })
+            }));
+    }
 
-                        let guard = SharedSenderRemoveGuard {
-                            key: &key,
-                            sender_map: sender_map.clone(),
-                        };
+    /// Pauses the store, e.g. for a maintenance window: the timeouts of its
+    /// sessions stop progressing until [`resume`](Self::resume), and pushes
+    /// are rejected or buffered as `pushes` says. Pausing a paused store only
+    /// changes what happens to pushes.
+    ///
+    /// The pause is measured by the store's clock, like session timeouts.
+    pub fn pause(&self, pushes: PausedPushes) {
+        self.pause.send_modify(|state| {
+            let paused_at = state.paused.map_or_else(|| self.clock.now(), |(at, _)| at);
+            state.paused = Some((paused_at, pushes));
+        });
+        if let StoreBackend::TimerWheel { sessions, .. } = &self.backend {
+            sessions.set_paused(true);
+        }
+    }
 
-                        session =
-                            DelaySession::start_with_receiver(decoder_factory(), signal_receiver);
-
-                        if !session.is_open() {
-                            if let Some(map) = sender_map.upgrade() {
-                                let key_clone = key.clone();
-                                forget(guard);
-                                let key_mut = &mut key;
-                                join!(
-                                    async move {
-                                        map.lock().await.remove(&*key_mut);
-                                    },
-                                    async move {
-                                        let _ = result_sender.send((key_clone, result)).await;
-                                    }
-                                );
-                            } else {
-                                forget(guard);
-                                let _ = result_sender.send((key, result)).await;
-                            }
-                            break;
-                        } else {
-                            let key_clone = key.clone();
-                            forget(guard);
-                            let guard = UniqueSenderRemoveGuard {
-                                key: &mut key,
-                                sender_map: sender_map.clone(),
-                            };
-                            let _ = result_sender.send((key_clone, result)).await;
-                            forget(guard);
-                        }
+    /// Resumes a paused store. The pause is left out of the timeouts of the
+    /// sessions that were open, which are extended by its length, but not
+    /// out of their durations, which span it. Buffered pushes are then
+    /// delivered in order, each at its own instant, and the timeout that
+    /// starts at a buffered signal leaves out the rest of the pause. A
+    /// buffered push is screened by the store's [`InstantPolicy`] as of when
+    /// it was made.
+    pub async fn resume(&self) {
+        let Some((paused_at, _)) = self.pause.borrow().paused else {
+            return;
+        };
+        let now = self.clock.now();
+        let paused_for = now.saturating_duration_since(paused_at);
+
+        match &self.backend {
+            StoreBackend::Task(sender_map) => {
+                for entry in sender_map.lock().await.values_mut() {
+                    entry.timeout.extend(paused_for);
+                    if let Some((paused_at, _)) = &mut entry.paused {
+                        *paused_at = timeout_instant(*paused_at, paused_for);
                     }
-                });
+                }
+            }
+            StoreBackend::TimerWheel { sessions, .. } => {
+                sessions.shift(paused_for);
+                sessions.set_paused(false);
+            }
+        }
+        self.pause.send_modify(|state| {
+            state.paused = None;
+            state.total += paused_for;
+        });
 
-                Ok(())
+        let buffered = take(&mut *self.buffered.lock().unwrap_or_else(PoisonError::into_inner));
+        for push in buffered {
+            push(self, now).await;
+        }
+    }
+
+    /// Waits until the store takes in pushes, for the readiness of
+    /// [`DelaySessionService`]: while it is paused rejecting pushes, while
+    /// it has its `max_sessions` and rejects new keys, and, unless results
+    /// overflow, while its result stream is full, as then sessions closing
+    /// wait to emit. Fails like a push once the store is cancelled or its
+    /// result stream is closed.
+    #[cfg(feature = "tower")]
+    pub(crate) async fn push_ready(&self) -> Result<(), PushError> {
+        loop {
+            let mut pause = self.pause.subscribe();
+            match self.admit_push() {
+                Err(PushError::Paused) => {
+                    let resumed = pin!(pause.wait_for(|state| {
+                        !matches!(state.paused, Some((_, PausedPushes::Reject)))
+                    }));
+                    select(resumed, pin!(self.emitter.cancellation.cancelled())).await;
+                    continue;
+                }
+                Err(err) => return Err(err),
+                Ok(_) => {}
+            }
+            if let Some((max_sessions, SessionLimitPolicy::Reject)) = self.config.max_sessions {
+                let mut closed = pin!(self.emitter.closed.notified());
+                closed.as_mut().enable();
+                if self.session_count().await >= max_sessions {
+                    select(closed, pin!(self.emitter.cancellation.cancelled())).await;
+                    continue;
+                }
+            }
+            if self.emitter.overflow != ResultOverflow::Wait {
+                return Ok(());
+            }
+            let room = pin!(self.emitter.result_sender.has_room());
+            match select(room, pin!(self.emitter.cancellation.cancelled())).await {
+                Either::Left((true, _)) => return Ok(()),
+                Either::Left((false, _)) => return Err(PushError::ResultStreamClosed),
+                Either::Right(_) => {}
             }
         }
     }
-}
 
-#[derive(Debug)]
-pub struct DelaySessionStream<K> {
-    receiver: Receiver<(K, BitVec)>,
-}
+    /// Tells a session closed by the store's cancellation from one that
+    /// closed while the store kept running.
+    fn delivered<R>(&self, result: Result<R, PushError>) -> Result<R, PushError> {
+        match result {
+            Err(PushError::SessionClosed) if self.emitter.cancellation.is_cancelled() => {
+                Err(PushError::StoreShutdown)
+            }
+            result => result,
+        }
+    }
+
+    /// Checks every push before it reaches the store's sessions: it fails
+    /// once the store is cancelled or its result stream is closed, and while
+    /// the store is paused it fails or is to be buffered, as the pause says.
+    fn admit_push(&self) -> Result<PushAdmission, PushError> {
+        if self.emitter.cancellation.is_cancelled() {
+            return Err(PushError::Cancelled);
+        }
+        if self.emitter.is_closed() {
+            return Err(PushError::ResultStreamClosed);
+        }
+        match self.paused() {
+            Some(PausedPushes::Reject) => Err(PushError::Paused),
+            Some(PausedPushes::Buffer) => Ok(PushAdmission::Buffer),
+            None => Ok(PushAdmission::Deliver),
+        }
+    }
 
-impl<K> Stream for DelaySessionStream<K> {
-    type Item = (K, BitVec);
+    /// Feeds a pushed signal to the forensic buffer, the key statistics and
+    /// the tap, if any, and returns whether sampling lets it reach its
+    /// session. Signals sampled out are counted in the store's metrics.
+    fn admit_signal<Q>(&self, key: &Q, to_owned: impl Fn(&Q) -> K, instant: Instant) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.observers.observe(key, &to_owned, instant);
+        let admitted = self.observers.admit(key, to_owned, instant);
+        if !admitted {
+            self.metrics.record_push_outcome(PushOutcome::SampledOut);
+        }
+        admitted
+    }
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.receiver.poll_recv(cx)
+    /// Subscribes to diagnostics about signals the store dropped or rejected
+    /// and results it failed to send, from now on.
+    pub fn subscribe_diagnostics(&self) -> DiagnosticStream<K> {
+        self.emitter.diagnostics.subscribe()
     }
-}
 
-pub fn delay_session_store<K>(
-    timeout_duration: Duration,
-) -> (DelaySessionStore<K>, DelaySessionStream<K>) {
-    let (sender, receiver) = channel(8);
+    /// Subscribes to the results the store drops from now on: those dropped
+    /// by its `ResultOverflow` policy, those lost to a closed result stream,
+    /// and, if the store was built with
+    /// [`DelaySessionStoreBuilder::dead_letter_suppressed`], the bits of
+    /// those its result mapper suppressed. Subscribing again ends the
+    /// previous subscription's stream.
+    pub fn subscribe_dead_letters(&self) -> DeadLetterStream<K, T, O> {
+        self.emitter.dead_letters.subscribe()
+    }
 
-    (
-        DelaySessionStore {
-            timeout_duration,
-            sender_map: Default::default(),
-            result_sender: sender,
-        },
-        DelaySessionStream { receiver },
-    )
+    /// Returns the latest raw signals pushed for `key`, if the store keeps a
+    /// forensic buffer and still remembers the key. Keys are remembered after
+    /// their sessions close, until evicted.
+    pub fn export_forensics<Q>(&self, key: &Q) -> Option<ForensicTrace>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.observers.forensics.as_ref()?.export(key)
+    }
+
+    /// Returns rolling timing statistics of `key`'s recent signals, if the
+    /// store keeps key statistics and still tracks the key. Keys are tracked
+    /// across sessions, until evicted.
+    pub fn stats_for<Q>(&self, key: &Q) -> Option<KeyStats>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.observers
+            .key_stats
+            .as_ref()?
+            .stats(key, self.clock.now())
+    }
+
+    async fn finish_wheel_push(
+        &self,
+        pushed: PushedSignal<K, O>,
+        key: impl FnOnce() -> K,
+    ) -> Result<(), PushError> {
+        let (rejected, at_session_limit) = (pushed.rejected, pushed.at_session_limit);
+        self.report_wheel_push(&pushed, key);
+        if let Some((key, bits, summary)) = pushed.closed {
+            self.emitter.emit(key, bits, summary).await;
+        }
+
+        if rejected {
+            Err(PushError::ImplausibleInstant)
+        } else if at_session_limit {
+            Err(PushError::SessionLimitReached)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reports a signal the timer wheel rejected, which closed its session if
+    /// it returned one, one that closed its session at the duration limit,
+    /// or one that met the session limit, evicting the session it returned.
+    fn report_wheel_push(&self, pushed: &PushedSignal<K, O>, key: impl FnOnce() -> K) {
+        if pushed.at_session_limit {
+            return self
+                .emitter
+                .report(DiagnosticReason::SessionLimitReached, key);
+        }
+        let reason = match (pushed.rejected, pushed.limit_reached, pushed.evicted) {
+            (true, ..) => DiagnosticReason::SessionClosedImplausible,
+            (false, true, _) => DiagnosticReason::SessionDurationLimit,
+            (false, false, true) => DiagnosticReason::SessionEvicted,
+            (false, false, false) => return,
+        };
+        match &pushed.closed {
+            Some((closed, ..)) => self.emitter.report(reason, || closed.clone()),
+            None => self
+                .emitter
+                .report(DiagnosticReason::ImplausibleInstant, key),
+        }
+    }
+
+    /// Pushes a signal without ever awaiting: the map lock is only tried, and
+    /// the signal is dropped instead of waiting for room in a full session
+    /// channel. Dropped signals are counted in the store's metrics.
+    pub fn try_push_signal<D>(
+        &self,
+        key: K,
+        instant: Instant,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> PushOutcome
+    where
+        M: Default,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        match self.admit_push() {
+            Ok(PushAdmission::Deliver) => {}
+            Ok(PushAdmission::Buffer) => {
+                let push = SignalPush::new(instant, M::default());
+                self.buffer_push(key, push, decoder_factory);
+                return PushOutcome::Buffered;
+            }
+            Err(PushError::Paused) => return PushOutcome::RejectedPaused,
+            Err(PushError::ResultStreamClosed) => return PushOutcome::ResultStreamClosed,
+            Err(_) => return PushOutcome::Cancelled,
+        }
+        if !self.admit_signal(&key, K::clone, instant) {
+            return PushOutcome::SampledOut;
+        }
+
+        let screen = self.screen();
+        let outcome = match &self.backend {
+            StoreBackend::Task(sender_map) => match sender_map.try_lock() {
+                Ok(mut sender_map) => match sender_map.get_mut(&key) {
+                    Some(entry) => {
+                        let instant = match entry.screen(&screen, instant, &self.config, None) {
+                            Screened::Accept(instant) => instant,
+                            Screened::Reject => {
+                                self.emitter
+                                    .report(DiagnosticReason::ImplausibleInstant, || key);
+                                return PushOutcome::RejectedImplausible;
+                            }
+                            Screened::CloseSession => {
+                                if let Some(entry) = sender_map.remove(&key) {
+                                    entry.close(CloseReason::Flushed);
+                                }
+                                self.emitter
+                                    .report(DiagnosticReason::SessionClosedImplausible, || key);
+                                return PushOutcome::RejectedImplausible;
+                            }
+                        };
+                        match entry.sender.try_send(Signal::new(
+                            instant,
+                            entry.timeout.deadline(),
+                            M::default(),
+                        )) {
+                            Ok(()) => PushOutcome::Delivered,
+                            Err(TrySendError::Full(_)) => {
+                                instrument::signal_dropped(
+                                    self.emitter.redactor(),
+                                    &key,
+                                    "session queue full",
+                                );
+                                self.emitter
+                                    .report(DiagnosticReason::SessionQueueFull, || key);
+                                PushOutcome::DroppedFull
+                            }
+                            Err(TrySendError::Closed(_))
+                                if self.emitter.cancellation.is_cancelled() =>
+                            {
+                                PushOutcome::Cancelled
+                            }
+                            Err(TrySendError::Closed(_)) => PushOutcome::SessionClosed,
+                        }
+                    }
+                    None => match screen.check(None, instant) {
+                        Screened::Accept(instant) => {
+                            match self.start_task_session(
+                                &mut sender_map,
+                                &screen,
+                                key,
+                                instant,
+                                None,
+                                decoder_factory,
+                            ) {
+                                Ok(()) => PushOutcome::Delivered,
+                                Err(_) => PushOutcome::RejectedSessionLimit,
+                            }
+                        }
+                        Screened::Reject | Screened::CloseSession => {
+                            self.emitter
+                                .report(DiagnosticReason::ImplausibleInstant, || key);
+                            PushOutcome::RejectedImplausible
+                        }
+                    },
+                },
+                Err(_) => {
+                    instrument::signal_dropped(self.emitter.redactor(), &key, "store lock busy");
+                    self.emitter.report(DiagnosticReason::LockBusy, || key);
+                    PushOutcome::DroppedLockBusy
+                }
+            },
+            StoreBackend::TimerWheel { sessions, .. } => {
+                match sessions.try_push_signal(
+                    &key,
+                    K::clone,
+                    SignalPush::new(instant, M::default()),
+                    screen,
+                    decoder_factory,
+                ) {
+                    Ok(pushed) => {
+                        let (rejected, at_session_limit) =
+                            (pushed.rejected, pushed.at_session_limit);
+                        self.report_wheel_push(&pushed, || key);
+                        if let Some((key, bits, summary)) = pushed.closed {
+                            self.emitter.emit_now(key, bits, summary);
+                        }
+
+                        if rejected {
+                            PushOutcome::RejectedImplausible
+                        } else if at_session_limit {
+                            PushOutcome::RejectedSessionLimit
+                        } else {
+                            PushOutcome::Delivered
+                        }
+                    }
+                    Err(ShardBusy) => {
+                        instrument::signal_dropped(
+                            self.emitter.redactor(),
+                            &key,
+                            "shard lock busy",
+                        );
+                        self.emitter.report(DiagnosticReason::LockBusy, || key);
+                        PushOutcome::DroppedLockBusy
+                    }
+                }
+            }
+        };
+
+        self.metrics.record_push_outcome(outcome);
+        outcome
+    }
+
+    /// Makes room for a session under the store's `max_sessions`, closing
+    /// the least recently signaled one if its policy says so.
+    fn make_room(
+        &self,
+        sender_map: &mut HashMap<K, SessionEntry<O, M>>,
+        key: &K,
+    ) -> Result<(), PushError> {
+        let Some((max_sessions, policy)) = self.config.max_sessions else {
+            return Ok(());
+        };
+        while sender_map.len() >= max_sessions {
+            if policy == SessionLimitPolicy::Reject {
+                self.emitter
+                    .report(DiagnosticReason::SessionLimitReached, || key.clone());
+                return Err(PushError::SessionLimitReached);
+            }
+            let least_recent = sender_map
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_instant)
+                .map(|(key, _)| key.clone());
+            if let Some((evicted, entry)) =
+                least_recent.and_then(|key| sender_map.remove_entry(&key))
+            {
+                entry.close(CloseReason::Evicted);
+                self.emitter
+                    .report(DiagnosticReason::SessionEvicted, || evicted);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts `key`'s session, which `sender_map` has none for, once there
+    /// is room for it. The timeout of a replayed signal does not count the
+    /// rest of the store's pause.
+    fn start_task_session<D>(
+        &self,
+        sender_map: &mut HashMap<K, SessionEntry<O, M>>,
+        screen: &InstantScreen<'_>,
+        key: K,
+        instant: Instant,
+        fixed_timeout: Option<Duration>,
+        mut decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Result<(), PushError>
+    where
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        self.make_room(sender_map, &key)?;
+        let (closing, closing_reason) = watch::channel(None);
+        let mut timeout = self.config.timeout.start(instant, fixed_timeout);
+        timeout.extend(screen.pause_left(instant));
+        let (signal_sender, session) = delay_session_with_capacity(
+            decoder_factory(),
+            instant,
+            timeout.deadline(),
+            self.config.signal_capacity,
+        );
+        let (snapshot_sender, snapshot_receiver) = channel(1);
+        let id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+        instrument::session_created(self.emitter.redactor(), &key);
+        sender_map.insert(
+            key.clone(),
+            SessionEntry {
+                sender: signal_sender,
+                snapshots: snapshot_sender,
+                paused: None,
+                closing,
+                id,
+                last_instant: instant,
+                timeout,
+                fixed_timeout,
+                started_instant: instant,
+                durations: 0,
+                queued: Arc::default(),
+            },
+        );
+
+        let session_task = SessionTask {
+            key: key.clone(),
+            id,
+            session,
+            decoder_factory,
+            snapshots: snapshot_receiver,
+            closing: closing_reason,
+            link: self.link.clone(),
+            config: self.config.clone(),
+            clock: self.clock.clone(),
+            pause: self.pause.subscribe(),
+            cancellation: self.emitter.cancellation.clone(),
+            emit_interval: self.emitter.emit_interval(),
+        };
+        task::spawn_session(
+            &self.emitter.name,
+            self.emitter.redactor(),
+            &key,
+            self.emitter.cancellation.track(session_task.run()),
+        );
+        Ok(())
+    }
+
+    /// Moves every active session into `target`, so decoding continues
+    /// uninterrupted and their results are emitted on `target`'s stream.
+    ///
+    /// If `target` already has a session for a key, the target's session is
+    /// kept and the migrated one is closed early; its partial result is still
+    /// emitted on `target`'s stream. Both stores must use the same backend,
+    /// otherwise `self` is handed back unchanged.
+    ///
+    /// Migrated sessions are paused, resumed and cancelled with `target`
+    /// from then on, and `target`'s result stream only ends after them.
+    pub async fn migrate_into(self, target: &DelaySessionStore<K, T, O, M>) -> Result<(), Self> {
+        match (&self.backend, &target.backend) {
+            (StoreBackend::Task(source_map), StoreBackend::Task(target_map)) => {
+                let mut source_map = source_map.lock().await;
+                *self.link.write().unwrap_or_else(PoisonError::into_inner) =
+                    SessionLink::Migrated(target.link.clone());
+
+                let mut target_map = target_map.lock().await;
+                for (key, entry) in source_map.drain() {
+                    match target_map.entry(key) {
+                        Entry::Vacant(vacant) => {
+                            vacant.insert(entry);
+                        }
+                        Entry::Occupied(_) => entry.close(CloseReason::Flushed),
+                    }
+                }
+
+                Ok(())
+            }
+            (
+                StoreBackend::TimerWheel {
+                    sessions: source_sessions,
+                    ..
+                },
+                StoreBackend::TimerWheel {
+                    sessions: target_sessions,
+                    ..
+                },
+            ) => {
+                for (key, bits, summary) in source_sessions.migrate_into(target_sessions) {
+                    target.emitter.emit(key, bits, summary).await;
+                }
+
+                Ok(())
+            }
+            _ => Err(self),
+        }
+    }
+
+    /// Ends `key`'s session right away, e.g. on a terminator request, instead
+    /// of waiting out its timeout. Signals pushed before are still decoded,
+    /// and the result is emitted as usual. Returns whether `key` had an open
+    /// session.
+    pub async fn flush<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match &self.backend {
+            StoreBackend::Task(sender_map) => match sender_map.lock().await.remove(key) {
+                Some(entry) => {
+                    entry.close(CloseReason::Flushed);
+                    true
+                }
+                None => false,
+            },
+            StoreBackend::TimerWheel { sessions, .. } => {
+                match sessions.flush(key, CloseReason::Flushed, "flushed") {
+                    Some((key, bits, summary)) => {
+                        self.emitter.emit(key, bits, summary).await;
+                        true
+                    }
+                    None => false,
+                }
+            }
+        }
+    }
+
+    /// Like [`flush`](Self::flush), but fails with `CloseError::NoSession`
+    /// if `key` has no open session. The partial result arrives on the
+    /// result stream, and a later push for `key` starts a new session.
+    pub async fn close_session<Q>(&self, key: &Q) -> Result<(), CloseError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.flush(key).await {
+            Ok(())
+        } else {
+            Err(CloseError::NoSession)
+        }
+    }
+
+    /// Flushes every open session, e.g. on graceful shutdown, so each emits
+    /// what it has decoded. Sessions started by pushes meanwhile stay open.
+    /// Returns how many sessions were closed.
+    pub async fn close_all(&self) -> usize {
+        match &self.backend {
+            StoreBackend::Task(sender_map) => {
+                let entries: Vec<_> = sender_map.lock().await.drain().collect();
+                let closed = entries.len();
+                for (_, entry) in entries {
+                    entry.close(CloseReason::Flushed);
+                }
+                closed
+            }
+            StoreBackend::TimerWheel { sessions, .. } => {
+                let results = sessions.close_all(CloseReason::Flushed, "flushed");
+                let closed = results.len();
+                for (key, bits, summary) in results {
+                    self.emitter.emit(key, bits, summary).await;
+                }
+                closed
+            }
+        }
+    }
+
+    /// Cancels `key`'s session right away: unlike [`flush`](Self::flush),
+    /// signals still queued for it are not taken in, and its partial result
+    /// is emitted or not as the store's [`CancelBehavior`] says. Reported as
+    /// `DiagnosticReason::SessionCancelled`. While the store is paused, a
+    /// session task only closes once it resumes. Returns whether `key` had
+    /// an open session.
+    pub async fn cancel_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match &self.backend {
+            StoreBackend::Task(sender_map) => match sender_map.lock().await.remove(key) {
+                Some(entry) => {
+                    entry.close(CloseReason::Cancelled);
+                    true
+                }
+                None => false,
+            },
+            StoreBackend::TimerWheel { sessions, .. } => {
+                match sessions.flush(key, CloseReason::Cancelled, "cancelled") {
+                    Some((key, bits, summary)) => {
+                        self.emitter
+                            .report(DiagnosticReason::SessionCancelled, || key.clone());
+                        if self.config.cancel_behavior == CancelBehavior::Emit {
+                            self.emitter.emit(key, bits, summary).await;
+                        }
+                        true
+                    }
+                    None => false,
+                }
+            }
+        }
+    }
+
+    /// Returns how many sessions are open. This is racy: sessions open and
+    /// close concurrently, and a session task that has just emitted its
+    /// result may still count until it has removed itself. The store is only
+    /// locked briefly, so this can be called from the task that pushes.
+    pub async fn session_count(&self) -> usize {
+        match &self.backend {
+            StoreBackend::Task(sender_map) => sender_map.lock().await.len(),
+            StoreBackend::TimerWheel { sessions, .. } => sessions.len(),
+        }
+    }
+
+    /// Returns whether `key` has an open session, as racy as
+    /// [`session_count`](Self::session_count).
+    pub async fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match &self.backend {
+            StoreBackend::Task(sender_map) => sender_map.lock().await.contains_key(key),
+            StoreBackend::TimerWheel { sessions, .. } => sessions.contains(key),
+        }
+    }
+
+    /// Copies out the keys of the open sessions, as racy as
+    /// [`session_count`](Self::session_count). With the timer-wheel backend
+    /// the shards are locked one at a time, so the copy is not a single
+    /// point in time. See [`session_info`](Self::session_info) for a key's
+    /// state.
+    pub async fn keys(&self) -> Vec<K> {
+        match &self.backend {
+            StoreBackend::Task(sender_map) => sender_map.lock().await.keys().cloned().collect(),
+            StoreBackend::TimerWheel { sessions, .. } => sessions.keys(),
+        }
+    }
+
+    /// Returns the state of `key`'s session, or `None` if it has none,
+    /// without disturbing it. Signals are counted as they are pushed, so a
+    /// session task may not have taken in the last few yet, and the deadline
+    /// does not account for a pause of the store.
+    pub async fn session_info<Q>(&self, key: &Q) -> Option<SessionInfo>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match &self.backend {
+            StoreBackend::Task(sender_map) => sender_map
+                .lock()
+                .await
+                .get(key)
+                .map(|entry| entry.info(self.config.max_lifetime)),
+            StoreBackend::TimerWheel { sessions, .. } => sessions.session_info(key),
+        }
+    }
+
+    /// Copies out every active session at a single point in time, with the
+    /// bits its decoder has decoded so far.
+    ///
+    /// With the timer-wheel backend every shard is locked for the copy. With
+    /// one task per key, pushes are held up only while each session task is
+    /// sent a request for its bits, which it answers once it has taken in
+    /// every signal sent to it; the answers are gathered after the lock is
+    /// released, so a session's bits may also take in signals pushed after
+    /// the copy of its other fields. Sessions that close meanwhile are left
+    /// out, and those that
+    /// do not answer within a short wait are kept with
+    /// [`answered`](SessionSnapshot::answered) unset, so a snapshot missing
+    /// bits can be told from a complete one.
+    pub async fn snapshot_all(&self) -> Vec<SessionSnapshot<K, O>> {
+        let sender_map = match &self.backend {
+            StoreBackend::Task(sender_map) => sender_map,
+            StoreBackend::TimerWheel { sessions, .. } => return sessions.snapshot_all(),
+        };
+
+        let requests: Vec<_> = sender_map
+            .lock()
+            .await
+            .iter()
+            .filter_map(|(key, entry)| {
+                let (request, reply) = oneshot::channel();
+                let reply = match entry.snapshots.try_send(request) {
+                    Ok(()) => Some(reply),
+                    Err(TrySendError::Full(_)) => None,
+                    Err(TrySendError::Closed(_)) => return None,
+                };
+                let snapshot = SessionSnapshot {
+                    key: key.clone(),
+                    bits: None,
+                    answered: false,
+                    started_instant: entry.started_instant,
+                    deadline: entry.timeout.deadline(),
+                    // Signals queued for a full channel are not in the
+                    // session's bits yet.
+                    signals: entry.durations + 1 - entry.queued.load(Ordering::Relaxed),
+                };
+                Some((snapshot, reply))
+            })
+            .collect();
+
+        let deadline = tokio::time::Instant::now() + SNAPSHOT_WAIT;
+        let answers = join_all(
+            requests
+                .into_iter()
+                .map(|(mut snapshot, reply)| async move {
+                    let Some(reply) = reply else {
+                        return Some(snapshot);
+                    };
+                    match timeout_at(deadline, reply).await {
+                        Ok(Ok(bits)) => {
+                            snapshot.bits = bits;
+                            snapshot.answered = true;
+                        }
+                        // The session closed meanwhile.
+                        Ok(Err(_)) => return None,
+                        Err(_) => {}
+                    }
+                    Some(snapshot)
+                }),
+        )
+        .await;
+
+        answers.into_iter().flatten().collect()
+    }
+
+    /// Spawns a watchdog alerting about long-open sessions on the returned
+    /// stream. Each scan copies the sessions out under the lock and evaluates
+    /// them afterwards, so pushes are only blocked for the copy. The watchdog
+    /// stops once the store or the stream is dropped. Must be called within a
+    /// Tokio runtime.
+    pub fn watchdog(&self, config: WatchdogConfig) -> WatchdogStream<K> {
+        assert!(
+            !config.interval.is_zero(),
+            "watchdog interval must be non-zero"
+        );
+
+        spawn_watchdog(
+            &self.emitter.name,
+            self.backend.watched(),
+            config,
+            self.clock.clone(),
+        )
+    }
+
+    /// Resolves with a copy of the next result emitted for `key`'s active
+    /// session, which the result stream receives as well. Resolves with
+    /// `None` right away if `key` has no active session, or once the store
+    /// and its sessions are gone. A session closing just as this is called
+    /// may be missed, leaving the key's next session to resolve it.
+    pub fn await_result(&self, key: K) -> impl Future<Output = Option<T>> + Send + 'static
+    where
+        T: Clone,
+    {
+        let result = self.emitter.waiters.register(key.clone());
+        let waiters = Arc::downgrade(&self.emitter.waiters);
+        let sessions = self.backend.watched();
+
+        async move {
+            if !sessions.contains(key.clone()).await {
+                drop(result);
+                if let Some(waiters) = waiters.upgrade() {
+                    waiters.prune(&key);
+                }
+                return None;
+            }
+            result.await.ok()
+        }
+    }
+
+    /// Like [`DelaySessionStore::await_result`], but if `key` has no active
+    /// session, waits for the result of its next one.
+    pub fn await_next_result(&self, key: K) -> impl Future<Output = Option<T>> + Send + 'static
+    where
+        T: Clone,
+    {
+        let result = self.emitter.waiters.register(key);
+        async move { result.await.ok() }
+    }
+
+    /// Returns a `Sink` that pushes every item into this store, creating
+    /// decoders for new sessions with `decoder_factory`.
+    pub fn sink<D>(
+        self: &Arc<Self>,
+        decoder_factory: impl FnMut() -> D + Clone + Send + Sync + 'static,
+    ) -> DelaySessionSink<K>
+    where
+        M: Default,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        let store = self.clone();
+        DelaySessionSink::new(move |key, instant| {
+            let store = store.clone();
+            let decoder_factory = decoder_factory.clone();
+            Box::pin(async move { store.push_signal(key, instant, decoder_factory).await })
+        })
+    }
+
+    /// Returns a `tower::Service` that pushes every request into this store,
+    /// creating decoders for new sessions with `decoder_factory`.
+    #[cfg(feature = "tower")]
+    pub fn service<F, D>(self: &Arc<Self>, decoder_factory: F) -> DelaySessionService<K, T, F, O, M>
+    where
+        F: FnMut() -> D + Clone + Send + 'static,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        DelaySessionService::new(self.clone(), decoder_factory)
+    }
+
+    #[cfg(feature = "libc")]
+    pub async fn push_signal_timespec<D>(
+        &self,
+        key: K,
+        timespec: &libc::timespec,
+        anchor: &TimeAnchor,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Result<(), PushError>
+    where
+        M: Default,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        let instant = anchor
+            .instant_at_timespec(timespec)
+            .ok_or(PushError::InvalidTimestamp)?;
+        self.push_signal(key, instant, decoder_factory).await
+    }
+}
+
+#[derive(Debug)]
+pub struct DelaySessionStream<K, T = BitVec> {
+    /// Shared with the store's emitter, which evicts from it with
+    /// `ResultOverflow::DropOldest`.
+    receiver: Arc<SharedResultReceiver<K, T>>,
+    /// Ends the stream once a cancelled store has been drained.
+    end: StreamEnd,
+}
+
+impl<K, T> DelaySessionStream<K, T> {
+    /// Closes the stream from the consumer side. Results already buffered can
+    /// still be received, but session tasks fail to send new ones, and the
+    /// store treats a closed stream as shutting down: every later push fails
+    /// with `PushError::ResultStreamClosed`.
+    pub fn close(&mut self) {
+        self.receiver().close();
+    }
+
+    /// Closes the stream and returns every result that was already buffered.
+    pub fn drain_remaining(&mut self) -> Vec<(K, T)> {
+        self.close();
+
+        let mut receiver = self.receiver();
+        let mut remaining = Vec::new();
+        while let Ok(result) = receiver.try_recv() {
+            remaining.push(result);
+        }
+
+        remaining
+    }
+
+    fn receiver(&self) -> MutexGuard<'_, Receiver<(K, T)>> {
+        self.receiver.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<K> DelaySessionStream<K> {
+    /// Assembles each result's bits into bytes with [`bits_to_bytes`], along
+    /// with the number of bits in the trailing partial byte.
+    pub fn bytes(self, order: BitOrder) -> impl Stream<Item = (K, Vec<u8>, usize)> {
+        self.map(move |(key, bits)| {
+            let (bytes, trailing_bits) = bits_to_bytes(&bits, order);
+            (key, bytes, trailing_bits)
+        })
+    }
+}
+
+impl<K, T> Stream for DelaySessionStream<K, T> {
+    type Item = (K, T);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut receiver = this.receiver.lock().unwrap_or_else(PoisonError::into_inner);
+        match receiver.poll_recv(cx) {
+            Poll::Pending if this.end.poll_drained(cx).is_ready() => {
+                // Every result the store had left has been sent.
+                receiver.close();
+                Poll::Ready(receiver.try_recv().ok())
+            }
+            poll => poll,
+        }
+    }
+}
+
+pub struct DelaySessionStoreBuilder<K, T = BitVec, O = BitVec> {
+    config: SessionConfig,
+    result_capacity: usize,
+    result_mapper: ResultMapper<K, T, O>,
+    redactor: KeyRedactor<K>,
+    timer_wheel: Option<TimerWheelConfig>,
+    clock: Arc<dyn Clock>,
+    instant_policy: InstantPolicy,
+    forensics: Option<ForensicConfig>,
+    key_stats: Option<KeyStatsConfig>,
+    tap: Option<SignalTap<K>>,
+    intermediate: Option<(Duration, ResultMapper<K, T, O>)>,
+    fairness: Option<FairnessConfig>,
+    overflow: ResultOverflow,
+    dead_letter_suppressed: bool,
+    name: Arc<str>,
+    sampler: Option<Arc<Sampler<K>>>,
+    cancellation: Cancellation,
+}
+
+impl<K, O> DelaySessionStoreBuilder<K, O, O> {
+    /// Starts a builder for a store of decoders that output `O`, emitting
+    /// their output unmapped. `O` is inferred from the decoders pushed.
+    /// Sessions time out `timeout_duration` after their latest signal.
+    pub fn new(timeout_duration: Duration) -> Self {
+        Self::with_timeout_policy(FixedTimeout(timeout_duration))
+    }
+
+    /// Like [`new`](Self::new), for sessions that time out as `policy` says.
+    /// Every session runs its own clone of `policy`.
+    pub fn with_timeout_policy(policy: impl TimeoutPolicy + Clone + Sync) -> Self {
+        Self {
+            config: SessionConfig::new(TimeoutPolicyFactory::new(policy)),
+            result_capacity: DEFAULT_RESULT_CAPACITY,
+            result_mapper: Arc::new(|_, bits, _| Some(bits)),
+            redactor: KeyRedactor::redacted(),
+            timer_wheel: None,
+            clock: Arc::new(TokioClock),
+            instant_policy: InstantPolicy::default(),
+            forensics: None,
+            key_stats: None,
+            tap: None,
+            intermediate: None,
+            fairness: None,
+            overflow: ResultOverflow::Wait,
+            dead_letter_suppressed: false,
+            name: Arc::from(DEFAULT_STORE_NAME),
+            sampler: None,
+            cancellation: Cancellation::default(),
+        }
+    }
+}
+
+impl<K, T, O> DelaySessionStoreBuilder<K, T, O> {
+    /// See [`delay_session_store_with_mapper`]. This replaces any emit
+    /// interval or sampling set before.
+    pub fn result_mapper<U>(
+        self,
+        result_mapper: impl Fn(&K, O) -> Option<U> + Send + Sync + 'static,
+    ) -> DelaySessionStoreBuilder<K, U, O> {
+        DelaySessionStoreBuilder {
+            config: self.config,
+            result_capacity: self.result_capacity,
+            result_mapper: Arc::new(move |key, bits, _| result_mapper(key, bits)),
+            redactor: self.redactor,
+            timer_wheel: self.timer_wheel,
+            clock: self.clock,
+            instant_policy: self.instant_policy,
+            forensics: self.forensics,
+            key_stats: self.key_stats,
+            tap: self.tap,
+            intermediate: None,
+            fairness: self.fairness,
+            overflow: self.overflow,
+            dead_letter_suppressed: self.dead_letter_suppressed,
+            name: self.name,
+            sampler: None,
+            cancellation: self.cancellation,
+        }
+    }
+
+    /// Makes every open session emit a snapshot of its bits every `interval`
+    /// since it started, as a [`SessionResult::Intermediate`], without closing
+    /// or resetting it. Closed sessions emit a [`SessionResult::Final`] as
+    /// usual. Snapshots go through the result mapper too, and are skipped for
+    /// decoders that return no `DelayDecoder::snapshot`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn emit_interval(
+        self,
+        interval: Duration,
+    ) -> DelaySessionStoreBuilder<K, SessionResult<T>, O>
+    where
+        K: 'static,
+        T: 'static,
+        O: DecoderOutput + 'static,
+    {
+        assert!(!interval.is_zero(), "emit interval must be non-zero");
+
+        let mapper = self.result_mapper;
+        let intermediate_mapper = mapper.clone();
+        DelaySessionStoreBuilder {
+            config: self.config,
+            result_capacity: self.result_capacity,
+            result_mapper: Arc::new(move |key, bits, summary| {
+                mapper(key, bits, summary).map(SessionResult::Final)
+            }),
+            redactor: self.redactor,
+            timer_wheel: self.timer_wheel,
+            clock: self.clock,
+            instant_policy: self.instant_policy,
+            forensics: self.forensics,
+            key_stats: self.key_stats,
+            tap: self.tap,
+            fairness: self.fairness,
+            overflow: self.overflow,
+            dead_letter_suppressed: self.dead_letter_suppressed,
+            name: self.name,
+            sampler: self.sampler,
+            cancellation: self.cancellation,
+            intermediate: Some((
+                interval,
+                Arc::new(move |key, bits: O, summary| {
+                    let len = bits.symbol_count();
+                    intermediate_mapper(key, bits, summary)
+                        .map(|result| SessionResult::Intermediate { result, bits: len })
+                }),
+            )),
+        }
+    }
+
+    /// Samples the signals of keys sending more than
+    /// `config.rate_threshold` signals per second, so that only every
+    /// `config.factor`-th signal past the threshold reaches their sessions.
+    /// Results are wrapped in [`Sampled`], recording the factor the key was
+    /// last sampled with. Forensics, statistics and taps still see every
+    /// signal.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.factor` is zero.
+    pub fn sampling(self, config: SamplingConfig) -> DelaySessionStoreBuilder<K, Sampled<T>, O>
+    where
+        K: Eq + Hash + Send + Sync + 'static,
+        T: 'static,
+        O: 'static,
+    {
+        let sampler = Arc::new(Sampler::new(config));
+        let sampled = |mapper: ResultMapper<K, T, O>| -> ResultMapper<K, Sampled<T>, O> {
+            let sampler = sampler.clone();
+            Arc::new(move |key, bits, summary| {
+                mapper(key, bits, summary).map(|result| Sampled {
+                    result,
+                    factor: sampler.factor(key),
+                })
+            })
+        };
+
+        DelaySessionStoreBuilder {
+            config: self.config,
+            result_capacity: self.result_capacity,
+            result_mapper: sampled(self.result_mapper),
+            redactor: self.redactor,
+            timer_wheel: self.timer_wheel,
+            clock: self.clock,
+            instant_policy: self.instant_policy,
+            forensics: self.forensics,
+            key_stats: self.key_stats,
+            tap: self.tap,
+            intermediate: self
+                .intermediate
+                .map(|(interval, mapper)| (interval, sampled(mapper))),
+            fairness: self.fairness,
+            overflow: self.overflow,
+            dead_letter_suppressed: self.dead_letter_suppressed,
+            name: self.name,
+            sampler: Some(sampler),
+            cancellation: self.cancellation,
+        }
+    }
+
+    /// Emits every result along with the [`SessionSummary`] of its session,
+    /// e.g. to tell results of timed out sessions from flushed ones.
+    /// Snapshots of open sessions, see [`emit_interval`](Self::emit_interval),
+    /// carry no summary.
+    pub fn summarized(self) -> DelaySessionStoreBuilder<K, Summarized<T>, O>
+    where
+        K: 'static,
+        T: 'static,
+        O: 'static,
+    {
+        let summarized = |mapper: ResultMapper<K, T, O>| -> ResultMapper<K, Summarized<T>, O> {
+            Arc::new(move |key, bits, summary| {
+                mapper(key, bits, summary).map(|result| Summarized {
+                    result,
+                    summary: summary.copied(),
+                })
+            })
+        };
+
+        DelaySessionStoreBuilder {
+            config: self.config,
+            result_capacity: self.result_capacity,
+            result_mapper: summarized(self.result_mapper),
+            redactor: self.redactor,
+            timer_wheel: self.timer_wheel,
+            clock: self.clock,
+            instant_policy: self.instant_policy,
+            forensics: self.forensics,
+            key_stats: self.key_stats,
+            tap: self.tap,
+            intermediate: self
+                .intermediate
+                .map(|(interval, mapper)| (interval, summarized(mapper))),
+            fairness: self.fairness,
+            overflow: self.overflow,
+            dead_letter_suppressed: self.dead_letter_suppressed,
+            name: self.name,
+            sampler: self.sampler,
+            cancellation: self.cancellation,
+        }
+    }
+
+    /// Names the store's tasks after `name`, so several stores can be told
+    /// apart in tokio-console.
+    pub fn name(mut self, name: impl Into<Arc<str>>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Formats keys in log output with `key_formatter`. Keys are redacted
+    /// unless a formatter is set, since they often identify clients.
+    pub fn key_formatter(
+        mut self,
+        key_formatter: impl Fn(&K, &mut Formatter<'_>) -> fmt::Result + Send + Sync + 'static,
+    ) -> Self {
+        self.redactor = KeyRedactor::new(key_formatter);
+        self
+    }
+
+    /// Formats keys in log output with their `Debug` implementation.
+    pub fn debug_keys(self) -> Self
+    where
+        K: Debug,
+    {
+        self.key_formatter(|key, f| Debug::fmt(key, f))
+    }
+
+    /// Sets the clock used for every instant the store captures itself and
+    /// for every timer of its sessions, [`TokioClock`] by default.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the policy for signals whose instants are implausibly far from
+    /// the previous signal or from the store's clock.
+    pub const fn instant_policy(mut self, instant_policy: InstantPolicy) -> Self {
+        self.instant_policy = instant_policy;
+        self
+    }
+
+    /// Keeps the latest raw signals of every key for
+    /// [`DelaySessionStore::export_forensics`].
+    pub const fn forensics(mut self, config: ForensicConfig) -> Self {
+        self.forensics = Some(config);
+        self
+    }
+
+    /// Keeps rolling timing statistics of every key for
+    /// [`DelaySessionStore::stats_for`].
+    pub const fn key_stats(mut self, config: KeyStatsConfig) -> Self {
+        self.key_stats = Some(config);
+        self
+    }
+
+    /// Copies every pushed signal to the recorder paired with `tap`.
+    pub fn tap(mut self, tap: SignalTap<K>) -> Self {
+        self.tap = Some(tap);
+        self
+    }
+
+    /// Queues results per key in front of the result stream and forwards
+    /// them round robin, so a key closing many sessions at once cannot delay
+    /// other keys' results behind its own. Off by default.
+    pub const fn fair_emission(mut self, config: FairnessConfig) -> Self {
+        self.fairness = Some(config);
+        self
+    }
+
+    /// Sets what the store does with a result while the result stream is
+    /// full. Dropped results go to [`DelaySessionStore::subscribe_dead_letters`].
+    pub const fn result_overflow(mut self, overflow: ResultOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Routes the bits of results the result mapper suppresses to
+    /// [`DelaySessionStore::subscribe_dead_letters`] too. This clones every
+    /// result's bits while someone is subscribed.
+    pub const fn dead_letter_suppressed(mut self) -> Self {
+        self.dead_letter_suppressed = true;
+        self
+    }
+
+    /// Shuts the store down cooperatively once `token` is cancelled: pushes
+    /// fail with `PushError::Cancelled`, or with `PushError::StoreShutdown`
+    /// if they were already waiting on their session, every open session is
+    /// closed early and emits its partial result as
+    /// [`cancel_behavior`](Self::cancel_behavior) says, reported as
+    /// `DiagnosticReason::SessionCancelled`, and the result stream ends once
+    /// those results have been received.
+    #[cfg(feature = "tokio-util")]
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Cancellation::new(token);
+        self
+    }
+
+    /// Sets what happens to the partial results of cancelled sessions,
+    /// emitted by default.
+    pub const fn cancel_behavior(mut self, behavior: CancelBehavior) -> Self {
+        self.config.cancel_behavior = behavior;
+        self
+    }
+
+    /// Sets how long a session task waits on the store's clock, after its
+    /// session closed, for a signal to start the key's next session with, 10
+    /// milliseconds by default. A signal pushed while the task wraps up is otherwise refused
+    /// with `PushError::SessionClosed`. The closed session's result is
+    /// emitted before the wait.
+    pub const fn restart_grace(mut self, grace: Duration) -> Self {
+        self.config.restart_grace = grace;
+        self
+    }
+
+    /// Closes a session, as if it timed out, once it took in `max_durations`
+    /// durations, so a client cannot keep it open forever
+    /// by signalling just before every timeout. Such closes are reported as
+    /// `DiagnosticReason::SessionDurationLimit`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_durations` is zero.
+    pub fn max_durations(mut self, max_durations: usize) -> Self {
+        assert!(max_durations > 0, "duration limit must be positive");
+        self.config.max_durations = Some(max_durations);
+        self
+    }
+
+    /// Closes a session `max_lifetime` after it started, however recently it
+    /// was signalled, so a client trickling signals cannot hold it open.
+    /// A signal at or after that closes the session and starts a new one,
+    /// like a signal after a timeout does.
+    pub const fn max_lifetime(mut self, max_lifetime: Duration) -> Self {
+        self.config.max_lifetime = Some(max_lifetime);
+        self
+    }
+
+    /// Handles signals arriving out of order as `policy` says, on either
+    /// backend. Without it their durations saturate to zero.
+    pub const fn out_of_order(mut self, policy: OutOfOrderPolicy) -> Self {
+        self.config.out_of_order = policy;
+        self
+    }
+
+    /// Drops signals within `gap` of the latest signal of their key's
+    /// session, on either backend, see
+    /// [`DelaySession::min_signal_gap`](crate::session::DelaySession::min_signal_gap).
+    pub const fn min_signal_gap(mut self, gap: Duration) -> Self {
+        self.config.min_signal_gap = gap;
+        self
+    }
+
+    /// Bounds the sessions open at once to `max`, on either backend, so keys
+    /// cycled by a client cannot grow the store without bound. A push for a
+    /// new key past it is handled as `policy` says. The timer-wheel backend
+    /// only evicts from shards no other push holds at the time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub const fn max_sessions(mut self, max: usize, policy: SessionLimitPolicy) -> Self {
+        assert!(max > 0, "max_sessions must be non-zero");
+        self.config.max_sessions = Some((max, policy));
+        self
+    }
+
+    /// Lets up to `capacity` signals wait for each session task, 8 by
+    /// default, before pushes to its key wait. The timer-wheel backend has
+    /// no signal channels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn signal_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity >= 1, "signal channel capacity must be positive");
+        self.config.signal_capacity = capacity;
+        self
+    }
+
+    /// Lets up to `capacity` results wait for the stream, 8 by default,
+    /// before sessions wait or drop results as
+    /// [`result_overflow`](Self::result_overflow) says. Results that wait
+    /// are counted in [`StoreMetrics::results_send_blocked`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn result_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity >= 1, "result channel capacity must be positive");
+        self.result_capacity = capacity;
+        self
+    }
+
+    /// Pushes the gap that ends a session to its decoder as `terminal` says,
+    /// on either backend.
+    pub const fn terminal_duration(mut self, terminal: TerminalDuration) -> Self {
+        self.config.terminal_duration = terminal;
+        self
+    }
+
+    /// Handles keepalives, see [`push_keepalive`](DelaySessionStore::push_keepalive),
+    /// as `policy` says, on either backend.
+    pub const fn keepalive_policy(mut self, policy: KeepalivePolicy) -> Self {
+        self.config.keepalive_policy = policy;
+        self
+    }
+
+    /// Measures the duration spanning a pause of a key's session, see
+    /// [`pause_key`](DelaySessionStore::pause_key), as `policy` says, on
+    /// either backend.
+    pub const fn pause_gap_policy(mut self, policy: PauseGapPolicy) -> Self {
+        self.config.pause_gap = policy;
+        self
+    }
+
+    /// Runs sessions on the timer-wheel backend instead of one task per key.
+    pub const fn timer_wheel(mut self, config: TimerWheelConfig) -> Self {
+        self.timer_wheel = Some(config);
+        self
+    }
+
+    /// Builds the store. With the timer-wheel backend or fair emission this
+    /// spawns tasks, so it must be called within a Tokio runtime.
+    pub fn build(self) -> (DelaySessionStore<K, T, O>, DelaySessionStream<K, T>)
+    where
+        K: Clone + Eq + Hash + Send + 'static,
+        T: Send + 'static,
+        O: DecoderOutput + Clone + Send + 'static,
+    {
+        self.build_with_metadata()
+    }
+
+    /// Builds a store that owns `decoder_factory`, which creates the decoder
+    /// of every session, see [`FactoryDelaySessionStore`].
+    pub fn build_with_factory<D>(
+        self,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> (FactoryDelaySessionStore<K, T, O>, DelaySessionStream<K, T>)
+    where
+        K: Clone + Eq + Hash + Send + 'static,
+        T: Send + 'static,
+        O: DecoderOutput + Clone + Send + 'static,
+        D: MetaDelayDecoder<(), Output = O> + Send + 'static,
+    {
+        let (store, stream) = self.build();
+        (
+            FactoryDelaySessionStore::new(store, decoder_factory),
+            stream,
+        )
+    }
+
+    /// Builds a store whose signals carry metadata `M`, see
+    /// [`DelaySessionStore::push_signal_with`].
+    pub fn build_with_metadata<M>(self) -> (DelaySessionStore<K, T, O, M>, DelaySessionStream<K, T>)
+    where
+        K: Clone + Eq + Hash + Send + 'static,
+        T: Send + 'static,
+        O: DecoderOutput + Clone + Send + 'static,
+        M: Send + 'static,
+    {
+        let (sender, receiver) = channel(self.result_capacity);
+        let result_sender = match self.fairness {
+            Some(config) => {
+                ResultSender::Fair(Arc::new(FairSender::spawn(config, sender, &self.name)))
+            }
+            None => ResultSender::Direct(sender),
+        };
+        let receiver = Arc::new(StdMutex::new(receiver));
+        let emitter = ResultEmitter {
+            overflow: self.overflow,
+            oldest: (self.overflow == ResultOverflow::DropOldest)
+                .then(|| Arc::downgrade(&receiver)),
+            dead_letters: Arc::new(DeadLetterHub::new(self.dead_letter_suppressed)),
+            cancellation: self.cancellation,
+            name: self.name,
+            ..ResultEmitter::new(
+                self.result_mapper,
+                result_sender,
+                self.redactor,
+                self.intermediate,
+                self.clock.clone(),
+            )
+        };
+
+        let backend = match self.timer_wheel {
+            Some(config) => {
+                let sessions = Arc::new(TimerWheelSessions::new(
+                    config,
+                    emitter.redactor().clone(),
+                    emitter.emit_interval(),
+                    self.config.clone(),
+                    self.clock.clone(),
+                ));
+                let alive = Arc::new(());
+                sessions.spawn_workers(config.workers, Arc::downgrade(&alive), emitter.clone());
+
+                StoreBackend::TimerWheel {
+                    sessions,
+                    _alive: alive,
+                }
+            }
+            None => StoreBackend::Task(Default::default()),
+        };
+
+        #[cfg_attr(not(feature = "tokio-util"), allow(unused_mut))]
+        let mut store = DelaySessionStore::new(
+            self.config,
+            backend,
+            emitter,
+            self.clock,
+            self.instant_policy,
+            SignalObservers {
+                forensics: self.forensics.map(ForensicBuffer::new),
+                key_stats: self.key_stats.map(KeyStatsTracker::new),
+                tap: self.tap,
+                sampler: self.sampler,
+            },
+        );
+        #[cfg_attr(not(feature = "tokio-util"), allow(unused_mut))]
+        let mut end = StreamEnd::default();
+
+        #[cfg(feature = "tokio-util")]
+        if store.emitter.cancellation.has_token() {
+            let dropped = CancellationToken::new();
+            let drained = CancellationToken::new();
+            task::spawn(
+                || format!("{} drain", store.emitter.name),
+                drain_cancelled(
+                    store.emitter.cancellation.clone(),
+                    Arc::downgrade(&store.link),
+                    store.backend.watched(),
+                    store.config.cancel_behavior,
+                    dropped.clone(),
+                    drained.clone(),
+                ),
+            );
+            store._drain = Some(dropped.drop_guard());
+            end = StreamEnd::new(drained);
+        }
+
+        (store, DelaySessionStream { receiver, end })
+    }
+}
+
+impl<K, T, O> Debug for DelaySessionStoreBuilder<K, T, O> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DelaySessionStoreBuilder")
+            .field("timeout", &self.config.timeout)
+            .field("signal_capacity", &self.config.signal_capacity)
+            .field("result_capacity", &self.result_capacity)
+            .field("restart_grace", &self.config.restart_grace)
+            .field("timer_wheel", &self.timer_wheel)
+            .field("clock", &self.clock)
+            .field("instant_policy", &self.instant_policy)
+            .field("forensics", &self.forensics)
+            .field("key_stats", &self.key_stats)
+            .field("fairness", &self.fairness)
+            .field("overflow", &self.overflow)
+            .field("name", &self.name)
+            .field(
+                "emit_interval",
+                &self.intermediate.as_ref().map(|(interval, _)| interval),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+/// Creates a store emitting what its sessions decoded unmapped. The decoder
+/// output `O` is inferred from the decoders pushed, and is `BitVec` for the
+/// decoders of this crate except `MultiLevelDelayDecoder`.
+pub fn delay_session_store<K, O>(
+    timeout_duration: Duration,
+) -> (DelaySessionStore<K, O, O>, DelaySessionStream<K, O>) {
+    delay_session_store_with_mapper(timeout_duration, |_, bits| Some(bits))
+}
+
+/// Creates a store that owns `decoder_factory`, emitting what its sessions
+/// decoded unmapped. See [`FactoryDelaySessionStore`].
+pub fn delay_session_store_with_factory<K, O, D>(
+    timeout_duration: Duration,
+    decoder_factory: impl FnMut() -> D + Send + 'static,
+) -> (FactoryDelaySessionStore<K, O, O>, DelaySessionStream<K, O>)
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    O: DecoderOutput + Clone + Send + 'static,
+    D: MetaDelayDecoder<(), Output = O> + Send + 'static,
+{
+    let (store, stream) = delay_session_store(timeout_duration);
+    (
+        FactoryDelaySessionStore::new(store, decoder_factory),
+        stream,
+    )
+}
+
+/// Creates a store whose sessions map their results with `result_mapper`
+/// before emitting them. Results mapped to `None` are never sent, so they do
+/// not take up capacity in the result channel. The mapper runs in the session
+/// task, outside of any lock.
+pub fn delay_session_store_with_mapper<K, T, O>(
+    timeout_duration: Duration,
+    result_mapper: impl Fn(&K, O) -> Option<T> + Send + Sync + 'static,
+) -> (DelaySessionStore<K, T, O>, DelaySessionStream<K, T>) {
+    let (sender, receiver) = channel(DEFAULT_RESULT_CAPACITY);
+    let clock: Arc<dyn Clock> = Arc::new(TokioClock);
+
+    (
+        DelaySessionStore::new(
+            SessionConfig::new(TimeoutPolicyFactory::new(FixedTimeout(timeout_duration))),
+            StoreBackend::Task(Default::default()),
+            ResultEmitter::new(
+                Arc::new(move |key, bits, _| result_mapper(key, bits)),
+                ResultSender::Direct(sender),
+                KeyRedactor::redacted(),
+                None,
+                clock.clone(),
+            ),
+            clock,
+            InstantPolicy::default(),
+            SignalObservers::default(),
+        ),
+        DelaySessionStream {
+            receiver: Arc::new(StdMutex::new(receiver)),
+            end: StreamEnd::default(),
+        },
+    )
+}
+
+/// Creates a store that owns `decoder_factory`, like
+/// [`delay_session_store_with_factory`], whose decoders start from the
+/// shared `calibration`, e.g. thresholds learnt over time. The factory gets
+/// the session's key and the calibration as it is when the session starts
+/// or restarts, so updates through another handle on `calibration` reach
+/// every session created after them.
+pub fn delay_session_store_with_calibration<K, C, D>(
+    timeout_duration: Duration,
+    calibration: Arc<RwLock<C>>,
+    mut decoder_factory: impl FnMut(&K, &C) -> D + Send + 'static,
+) -> (FactoryDelaySessionStore<K>, DelaySessionStream<K>)
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    C: Send + Sync + 'static,
+    D: MetaDelayDecoder<(), Output = BitVec> + Send + 'static,
+{
+    let (store, stream) = delay_session_store(timeout_duration);
+    (
+        FactoryDelaySessionStore::keyed(store, move |key| {
+            decoder_factory(
+                key,
+                &calibration.read().unwrap_or_else(PoisonError::into_inner),
+            )
+        }),
+        stream,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, RwLock},
+        time::{Duration, Instant},
+    };
+
+    use bitvec::{bitvec, order::Lsb0, vec::BitVec};
+    use futures::{FutureExt, StreamExt};
+    use tokio::time::{sleep, sleep_until, timeout};
+
+    use super::{
+        delay_session_store_with_calibration, CancelBehavior, DelaySessionStore,
+        DelaySessionStoreBuilder, DelaySessionStream, PushOutcome, ResultOverflow,
+        SessionLimitPolicy, SessionResult, StoreBackend,
+    };
+    use crate::{
+        clock::ManualClock,
+        dead_letter::DeadLetterReason,
+        decoder::{AverageDelayDecoder, MetadataFilterDecoder, ThresholdDelayDecoder},
+        error::CloseError,
+        instant_policy::{InstantPolicy, OutOfOrderPolicy},
+        pause::PausedPushes,
+        sampling::SamplingConfig,
+        session::{CloseReason, KeepalivePolicy, TerminalDuration},
+        test_alloc,
+        timeout_policy::AdaptiveTimeout,
+        timer_wheel::TimerWheelConfig,
+        watchdog::WatchdogConfig,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn migrated_sessions_follow_the_target_pause() {
+        let (source, _source_results) =
+            DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(1)).build();
+        let (target, mut results) =
+            DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(1)).build();
+        let start = source.clock().now();
+        source
+            .push_signal(1, start, AverageDelayDecoder::new)
+            .await
+            .unwrap();
+        source.migrate_into(&target).await.unwrap();
+
+        target.pause(PausedPushes::Reject);
+        sleep(Duration::from_secs(10)).await;
+        assert!(target.contains_key(&1).await);
+
+        target.resume().await;
+        let (key, _) = timeout(Duration::from_secs(2), results.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(key, 1);
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test(start_paused = true)]
+    async fn migrated_sessions_follow_the_target_cancellation() {
+        use tokio_util::sync::CancellationToken;
+
+        let token = CancellationToken::new();
+        let (source, _source_results) =
+            DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(3600)).build();
+        let (target, mut results) = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(3600))
+            .cancellation_token(token.clone())
+            .build();
+        let start = source.clock().now();
+        source
+            .push_signal(1, start, AverageDelayDecoder::new)
+            .await
+            .unwrap();
+        source.migrate_into(&target).await.unwrap();
+
+        token.cancel();
+        let (key, _) = timeout(Duration::from_secs(60), results.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(key, 1);
+        // The stream ends once the migrated session has been drained.
+        assert!(timeout(Duration::from_secs(60), results.next())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test(start_paused = true)]
+    async fn a_push_waiting_on_a_cancelled_store_fails_as_shut_down() {
+        use tokio_util::sync::CancellationToken;
+
+        use crate::error::PushError;
+
+        let token = CancellationToken::new();
+        let (store, _results) = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(3600))
+            .signal_capacity(1)
+            .cancellation_token(token.clone())
+            .build();
+        let start = store.clock().now();
+        for offset in [0, 10] {
+            store
+                .push_signal(
+                    1,
+                    start + Duration::from_millis(offset),
+                    AverageDelayDecoder::new,
+                )
+                .await
+                .unwrap();
+        }
+
+        // The session has not run yet, so the push waits for its full channel.
+        let mut push = Box::pin(store.push_signal(
+            1,
+            start + Duration::from_millis(20),
+            AverageDelayDecoder::new,
+        ));
+        assert!(futures::poll!(push.as_mut()).is_pending());
+        token.cancel();
+        assert_eq!(push.await, Err(PushError::StoreShutdown));
+    }
+
+    #[cfg(feature = "libc")]
+    #[tokio::test(start_paused = true)]
+    async fn unrepresentable_timespecs_are_invalid_timestamps() {
+        use crate::{
+            error::PushError,
+            time_anchor::{TimeAnchor, TimestampClock},
+        };
+
+        let (store, _results) =
+            DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60)).build();
+        let anchor = TimeAnchor::new(
+            TimestampClock::Monotonic,
+            store.clock().now(),
+            Duration::from_secs(1000),
+        );
+
+        for (tv_sec, tv_nsec) in [(i64::MAX, 999_999_999), (-1, 0), (1000, 1_000_000_000)] {
+            let timespec = libc::timespec { tv_sec, tv_nsec };
+            assert_eq!(
+                store
+                    .push_signal_timespec(1, &timespec, &anchor, AverageDelayDecoder::new)
+                    .await,
+                Err(PushError::InvalidTimestamp),
+                "{tv_sec}s {tv_nsec}ns"
+            );
+        }
+        assert_eq!(store.session_count().await, 0);
+    }
+
+    #[cfg(feature = "libc")]
+    #[tokio::test(start_paused = true)]
+    async fn timespecs_keep_their_nanosecond_distances_across_the_anchor() {
+        use crate::time_anchor::{TimeAnchor, TimestampClock};
+
+        let (store, _results) =
+            DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(60)).build();
+        let anchored = store.clock().now();
+        let anchor = TimeAnchor::new(
+            TimestampClock::Monotonic,
+            anchored,
+            Duration::from_secs(1000),
+        );
+
+        // Straddles the anchor, carrying the nanoseconds into the seconds.
+        for (tv_sec, tv_nsec) in [
+            (999, 999_999_990),
+            (1000, 10),
+            (1000, 999_999_999),
+            (1001, 5),
+        ] {
+            let timespec = libc::timespec { tv_sec, tv_nsec };
+            store
+                .push_signal_timespec(1, &timespec, &anchor, AverageDelayDecoder::new)
+                .await
+                .unwrap();
+        }
+
+        let info = store.session_info(&1).await.unwrap();
+        assert_eq!(info.signals, 4);
+        assert_eq!(anchored - info.started_instant, Duration::from_nanos(10));
+        assert_eq!(info.last_signal_instant - anchored, Duration::new(1, 5));
+    }
+
+    /// Builds a store, with instants counted in milliseconds from its start.
+    fn outcome_store<T: Send + 'static>(
+        builder: DelaySessionStoreBuilder<u32, T>,
+    ) -> (
+        DelaySessionStore<u32, T>,
+        DelaySessionStream<u32, T>,
+        impl Fn(u64) -> Instant,
+    ) {
+        let (store, results) = builder.build();
+        let start = store.clock().now();
+        (store, results, move |millis| {
+            start + Duration::from_millis(millis)
+        })
+    }
+
+    fn builder() -> DelaySessionStoreBuilder<u32> {
+        DelaySessionStoreBuilder::new(Duration::from_secs(60))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_push_to_a_new_or_open_session_is_delivered() {
+        let (store, _results, at) = outcome_store(builder());
+        for millis in [0, 10] {
+            let outcome = store.try_push_signal(1, at(millis), AverageDelayDecoder::new);
+            assert_eq!(outcome, PushOutcome::Delivered);
+        }
+        assert_eq!(store.session_info(&1).await.unwrap().signals, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_push_to_a_full_channel_is_dropped() {
+        let (store, _results, at) = outcome_store(builder().signal_capacity(1));
+        store.try_push_signal(1, at(0), AverageDelayDecoder::new);
+        store.try_push_signal(1, at(10), AverageDelayDecoder::new);
+        let outcome = store.try_push_signal(1, at(20), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::DroppedFull);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_push_while_the_store_is_locked_is_dropped() {
+        let (store, _results, at) = outcome_store(builder());
+        let StoreBackend::Task(sender_map) = &store.backend else {
+            unreachable!("the store runs session tasks");
+        };
+        let _locked = sender_map.lock().await;
+        let outcome = store.try_push_signal(1, at(0), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::DroppedLockBusy);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_push_to_a_session_that_stopped_receiving_is_closed() {
+        let (store, _results, at) = outcome_store(builder());
+        store.try_push_signal(1, at(0), AverageDelayDecoder::new);
+        let StoreBackend::Task(sender_map) = &store.backend else {
+            unreachable!("the store runs session tasks");
+        };
+        // As if the session task had died.
+        let (closed, _) = tokio::sync::mpsc::channel(1);
+        sender_map.lock().await.get_mut(&1).unwrap().sender = closed;
+
+        let outcome = store.try_push_signal(1, at(10), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::SessionClosed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_push_after_the_results_are_closed_is_refused() {
+        let (store, mut results, at) = outcome_store(builder());
+        results.close();
+        let outcome = store.try_push_signal(1, at(0), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::ResultStreamClosed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_push_past_the_skew_is_rejected() {
+        let (store, _results, at) = outcome_store(builder().instant_policy(InstantPolicy {
+            max_skew: Some(Duration::from_secs(1)),
+            ..InstantPolicy::default()
+        }));
+        store.try_push_signal(1, at(0), AverageDelayDecoder::new);
+        let outcome = store.try_push_signal(1, at(5000), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::RejectedImplausible);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_push_to_a_store_rejecting_while_paused_is_rejected() {
+        let (store, _results, at) = outcome_store(builder());
+        store.pause(PausedPushes::Reject);
+        let outcome = store.try_push_signal(1, at(0), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::RejectedPaused);
+        assert_eq!(store.session_count().await, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_push_to_a_store_buffering_while_paused_is_delivered_on_resume() {
+        let (store, _results, at) = outcome_store(builder());
+        store.pause(PausedPushes::Buffer);
+        let outcome = store.try_push_signal(1, at(0), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::Buffered);
+        assert_eq!(store.session_count().await, 0);
+
+        store.resume().await;
+        assert!(store.contains_key(&1).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pushes_of_a_chatty_key_are_sampled_out() {
+        let (store, _results) = builder()
+            .sampling(SamplingConfig {
+                rate_threshold: 10,
+                factor: 2,
+                max_keys: 16,
+            })
+            .build();
+        let start = store.clock().now();
+        let outcomes: Vec<_> = (0..100)
+            .map(|millis| {
+                store.try_push_signal(
+                    1,
+                    start + Duration::from_millis(millis),
+                    AverageDelayDecoder::new,
+                )
+            })
+            .collect();
+        assert!(outcomes.contains(&PushOutcome::SampledOut));
+        assert!(outcomes.contains(&PushOutcome::Delivered));
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test(start_paused = true)]
+    async fn a_push_to_a_cancelled_store_is_cancelled() {
+        let token = tokio_util::sync::CancellationToken::new();
+        let (store, _results, at) = outcome_store(builder().cancellation_token(token.clone()));
+        token.cancel();
+        let outcome = store.try_push_signal(1, at(0), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::Cancelled);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_push_for_a_new_key_past_the_session_limit_is_rejected() {
+        let (store, _results, at) =
+            outcome_store(builder().max_sessions(1, SessionLimitPolicy::Reject));
+        store.try_push_signal(1, at(0), AverageDelayDecoder::new);
+        let outcome = store.try_push_signal(2, at(0), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::RejectedSessionLimit);
+        let outcome = store.try_push_signal(1, at(10), AverageDelayDecoder::new);
+        assert_eq!(outcome, PushOutcome::Delivered);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn draining_a_closed_stream_delivers_each_buffered_result_once() {
+        for timer_wheel in [false, true] {
+            let builder = builder();
+            let builder = if timer_wheel {
+                builder.timer_wheel(TimerWheelConfig::default())
+            } else {
+                builder
+            };
+            let (store, mut results, at) = outcome_store(builder.result_capacity(8));
+            for key in 0..6 {
+                for millis in [0, 10] {
+                    store
+                        .push_signal(key, at(millis), AverageDelayDecoder::new)
+                        .await
+                        .unwrap();
+                }
+            }
+            sleep(Duration::from_secs(120)).await;
+
+            let (first, _) = results.next().await.unwrap();
+            results.close();
+            let mut keys: Vec<_> = results
+                .drain_remaining()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect();
+            keys.push(first);
+            keys.sort_unstable();
+            assert_eq!(keys, [0, 1, 2, 3, 4, 5], "timer wheel: {timer_wheel}");
+
+            assert!(results.drain_remaining().is_empty());
+            assert!(results.next().await.is_none());
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_stalled_session_raises_one_alert_at_the_first_level() {
+        for timer_wheel in [false, true] {
+            let builder = if timer_wheel {
+                builder().timer_wheel(TimerWheelConfig::default())
+            } else {
+                builder()
+            };
+            let (store, _results) = builder.build();
+            let mut alerts = store.watchdog(WatchdogConfig {
+                interval: Duration::from_secs(1),
+                thresholds: vec![Duration::from_secs(10), Duration::from_secs(3600)],
+            });
+
+            // A trickle of signals keeps the session open for 40 s, past the
+            // first threshold but well short of the second.
+            for _ in 0..8 {
+                let now = store.clock().now();
+                store
+                    .push_signal(1, now, AverageDelayDecoder::new)
+                    .await
+                    .unwrap();
+                sleep(Duration::from_secs(5)).await;
+            }
+
+            let alert = alerts.next().now_or_never().flatten().unwrap();
+            assert_eq!(alert.key, 1, "timer wheel: {timer_wheel}");
+            assert_eq!(alert.level, 0, "timer wheel: {timer_wheel}");
+            assert!(alert.open_for >= Duration::from_secs(10));
+            assert!(alert.open_for < Duration::from_secs(12));
+            assert!(alert.bits_so_far >= 1);
+            assert!(alerts.next().now_or_never().is_none());
+        }
+    }
+
+    /// Closes the sessions of keys 0 to 3 in order, 100 ms apart, into a
+    /// result stream with room for two, returning the store, its stream and
+    /// the dead letters it routed.
+    async fn overflow_store(
+        overflow: ResultOverflow,
+        timer_wheel: bool,
+    ) -> (
+        DelaySessionStore<u32>,
+        DelaySessionStream<u32>,
+        Vec<(u32, DeadLetterReason)>,
+    ) {
+        let builder = builder().result_capacity(2).result_overflow(overflow);
+        let builder = if timer_wheel {
+            builder.timer_wheel(TimerWheelConfig::default())
+        } else {
+            builder
+        };
+        let (store, results, at) = outcome_store(builder);
+        let mut dead_letters = store.subscribe_dead_letters();
+        for key in 0..4 {
+            for millis in [0, 10] {
+                let instant = at(u64::from(key) * 100 + millis);
+                store
+                    .push_signal(key, instant, AverageDelayDecoder::new)
+                    .await
+                    .unwrap();
+            }
+        }
+        sleep(Duration::from_secs(120)).await;
+
+        let mut dead = Vec::new();
+        while let Some(Some(letter)) = dead_letters.next().now_or_never() {
+            dead.push((letter.key, letter.reason));
+        }
+        (store, results, dead)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waiting_on_a_full_result_stream_drops_nothing() {
+        for timer_wheel in [false, true] {
+            let (_store, mut results, dead) =
+                overflow_store(ResultOverflow::Wait, timer_wheel).await;
+            assert!(dead.is_empty(), "timer wheel: {timer_wheel}");
+
+            let mut keys = Vec::new();
+            for _ in 0..4 {
+                keys.push(results.next().await.unwrap().0);
+            }
+            keys.sort_unstable();
+            assert_eq!(keys, [0, 1, 2, 3], "timer wheel: {timer_wheel}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_full_result_stream_drops_the_newest_results() {
+        for timer_wheel in [false, true] {
+            let (_store, mut results, dead) =
+                overflow_store(ResultOverflow::DropNewest, timer_wheel).await;
+            let keys: Vec<_> = results
+                .drain_remaining()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect();
+            assert_eq!(keys, [0, 1], "timer wheel: {timer_wheel}");
+            assert_eq!(
+                dead,
+                [
+                    (2, DeadLetterReason::OverflowNewest),
+                    (3, DeadLetterReason::OverflowNewest)
+                ],
+                "timer wheel: {timer_wheel}"
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_full_result_stream_drops_the_oldest_results() {
+        for timer_wheel in [false, true] {
+            let (_store, mut results, dead) =
+                overflow_store(ResultOverflow::DropOldest, timer_wheel).await;
+            let keys: Vec<_> = results
+                .drain_remaining()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect();
+            assert_eq!(keys, [2, 3], "timer wheel: {timer_wheel}");
+            assert_eq!(
+                dead,
+                [
+                    (0, DeadLetterReason::OverflowOldest),
+                    (1, DeadLetterReason::OverflowOldest)
+                ],
+                "timer wheel: {timer_wheel}"
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn results_waiting_on_a_closed_stream_are_dead_lettered() {
+        for timer_wheel in [false, true] {
+            let (store, mut results, dead) =
+                overflow_store(ResultOverflow::Wait, timer_wheel).await;
+            let mut dead_letters = store.subscribe_dead_letters();
+            let delivered = results.drain_remaining().len();
+            assert!(dead.is_empty());
+
+            // The results that waited for room fail once the stream closes.
+            let mut waited = Vec::new();
+            for _ in delivered..4 {
+                let letter = timeout(Duration::from_secs(60), dead_letters.next())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(letter.reason, DeadLetterReason::StreamClosed);
+                waited.push(letter.key);
+            }
+            assert_eq!(delivered + waited.len(), 4, "timer wheel: {timer_wheel}");
+        }
+    }
+
+    #[tokio::test]
+    async fn borrowed_pushes_to_an_open_session_do_not_allocate_a_key() {
+        const PUSHES: usize = 1000;
+
+        let (store, _results) =
+            DelaySessionStoreBuilder::<String>::new(Duration::from_secs(3600)).build();
+        let decoder =
+            || ThresholdDelayDecoder::with_capacity(Duration::from_millis(20), 2 * PUSHES);
+        let key = "203.0.113.7";
+        let start = store.clock().now();
+        store.push_signal_ref(key, start, decoder).await.unwrap();
+
+        let before = test_alloc::allocations();
+        for n in 1..=PUSHES {
+            let instant = start + Duration::from_millis(10) * n as u32;
+            store.push_signal_ref(key, instant, decoder).await.unwrap();
+        }
+        let borrowed = test_alloc::allocations() - before;
+
+        let before = test_alloc::allocations();
+        for n in PUSHES + 1..=2 * PUSHES {
+            let instant = start + Duration::from_millis(10) * n as u32;
+            store
+                .push_signal(key.to_owned(), instant, decoder)
+                .await
+                .unwrap();
+        }
+        let owned = test_alloc::allocations() - before;
+
+        // Both take the allocations of the session re-arming its timeout,
+        // but only owned pushes allocate a key each.
+        assert!(
+            owned >= borrowed + PUSHES,
+            "{owned} allocations pushing owned keys, {borrowed} pushing borrowed ones"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_ten_minute_session_emits_a_snapshot_every_two_minutes() {
+        for timer_wheel in [false, true] {
+            let builder = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(30))
+                .emit_interval(Duration::from_secs(120));
+            let builder = if timer_wheel {
+                builder.timer_wheel(TimerWheelConfig {
+                    tick: Duration::from_secs(1),
+                    ..TimerWheelConfig::default()
+                })
+            } else {
+                builder
+            };
+            let (store, mut results) = builder.build();
+            let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(17_500));
+
+            // 8:45 minutes of signals, and the session times out 30 s later.
+            store.push_signal_now(1, decoder).await.unwrap();
+            for delay in [10, 25].repeat(15) {
+                sleep(Duration::from_secs(delay)).await;
+                store.push_signal_now(1, decoder).await.unwrap();
+            }
+
+            let mut snapshots = Vec::new();
+            let final_bits = loop {
+                match results.next().await.unwrap() {
+                    (1, SessionResult::Intermediate { result, bits }) => {
+                        assert_eq!(result.len(), bits);
+                        snapshots.push(result);
+                    }
+                    (1, SessionResult::Final(result)) => break result,
+                    (key, _) => panic!("unexpected key {key}"),
+                }
+            };
+            assert_eq!(final_bits.len(), 30);
+            assert_eq!(snapshots.len(), 4, "timer wheel: {timer_wheel}");
+            for snapshot in snapshots {
+                assert!(final_bits.starts_with(&snapshot));
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pushes_buffered_over_a_long_pause_keep_their_gaps() {
+        // Gaps of 100, 500, 100 and 300 ms; all but the first are pushed
+        // during a pause four times longer than the session timeout.
+        async fn decode(timer_wheel: bool, pause: bool) -> BitVec {
+            let builder = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(1));
+            let builder = if timer_wheel {
+                builder.timer_wheel(TimerWheelConfig::default())
+            } else {
+                builder
+            };
+            let (store, mut results) = builder.build();
+            let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(200));
+
+            store.push_signal_now(1, decoder).await.unwrap();
+            sleep(Duration::from_millis(100)).await;
+            store.push_signal_now(1, decoder).await.unwrap();
+            if pause {
+                store.pause(PausedPushes::Buffer);
+            }
+            for gap in [500, 100, 300] {
+                sleep(Duration::from_millis(gap)).await;
+                store.push_signal_now(1, decoder).await.unwrap();
+            }
+            if pause {
+                sleep(Duration::from_secs(4)).await;
+                assert!(store.contains_key(&1).await);
+                store.resume().await;
+            }
+            let (key, result) = results.next().await.unwrap();
+            assert_eq!(key, 1);
+            result
+        }
+
+        for timer_wheel in [false, true] {
+            let bits = decode(timer_wheel, true).await;
+            let unpaused = decode(timer_wheel, false).await;
+            assert_eq!(bits.len(), 4, "timer wheel: {timer_wheel}");
+            assert_eq!(bits, unpaused, "timer wheel: {timer_wheel}");
+            assert_ne!(bits[0], bits[1]);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn buffered_pushes_replay_at_their_own_instants() {
+        for timer_wheel in [false, true] {
+            let builder = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(1));
+            let builder = if timer_wheel {
+                builder.timer_wheel(TimerWheelConfig::default())
+            } else {
+                builder
+            };
+            let (store, mut results) = builder.build();
+            let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(200));
+
+            store.push_signal_now(1, decoder).await.unwrap();
+            sleep(Duration::from_millis(100)).await;
+            store.push_signal_now(1, decoder).await.unwrap();
+            store.pause(PausedPushes::Buffer);
+            sleep(Duration::from_millis(300)).await;
+            store.push_signal_now(1, decoder).await.unwrap();
+            sleep(Duration::from_secs(3)).await;
+            store.resume().await;
+
+            // The buffered signal's timeout starts at the resume, and the
+            // next duration spans the rest of the pause, rather than the
+            // buffered signal landing 300 ms after the resume.
+            sleep(Duration::from_millis(100)).await;
+            store.push_signal_now(1, decoder).await.unwrap();
+            sleep(Duration::from_millis(900)).await;
+            store.push_signal_now(1, decoder).await.unwrap();
+
+            let (key, bits) = results.next().await.unwrap();
+            assert_eq!(key, 1);
+            assert_eq!(bits, bitvec![0, 1, 1, 1], "timer wheel: {timer_wheel}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn signals_held_back_do_not_count_toward_the_duration_limit() {
+        for timer_wheel in [false, true] {
+            let builder = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(3600))
+                .max_durations(3)
+                .out_of_order(OutOfOrderPolicy::Reorder { window: 1 })
+                .keepalive_policy(KeepalivePolicy::RestartDuration);
+            let builder = if timer_wheel {
+                builder.timer_wheel(TimerWheelConfig::default())
+            } else {
+                builder
+            };
+            let (store, mut results) = builder.build();
+            let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(200));
+
+            // Four signals push two durations, the last signal held back.
+            store.push_signal_now(1, decoder).await.unwrap();
+            for _ in 0..3 {
+                sleep(Duration::from_millis(100)).await;
+                store.push_signal_now(1, decoder).await.unwrap();
+            }
+            assert!(
+                timeout(Duration::from_secs(1), results.next())
+                    .await
+                    .is_err(),
+                "timer wheel: {timer_wheel}"
+            );
+
+            // The keepalive pushes the held signal, reaching the limit.
+            store.push_keepalive(&1).await.unwrap();
+            let (key, bits) = timeout(Duration::from_secs(1), results.next())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(key, 1);
+            assert_eq!(bits.len(), 3, "timer wheel: {timer_wheel}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_signal_at_the_end_of_the_restart_grace_starts_a_session() {
+        let clock = ManualClock::new(Instant::now());
+        let (store, mut results) = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(1))
+            .restart_grace(Duration::from_millis(100))
+            .clock(clock.clone())
+            .build();
+
+        store
+            .push_signal_now(1, AverageDelayDecoder::new)
+            .await
+            .unwrap();
+        clock.advance(Duration::from_secs(1));
+        let (key, _) = results.next().await.unwrap();
+        assert_eq!(key, 1);
+
+        // The grace runs on the store's clock, not Tokio's.
+        sleep(Duration::from_secs(10)).await;
+        assert!(store.contains_key(&1).await);
+
+        clock.advance(Duration::from_millis(100));
+        store
+            .push_signal_now(1, AverageDelayDecoder::new)
+            .await
+            .unwrap();
+        sleep(Duration::from_secs(10)).await;
+        assert!(store.contains_key(&1).await);
+
+        clock.advance(Duration::from_secs(1));
+        let (key, _) = timeout(Duration::from_secs(1), results.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(key, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sessions_timing_out_together_keep_their_results_in_order() {
+        const KEYS: u32 = 1000;
+
+        for timer_wheel in [false, true] {
+            let builder =
+                DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(1)).result_capacity(8);
+            let builder = if timer_wheel {
+                builder.timer_wheel(TimerWheelConfig::default())
+            } else {
+                builder
+            };
+            let (store, results) = builder.build();
+            let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(200));
+
+            // Takes a result every 5 ms, a tenth of the rate sessions close at.
+            let consumer = tokio::spawn(
+                results
+                    .then(|result| async move {
+                        sleep(Duration::from_millis(5)).await;
+                        result
+                    })
+                    .take(2 * KEYS as usize)
+                    .collect::<Vec<_>>(),
+            );
+
+            // Every key decodes a 1, times out, and decodes a 0 in a second
+            // session while the results of the first still queue.
+            for gap in [300, 100] {
+                for key in 0..KEYS {
+                    store.push_signal_now(key, decoder).await.unwrap();
+                }
+                sleep(Duration::from_millis(gap)).await;
+                for key in 0..KEYS {
+                    store.push_signal_now(key, decoder).await.unwrap();
+                }
+                sleep(Duration::from_secs(2)).await;
+            }
+
+            let mut by_key = vec![Vec::new(); KEYS as usize];
+            for (key, bits) in consumer.await.unwrap() {
+                by_key[key as usize].push(bits);
+            }
+            for (key, results) in by_key.iter().enumerate() {
+                assert_eq!(
+                    *results,
+                    [bitvec![1], bitvec![0]],
+                    "key {key}, timer wheel: {timer_wheel}"
+                );
+            }
+            assert_eq!(store.session_count().await, 0);
+            assert!(store.metrics().results_send_blocked() > 0);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_restarted_session_decodes_with_the_updated_calibration() {
+        let calibration = Arc::new(RwLock::new(Duration::ZERO));
+        let (store, mut results) = delay_session_store_with_calibration(
+            Duration::from_secs(1),
+            calibration.clone(),
+            |_: &u32, threshold: &Duration| ThresholdDelayDecoder::new(*threshold),
+        );
+
+        // The same gap decodes as a 1, and as a 0 once the threshold rises.
+        for (threshold, bits) in [(200, bitvec![1]), (400, bitvec![0])] {
+            *calibration.write().unwrap() = Duration::from_millis(threshold);
+            store.push_signal_now(1).await.unwrap();
+            sleep(Duration::from_millis(300)).await;
+            store.push_signal_now(1).await.unwrap();
+            assert_eq!(results.next().await.unwrap(), (1, bits));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn snapshots_of_concurrent_sessions_are_complete() {
+        const KEYS: u32 = 200;
+
+        for timer_wheel in [false, true] {
+            let builder = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(3600));
+            let builder = if timer_wheel {
+                builder.timer_wheel(TimerWheelConfig::default())
+            } else {
+                builder
+            };
+            let (store, _results) = builder.build();
+            let store = Arc::new(store);
+            let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(5));
+            for key in 0..KEYS {
+                store.push_signal_now(key, decoder).await.unwrap();
+            }
+
+            let pushers: Vec<_> = (0..4)
+                .map(|pusher| {
+                    let store = Arc::clone(&store);
+                    tokio::spawn(async move {
+                        for round in 0..50 {
+                            for key in (pusher..KEYS).step_by(4) {
+                                store.push_signal_now(key, decoder).await.unwrap();
+                            }
+                            if round % 10 == 0 {
+                                tokio::task::yield_now().await;
+                            }
+                        }
+                    })
+                })
+                .collect();
+            let mut snapshots = Vec::new();
+            for _ in 0..5 {
+                snapshots.push(store.snapshot_all().await);
+                tokio::task::yield_now().await;
+            }
+            for pusher in pushers {
+                pusher.await.unwrap();
+            }
+            snapshots.push(store.snapshot_all().await);
+
+            for snapshot in snapshots {
+                let mut keys: Vec<_> = snapshot.iter().map(|session| session.key).collect();
+                keys.sort_unstable();
+                keys.dedup();
+                assert_eq!(keys.len(), snapshot.len(), "timer wheel: {timer_wheel}");
+                assert_eq!(keys.len(), KEYS as usize, "timer wheel: {timer_wheel}");
+                for session in snapshot {
+                    assert!(session.answered, "timer wheel: {timer_wheel}");
+                    // Bits may take in signals pushed after the request.
+                    let bits = session.bits.unwrap();
+                    assert!(bits.len() as u64 >= session.signals - 1);
+                }
+            }
+        }
+    }
+
+    /// A decoder that blocks its session task on its first duration until
+    /// released, so nothing drains the session's channel.
+    struct StalledDecoder {
+        stalled: Option<tokio::sync::oneshot::Sender<()>>,
+        release: std::sync::mpsc::Receiver<()>,
+    }
+
+    impl crate::decoder::DelayDecoder for StalledDecoder {
+        type Output = BitVec;
+
+        fn push_duration(&mut self, _: Duration) {
+            if let Some(stalled) = self.stalled.take() {
+                let _ = stalled.send(());
+                let _ = self.release.recv();
+            }
+        }
+
+        fn close(self) -> BitVec {
+            BitVec::new()
+        }
+    }
+
+    impl crate::decoder::MetaDelayDecoder<()> for StalledDecoder {}
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn a_full_session_channel_only_holds_up_its_own_key() {
+        let (store, _results) = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(3600))
+            .signal_capacity(1)
+            .build();
+        let store = Arc::new(store);
+        let (stalled, on_stall) = tokio::sync::oneshot::channel();
+        let (release, on_release) = std::sync::mpsc::channel();
+        let mut decoder = Some(StalledDecoder {
+            stalled: Some(stalled),
+            release: on_release,
+        });
+        let start = store.clock().now();
+        let stalled_decoder = move || decoder.take().unwrap();
+        let open = || -> StalledDecoder { unreachable!("the session is open") };
+        store.push_signal(0, start, stalled_decoder).await.unwrap();
+        let second = start + Duration::from_millis(10);
+        store.push_signal(0, second, open).await.unwrap();
+        on_stall.await.unwrap();
+
+        // One signal fills the channel, the next waits for room.
+        let third = start + Duration::from_millis(20);
+        store.push_signal(0, third, open).await.unwrap();
+        let blocked = tokio::spawn({
+            let store = Arc::clone(&store);
+            async move {
+                let fourth = start + Duration::from_millis(30);
+                store.push_signal(0, fourth, open).await
+            }
+        });
+        sleep(Duration::from_millis(50)).await;
+        assert!(!blocked.is_finished());
+
+        let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(15));
+        for key in 1..=10 {
+            for n in 0..4 {
+                let instant = start + Duration::from_millis(10) * n;
+                timeout(
+                    Duration::from_secs(1),
+                    store.push_signal(key, instant, decoder),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            }
+            timeout(Duration::from_secs(1), store.push_keepalive(&key))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        assert!(!blocked.is_finished());
+
+        release.send(()).unwrap();
+        timeout(Duration::from_secs(1), blocked)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    fn on_backend<T, O>(
+        builder: DelaySessionStoreBuilder<u32, T, O>,
+        timer_wheel: bool,
+    ) -> DelaySessionStoreBuilder<u32, T, O> {
+        if timer_wheel {
+            builder.timer_wheel(TimerWheelConfig::default())
+        } else {
+            builder
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_steadily_signalled_session_closes_at_its_lifetime() {
+        for timer_wheel in [false, true] {
+            let builder = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(1))
+                .max_lifetime(Duration::from_millis(2500))
+                .summarized();
+            let (store, mut results) = on_backend(builder, timer_wheel).build();
+            let start = store.clock().now();
+
+            for _ in 0..5 {
+                let now = store.clock().now();
+                store
+                    .push_signal(1, now, AverageDelayDecoder::new)
+                    .await
+                    .unwrap();
+                sleep(Duration::from_millis(500)).await;
+            }
+
+            let (key, summarized) = results.next().await.unwrap();
+            assert_eq!(key, 1);
+            let summary = summarized.summary.unwrap();
+            assert_eq!(summary.close_reason, CloseReason::Timeout);
+            let lifetime = summary.closed_at - start;
+            assert!(
+                lifetime >= Duration::from_millis(2500) && lifetime < Duration::from_millis(2600),
+                "timer wheel: {timer_wheel}, {lifetime:?}"
+            );
+            assert_eq!(summarized.result.len(), 4, "timer wheel: {timer_wheel}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_flushed_session_emits_right_away_and_others_stay_open() {
+        for timer_wheel in [false, true] {
+            let builder = builder().summarized();
+            let (store, mut results, at) = outcome_store(on_backend(builder, timer_wheel));
+            for millis in [0, 10, 40] {
+                for key in [1, 2] {
+                    store
+                        .push_signal(key, at(millis), AverageDelayDecoder::new)
+                        .await
+                        .unwrap();
+                }
+            }
+
+            assert!(store.flush(&1).await);
+            let (key, summarized) = timeout(Duration::from_millis(1), results.next())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(key, 1);
+            assert_eq!(summarized.result, bitvec![0, 1]);
+            let summary = summarized.summary.unwrap();
+            assert_eq!(summary.close_reason, CloseReason::Flushed);
+            assert_eq!(summary.signal_count, 3);
+
+            assert!(!store.flush(&1).await, "timer wheel: {timer_wheel}");
+            assert!(store.contains_key(&2).await);
+            assert!(!store.contains_key(&1).await);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn signal_metadata_reaches_the_decoder_with_its_duration() {
+        for timer_wheel in [false, true] {
+            let builder = on_backend(builder(), timer_wheel);
+            let (store, mut results) = builder.build_with_metadata::<&'static str>();
+            let start = store.clock().now();
+
+            // Asset requests in between are merged into the page gaps.
+            let signals = [
+                (0, "/"),
+                (10, "/a"),
+                (15, "/logo.png"),
+                (40, "/b"),
+                (50, "/c"),
+            ];
+            for (millis, path) in signals {
+                store
+                    .push_signal_with(1, start + Duration::from_millis(millis), path, || {
+                        MetadataFilterDecoder::new(
+                            ThresholdDelayDecoder::new(Duration::from_millis(20)),
+                            |path: &&str| !path.ends_with(".png"),
+                        )
+                    })
+                    .await
+                    .unwrap();
+            }
+            assert!(store.flush(&1).await);
+
+            let (_, bits) = results.next().await.unwrap();
+            assert_eq!(bits, bitvec![0, 1, 0], "timer wheel: {timer_wheel}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn session_info_follows_the_pushes_of_a_key() {
+        for timer_wheel in [false, true] {
+            let (store, _results, at) = outcome_store(on_backend(builder(), timer_wheel));
+            assert!(store.session_info(&1).await.is_none());
+            for millis in [0, 10, 40] {
+                store
+                    .push_signal(1, at(millis), AverageDelayDecoder::new)
+                    .await
+                    .unwrap();
+            }
+
+            let info = store.session_info(&1).await.unwrap();
+            assert_eq!(info.started_instant, at(0), "timer wheel: {timer_wheel}");
+            assert_eq!(info.last_signal_instant, at(40));
+            assert_eq!(info.deadline, at(60_040));
+            assert_eq!(info.signals, 3);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_burst_fits_a_raised_signal_capacity() {
+        for capacity in [None, Some(64)] {
+            let builder = match capacity {
+                Some(capacity) => builder().signal_capacity(capacity),
+                None => builder(),
+            };
+            let (store, mut results, at) = outcome_store(builder);
+            let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(15));
+
+            // The session task does not run in between, so only the channel
+            // takes the burst in.
+            let mut waited = false;
+            for millis in (0..64).map(|index| index * 10) {
+                let mut push = Box::pin(store.push_signal(1, at(millis), decoder));
+                if futures::poll!(push.as_mut()).is_pending() {
+                    waited = true;
+                    push.await.unwrap();
+                }
+            }
+            assert_eq!(waited, capacity.is_none());
+
+            let (_, bits) = results.next().await.unwrap();
+            assert_eq!(bits.len(), 63, "capacity: {capacity:?}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "signal channel capacity must be positive")]
+    fn a_zero_signal_capacity_is_rejected() {
+        let _ = builder().signal_capacity(0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_cancelled_key_emits_or_discards_its_partial_result() {
+        for timer_wheel in [false, true] {
+            for behavior in [CancelBehavior::Emit, CancelBehavior::Discard] {
+                let builder = builder().cancel_behavior(behavior).summarized();
+                let (store, mut results, at) = outcome_store(on_backend(builder, timer_wheel));
+                for millis in [0, 10, 40] {
+                    for key in [1, 2] {
+                        store
+                            .push_signal(key, at(millis), AverageDelayDecoder::new)
+                            .await
+                            .unwrap();
+                    }
+                }
+                sleep(Duration::from_millis(1)).await;
+
+                assert!(store.cancel_key(&1).await);
+                assert!(!store.cancel_key(&1).await);
+                assert!(!store.contains_key(&1).await);
+                assert!(store.contains_key(&2).await);
+
+                let result = timeout(Duration::from_millis(1), results.next()).await;
+                match behavior {
+                    CancelBehavior::Emit => {
+                        let (key, summarized) = result.unwrap().unwrap();
+                        assert_eq!(key, 1);
+                        assert_eq!(summarized.result, bitvec![0, 1]);
+                        let summary = summarized.summary.unwrap();
+                        assert_eq!(summary.close_reason, CloseReason::Cancelled);
+                        assert_eq!(summary.signal_count, 3);
+                    }
+                    CancelBehavior::Discard => {
+                        assert!(result.is_err(), "timer wheel: {timer_wheel}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test(start_paused = true)]
+    async fn a_cancelled_store_emits_every_partial_result_and_ends() {
+        use tokio_util::sync::CancellationToken;
+
+        for timer_wheel in [false, true] {
+            let token = CancellationToken::new();
+            let builder = builder().cancellation_token(token.clone());
+            let (store, mut results, at) = outcome_store(on_backend(builder, timer_wheel));
+            for millis in [0, 10, 40] {
+                for key in [1, 2] {
+                    store
+                        .push_signal(key, at(millis), AverageDelayDecoder::new)
+                        .await
+                        .unwrap();
+                }
+            }
+            sleep(Duration::from_millis(1)).await;
+
+            token.cancel();
+            let mut emitted = timeout(
+                Duration::from_millis(10),
+                results.by_ref().collect::<Vec<_>>(),
+            )
+            .await
+            .unwrap();
+            emitted.sort_unstable_by_key(|(key, _)| *key);
+            assert_eq!(
+                emitted,
+                [(1, bitvec![0, 1]), (2, bitvec![0, 1])],
+                "timer wheel: {timer_wheel}"
+            );
+            assert_eq!(store.session_count().await, 0);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_timeout_pushes_the_terminal_gap_on_either_backend() {
+        for timer_wheel in [false, true] {
+            for terminal in [TerminalDuration::Signal, TerminalDuration::SignalOrTimeout] {
+                let builder = builder().terminal_duration(terminal);
+                let (store, mut results, at) = outcome_store(on_backend(builder, timer_wheel));
+                for millis in [0, 100, 200] {
+                    store
+                        .push_signal(1, at(millis), || {
+                            ThresholdDelayDecoder::new(Duration::from_millis(150))
+                        })
+                        .await
+                        .unwrap();
+                }
+
+                let (_, bits) = results.next().await.unwrap();
+                let expected = match terminal {
+                    TerminalDuration::SignalOrTimeout => bitvec![0, 0, 1],
+                    _ => bitvec![0, 0],
+                };
+                assert_eq!(bits, expected, "timer wheel: {timer_wheel}, {terminal:?}");
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_keep_their_order_and_time_out_after_the_last() {
+        const SIGNALS: usize = 10_000;
+
+        for timer_wheel in [false, true] {
+            let (store, mut results, at) = outcome_store(on_backend(builder(), timer_wheel));
+            let bits: BitVec = (0..SIGNALS - 1).map(|index| index % 3 == 0).collect();
+            let mut millis = 0;
+            let instants: Vec<_> = std::iter::once(0)
+                .chain(bits.iter().map(|bit| {
+                    millis += if *bit { 30 } else { 10 };
+                    millis
+                }))
+                .map(&at)
+                .collect();
+
+            for batch in instants.chunks(100) {
+                store
+                    .push_signals(1, batch.iter().copied(), || {
+                        ThresholdDelayDecoder::new(Duration::from_millis(20))
+                    })
+                    .await
+                    .unwrap();
+            }
+            sleep(Duration::from_millis(1)).await;
+            let info = store.session_info(&1).await.unwrap();
+            assert_eq!(info.signals, SIGNALS as u64, "timer wheel: {timer_wheel}");
+            assert_eq!(info.last_signal_instant, at(millis));
+            assert_eq!(info.deadline, at(millis) + Duration::from_secs(60));
+
+            let (_, decoded) = results.next().await.unwrap();
+            assert_eq!(decoded, bits, "timer wheel: {timer_wheel}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalives_hold_an_open_session_and_start_none() {
+        for timer_wheel in [false, true] {
+            let builder = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(1));
+            let (store, mut results) = on_backend(builder, timer_wheel).build();
+            assert_eq!(store.push_keepalive(&1).await, Ok(false));
+            assert!(!store.contains_key(&1).await);
+
+            store
+                .push_signal_now(1, AverageDelayDecoder::new)
+                .await
+                .unwrap();
+            for _ in 0..5 {
+                sleep(Duration::from_millis(800)).await;
+                assert_eq!(store.push_keepalive(&1).await, Ok(true));
+            }
+            sleep(Duration::from_millis(800)).await;
+            assert!(store.contains_key(&1).await, "timer wheel: {timer_wheel}");
+
+            let (key, bits) = results.next().await.unwrap();
+            assert_eq!(key, 1);
+            assert!(bits.is_empty());
+            // Past the restart grace of the task backend.
+            sleep(Duration::from_millis(20)).await;
+            assert!(!store.contains_key(&1).await);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn an_adaptive_timeout_keeps_a_slowing_session_open() {
+        for timer_wheel in [false, true] {
+            let policy =
+                AdaptiveTimeout::new(2.0, Duration::from_millis(500), Duration::from_secs(4));
+            let builder = DelaySessionStoreBuilder::<u32>::with_timeout_policy(policy);
+            let (store, mut results, at) = outcome_store(on_backend(builder, timer_wheel));
+
+            // Each gap is longer than a fixed 500 ms timeout would allow.
+            for millis in [0, 400, 1100, 2400, 4900] {
+                sleep_until(at(millis).into()).await;
+                store
+                    .push_signal(1, at(millis), AverageDelayDecoder::new)
+                    .await
+                    .unwrap();
+            }
+            let (_, bits) = results.next().await.unwrap();
+            assert_eq!(bits, bitvec![0, 0, 1, 1], "timer wheel: {timer_wheel}");
+            let closed = store.clock().now();
+            assert!(
+                closed >= at(8900) && closed < at(9000),
+                "timer wheel: {timer_wheel}, {:?}",
+                closed - at(8900)
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn summarized_results_tell_how_each_session_closed() {
+        for timer_wheel in [false, true] {
+            let builder = builder()
+                .max_durations(2)
+                .max_sessions(2, SessionLimitPolicy::EvictLeastRecent)
+                .summarized();
+            let (store, mut results, at) = outcome_store(on_backend(builder, timer_wheel));
+            let push = |key, millis| {
+                store.push_signal(key, at(millis), || {
+                    ThresholdDelayDecoder::new(Duration::from_millis(15))
+                })
+            };
+            // Key 1 ends on a signal past its deadline, key 2 reaches its
+            // duration limit, and key 3 is evicted by key 4.
+            for (key, millis) in [(1, 0), (1, 10), (1, 70_010), (2, 0), (2, 10), (2, 40)] {
+                push(key, millis).await.unwrap();
+            }
+            push(3, 0).await.unwrap();
+            push(4, 5).await.unwrap();
+
+            let mut closed = Vec::new();
+            for _ in 0..3 {
+                let (key, summarized) = timeout(Duration::from_millis(1), results.next())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let summary = summarized.summary.unwrap();
+                closed.push((key, summarized.result, summary.close_reason));
+                assert_eq!(summary.started_at, at(0));
+            }
+            closed.sort_unstable_by_key(|(key, ..)| *key);
+            assert_eq!(
+                closed,
+                [
+                    (1, bitvec![0], CloseReason::TerminatingSignal),
+                    (2, bitvec![0, 1], CloseReason::LimitReached),
+                    (3, bitvec![], CloseReason::Evicted),
+                ],
+                "timer wheel: {timer_wheel}"
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stores_drop_signals_within_the_min_gap() {
+        for timer_wheel in [false, true] {
+            let builder = builder().min_signal_gap(Duration::from_millis(1));
+            let (store, mut results, at) = outcome_store(on_backend(builder, timer_wheel));
+            let spam = |millis: u64| at(millis) + Duration::from_micros(300);
+            for instant in [at(0), spam(0), at(10), at(40), spam(40), spam(40), at(50)] {
+                store
+                    .push_signal(1, instant, || {
+                        ThresholdDelayDecoder::new(Duration::from_millis(15))
+                    })
+                    .await
+                    .unwrap();
+            }
+            sleep(Duration::from_millis(1)).await;
+            assert_eq!(store.session_info(&1).await.unwrap().signals, 4);
+
+            let (_, bits) = results.next().await.unwrap();
+            assert_eq!(bits, bitvec![0, 1, 0], "timer wheel: {timer_wheel}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_closed_session_emits_at_once_and_its_key_starts_over() {
+        for timer_wheel in [false, true] {
+            let (store, mut results, at) = outcome_store(on_backend(builder(), timer_wheel));
+            let decoder = || ThresholdDelayDecoder::new(Duration::from_millis(15));
+            assert_eq!(store.close_session(&1).await, Err(CloseError::NoSession));
+            for millis in [0, 10, 40] {
+                store.push_signal(1, at(millis), decoder).await.unwrap();
+            }
+
+            assert_eq!(store.close_session(&1).await, Ok(()));
+            let (key, bits) = timeout(Duration::from_millis(1), results.next())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!((key, bits), (1, bitvec![0, 1]));
+            assert!(!store.contains_key(&1).await);
+            assert_eq!(store.close_session(&1).await, Err(CloseError::NoSession));
+
+            store.push_signal(1, at(50), decoder).await.unwrap();
+            let info = store.session_info(&1).await.unwrap();
+            assert_eq!(info.started_instant, at(50), "timer wheel: {timer_wheel}");
+            assert_eq!(info.signals, 1);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_all_emits_every_open_session() {
+        for timer_wheel in [false, true] {
+            let (store, mut results, at) = outcome_store(on_backend(builder(), timer_wheel));
+            for key in 1..=3 {
+                for millis in [0, 10, 40] {
+                    store
+                        .push_signal(key, at(millis), || {
+                            ThresholdDelayDecoder::new(Duration::from_millis(15))
+                        })
+                        .await
+                        .unwrap();
+                }
+            }
+
+            assert_eq!(store.close_all().await, 3);
+            assert_eq!(store.session_count().await, 0);
+            let mut closed = Vec::new();
+            for _ in 1..=3 {
+                let result = timeout(Duration::from_millis(1), results.next())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                closed.push(result);
+            }
+            closed.sort_unstable_by_key(|(key, _)| *key);
+            let expected: Vec<_> = (1..=3).map(|key| (key, bitvec![0, 1])).collect();
+            assert_eq!(closed, expected, "timer wheel: {timer_wheel}");
+            assert_eq!(store.close_all().await, 0);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn introspection_tracks_sessions_as_they_open_and_time_out() {
+        for timer_wheel in [false, true] {
+            let (store, mut results) = on_backend(builder(), timer_wheel).build();
+            assert_eq!(store.session_count().await, 0);
+            assert!(store.keys().await.is_empty());
+
+            store
+                .push_signal_now(1, AverageDelayDecoder::new)
+                .await
+                .unwrap();
+            sleep(Duration::from_secs(30)).await;
+            for key in [2, 3] {
+                store
+                    .push_signal_now(key, AverageDelayDecoder::new)
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(store.session_count().await, 3);
+            let mut keys = store.keys().await;
+            keys.sort_unstable();
+            assert_eq!(keys, [1, 2, 3]);
+
+            let (key, _) = results.next().await.unwrap();
+            assert_eq!(key, 1);
+            sleep(Duration::from_millis(20)).await;
+            assert_eq!(store.session_count().await, 2, "timer wheel: {timer_wheel}");
+            assert!(!store.contains_key(&1).await);
+            assert!(store.contains_key(&2).await && store.contains_key(&3).await);
+            let mut keys = store.keys().await;
+            keys.sort_unstable();
+            assert_eq!(keys, [2, 3]);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keys_time_out_after_their_own_timeouts() {
+        for timer_wheel in [false, true] {
+            let (store, mut results, at) = outcome_store(on_backend(builder(), timer_wheel));
+            for (key, timeout) in [(1, 1), (2, 5)] {
+                store
+                    .push_signal_with_timeout(
+                        key,
+                        at(0),
+                        Duration::from_secs(timeout),
+                        AverageDelayDecoder::new,
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            let (key, _) = results.next().await.unwrap();
+            assert_eq!(key, 1);
+            let closed = store.clock().now();
+            assert!(
+                closed >= at(1000) && closed < at(1100),
+                "timer wheel: {timer_wheel}"
+            );
+
+            // A plain push keeps the key's timeout.
+            sleep_until(at(2000).into()).await;
+            store
+                .push_signal(2, at(2000), AverageDelayDecoder::new)
+                .await
+                .unwrap();
+            sleep_until(at(6900).into()).await;
+            assert!(store.contains_key(&2).await, "timer wheel: {timer_wheel}");
+
+            // A later push changes it from its own signal on.
+            store
+                .push_signal_with_timeout(
+                    2,
+                    at(6900),
+                    Duration::from_millis(500),
+                    AverageDelayDecoder::new,
+                )
+                .await
+                .unwrap();
+            let (key, bits) = results.next().await.unwrap();
+            assert_eq!(key, 2);
+            assert_eq!(bits.len(), 2);
+            let closed = store.clock().now();
+            assert!(
+                closed >= at(7400) && closed < at(7500),
+                "timer wheel: {timer_wheel}"
+            );
+        }
+    }
 }
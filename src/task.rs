@@ -0,0 +1,119 @@
+use std::{
+    future::Future,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use tokio::task::JoinHandle;
+
+use crate::instrument::KeyRedactor;
+
+/// Spawns `future` as a task named by `name`, which is only called if the
+/// name can be applied: with the `console` feature and `--cfg tokio_unstable`,
+/// so tasks can be told apart in tokio-console. With the `tracing` feature the
+/// task runs in the span it was spawned from.
+#[cfg_attr(not(all(feature = "console", tokio_unstable)), allow(unused_variables))]
+pub(crate) fn spawn<F>(name: impl FnOnce() -> String, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(feature = "tracing")]
+    let future = tracing::Instrument::in_current_span(future);
+
+    #[cfg(all(feature = "console", tokio_unstable))]
+    let handle = tokio::task::Builder::new()
+        .name(&name())
+        .spawn(future)
+        .expect("spawning a task on the current runtime cannot fail");
+
+    #[cfg(not(all(feature = "console", tokio_unstable)))]
+    let handle = tokio::spawn(future);
+
+    handle
+}
+
+/// Spawns the task of `key`'s sessions, named after `store` and a digest of
+/// the key. With the `tracing` feature it runs in a span carrying the key as
+/// formatted for logs, so tasks and their events can be filtered by key.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn spawn_session<K, F>(
+    store: &str,
+    redactor: &KeyRedactor<K>,
+    key: &K,
+    future: F,
+) -> JoinHandle<F::Output>
+where
+    K: Hash,
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let name = || format!("{store} session {:08x}", key_digest(key));
+
+    #[cfg(feature = "tracing")]
+    {
+        use tracing::Instrument;
+
+        let span = tracing::info_span!("session", store, key = ?redactor.key(key));
+        spawn(name, future.instrument(span))
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    spawn(name, future)
+}
+
+/// A short digest of `key` that tells keys apart without revealing them.
+fn key_digest<K: Hash>(key: &K) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as u32
+}
+
+#[cfg(all(test, feature = "console", tokio_unstable))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id},
+        Subscriber,
+    };
+    use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+    use super::spawn_session;
+    use crate::instrument::KeyRedactor;
+
+    /// Collects the names tokio gives the spans of spawned tasks.
+    #[derive(Clone, Default)]
+    struct TaskNames(Arc<Mutex<Vec<String>>>);
+
+    impl<S: Subscriber> Layer<S> for TaskNames {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            struct Name<'a>(&'a Mutex<Vec<String>>);
+
+            impl Visit for Name<'_> {
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "task.name" {
+                        self.0.lock().unwrap().push(format!("{value:?}"));
+                    }
+                }
+            }
+
+            attrs.record(&mut Name(&self.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn session_tasks_are_named_after_their_store() {
+        let names = TaskNames::default();
+        let _guard = tracing_subscriber::registry()
+            .with(names.clone())
+            .set_default();
+
+        let handle = spawn_session("bench", &KeyRedactor::redacted(), &7u32, async { 42 });
+        assert_eq!(handle.await.unwrap(), 42);
+
+        let names = names.0.lock().unwrap();
+        assert_eq!(names.len(), 1);
+        assert!(names[0].starts_with("bench session "), "{names:?}");
+    }
+}
@@ -0,0 +1,739 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    mem::forget,
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, PoisonError, RwLock, Weak,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{pending, select, BoxFuture, Either},
+    join, FutureExt,
+};
+use tokio::sync::{
+    mpsc::{
+        error::{SendError, TrySendError},
+        OwnedPermit, Receiver, Sender,
+    },
+    oneshot, watch, Mutex, Notify,
+};
+
+use crate::{
+    cancel::{Cancellation, InFlight},
+    clock::Clock,
+    decoder::{DecoderOutput, MetaDelayDecoder},
+    diagnostics::DiagnosticReason,
+    emitter::ResultEmitter,
+    error::PushError,
+    instant_policy::{within, InstantScreen, OutOfOrderPolicy, Screened},
+    instrument,
+    pause::PauseReceiver,
+    session::{
+        timeout_instant, CloseReason, DelaySession, PauseGapPolicy, Signal, SignalKind,
+        SignalReceiver, SignalSender,
+    },
+    session_config::SessionConfig,
+    session_store::{CancelBehavior, SessionInfo},
+    task,
+    timeout_policy::SessionTimeout,
+};
+
+/// Asks a session task for what its decoder decoded so far.
+pub(crate) type SnapshotRequest<O> = oneshot::Sender<Option<O>>;
+pub(crate) type SnapshotReceiver<O> = Receiver<SnapshotRequest<O>>;
+
+#[derive(Debug)]
+pub(crate) struct SessionEntry<O, M> {
+    pub(crate) sender: SignalSender<M>,
+    pub(crate) snapshots: Sender<SnapshotRequest<O>>,
+    /// When the session was paused with `DelaySessionStore::pause_key`, and
+    /// how many signals were pushed since, which the session holds.
+    pub(crate) paused: Option<(Instant, usize)>,
+    /// Set to why the store closes the session, which cancels it right away
+    /// if it is cancelled, see `DelaySessionStore::cancel_key`.
+    pub(crate) closing: watch::Sender<Option<CloseReason>>,
+    pub(crate) id: u64,
+    pub(crate) last_instant: Instant,
+    pub(crate) timeout: SessionTimeout,
+    /// The timeout pushes to the key fixed in place of the store's policy.
+    pub(crate) fixed_timeout: Option<Duration>,
+    pub(crate) started_instant: Instant,
+    pub(crate) durations: u64,
+    /// Data signals counted in `durations` but still queued for the
+    /// session's full channel, see `QueuedSignal`.
+    pub(crate) queued: Arc<AtomicU64>,
+}
+
+impl<O, M> SessionEntry<O, M> {
+    /// Closes the session for `reason`. Dropping the entry drops the
+    /// session's only sender, which ends it once it has taken in the signals
+    /// queued before.
+    pub(crate) fn close(self, reason: CloseReason) {
+        self.closing.send_replace(Some(reason));
+    }
+
+    pub(crate) fn info(&self, max_lifetime: Option<Duration>) -> SessionInfo {
+        let timeout = self.timeout.deadline();
+        SessionInfo {
+            started_instant: self.started_instant,
+            last_signal_instant: self.last_instant,
+            deadline: match max_lifetime {
+                Some(max_lifetime) => {
+                    timeout.min(timeout_instant(self.started_instant, max_lifetime))
+                }
+                None => timeout,
+            },
+            signals: self.durations + 1,
+        }
+    }
+
+    /// Screens `instant` against the session's previous signal, recording it
+    /// as the new previous signal if accepted. A signal past the previous
+    /// one's timeout restarts the session, one out of order is counted, and
+    /// one within `min_signal_gap` is not, just as the session task will.
+    /// An accepted signal with a `fixed_timeout` fixes the session's timeout
+    /// from that signal on, and a replayed one's timeout does not count the
+    /// rest of the store's pause.
+    pub(crate) fn screen(
+        &mut self,
+        screen: &InstantScreen<'_>,
+        instant: Instant,
+        config: &SessionConfig,
+        fixed_timeout: Option<Duration>,
+    ) -> Screened {
+        let screened = screen.check(Some(self.last_instant), instant);
+        if let (Screened::Accept(_), Some(timeout)) = (screened, fixed_timeout) {
+            self.fixed_timeout = Some(timeout);
+            self.timeout.fix(timeout);
+        }
+        if let Some((_, held)) = &mut self.paused {
+            if matches!(screened, Screened::Accept(_)) {
+                *held += 1;
+            }
+            return screened;
+        }
+        if let Screened::Accept(instant) = screened {
+            if self.timed_out(instant, config.max_lifetime) {
+                self.started_instant = instant;
+                self.durations = 0;
+                self.last_instant = instant;
+                self.timeout = config.timeout.start(instant, self.fixed_timeout);
+                self.timeout.extend(screen.pause_left(instant));
+            } else if within(instant, self.last_instant, config.min_signal_gap) {
+                // Debounced by the session.
+            } else if instant >= self.last_instant
+                || config.out_of_order == OutOfOrderPolicy::SaturateZero
+            {
+                self.durations += 1;
+                self.last_instant = instant;
+                self.timeout.signal(instant);
+                self.timeout.extend(screen.pause_left(instant));
+            } else if config.out_of_order != OutOfOrderPolicy::Drop {
+                self.durations += 1;
+            }
+        }
+        screened
+    }
+
+    /// Records a keepalive at `instant`, returning the session's extended
+    /// deadline, or `None` if the keepalive found it timed out.
+    pub(crate) fn keep_alive(
+        &mut self,
+        instant: Instant,
+        max_lifetime: Option<Duration>,
+    ) -> Option<Instant> {
+        (!self.timed_out(instant, max_lifetime)).then(|| self.timeout.keepalive(instant))
+    }
+
+    /// Resumes the session at `resumed_at`, as the session will, returning
+    /// whether it was paused.
+    pub(crate) fn resume(
+        &mut self,
+        resumed_at: Instant,
+        policy: PauseGapPolicy,
+        pause_buffer: usize,
+    ) -> bool {
+        let Some((paused_at, held)) = self.paused.take() else {
+            return false;
+        };
+        let resumed_at = resumed_at.max(paused_at);
+        let gap = resumed_at.saturating_duration_since(paused_at);
+        self.timeout.shift(gap);
+        let held = held.min(pause_buffer) as u64;
+        if held > 0 {
+            self.durations += held;
+            self.last_instant = resumed_at;
+        } else if policy == PauseGapPolicy::Exclude {
+            self.last_instant = timeout_instant(self.last_instant, gap);
+        }
+        true
+    }
+
+    pub(crate) fn timed_out(&self, instant: Instant, max_lifetime: Option<Duration>) -> bool {
+        instant >= self.timeout.deadline()
+            || max_lifetime.is_some_and(|max_lifetime| {
+                instant >= timeout_instant(self.started_instant, max_lifetime)
+            })
+    }
+}
+
+type PermitFuture<M> = BoxFuture<'static, Result<OwnedPermit<Signal<M>>, SendError<()>>>;
+
+/// A signal taken from the locked sender map for a session whose channel is
+/// full. It queues for the channel while the map is locked, which keeps the
+/// signals pushed to one key in the order they were screened, and is sent
+/// with [`finish`](Self::finish) once the map is unlocked, so a full channel
+/// only holds up pushes to its own key.
+pub(crate) struct QueuedSignal<M> {
+    permit: PermitFuture<M>,
+    signal: Signal<M>,
+    _queued: QueuedData,
+}
+
+impl<M: Send + 'static> QueuedSignal<M> {
+    /// Sends `signal` right away if `sender`'s channel has room, and queues
+    /// it for the channel otherwise, counted in `queued` if it is data.
+    pub(crate) async fn send(
+        sender: &SignalSender<M>,
+        queued: &Arc<AtomicU64>,
+        signal: Signal<M>,
+    ) -> Result<Option<Self>, PushError> {
+        let signal = match sender.try_send(signal) {
+            Ok(()) => return Ok(None),
+            Err(TrySendError::Closed(_)) => return Err(PushError::SessionClosed),
+            Err(TrySendError::Full(signal)) => signal,
+        };
+        let mut permit: PermitFuture<M> = Box::pin(sender.clone().reserve_owned());
+        // Polled once, the permit takes its place in the channel's queue.
+        match futures::poll!(permit.as_mut()) {
+            Poll::Ready(Ok(permit)) => {
+                permit.send(signal);
+                Ok(None)
+            }
+            Poll::Ready(Err(_)) => Err(PushError::SessionClosed),
+            Poll::Pending => Ok(Some(Self {
+                permit,
+                _queued: QueuedData::new(queued, signal.kind),
+                signal,
+            })),
+        }
+    }
+
+    pub(crate) async fn finish(self) -> Result<(), PushError> {
+        let permit = self.permit.await.map_err(|_| PushError::SessionClosed)?;
+        permit.send(self.signal);
+        Ok(())
+    }
+}
+
+/// Counts a queued data signal in `SessionEntry::queued` until dropped.
+struct QueuedData(Option<Arc<AtomicU64>>);
+
+impl QueuedData {
+    fn new(queued: &Arc<AtomicU64>, kind: SignalKind) -> Self {
+        if kind != SignalKind::Data {
+            return Self(None);
+        }
+        queued.fetch_add(1, Ordering::Relaxed);
+        Self(Some(Arc::clone(queued)))
+    }
+}
+
+impl Drop for QueuedData {
+    fn drop(&mut self) {
+        if let Some(queued) = &self.0 {
+            queued.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Finishes `queued` from [`QueuedSignal::send`], if the signal was queued.
+pub(crate) async fn finish_queued<M: Send + 'static>(
+    queued: Result<Option<QueuedSignal<M>>, PushError>,
+) -> Result<(), PushError> {
+    match queued? {
+        Some(queued) => queued.finish().await,
+        None => Ok(()),
+    }
+}
+
+pub(crate) type SharedSignalSenderMap<K, O, M> = Mutex<HashMap<K, SessionEntry<O, M>>>;
+
+/// Removes `key` from `map` only if it still belongs to the session `id`,
+/// then wakes those waiting on `closed` for room under the session limit.
+pub(crate) async fn remove_session<K, O, M>(
+    map: &SharedSignalSenderMap<K, O, M>,
+    key: K,
+    id: u64,
+    closed: &Notify,
+) where
+    K: Eq + Hash,
+{
+    let mut map = map.lock().await;
+    if map.get(&key).is_some_and(|entry| entry.id == id) {
+        map.remove(&key);
+    }
+    drop(map);
+    closed.notify_waiters();
+}
+
+/// Where a store's session tasks deliver their results. Migrating a store
+/// points its link at the target's, so already spawned tasks follow along.
+pub(crate) enum SessionLink<K, T, O, M> {
+    Store(LinkTarget<K, T, O, M>),
+    Migrated(SharedSessionLink<K, T, O, M>),
+}
+
+pub(crate) type SharedSessionLink<K, T, O, M> = Arc<RwLock<SessionLink<K, T, O, M>>>;
+
+pub(crate) struct LinkTarget<K, T, O, M> {
+    pub(crate) sender_map: Weak<SharedSignalSenderMap<K, O, M>>,
+    pub(crate) emitter: ResultEmitter<K, T, O>,
+    /// The store's pause, which migrated session tasks listen to once their
+    /// own store is gone.
+    pub(crate) pause: PauseReceiver,
+}
+
+impl<K, T, O, M> Clone for LinkTarget<K, T, O, M> {
+    fn clone(&self) -> Self {
+        Self {
+            sender_map: self.sender_map.clone(),
+            emitter: self.emitter.clone(),
+            pause: self.pause.clone(),
+        }
+    }
+}
+
+/// Runs `session` to completion, emitting a snapshot of it every
+/// `emit_interval` since it started. While the store is paused the session is
+/// not polled, so it cannot time out, and on resume its deadlines are
+/// extended by the pause. Once the store is cancelled the session is closed early.
+/// Snapshot requests are answered after the session has taken
+/// in every signal already sent to it. `key` is only borrowed mutably so the future is `Send`
+/// without requiring `K: Sync`.
+async fn drive_session<K, T, D, M>(
+    mut session: Pin<&mut DelaySession<D, M>>,
+    key: &mut K,
+    link: &SharedSessionLink<K, T, D::Output, M>,
+    emit_interval: Option<Duration>,
+    clock: &dyn Clock,
+    binding: StoreBinding<'_>,
+    snapshots: &mut Option<SnapshotReceiver<D::Output>>,
+) -> (D::Output, SignalReceiver<M>)
+where
+    K: Clone + Eq + Hash,
+    D: MetaDelayDecoder<M>,
+    D::Output: Clone,
+{
+    let StoreBinding {
+        pause,
+        cancellation,
+        in_flight,
+    } = binding;
+    let mut next_emit = emit_interval.map(|interval| timeout_instant(clock.now(), interval));
+    let mut paused_total = pause
+        .as_ref()
+        .map_or(Duration::ZERO, |pause| pause.borrow().total);
+
+    loop {
+        if cancellation.is_cancelled() {
+            return session.as_mut().close().expect("driven sessions are open");
+        }
+
+        if let Some(receiver) = pause {
+            let state = *receiver.borrow_and_update();
+            let shift = state.total.saturating_sub(paused_total);
+            if !shift.is_zero() {
+                session.as_mut().shift(shift);
+                next_emit = next_emit.map(|next_emit| next_emit + shift);
+                paused_total = state.total;
+            }
+
+            if state.paused.is_some() {
+                let store_alive = {
+                    let request = pin!(next_request(snapshots));
+                    let cancelled = pin!(cancellation.cancelled());
+                    match select(pin!(receiver.changed()), select(request, cancelled)).await {
+                        Either::Left((changed, _)) => changed.is_ok(),
+                        Either::Right((Either::Left((request, _)), _)) => {
+                            let _ = request.send(session.snapshot());
+                            true
+                        }
+                        Either::Right((Either::Right(_), _)) => continue,
+                    }
+                };
+                if !store_alive {
+                    *pause = rebind(link, cancellation, in_flight);
+                    paused_total = pause
+                        .as_ref()
+                        .map_or(paused_total, |pause| pause.borrow().total);
+                }
+                continue;
+            }
+        }
+
+        // Whether a snapshot is due, or else whether the store is gone.
+        let (emit_due, store_alive) = {
+            let emit = pin!(async {
+                match next_emit {
+                    Some(next_emit) => clock.sleep_until(next_emit).await,
+                    None => pending().await,
+                }
+            });
+            let pause_changed = pin!(async {
+                match pause {
+                    Some(receiver) => receiver.changed().await.is_ok(),
+                    None => pending().await,
+                }
+            });
+
+            let request = pin!(next_request(snapshots));
+            let cancelled = pin!(cancellation.cancelled());
+
+            match select(
+                session.as_mut(),
+                select(emit, select(pause_changed, select(request, cancelled))),
+            )
+            .await
+            {
+                Either::Left((output, _)) => return output,
+                Either::Right((Either::Left(_), _)) => (true, true),
+                Either::Right((Either::Right((Either::Left((store_alive, _)), _)), _)) => {
+                    (false, store_alive)
+                }
+                Either::Right((
+                    Either::Right((Either::Right((Either::Left((request, _)), _)), _)),
+                    _,
+                )) => {
+                    // The session was just polled, so it has taken in every
+                    // signal sent before the request.
+                    if let Some(output) = session.as_mut().now_or_never() {
+                        return output;
+                    }
+                    let _ = request.send(session.snapshot());
+                    (false, true)
+                }
+                Either::Right((Either::Right((Either::Right((Either::Right(_), _)), _)), _)) => {
+                    continue
+                }
+            }
+        };
+
+        if !store_alive {
+            *pause = rebind(link, cancellation, in_flight);
+            paused_total = pause
+                .as_ref()
+                .map_or(paused_total, |pause| pause.borrow().total);
+        }
+        if emit_due {
+            if let Some(bits) = session.snapshot() {
+                resolve_link(link)
+                    .emitter
+                    .emit_intermediate(key.clone(), bits)
+                    .await;
+            }
+            if let (Some(next_emit), Some(interval)) = (&mut next_emit, emit_interval) {
+                *next_emit += interval;
+            }
+        }
+    }
+}
+
+/// The store whose cancellation and pause a session task follows: the one
+/// that started it, or once that is dropped after migrating, the store it
+/// migrated into.
+struct StoreBinding<'a> {
+    /// `None` once no store can pause or resume the session anymore.
+    pause: &'a mut Option<PauseReceiver>,
+    cancellation: &'a mut Cancellation,
+    /// Holds off the drain of a store migrated into until the session ends.
+    in_flight: &'a mut Option<InFlight>,
+}
+
+/// Binds a session task whose store is gone to the store its sessions
+/// migrated into, if any, returning that store's pause.
+fn rebind<K, T, O, M>(
+    link: &SharedSessionLink<K, T, O, M>,
+    cancellation: &mut Cancellation,
+    in_flight: &mut Option<InFlight>,
+) -> Option<PauseReceiver> {
+    let target = resolve_link(link);
+    // Without a migration, the link leads to the dropped store itself.
+    target.pause.has_changed().ok()?;
+    *cancellation = target.emitter.cancellation;
+    *in_flight = Some(cancellation.in_flight());
+    Some(target.pause)
+}
+
+/// Waits for the next snapshot request, forever once the store can send no
+/// more.
+async fn next_request<O>(snapshots: &mut Option<SnapshotReceiver<O>>) -> SnapshotRequest<O> {
+    if let Some(receiver) = snapshots {
+        if let Some(request) = receiver.recv().await {
+            return request;
+        }
+        *snapshots = None;
+    }
+    pending().await
+}
+
+/// Starts the next session on `receiver` once a signal arrives within
+/// `grace` on `clock`, refusing snapshot requests meanwhile, as there is no
+/// session to copy.
+async fn restart_session<D, M>(
+    decoder_factory: impl FnOnce() -> D,
+    receiver: SignalReceiver<M>,
+    grace: Duration,
+    clock: &dyn Clock,
+    snapshots: &mut Option<SnapshotReceiver<D::Output>>,
+) -> DelaySession<D, M>
+where
+    D: MetaDelayDecoder<M>,
+{
+    let start = pin!(DelaySession::start_with_receiver_graceful(
+        decoder_factory,
+        receiver,
+        grace,
+        clock
+    ));
+    let refuse = pin!(async {
+        loop {
+            drop(next_request(snapshots).await);
+        }
+    });
+    match select(start, refuse).await {
+        Either::Left((session, _)) => session,
+        Either::Right(_) => unreachable!("refusing snapshot requests never ends"),
+    }
+}
+
+pub(crate) fn resolve_link<K, T, O, M>(
+    link: &SharedSessionLink<K, T, O, M>,
+) -> LinkTarget<K, T, O, M> {
+    let mut link = link.clone();
+    loop {
+        let next = match &*link.read().unwrap_or_else(PoisonError::into_inner) {
+            SessionLink::Store(target) => return target.clone(),
+            SessionLink::Migrated(next) => next.clone(),
+        };
+        link = next;
+    }
+}
+
+/// What a session task runs on: the key's first session and what it needs
+/// to restart it, and the store it reports to.
+pub(crate) struct SessionTask<K, T, O, M, D, F> {
+    pub(crate) key: K,
+    /// Tells the key's entry from a later session's, see `remove_session`.
+    pub(crate) id: u64,
+    pub(crate) session: DelaySession<D, M>,
+    pub(crate) decoder_factory: F,
+    pub(crate) snapshots: SnapshotReceiver<O>,
+    /// Why the store closes the session, see `SessionEntry::closing`.
+    pub(crate) closing: watch::Receiver<Option<CloseReason>>,
+    pub(crate) link: SharedSessionLink<K, T, O, M>,
+    pub(crate) config: SessionConfig,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) pause: PauseReceiver,
+    pub(crate) cancellation: Cancellation,
+    pub(crate) emit_interval: Option<Duration>,
+}
+
+impl<K, T, O, M, D, F> SessionTask<K, T, O, M, D, F>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    T: Send + 'static,
+    O: DecoderOutput + Clone + Send + 'static,
+    M: Send + 'static,
+    D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    F: FnMut() -> D + Send + 'static,
+{
+    /// Drives the key's sessions, restarting them as signals keep arriving,
+    /// and emits each one's result, until one closes without a successor.
+    /// The key's entry is removed once the task ends.
+    pub(crate) async fn run(self) {
+        struct SessionRemoveGuard<'a, K, T, O, M>
+        where
+            K: Clone + Eq + Hash + Send + 'static,
+            O: Send + 'static,
+            M: Send + 'static,
+        {
+            key: &'a mut K,
+            id: u64,
+            link: &'a SharedSessionLink<K, T, O, M>,
+        }
+
+        impl<K, T, O, M> Drop for SessionRemoveGuard<'_, K, T, O, M>
+        where
+            K: Clone + Eq + Hash + Send + 'static,
+            O: Send + 'static,
+            M: Send + 'static,
+        {
+            fn drop(&mut self) {
+                let LinkTarget {
+                    sender_map,
+                    emitter,
+                    ..
+                } = resolve_link(self.link);
+                if let Some(map) = sender_map.upgrade() {
+                    let key = self.key.clone();
+                    let id = self.id;
+                    let closed = emitter.closed.clone();
+                    task::spawn(|| format!("{} session cleanup", emitter.name), async move {
+                        remove_session(&map, key, id, &closed).await;
+                    });
+                }
+            }
+        }
+
+        let Self {
+            mut key,
+            id,
+            session,
+            mut decoder_factory,
+            snapshots,
+            closing: store_reason,
+            link,
+            config,
+            clock,
+            pause,
+            mut cancellation,
+            emit_interval,
+        } = self;
+        let (cancel_behavior, restart_grace) = (config.cancel_behavior, config.restart_grace);
+        let mut pause = Some(pause);
+        let session_clock = clock.clone();
+        let closing_reason = store_reason.clone();
+        let configure = move |session: DelaySession<D, M>| {
+            let mut closing_reason = closing_reason.clone();
+            config.configure(
+                session
+                    .clock(session_clock.clone())
+                    .cancel_when(async move {
+                        // Without a cancel, the session's entry was removed.
+                        let cancelled = closing_reason
+                            .wait_for(|reason| *reason == Some(CloseReason::Cancelled))
+                            .await
+                            .is_ok();
+                        if !cancelled {
+                            pending().await
+                        }
+                    }),
+            )
+        };
+
+        let mut session = pin!(configure(session));
+        let mut snapshots = Some(snapshots);
+        let mut in_flight = None;
+        loop {
+            let guard = SessionRemoveGuard {
+                key: &mut key,
+                id,
+                link: &link,
+            };
+
+            let (result, signal_receiver) = drive_session(
+                session.as_mut(),
+                &mut *guard.key,
+                &link,
+                emit_interval,
+                &*clock,
+                StoreBinding {
+                    pause: &mut pause,
+                    cancellation: &mut cancellation,
+                    in_flight: &mut in_flight,
+                },
+                &mut snapshots,
+            )
+            .await;
+
+            let LinkTarget {
+                sender_map,
+                emitter,
+                ..
+            } = resolve_link(&link);
+            let mut summary = session.summary().expect("driven sessions are closed");
+            if cancellation.is_cancelled() {
+                summary.close_reason = CloseReason::Cancelled;
+            } else if summary.close_reason == CloseReason::SenderDropped {
+                // The store drops the sender of sessions it closes.
+                if let Some(reason) = *store_reason.borrow() {
+                    summary.close_reason = reason;
+                }
+            }
+            if summary.close_reason == CloseReason::Cancelled {
+                instrument::session_closed(
+                    emitter.redactor(),
+                    guard.key,
+                    "cancelled",
+                    result.symbol_count(),
+                );
+                emitter.report(DiagnosticReason::SessionCancelled, || guard.key.clone());
+                forget(guard);
+                // Fails pending snapshot requests, which would otherwise
+                // hold the sender map that the session is removed from.
+                drop(snapshots);
+                let key_clone = key.clone();
+                let closed = emitter.closed.clone();
+                join!(
+                    async move {
+                        if let Some(map) = sender_map.upgrade() {
+                            remove_session(&map, key_clone, id, &closed).await;
+                        }
+                    },
+                    async move {
+                        if cancel_behavior == CancelBehavior::Emit {
+                            emitter.emit(key, result, summary).await;
+                        }
+                    }
+                );
+                break;
+            }
+
+            let limit_reached = summary.close_reason == CloseReason::LimitReached;
+            instrument::session_closed(
+                emitter.redactor(),
+                guard.key,
+                match summary.close_reason {
+                    CloseReason::Timeout => "timeout",
+                    CloseReason::TerminatingSignal => "late signal",
+                    CloseReason::SenderDropped => "sender dropped",
+                    CloseReason::Flushed => "flushed",
+                    CloseReason::Cancelled => "cancelled",
+                    CloseReason::LimitReached => "duration limit",
+                    CloseReason::Evicted => "evicted",
+                },
+                result.symbol_count(),
+            );
+            if limit_reached {
+                emitter.report(DiagnosticReason::SessionDurationLimit, || guard.key.clone());
+            }
+            emitter.emit(guard.key.clone(), result, summary).await;
+            session.set(configure(
+                restart_session(
+                    &mut decoder_factory,
+                    signal_receiver,
+                    restart_grace,
+                    &*clock,
+                    &mut snapshots,
+                )
+                .await,
+            ));
+            forget(guard);
+            if session.is_open() {
+                continue;
+            }
+
+            drop(snapshots);
+            if let Some(map) = sender_map.upgrade() {
+                remove_session(&map, key, id, &emitter.closed).await;
+            }
+            break;
+        }
+    }
+}
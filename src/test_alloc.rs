@@ -0,0 +1,57 @@
+//! A global allocator counting what each thread allocates, for tests bounding
+//! memory or checking that a path does not allocate.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+struct CountingAllocator;
+
+thread_local! {
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record(bytes: isize, allocations: usize) {
+    // Thread locals are gone while a thread is torn down.
+    let _ = LIVE_BYTES.try_with(|live| live.set(live.get() + bytes));
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + allocations));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(layout.size() as isize, 1);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(layout.size() as isize, 1);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record(-(layout.size() as isize), 0);
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(new_size as isize - layout.size() as isize, 1);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// The bytes the current thread allocated and has not freed. Memory freed by
+/// another thread than the one allocating it is miscounted, so tests measure
+/// single-threaded runtimes.
+pub(crate) fn live_bytes() -> isize {
+    LIVE_BYTES.with(Cell::get)
+}
+
+/// The allocations the current thread made, reallocations included.
+pub(crate) fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bitvec::vec::BitVec;
+
+use crate::{encoder::DelayEncoder, rng::SplitMix64};
+
+/// How the signals of pure-noise keys are spaced.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ArrivalModel {
+    Constant(Duration),
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    /// Exponentially distributed gaps, i.e. Poisson arrivals.
+    Poisson {
+        mean: Duration,
+    },
+}
+
+impl ArrivalModel {
+    pub(crate) fn sample(&self, rng: &mut SplitMix64) -> Duration {
+        match *self {
+            Self::Constant(gap) => gap,
+            Self::Uniform { min, max } => min + max.saturating_sub(min).mul_f64(rng.next_f64()),
+            Self::Poisson { mean } => mean.mul_f64(-(1.0 - rng.next_f64()).ln()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TrafficConfig {
+    /// Total number of keys, covert and noise.
+    pub keys: usize,
+    /// Bits carried by each covert key's random message.
+    pub message_bits: usize,
+    /// Fraction of keys in `[0, 1]` that only carry background noise.
+    pub noise_key_ratio: f64,
+    /// Signals sent by each noise key.
+    pub noise_signals: usize,
+    pub arrival: ArrivalModel,
+    /// Keys start at a random offset within this window.
+    pub start_spread: Duration,
+    pub seed: u64,
+}
+
+impl Default for TrafficConfig {
+    fn default() -> Self {
+        Self {
+            keys: 16,
+            message_bits: 16,
+            noise_key_ratio: 0.5,
+            noise_signals: 16,
+            arrival: ArrivalModel::Poisson {
+                mean: Duration::from_millis(200),
+            },
+            start_spread: Duration::from_secs(1),
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GeneratedTraffic {
+    /// Every signal, ordered by instant.
+    pub events: Vec<(u64, Instant)>,
+    /// The message planted in each covert key.
+    pub messages: HashMap<u64, BitVec>,
+}
+
+impl GeneratedTraffic {
+    pub fn is_covert(&self, key: u64) -> bool {
+        self.messages.contains_key(&key)
+    }
+}
+
+/// Deterministic generator of interleaved covert and noise traffic.
+///
+/// Covert keys send one anchoring signal followed by one signal per message
+/// bit, spaced by the delays of `encoder`. Noise keys send signals spaced by the
+/// configured arrival model. The same config and seed always produce the same
+/// traffic relative to the start instant.
+#[derive(Debug)]
+pub struct TrafficGenerator<E> {
+    config: TrafficConfig,
+    encoder: E,
+}
+
+impl<E: DelayEncoder> TrafficGenerator<E> {
+    pub const fn new(config: TrafficConfig, encoder: E) -> Self {
+        Self { config, encoder }
+    }
+
+    pub fn generate(&mut self, start: Instant) -> GeneratedTraffic {
+        let mut rng = SplitMix64::new(self.config.seed);
+        let mut events = Vec::new();
+        let mut messages = HashMap::new();
+
+        for key in 0..self.config.keys as u64 {
+            let mut instant = start + self.config.start_spread.mul_f64(rng.next_f64());
+            events.push((key, instant));
+
+            if rng.next_f64() < self.config.noise_key_ratio {
+                for _ in 0..self.config.noise_signals {
+                    instant += self.config.arrival.sample(&mut rng);
+                    events.push((key, instant));
+                }
+            } else {
+                let message: BitVec = (0..self.config.message_bits)
+                    .map(|_| rng.next_bool())
+                    .collect();
+
+                for bit in message.iter().by_vals() {
+                    instant += self.encoder.next_delay(bit);
+                    events.push((key, instant));
+                }
+
+                messages.insert(key, message);
+            }
+        }
+
+        events.sort_by_key(|(_, instant)| *instant);
+        GeneratedTraffic { events, messages }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use tokio::time::sleep;
+
+    use super::{ArrivalModel, TrafficConfig, TrafficGenerator};
+    use crate::{
+        decoder::ThresholdDelayDecoder, encoder::ThresholdDelayEncoder,
+        session_store::delay_session_store_with_factory,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn covert_keys_decode_back_into_their_messages() {
+        let encoder =
+            ThresholdDelayEncoder::new(Duration::from_millis(10), Duration::from_millis(30))
+                .jitter(Duration::from_millis(5), 1);
+        let threshold = encoder.threshold();
+        let config = TrafficConfig {
+            arrival: ArrivalModel::Constant(Duration::from_millis(20)),
+            ..TrafficConfig::default()
+        };
+        let (store, mut results) =
+            delay_session_store_with_factory(Duration::from_millis(500), move || {
+                ThresholdDelayDecoder::new(threshold)
+            });
+        let traffic = TrafficGenerator::new(config, encoder).generate(store.store().clock().now());
+        assert!(!traffic.messages.is_empty());
+
+        for (key, instant) in &traffic.events {
+            store.push_signal(*key, *instant).await.unwrap();
+        }
+        sleep(Duration::from_secs(3)).await;
+
+        let mut decoded = 0;
+        for _ in 0..config.keys {
+            let (key, bits) = results.next().await.unwrap();
+            if let Some(message) = traffic.messages.get(&key) {
+                assert_eq!(&bits, message, "key {key}");
+                decoded += 1;
+            }
+        }
+        assert_eq!(decoded, traffic.messages.len());
+    }
+}
@@ -0,0 +1,171 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The clock an external timestamp was taken from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum TimestampClock {
+    /// `CLOCK_MONOTONIC`, which is what `Instant` uses on Linux.
+    Monotonic,
+    /// `CLOCK_REALTIME`, i.e. wall-clock time since the Unix epoch.
+    Realtime,
+}
+
+/// Maps timestamps taken from an external clock into the `Instant` domain.
+///
+/// An anchor pairs one `Instant` with a reading of the external clock taken at
+/// (approximately) the same moment. Every later timestamp from that clock is
+/// converted by offsetting the anchored `Instant` by its distance from the
+/// anchored reading, so nanosecond deltas between timestamps are preserved
+/// exactly. Capture the anchor once at startup and reuse it for every
+/// conversion; re-anchoring shifts all later instants by the capture error.
+///
+/// `CLOCK_REALTIME` can be stepped by NTP or an administrator, which shows up
+/// as a jump in the converted instants. Prefer `CLOCK_MONOTONIC` timestamps
+/// (e.g. `SO_TIMESTAMPING` with `SOF_TIMESTAMPING_RX_SOFTWARE` on a socket
+/// configured for monotonic stamps) whenever the source allows it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct TimeAnchor {
+    clock: TimestampClock,
+    instant: Instant,
+    reference: Duration,
+}
+
+impl TimeAnchor {
+    pub const fn new(clock: TimestampClock, instant: Instant, reference: Duration) -> Self {
+        Self {
+            clock,
+            instant,
+            reference,
+        }
+    }
+
+    /// Anchors `CLOCK_REALTIME` using `SystemTime`, without needing `libc`.
+    pub fn realtime() -> Self {
+        let instant = Instant::now();
+        let reference = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Self::new(TimestampClock::Realtime, instant, reference)
+    }
+
+    /// Anchors `clock` by reading it with `clock_gettime` next to `Instant::now()`.
+    #[cfg(feature = "libc")]
+    pub fn capture(clock: TimestampClock) -> Self {
+        let clock_id = match clock {
+            TimestampClock::Monotonic => libc::CLOCK_MONOTONIC,
+            TimestampClock::Realtime => libc::CLOCK_REALTIME,
+        };
+
+        let mut timespec = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let instant = Instant::now();
+        // SAFETY: `timespec` is a valid, writable `libc::timespec`.
+        unsafe {
+            libc::clock_gettime(clock_id, &mut timespec);
+        }
+
+        let reference = timespec_to_duration(&timespec).unwrap_or_default();
+        Self::new(clock, instant, reference)
+    }
+
+    pub const fn clock(&self) -> TimestampClock {
+        self.clock
+    }
+
+    pub const fn instant(&self) -> Instant {
+        self.instant
+    }
+
+    pub const fn reference(&self) -> Duration {
+        self.reference
+    }
+
+    /// Converts a reading of the anchored clock, returning `None` if the result
+    /// does not fit in an `Instant`.
+    pub fn instant_at(&self, timestamp: Duration) -> Option<Instant> {
+        if timestamp >= self.reference {
+            self.instant.checked_add(timestamp - self.reference)
+        } else {
+            self.instant.checked_sub(self.reference - timestamp)
+        }
+    }
+
+    /// Converts a `(seconds, nanoseconds)` pair, as found in a `timespec`.
+    pub fn instant_at_parts(&self, secs: i64, nanos: i64) -> Option<Instant> {
+        self.instant_at(parts_to_duration(secs, nanos)?)
+    }
+
+    #[cfg(feature = "libc")]
+    pub fn instant_at_timespec(&self, timespec: &libc::timespec) -> Option<Instant> {
+        self.instant_at(timespec_to_duration(timespec)?)
+    }
+}
+
+/// Converts a `(seconds, nanoseconds)` pair into a `Duration`, rejecting
+/// negative seconds and out-of-range nanoseconds.
+pub fn parts_to_duration(secs: i64, nanos: i64) -> Option<Duration> {
+    let secs = u64::try_from(secs).ok()?;
+    let nanos = u32::try_from(nanos)
+        .ok()
+        .filter(|nanos| *nanos < 1_000_000_000)?;
+
+    Some(Duration::new(secs, nanos))
+}
+
+#[cfg(feature = "libc")]
+#[allow(clippy::unnecessary_cast)]
+pub fn timespec_to_duration(timespec: &libc::timespec) -> Option<Duration> {
+    parts_to_duration(timespec.tv_sec as i64, timespec.tv_nsec as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{parts_to_duration, TimeAnchor, TimestampClock};
+
+    #[test]
+    fn parts_convert_up_to_the_last_nanosecond() {
+        assert_eq!(parts_to_duration(0, 0), Some(Duration::ZERO));
+        assert_eq!(
+            parts_to_duration(5, 999_999_999),
+            Some(Duration::new(5, 999_999_999))
+        );
+        assert_eq!(
+            parts_to_duration(i64::MAX, 0),
+            Some(Duration::from_secs(i64::MAX as u64))
+        );
+    }
+
+    #[test]
+    fn negative_or_overflowing_parts_are_rejected() {
+        for (secs, nanos) in [(-1, 0), (0, -1), (0, 1_000_000_000), (1, i64::MAX)] {
+            assert_eq!(parts_to_duration(secs, nanos), None, "{secs}s {nanos}ns");
+        }
+    }
+
+    #[test]
+    fn timestamps_before_and_after_the_anchor_keep_their_distance() {
+        let instant = Instant::now() + Duration::from_secs(10);
+        let anchor = TimeAnchor::new(
+            TimestampClock::Realtime,
+            instant,
+            Duration::new(100, 999_999_990),
+        );
+
+        assert_eq!(anchor.instant_at_parts(100, 999_999_990), Some(instant));
+        // The nanoseconds carry into the next second.
+        assert_eq!(
+            anchor.instant_at_parts(101, 5),
+            Some(instant + Duration::from_nanos(15))
+        );
+        // Readings taken before the anchor was captured map before it.
+        assert_eq!(
+            anchor.instant_at_parts(99, 999_999_995),
+            Some(instant - Duration::from_nanos(999_999_995))
+        );
+        assert_eq!(anchor.instant_at_parts(100, 1_000_000_000), None);
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Completeness levels of `TimeoutSuggestion::curve`.
+const CURVE_LEVELS: [f64; 8] = [0.5, 0.75, 0.9, 0.95, 0.99, 0.995, 0.999, 1.0];
+
+/// The timeout that keeps a requested fraction of observed sessions intact.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TimeoutSuggestion {
+    pub timeout: Duration,
+    /// Fraction of observed sessions `timeout` keeps intact, at least the
+    /// requested one.
+    pub completeness: f64,
+    pub sessions: usize,
+    /// The smallest timeout for each of a range of completeness levels, so
+    /// the trade-off between latency and truncated sessions can be seen.
+    pub curve: Vec<(f64, Duration)>,
+    /// The shortest gap seen between two sessions of a key. Timeouts above
+    /// it would have merged those sessions.
+    pub min_separation: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct OpenSession {
+    last_instant: Instant,
+    max_gap: Duration,
+}
+
+/// Learns which timeout suits a deployment by observing its raw signals, e.g.
+/// replayed from a recording with `record::replay_with`.
+///
+/// Signals of a key further apart than `horizon` are taken to belong to
+/// different sessions, so `horizon` should be well above any plausible
+/// timeout. A timeout keeps a session intact if it exceeds every gap within
+/// it, so the suggestion is a quantile of the sessions' largest gaps.
+#[derive(Debug)]
+pub struct TimeoutAdvisor<K> {
+    horizon: Duration,
+    open: HashMap<K, OpenSession>,
+    /// The largest gap within each session that ended.
+    closed_max_gaps: Vec<Duration>,
+    min_separation: Option<Duration>,
+}
+
+impl<K> TimeoutAdvisor<K>
+where
+    K: Eq + Hash,
+{
+    pub fn new(horizon: Duration) -> Self {
+        Self {
+            horizon,
+            open: HashMap::new(),
+            closed_max_gaps: Vec::new(),
+            min_separation: None,
+        }
+    }
+
+    /// Observes a signal. Signals of each key should arrive in order; earlier
+    /// ones count as a zero gap.
+    pub fn observe(&mut self, key: K, instant: Instant) {
+        match self.open.get_mut(&key) {
+            Some(session) => {
+                let gap = instant.saturating_duration_since(session.last_instant);
+                if gap >= self.horizon {
+                    self.closed_max_gaps.push(session.max_gap);
+                    self.min_separation = Some(self.min_separation.map_or(gap, |min| min.min(gap)));
+                    session.max_gap = Duration::ZERO;
+                } else {
+                    session.max_gap = session.max_gap.max(gap);
+                }
+                session.last_instant = session.last_instant.max(instant);
+            }
+            None => {
+                self.open.insert(
+                    key,
+                    OpenSession {
+                        last_instant: instant,
+                        max_gap: Duration::ZERO,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Sessions observed so far, including those still open.
+    pub fn sessions(&self) -> usize {
+        self.closed_max_gaps.len() + self.open.len()
+    }
+
+    /// Suggests the smallest timeout that would have kept at least
+    /// `target_completeness` of the sessions observed so far intact, or
+    /// `None` before any signal.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target_completeness` is not within `[0, 1]`.
+    pub fn suggest_timeout(&self, target_completeness: f64) -> Option<TimeoutSuggestion> {
+        assert!(
+            (0.0..=1.0).contains(&target_completeness),
+            "target completeness must be within [0, 1]"
+        );
+
+        let mut max_gaps: Vec<_> = self
+            .closed_max_gaps
+            .iter()
+            .copied()
+            .chain(self.open.values().map(|session| session.max_gap))
+            .collect();
+        if max_gaps.is_empty() {
+            return None;
+        }
+        max_gaps.sort_unstable();
+
+        let (timeout, completeness) = timeout_for(&max_gaps, target_completeness);
+        Some(TimeoutSuggestion {
+            timeout,
+            completeness,
+            sessions: max_gaps.len(),
+            curve: CURVE_LEVELS
+                .iter()
+                .map(|level| (*level, timeout_for(&max_gaps, *level).0))
+                .collect(),
+            min_separation: self.min_separation,
+        })
+    }
+}
+
+/// The smallest timeout keeping `completeness` of sessions with the sorted
+/// largest gaps intact, and the completeness it actually achieves. A signal
+/// closes its session when its gap reaches the timeout, so the timeout must
+/// exceed the gaps it keeps.
+fn timeout_for(sorted_max_gaps: &[Duration], completeness: f64) -> (Duration, f64) {
+    let len = sorted_max_gaps.len();
+    let kept = ((completeness * len as f64).ceil() as usize).clamp(1, len);
+    let timeout = sorted_max_gaps[kept - 1].saturating_add(Duration::from_nanos(1));
+    let achieved = sorted_max_gaps.partition_point(|gap| *gap < timeout);
+
+    (timeout, achieved as f64 / len as f64)
+}
@@ -0,0 +1,268 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::session::timeout_instant;
+
+/// Decides when a session times out after each of its signals. Every session
+/// of a store runs its own clone of the store's policy, so a policy may learn
+/// from the durations of its session.
+pub trait TimeoutPolicy: Debug + Send + 'static {
+    /// Returns the deadline of a session after a signal at `signal_instant`
+    /// ending a duration of `observed`. A session's first signal ends no
+    /// duration, and has no `previous_deadline`.
+    fn next_deadline(
+        &mut self,
+        signal_instant: Instant,
+        previous_deadline: Option<Instant>,
+        observed: Duration,
+    ) -> Instant;
+}
+
+/// Times a session out a fixed duration after its latest signal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct FixedTimeout(pub Duration);
+
+impl TimeoutPolicy for FixedTimeout {
+    fn next_deadline(
+        &mut self,
+        signal_instant: Instant,
+        _: Option<Instant>,
+        _: Duration,
+    ) -> Instant {
+        timeout_instant(signal_instant, self.0)
+    }
+}
+
+/// Times a session out `multiplier` times its longest duration so far after
+/// its latest signal, kept between `floor` and `ceiling`, so a slow but
+/// steady sender is not cut off by a timeout tuned for fast ones.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveTimeout {
+    multiplier: f64,
+    floor: Duration,
+    ceiling: Duration,
+    max_observed: Duration,
+}
+
+impl AdaptiveTimeout {
+    /// # Panics
+    ///
+    /// Panics if `multiplier` is not positive and finite, or if `floor` is
+    /// above `ceiling`.
+    pub fn new(multiplier: f64, floor: Duration, ceiling: Duration) -> Self {
+        assert!(
+            multiplier.is_finite() && multiplier > 0.0,
+            "timeout multiplier must be positive and finite"
+        );
+        assert!(
+            floor <= ceiling,
+            "timeout floor must not be above its ceiling"
+        );
+
+        Self {
+            multiplier,
+            floor,
+            ceiling,
+            max_observed: Duration::ZERO,
+        }
+    }
+
+    /// The longest duration observed so far.
+    pub const fn max_observed(&self) -> Duration {
+        self.max_observed
+    }
+
+    /// The gap allowed after a signal, given the durations observed so far.
+    pub fn timeout(&self) -> Duration {
+        Duration::try_from_secs_f64(self.max_observed.as_secs_f64() * self.multiplier)
+            .unwrap_or(Duration::MAX)
+            .clamp(self.floor, self.ceiling)
+    }
+}
+
+impl TimeoutPolicy for AdaptiveTimeout {
+    fn next_deadline(
+        &mut self,
+        signal_instant: Instant,
+        _: Option<Instant>,
+        observed: Duration,
+    ) -> Instant {
+        self.max_observed = self.max_observed.max(observed);
+        timeout_instant(signal_instant, self.timeout())
+    }
+}
+
+/// Hands every new session a fresh clone of a store's policy.
+#[derive(Clone)]
+pub(crate) struct TimeoutPolicyFactory(Arc<dyn NewTimeoutPolicy>);
+
+trait NewTimeoutPolicy: Debug + Send + Sync {
+    fn new_policy(&self) -> Box<dyn TimeoutPolicy>;
+}
+
+impl<P> NewTimeoutPolicy for P
+where
+    P: TimeoutPolicy + Clone + Sync,
+{
+    fn new_policy(&self) -> Box<dyn TimeoutPolicy> {
+        Box::new(self.clone())
+    }
+}
+
+impl TimeoutPolicyFactory {
+    pub(crate) fn new(policy: impl TimeoutPolicy + Clone + Sync) -> Self {
+        Self(Arc::new(policy))
+    }
+
+    /// Starts the timeout of a session whose first signal is at `instant`,
+    /// timing it out `fixed` after each signal instead of by the store's
+    /// policy if given.
+    pub(crate) fn start(&self, instant: Instant, fixed: Option<Duration>) -> SessionTimeout {
+        let mut policy: Box<dyn TimeoutPolicy> = match fixed {
+            Some(timeout) => Box::new(FixedTimeout(timeout)),
+            None => self.0.new_policy(),
+        };
+        let deadline = policy.next_deadline(instant, None, Duration::ZERO);
+        SessionTimeout {
+            policy,
+            last: instant,
+            allowed: deadline.saturating_duration_since(instant),
+            deadline,
+        }
+    }
+}
+
+impl Debug for TimeoutPolicyFactory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A session's timeout policy and the deadline it set last.
+#[derive(Debug)]
+pub(crate) struct SessionTimeout {
+    policy: Box<dyn TimeoutPolicy>,
+    /// The latest signal, which the next duration is observed from.
+    last: Instant,
+    /// The gap the policy allowed after the latest signal, which keepalives
+    /// extend the deadline by.
+    allowed: Duration,
+    deadline: Instant,
+}
+
+impl SessionTimeout {
+    pub(crate) const fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Asks the policy for the deadline after a signal at `instant`, which
+    /// is the session's latest.
+    pub(crate) fn signal(&mut self, instant: Instant) {
+        let observed = instant.saturating_duration_since(self.last);
+        self.deadline = self
+            .policy
+            .next_deadline(instant, Some(self.deadline), observed);
+        self.last = instant;
+        self.allowed = self.deadline.saturating_duration_since(instant);
+    }
+
+    /// Times the session out `timeout` after each signal from the next one
+    /// on, in place of its policy.
+    pub(crate) fn fix(&mut self, timeout: Duration) {
+        self.policy = Box::new(FixedTimeout(timeout));
+    }
+
+    /// Extends the deadline to the gap last allowed after a keepalive at
+    /// `instant`, without asking the policy, and returns it.
+    pub(crate) fn keepalive(&mut self, instant: Instant) -> Instant {
+        self.deadline = self.deadline.max(timeout_instant(instant, self.allowed));
+        self.deadline
+    }
+
+    /// Moves the session `by` later, as pausing its key does.
+    pub(crate) fn shift(&mut self, by: Duration) {
+        self.last = timeout_instant(self.last, by);
+        self.extend(by);
+    }
+
+    /// Moves the deadline `by` later, leaving time the store spent paused
+    /// out of the timeout but not out of the next observed gap.
+    pub(crate) fn extend(&mut self, by: Duration) {
+        self.deadline = timeout_instant(self.deadline, by);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{AdaptiveTimeout, FixedTimeout, TimeoutPolicy, TimeoutPolicyFactory};
+
+    #[test]
+    fn the_adaptive_timeout_follows_the_longest_duration_within_bounds() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut policy =
+            AdaptiveTimeout::new(2.0, Duration::from_millis(500), Duration::from_millis(3000));
+
+        assert_eq!(policy.next_deadline(at(0), None, Duration::ZERO), at(500));
+        let mut previous = at(500);
+        for (millis, observed, deadline) in [
+            (400, 400, 1200),
+            (500, 100, 1300),
+            (1200, 700, 2600),
+            (3200, 2000, 6200),
+        ] {
+            let next =
+                policy.next_deadline(at(millis), Some(previous), Duration::from_millis(observed));
+            assert_eq!(next, at(deadline), "after {millis} ms");
+            previous = next;
+        }
+        assert_eq!(policy.max_observed(), Duration::from_millis(2000));
+        assert_eq!(policy.timeout(), Duration::from_millis(3000));
+    }
+
+    #[test]
+    #[should_panic(expected = "timeout floor must not be above its ceiling")]
+    fn an_adaptive_floor_above_the_ceiling_is_rejected() {
+        AdaptiveTimeout::new(1.0, Duration::from_secs(2), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn sessions_start_fresh_policies_and_keepalives_reuse_the_last_gap() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let factory = TimeoutPolicyFactory::new(AdaptiveTimeout::new(
+            1.0,
+            Duration::from_millis(100),
+            Duration::from_secs(10),
+        ));
+
+        let mut timeout = factory.start(at(0), None);
+        timeout.signal(at(1000));
+        assert_eq!(timeout.deadline(), at(2000));
+        assert_eq!(timeout.keepalive(at(1500)), at(2500));
+        // A keepalive never moves the deadline earlier.
+        assert_eq!(timeout.keepalive(at(1200)), at(2500));
+
+        // The observed durations of one session do not carry over.
+        let timeout = factory.start(at(0), None);
+        assert_eq!(timeout.deadline(), at(100));
+        let mut fixed = factory.start(at(0), Some(Duration::from_secs(3)));
+        assert_eq!(fixed.deadline(), at(3000));
+        fixed.signal(at(50));
+        assert_eq!(fixed.deadline(), at(3050));
+
+        let mut timeout = factory.start(at(0), None);
+        timeout.fix(Duration::from_secs(1));
+        timeout.signal(at(5000));
+        assert_eq!(timeout.deadline(), at(6000));
+        assert_eq!(
+            FixedTimeout(Duration::from_secs(1)).next_deadline(at(5), None, Duration::ZERO),
+            at(1005)
+        );
+    }
+}
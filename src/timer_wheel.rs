@@ -0,0 +1,1344 @@
+use std::{
+    borrow::Borrow,
+    collections::{hash_map::Entry, HashMap},
+    fmt::{self, Debug, Formatter},
+    hash::{BuildHasher, Hash, RandomState},
+    mem::{replace, take},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError, TryLockError, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::Clock,
+    decoder::{DecoderOutput, MetaDelayDecoder},
+    emitter::ResultEmitter,
+    instant_policy::{InstantScreen, Screened, SignalSequencer},
+    instrument::{self, KeyRedactor},
+    session::{
+        shift_held, timeout_instant, CloseReason, KeepalivePolicy, PauseGapPolicy, SessionSummary,
+    },
+    session_config::SessionConfig,
+    session_store::{SessionInfo, SessionLimitPolicy, SessionSnapshot, SignalPush},
+    task,
+    timeout_policy::SessionTimeout,
+    watchdog::WatchedSession,
+};
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+const MAX_TICKS: u64 = (1 << (SLOT_BITS as usize * LEVELS)) - 1;
+
+/// Configuration of the timer-wheel store backend.
+///
+/// Instead of one task, channel and `Sleep` per key, sessions are plain state
+/// kept in `shards` maps, each shard owning a hierarchical timer wheel with a
+/// resolution of `tick`. A fixed pool of `workers` tasks advances the wheels
+/// and closes expired sessions, so an idle session costs a map entry and its
+/// decoder. Timeouts fire up to one `tick` late, never early.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct TimerWheelConfig {
+    pub shards: usize,
+    pub workers: usize,
+    pub tick: Duration,
+}
+
+impl Default for TimerWheelConfig {
+    fn default() -> Self {
+        Self {
+            shards: 64,
+            workers: 4,
+            tick: Duration::from_millis(10),
+        }
+    }
+}
+
+/// A hierarchical timer wheel of `LEVELS` levels with `SLOTS` slots each,
+/// measured in ticks since its creation.
+struct TimerWheel<T> {
+    elapsed: u64,
+    levels: Vec<Level<T>>,
+}
+
+struct Level<T> {
+    occupied: u64,
+    slots: Vec<Vec<(u64, T)>>,
+}
+
+impl<T> TimerWheel<T> {
+    fn new() -> Self {
+        Self {
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| Level {
+                    occupied: 0,
+                    slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Schedules `item` to expire at tick `when`. Items that are already due
+    /// expire on the next advance.
+    fn insert(&mut self, when: u64, item: T) {
+        let target = when.clamp(self.elapsed + 1, self.elapsed + MAX_TICKS);
+        let masked = (self.elapsed ^ target) | (SLOTS as u64 - 1);
+        let significant = (u64::BITS - 1 - masked.leading_zeros()) as usize;
+        let level = (significant / SLOT_BITS as usize).min(LEVELS - 1);
+        let slot = (target >> (level as u32 * SLOT_BITS)) as usize & (SLOTS - 1);
+
+        self.levels[level].slots[slot].push((when, item));
+        self.levels[level].occupied |= 1 << slot;
+    }
+
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        let (level_index, level) = self
+            .levels
+            .iter()
+            .enumerate()
+            .find(|(_, level)| level.occupied != 0)?;
+
+        let slot_range = 1u64 << (level_index as u32 * SLOT_BITS);
+        let level_range = slot_range << SLOT_BITS;
+        let now_slot = ((self.elapsed / slot_range) % SLOTS as u64) as u32;
+        let slot =
+            (now_slot + level.occupied.rotate_right(now_slot).trailing_zeros()) as usize % SLOTS;
+
+        let level_start = self.elapsed & !(level_range - 1);
+        let mut deadline = level_start + slot as u64 * slot_range;
+        if deadline <= self.elapsed {
+            deadline += level_range;
+        }
+
+        Some((level_index, slot, deadline))
+    }
+
+    /// Advances the wheel to tick `now`, moving every expired item into `expired`
+    /// together with the tick it was scheduled for.
+    fn advance(&mut self, now: u64, expired: &mut Vec<(u64, T)>) {
+        while let Some((level, slot, deadline)) = self.next_expiration() {
+            if deadline > now {
+                break;
+            }
+
+            self.elapsed = deadline;
+            let entries = take(&mut self.levels[level].slots[slot]);
+            self.levels[level].occupied &= !(1 << slot);
+
+            for (when, item) in entries {
+                if when <= self.elapsed {
+                    expired.push((when, item));
+                } else {
+                    self.insert(when, item);
+                }
+            }
+        }
+
+        self.elapsed = self.elapsed.max(now);
+    }
+}
+
+trait WheelDecoder<O, M>: Send {
+    /// Whether a signal at `instant` is taken in rather than dropped as out
+    /// of order or within the minimum gap of the latest signal.
+    fn admits(&self, instant: Instant) -> bool;
+
+    /// Takes in an admitted signal, returning the instant of the latest
+    /// signal taken in and whether it pushed a duration.
+    fn push_signal(&mut self, instant: Instant, meta: M) -> (Instant, bool);
+
+    /// Closes the current decoder, once it took in the `terminal` duration
+    /// if any, and replaces it with a fresh one, for a session started by a
+    /// signal at `instant`.
+    fn restart(&mut self, instant: Instant, terminal: Option<(Instant, Option<&M>)>) -> O;
+
+    /// Measures the next duration from a keepalive at `instant`, returning
+    /// how many held durations it pushed.
+    fn mark(&mut self, instant: Instant) -> usize;
+
+    /// Measures the duration spanning a pause from `paused_at` to
+    /// `resumed_at` as `policy` says.
+    fn resume(&mut self, policy: PauseGapPolicy, paused_at: Instant, resumed_at: Instant);
+
+    /// Closes the decoder once it took in the `terminal` duration, if any.
+    fn close(self: Box<Self>, terminal: Option<(Instant, Option<&M>)>) -> O;
+
+    fn snapshot(&self) -> Option<O>;
+}
+
+struct FactoryDecoder<D, F, M> {
+    decoder: D,
+    decoder_factory: F,
+    sequencer: SignalSequencer<M>,
+}
+
+impl<D, F, M> WheelDecoder<D::Output, M> for FactoryDecoder<D, F, M>
+where
+    D: MetaDelayDecoder<M> + Send,
+    F: FnMut() -> D + Send,
+    M: Send + 'static,
+{
+    fn admits(&self, instant: Instant) -> bool {
+        self.sequencer.admits(instant) && !self.sequencer.debounces(instant)
+    }
+
+    fn push_signal(&mut self, instant: Instant, meta: M) -> (Instant, bool) {
+        let accepted = self.sequencer.accept(instant, meta);
+        let pushed = accepted.is_some();
+        if let Some((duration, meta)) = accepted {
+            self.decoder.push_duration_with(duration, &meta);
+        }
+        (self.sequencer.latest(), pushed)
+    }
+
+    fn restart(&mut self, instant: Instant, terminal: Option<(Instant, Option<&M>)>) -> D::Output {
+        self.sequencer.release(&mut self.decoder, terminal);
+        self.sequencer.restart(instant);
+        let decoder = (self.decoder_factory)();
+        replace(&mut self.decoder, decoder).close()
+    }
+
+    fn mark(&mut self, instant: Instant) -> usize {
+        self.sequencer.mark(&mut self.decoder, instant)
+    }
+
+    fn resume(&mut self, policy: PauseGapPolicy, paused_at: Instant, resumed_at: Instant) {
+        self.sequencer.resume(policy, paused_at, resumed_at);
+    }
+
+    fn close(mut self: Box<Self>, terminal: Option<(Instant, Option<&M>)>) -> D::Output {
+        self.sequencer.release(&mut self.decoder, terminal);
+        self.decoder.close()
+    }
+
+    fn snapshot(&self) -> Option<D::Output> {
+        self.decoder.snapshot()
+    }
+}
+
+struct WheelSession<O, M> {
+    decoder: Box<dyn WheelDecoder<O, M>>,
+    started_instant: Instant,
+    durations: u64,
+    /// The durations pushed to the decoder, which the duration limit counts.
+    pushed: u64,
+    last_signal_instant: Instant,
+    timeout: SessionTimeout,
+    /// The timeout pushes to the key fixed in place of the store's policy.
+    fixed_timeout: Option<Duration>,
+    /// The earlier of the timeout and the lifetime deadline.
+    deadline: Instant,
+    lifetime_deadline: Option<Instant>,
+    scheduled_tick: u64,
+    next_emit: Instant,
+    emit_tick: u64,
+    paused: Option<PausedSession<M>>,
+}
+
+/// A session paused with `DelaySessionStore::pause_key`.
+struct PausedSession<M> {
+    at: Instant,
+    /// The signals pushed since, with their instants, taken in on resume.
+    buffered: Vec<(Instant, M)>,
+}
+
+impl<O, M> WheelSession<O, M> {
+    fn summary(&self, close_reason: CloseReason, closed_at: Instant) -> SessionSummary {
+        SessionSummary {
+            close_reason,
+            started_at: self.started_instant,
+            closed_at,
+            signal_count: self.durations + 1,
+        }
+    }
+}
+
+/// The key, result and summary of a closed session.
+pub(crate) type ClosedSession<K, O> = (K, O, SessionSummary);
+
+/// The shard of a key was locked by another push.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct ShardBusy;
+
+/// What pushing a signal to the timer wheel produced.
+pub(crate) struct PushedSignal<K, O> {
+    /// The result of a session the signal closed.
+    pub(crate) closed: Option<ClosedSession<K, O>>,
+    /// Whether the signal was dropped by the store's `InstantPolicy`.
+    pub(crate) rejected: bool,
+    /// Whether the signal closed its session by reaching the store's
+    /// duration limit.
+    pub(crate) limit_reached: bool,
+    /// Whether the signal started a session in place of the one evicted to
+    /// make room for it, whose result is `closed`.
+    pub(crate) evicted: bool,
+    /// Whether the signal was dropped as its session would exceed the
+    /// store's `max_sessions`.
+    pub(crate) at_session_limit: bool,
+}
+
+struct Shard<K, O, M> {
+    sessions: HashMap<K, WheelSession<O, M>>,
+    wheel: TimerWheel<K>,
+    /// Schedules snapshots of open sessions, if the store has an emit interval.
+    emits: TimerWheel<K>,
+}
+
+pub(crate) struct TimerWheelSessions<K, O, M> {
+    clock: Arc<dyn Clock>,
+    origin: Instant,
+    tick: Duration,
+    hasher: RandomState,
+    shards: Box<[Mutex<Shard<K, O, M>>]>,
+    redactor: KeyRedactor<K>,
+    emit_interval: Option<Duration>,
+    config: SessionConfig,
+    /// Open sessions over all shards.
+    len: AtomicUsize,
+    /// Set while the store is paused, which stops workers from expiring
+    /// sessions.
+    paused: AtomicBool,
+}
+
+impl<K, O, M> Debug for TimerWheelSessions<K, O, M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimerWheelSessions")
+            .field("origin", &self.origin)
+            .field("tick", &self.tick)
+            .field("shards", &self.shards.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, O, M> TimerWheelSessions<K, O, M>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    O: DecoderOutput + Clone + Send + 'static,
+    M: Send + 'static,
+{
+    pub(crate) fn new(
+        wheel: TimerWheelConfig,
+        redactor: KeyRedactor<K>,
+        emit_interval: Option<Duration>,
+        config: SessionConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        assert!(wheel.shards >= 1, "timer wheel needs at least one shard");
+        assert!(!wheel.tick.is_zero(), "timer wheel tick must be non-zero");
+
+        Self {
+            origin: clock.now(),
+            clock,
+            tick: wheel.tick,
+            hasher: RandomState::new(),
+            shards: (0..wheel.shards)
+                .map(|_| {
+                    Mutex::new(Shard {
+                        sessions: HashMap::new(),
+                        wheel: TimerWheel::new(),
+                        emits: TimerWheel::new(),
+                    })
+                })
+                .collect(),
+            redactor,
+            emit_interval,
+            config,
+            len: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+        }
+    }
+
+    /// Spawns `workers` tasks driving the wheels. They exit once `alive` is
+    /// gone and every session has been closed.
+    pub(crate) fn spawn_workers<T>(
+        self: &Arc<Self>,
+        workers: usize,
+        alive: Weak<()>,
+        emitter: ResultEmitter<K, T, O>,
+    ) where
+        T: Send + 'static,
+    {
+        let workers = workers.clamp(1, self.shards.len());
+        for worker in 0..workers {
+            let sessions = self.clone();
+            let alive = alive.clone();
+            let emitter = emitter.clone();
+            let name = format!("{} wheel worker {worker}", emitter.name());
+
+            task::spawn(|| name, async move {
+                let mut results = Vec::new();
+                let mut snapshots = Vec::new();
+
+                loop {
+                    let next_tick = timeout_instant(sessions.clock.now(), sessions.tick);
+                    sessions.clock.sleep_until(next_tick).await;
+                    if sessions.paused.load(Ordering::Acquire) && alive.strong_count() > 0 {
+                        continue;
+                    }
+
+                    // Sessions expiring while the store is cancelled are
+                    // drained with the rest.
+                    let _in_flight = emitter.cancellation().in_flight();
+                    let active = sessions.expire(worker, workers, &mut results, &mut snapshots);
+                    for (key, bits, summary) in results.drain(..) {
+                        emitter.emit(key, bits, summary).await;
+                    }
+                    for (key, bits) in snapshots.drain(..) {
+                        emitter.emit_intermediate(key, bits).await;
+                    }
+
+                    if !active && alive.strong_count() == 0 {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+
+    fn shard<Q>(&self, key: &Q) -> &Mutex<Shard<K, O, M>>
+    where
+        Q: Hash + ?Sized,
+    {
+        &self.shards[self.hasher.hash_one(key) as usize % self.shards.len()]
+    }
+
+    fn deadline_tick(&self, deadline: Instant) -> u64 {
+        let nanos = deadline.saturating_duration_since(self.origin).as_nanos();
+        let tick = self.tick.as_nanos();
+        u64::try_from(nanos.div_ceil(tick)).unwrap_or(u64::MAX)
+    }
+
+    /// Schedules the session's next snapshot one emit interval after its
+    /// `next_emit`, or does nothing without an emit interval.
+    fn schedule_emit(&self, emits: &mut TimerWheel<K>, key: K, session: &mut WheelSession<O, M>) {
+        if let Some(interval) = self.emit_interval {
+            session.next_emit = timeout_instant(session.next_emit, interval);
+            session.emit_tick = self.deadline_tick(session.next_emit);
+            emits.insert(session.emit_tick, key);
+        }
+    }
+
+    /// The lifetime deadline of a session started at `instant`.
+    fn lifetime_deadline(&self, instant: Instant) -> Option<Instant> {
+        self.config
+            .max_lifetime
+            .map(|max_lifetime| timeout_instant(instant, max_lifetime))
+    }
+
+    fn elapsed_tick(&self, now: Instant) -> u64 {
+        let nanos = now.saturating_duration_since(self.origin).as_nanos();
+        u64::try_from(nanos / self.tick.as_nanos()).unwrap_or(u64::MAX)
+    }
+
+    /// Dispatches a signal to the key's session, returning the result of a
+    /// session that the signal arrived too late for or that `screen` closed.
+    /// `to_owned` is only called when an owned key needs to be stored or
+    /// returned, and the push's metadata is pushed to the decoder with the
+    /// duration the signal ends.
+    pub(crate) fn push_signal<Q, D>(
+        &self,
+        key: &Q,
+        to_owned: impl Fn(&Q) -> K,
+        push: SignalPush<M>,
+        screen: InstantScreen<'_>,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> PushedSignal<K, O>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        let mut shard = self
+            .shard(key)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.push_locked(
+            &mut shard,
+            key,
+            to_owned,
+            push,
+            &screen,
+            &mut Some(decoder_factory),
+        )
+    }
+
+    /// Like `push_signal` for each of `instants` in order, holding the key's
+    /// shard lock throughout. Every session started takes a clone of
+    /// `decoder_factory`. Returns what each push produced.
+    pub(crate) fn push_signals<Q, D, F>(
+        &self,
+        key: &Q,
+        to_owned: impl Fn(&Q) -> K,
+        instants: impl IntoIterator<Item = Instant>,
+        screen: InstantScreen<'_>,
+        decoder_factory: F,
+    ) -> Vec<PushedSignal<K, O>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        M: Default,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+        F: FnMut() -> D + Clone + Send + 'static,
+    {
+        let mut shard = self
+            .shard(key)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut factory = None;
+        instants
+            .into_iter()
+            .map(|instant| {
+                factory.get_or_insert_with(|| decoder_factory.clone());
+                self.push_locked(
+                    &mut shard,
+                    key,
+                    &to_owned,
+                    SignalPush::new(instant, M::default()),
+                    &screen,
+                    &mut factory,
+                )
+            })
+            .collect()
+    }
+
+    /// Like `push_signal`, but gives up with `ShardBusy` instead of waiting
+    /// if the key's shard is locked.
+    pub(crate) fn try_push_signal<Q, D>(
+        &self,
+        key: &Q,
+        to_owned: impl Fn(&Q) -> K,
+        push: SignalPush<M>,
+        screen: InstantScreen<'_>,
+        decoder_factory: impl FnMut() -> D + Send + 'static,
+    ) -> Result<PushedSignal<K, O>, ShardBusy>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+    {
+        let mut shard = match self.shard(key).try_lock() {
+            Ok(shard) => shard,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(ShardBusy),
+        };
+        Ok(self.push_locked(
+            &mut shard,
+            key,
+            to_owned,
+            push,
+            &screen,
+            &mut Some(decoder_factory),
+        ))
+    }
+
+    /// Pushes a signal to the locked shard, starting the key's session with
+    /// the factory taken from `decoder_factory` if it has none. A push's
+    /// timeout fixes the session's timeout from this signal on.
+    fn push_locked<Q, D, F>(
+        &self,
+        shard: &mut Shard<K, O, M>,
+        key: &Q,
+        to_owned: impl Fn(&Q) -> K,
+        push: SignalPush<M>,
+        screen: &InstantScreen<'_>,
+        decoder_factory: &mut Option<F>,
+    ) -> PushedSignal<K, O>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        D: MetaDelayDecoder<M, Output = O> + Send + 'static,
+        F: FnMut() -> D + Send + 'static,
+    {
+        let Shard {
+            sessions,
+            wheel,
+            emits,
+        } = shard;
+        let SignalPush {
+            instant,
+            meta,
+            timeout,
+        } = push;
+
+        let previous = sessions.get(key).map(|session| session.last_signal_instant);
+        let instant = match screen.check(previous, instant) {
+            Screened::Accept(instant) => instant,
+            Screened::Reject => {
+                return PushedSignal {
+                    closed: None,
+                    rejected: true,
+                    limit_reached: false,
+                    evicted: false,
+                    at_session_limit: false,
+                }
+            }
+            Screened::CloseSession => {
+                let closed = sessions.remove_entry(key).map(|(key, session)| {
+                    self.close_early(key, session, CloseReason::Flushed, "implausible instant")
+                });
+                return PushedSignal {
+                    closed,
+                    rejected: true,
+                    limit_reached: false,
+                    evicted: false,
+                    at_session_limit: false,
+                };
+            }
+        };
+
+        if let (Some(timeout), Some(session)) = (timeout, sessions.get_mut(key)) {
+            session.fixed_timeout = Some(timeout);
+            session.timeout.fix(timeout);
+        }
+
+        let (mut limit_reached, mut evicted) = (false, false);
+        let closed = match sessions.get_mut(key) {
+            Some(WheelSession {
+                paused: Some(paused),
+                ..
+            }) => {
+                if paused.buffered.len() < self.config.signal_capacity {
+                    paused.buffered.push((instant, meta));
+                } else {
+                    instrument::signal_dropped(&self.redactor, &to_owned(key), "pause buffer full");
+                }
+                None
+            }
+            Some(session) => {
+                let restarted = instant >= session.deadline;
+                if !restarted && !session.decoder.admits(instant) {
+                    return PushedSignal {
+                        closed: None,
+                        rejected: false,
+                        limit_reached: false,
+                        evicted: false,
+                        at_session_limit: false,
+                    };
+                }
+
+                let result = if restarted {
+                    let key = to_owned(key);
+                    let terminal = self
+                        .config
+                        .terminal_duration
+                        .on_signal()
+                        .then_some((instant, Some(&meta)));
+                    let summary = session.summary(CloseReason::TerminatingSignal, self.clock.now());
+                    let bits = session.decoder.restart(instant, terminal);
+                    instrument::session_closed(
+                        &self.redactor,
+                        &key,
+                        "late signal",
+                        bits.symbol_count(),
+                    );
+                    session.started_instant = instant;
+                    session.durations = 0;
+                    session.pushed = 0;
+                    session.last_signal_instant = instant;
+                    session.timeout = self.config.timeout.start(instant, session.fixed_timeout);
+                    session.timeout.extend(screen.pause_left(instant));
+                    session.lifetime_deadline = self.lifetime_deadline(instant);
+                    session.next_emit = instant;
+                    self.schedule_emit(emits, key.clone(), session);
+                    Some((key, bits, summary))
+                } else {
+                    let (latest, pushed) = session.decoder.push_signal(instant, meta);
+                    session.last_signal_instant = latest;
+                    if latest == instant {
+                        session.timeout.signal(instant);
+                        session.timeout.extend(screen.pause_left(instant));
+                    }
+                    session.durations += 1;
+                    session.pushed += u64::from(pushed);
+                    limit_reached = pushed && self.limit_reached(session);
+                    None
+                };
+
+                let deadline =
+                    clamp_deadline(session.timeout.deadline(), session.lifetime_deadline);
+                let tick = self.deadline_tick(deadline);
+                session.deadline = deadline;
+                if limit_reached {
+                    sessions.remove_entry(key).map(|(key, session)| {
+                        self.close_early(key, session, CloseReason::LimitReached, "duration limit")
+                    })
+                } else {
+                    if tick < session.scheduled_tick {
+                        session.scheduled_tick = tick;
+                        wheel.insert(tick, to_owned(key));
+                    }
+                    result
+                }
+            }
+            None => {
+                let Some(evicted_session) = self.reserve(sessions) else {
+                    return PushedSignal {
+                        closed: None,
+                        rejected: false,
+                        limit_reached: false,
+                        evicted: false,
+                        at_session_limit: true,
+                    };
+                };
+                evicted = evicted_session.is_some();
+
+                let mut decoder_factory = decoder_factory
+                    .take()
+                    .expect("a push starts at most one session");
+                let mut sequencer = SignalSequencer::new(self.config.out_of_order, instant);
+                sequencer.set_min_gap(self.config.min_signal_gap);
+                let decoder = Box::new(FactoryDecoder {
+                    decoder: decoder_factory(),
+                    decoder_factory,
+                    sequencer,
+                });
+
+                let fixed_timeout = timeout;
+                let mut timeout = self.config.timeout.start(instant, fixed_timeout);
+                timeout.extend(screen.pause_left(instant));
+                let lifetime_deadline = self.lifetime_deadline(instant);
+                let deadline = clamp_deadline(timeout.deadline(), lifetime_deadline);
+                let tick = self.deadline_tick(deadline);
+
+                let key = to_owned(key);
+                instrument::session_created(&self.redactor, &key);
+                wheel.insert(tick, key.clone());
+                let mut session = WheelSession {
+                    decoder,
+                    started_instant: instant,
+                    durations: 0,
+                    pushed: 0,
+                    last_signal_instant: instant,
+                    timeout,
+                    fixed_timeout,
+                    deadline,
+                    lifetime_deadline,
+                    scheduled_tick: tick,
+                    next_emit: instant,
+                    emit_tick: 0,
+                    paused: None,
+                };
+                self.schedule_emit(emits, key.clone(), &mut session);
+                sessions.insert(key, session);
+
+                evicted_session
+            }
+        };
+
+        PushedSignal {
+            closed,
+            rejected: false,
+            limit_reached,
+            evicted,
+            at_session_limit: false,
+        }
+    }
+
+    /// Counts a session about to start in the shard holding `own`, first
+    /// evicting the least recently signaled one if the store is at its
+    /// `max_sessions` and its policy says so. Returns the evicted session's
+    /// result, or `None` if there is no room.
+    fn reserve(
+        &self,
+        own: &mut HashMap<K, WheelSession<O, M>>,
+    ) -> Option<Option<ClosedSession<K, O>>> {
+        let Some((max_sessions, policy)) = self.config.max_sessions else {
+            self.len.fetch_add(1, Ordering::Relaxed);
+            return Some(None);
+        };
+        let reserved = self
+            .len
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |len| {
+                (len < max_sessions).then_some(len + 1)
+            })
+            .is_ok();
+        if reserved {
+            return Some(None);
+        }
+        if policy == SessionLimitPolicy::Reject {
+            return None;
+        }
+
+        let evicted = self.evict_least_recent(own)?;
+        self.len.fetch_add(1, Ordering::Relaxed);
+        Some(Some(evicted))
+    }
+
+    /// Closes the least recently signaled session among `own`, the sessions
+    /// of the shard the caller holds, and those of the shards no other push
+    /// holds.
+    fn evict_least_recent(
+        &self,
+        own: &mut HashMap<K, WheelSession<O, M>>,
+    ) -> Option<ClosedSession<K, O>> {
+        let least_recent = |sessions: &HashMap<K, WheelSession<O, M>>| {
+            sessions
+                .iter()
+                .min_by_key(|(_, session)| session.last_signal_instant)
+                .map(|(key, session)| (session.last_signal_instant, key.clone()))
+        };
+
+        // The caller's own shard is busy too, so it is only searched as `own`.
+        let mut others: Vec<_> = self
+            .shards
+            .iter()
+            .filter_map(|shard| match shard.try_lock() {
+                Ok(shard) => Some(shard),
+                Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            })
+            .collect();
+        let in_others = others
+            .iter()
+            .enumerate()
+            .filter_map(|(index, shard)| {
+                least_recent(&shard.sessions).map(|(instant, key)| (instant, index, key))
+            })
+            .min_by_key(|(instant, ..)| *instant);
+
+        let (key, session) = match (least_recent(own), in_others) {
+            (Some((instant, _)), Some((other, index, key))) if other < instant => {
+                others[index].sessions.remove_entry(&key)?
+            }
+            (Some((_, key)), _) => own.remove_entry(&key)?,
+            (None, Some((_, index, key))) => others[index].sessions.remove_entry(&key)?,
+            (None, None) => return None,
+        };
+        Some(self.close_early(key, session, CloseReason::Evicted, "evicted"))
+    }
+
+    /// Extends `key`'s session by a keepalive at `instant`, returning `None`
+    /// if it has none, or the session's result if it timed out before the
+    /// keepalive.
+    pub(crate) fn keep_alive<Q>(
+        &self,
+        key: &Q,
+        instant: Instant,
+    ) -> Option<Option<ClosedSession<K, O>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut shard = self
+            .shard(key)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let session = shard.sessions.get_mut(key)?;
+        if instant >= session.deadline {
+            let (key, session) = shard.sessions.remove_entry(key)?;
+            return Some(Some(self.close_timed_out(key, session)));
+        }
+        if session.paused.is_some() {
+            return Some(None);
+        }
+
+        if self.config.keepalive_policy == KeepalivePolicy::RestartDuration {
+            let pushed = session.decoder.mark(instant);
+            session.pushed += pushed as u64;
+            if pushed > 0 && self.limit_reached(session) {
+                let (key, session) = shard.sessions.remove_entry(key)?;
+                return Some(Some(self.close_early(
+                    key,
+                    session,
+                    CloseReason::LimitReached,
+                    "duration limit",
+                )));
+            }
+        }
+        // A later deadline is picked up when the scheduled tick comes.
+        session.deadline = clamp_deadline(
+            session.timeout.keepalive(instant),
+            session.lifetime_deadline,
+        );
+        Some(None)
+    }
+
+    /// Pauses `key`'s session at `instant`, returning `None` if it has none,
+    /// or the session's result if it timed out before the pause.
+    pub(crate) fn pause<Q>(&self, key: &Q, instant: Instant) -> Option<Option<ClosedSession<K, O>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut shard = self
+            .shard(key)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let session = shard.sessions.get_mut(key)?;
+        if session.paused.is_none() && instant >= session.deadline {
+            let (key, session) = shard.sessions.remove_entry(key)?;
+            return Some(Some(self.close_timed_out(key, session)));
+        }
+
+        // The scheduled tick finds the session paused and leaves it be.
+        session.paused.get_or_insert(PausedSession {
+            at: instant,
+            buffered: Vec::new(),
+        });
+        Some(None)
+    }
+
+    /// Resumes `key`'s paused session at `instant`, taking in the signals it
+    /// held. Returns `None` if it has none or it was not paused, or the
+    /// session's result if those signals reached the store's duration limit.
+    pub(crate) fn resume<Q>(&self, key: &Q, instant: Instant) -> Option<Option<ClosedSession<K, O>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut shard = self
+            .shard(key)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let Shard {
+            sessions,
+            wheel,
+            emits,
+        } = &mut *shard;
+
+        let session = sessions.get_mut(key)?;
+        let mut paused = session.paused.take()?;
+        let (key, mut session) = sessions.remove_entry(key)?;
+        let resumed_at = instant.max(paused.at);
+        let gap = resumed_at.saturating_duration_since(paused.at);
+        let gap_end = shift_held(&mut paused.buffered, paused.at, resumed_at);
+        session
+            .decoder
+            .resume(self.config.pause_gap, paused.at, gap_end);
+        if self.config.pause_gap == PauseGapPolicy::Exclude {
+            session.last_signal_instant = timeout_instant(session.last_signal_instant, gap);
+        }
+        session.timeout.shift(gap);
+        session.lifetime_deadline = session
+            .lifetime_deadline
+            .map(|deadline| timeout_instant(deadline, gap));
+
+        for (instant, meta) in paused.buffered {
+            let (latest, pushed) = session.decoder.push_signal(instant, meta);
+            session.last_signal_instant = latest;
+            session.durations += 1;
+            session.pushed += u64::from(pushed);
+            if pushed && self.limit_reached(&session) {
+                return Some(Some(self.close_early(
+                    key,
+                    session,
+                    CloseReason::LimitReached,
+                    "duration limit",
+                )));
+            }
+        }
+
+        session.deadline = clamp_deadline(session.timeout.deadline(), session.lifetime_deadline);
+        session.scheduled_tick = self.deadline_tick(session.deadline);
+        wheel.insert(session.scheduled_tick, key.clone());
+        if self.emit_interval.is_some() {
+            session.next_emit = timeout_instant(session.next_emit, gap);
+            session.emit_tick = self.deadline_tick(session.next_emit);
+            emits.insert(session.emit_tick, key.clone());
+        }
+        sessions.insert(key, session);
+        Some(None)
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Release);
+    }
+
+    /// Extends every session's deadlines `by`, rescheduling its timeout and
+    /// next snapshot, as `DelaySession::shift` does.
+    pub(crate) fn shift(&self, by: Duration) {
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            let Shard {
+                sessions,
+                wheel,
+                emits,
+            } = &mut *shard;
+
+            for (key, session) in sessions.iter_mut() {
+                if let Some(paused) = &mut session.paused {
+                    paused.at = timeout_instant(paused.at, by);
+                }
+                session.timeout.extend(by);
+                session.deadline = timeout_instant(session.deadline, by);
+                session.lifetime_deadline = session
+                    .lifetime_deadline
+                    .map(|deadline| timeout_instant(deadline, by));
+                session.scheduled_tick = self.deadline_tick(session.deadline);
+                wheel.insert(session.scheduled_tick, key.clone());
+
+                if self.emit_interval.is_some() {
+                    session.next_emit = timeout_instant(session.next_emit, by);
+                    session.emit_tick = self.deadline_tick(session.next_emit);
+                    emits.insert(session.emit_tick, key.clone());
+                }
+            }
+        }
+    }
+
+    /// Open sessions over all shards.
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sessions
+            .contains_key(key)
+    }
+
+    /// Copies out every session's progress, locking one shard at a time.
+    pub(crate) fn snapshot(&self) -> Vec<WatchedSession<K>> {
+        let mut snapshot = Vec::new();
+
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            snapshot.extend(shard.sessions.iter().map(|(key, session)| WatchedSession {
+                key: key.clone(),
+                started_instant: session.started_instant,
+                durations: session.durations,
+            }));
+        }
+
+        snapshot
+    }
+
+    /// Copies out every session's key, locking one shard at a time.
+    pub(crate) fn keys(&self) -> Vec<K> {
+        let mut keys = Vec::new();
+
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            keys.extend(shard.sessions.keys().cloned());
+        }
+
+        keys
+    }
+
+    /// Copies out every session, including its bits, with every shard locked
+    /// at once so the copy is a single point in time.
+    pub(crate) fn snapshot_all(&self) -> Vec<SessionSnapshot<K, O>> {
+        let shards: Vec<_> = self
+            .shards
+            .iter()
+            .map(|shard| shard.lock().unwrap_or_else(PoisonError::into_inner))
+            .collect();
+
+        shards
+            .iter()
+            .flat_map(|shard| &shard.sessions)
+            .map(|(key, session)| SessionSnapshot {
+                key: key.clone(),
+                bits: session.decoder.snapshot(),
+                answered: true,
+                started_instant: session.started_instant,
+                deadline: session.deadline,
+                signals: session.durations + 1,
+            })
+            .collect()
+    }
+
+    pub(crate) fn session_info<Q>(&self, key: &Q) -> Option<SessionInfo>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let shard = self
+            .shard(key)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        shard.sessions.get(key).map(|session| SessionInfo {
+            started_instant: session.started_instant,
+            last_signal_instant: session.last_signal_instant,
+            deadline: session.deadline,
+            signals: session.durations + 1,
+        })
+    }
+
+    /// Closes `key`'s session for `reason`, returning its result. `label`
+    /// names the reason in logs.
+    pub(crate) fn flush<Q>(
+        &self,
+        key: &Q,
+        reason: CloseReason,
+        label: &'static str,
+    ) -> Option<ClosedSession<K, O>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, session) = self
+            .shard(key)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sessions
+            .remove_entry(key)?;
+        Some(self.close_early(key, session, reason, label))
+    }
+
+    /// Closes every session early for `reason`, returning their results.
+    pub(crate) fn close_all(
+        &self,
+        reason: CloseReason,
+        label: &'static str,
+    ) -> Vec<ClosedSession<K, O>> {
+        let mut results = Vec::new();
+
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            let Shard {
+                sessions,
+                wheel,
+                emits,
+            } = &mut *shard;
+            *wheel = TimerWheel::new();
+            *emits = TimerWheel::new();
+
+            for (key, session) in sessions.drain() {
+                results.push(self.close_early(key, session, reason, label));
+            }
+        }
+
+        results
+    }
+
+    /// Moves every session into `target`, returning the closed results of
+    /// sessions whose key `target` already had.
+    pub(crate) fn migrate_into(&self, target: &Self) -> Vec<ClosedSession<K, O>> {
+        let mut conflicts = Vec::new();
+
+        for shard in self.shards.iter() {
+            let sessions = take(
+                &mut shard
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .sessions,
+            );
+
+            for (key, mut session) in sessions {
+                let mut target_shard = target
+                    .shard(&key)
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let Shard {
+                    sessions,
+                    wheel,
+                    emits,
+                } = &mut *target_shard;
+
+                match sessions.entry(key) {
+                    Entry::Occupied(entry) => {
+                        conflicts.push(self.close_early(
+                            entry.key().clone(),
+                            session,
+                            CloseReason::Flushed,
+                            "migration conflict",
+                        ));
+                    }
+                    Entry::Vacant(entry) => {
+                        session.scheduled_tick = target.deadline_tick(session.deadline);
+                        wheel.insert(session.scheduled_tick, entry.key().clone());
+                        session.next_emit = session.started_instant;
+                        target.schedule_emit(emits, entry.key().clone(), &mut session);
+                        self.len.fetch_sub(1, Ordering::Relaxed);
+                        target.len.fetch_add(1, Ordering::Relaxed);
+                        entry.insert(session);
+                    }
+                }
+            }
+        }
+
+        conflicts
+    }
+
+    /// Advances the wheels of the worker's shards, closing expired sessions
+    /// and then taking due snapshots of the remaining ones. Returns whether
+    /// any of those shards still has open sessions.
+    fn expire(
+        &self,
+        worker: usize,
+        workers: usize,
+        results: &mut Vec<ClosedSession<K, O>>,
+        snapshots: &mut Vec<(K, O)>,
+    ) -> bool {
+        let now = self.clock.now();
+        let now_tick = self.elapsed_tick(now);
+        let mut expired = Vec::new();
+        let mut active = false;
+
+        for shard in self.shards.iter().skip(worker).step_by(workers) {
+            let mut shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            let Shard {
+                sessions,
+                wheel,
+                emits,
+            } = &mut *shard;
+
+            wheel.advance(now_tick, &mut expired);
+            for (when, key) in expired.drain(..) {
+                let Entry::Occupied(mut entry) = sessions.entry(key) else {
+                    continue;
+                };
+
+                let session = entry.get_mut();
+                if session.scheduled_tick != when || session.paused.is_some() {
+                    continue;
+                }
+
+                if session.deadline <= now {
+                    let (key, session) = entry.remove_entry();
+                    results.push(self.close_timed_out(key, session));
+                } else {
+                    let tick = self.deadline_tick(session.deadline);
+                    session.scheduled_tick = tick;
+                    wheel.insert(tick, entry.key().clone());
+                }
+            }
+
+            emits.advance(now_tick, &mut expired);
+            for (when, key) in expired.drain(..) {
+                let Entry::Occupied(entry) = sessions.entry(key) else {
+                    continue;
+                };
+
+                if entry.get().emit_tick != when {
+                    continue;
+                }
+
+                let key = entry.key().clone();
+                let session = entry.into_mut();
+                if let Some(bits) = session.decoder.snapshot() {
+                    snapshots.push((key.clone(), bits));
+                }
+                self.schedule_emit(emits, key, session);
+            }
+
+            active |= !sessions.is_empty();
+        }
+
+        active
+    }
+}
+
+impl<K, O, M> TimerWheelSessions<K, O, M>
+where
+    O: DecoderOutput,
+{
+    /// Closes a session removed at its deadline.
+    fn close_timed_out(&self, key: K, session: WheelSession<O, M>) -> ClosedSession<K, O> {
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        let summary = session.summary(CloseReason::Timeout, self.clock.now());
+        let terminal = self
+            .config
+            .terminal_duration
+            .on_timeout()
+            .then_some((session.deadline, None));
+        let bits = session.decoder.close(terminal);
+        instrument::session_closed(&self.redactor, &key, "timeout", bits.symbol_count());
+        (key, bits, summary)
+    }
+
+    /// Whether `session` pushed as many durations as the store allows.
+    fn limit_reached(&self, session: &WheelSession<O, M>) -> bool {
+        self.config
+            .max_durations
+            .is_some_and(|max| session.pushed >= max as u64)
+    }
+
+    /// Closes a session removed before its deadline for `reason`.
+    fn close_early(
+        &self,
+        key: K,
+        session: WheelSession<O, M>,
+        reason: CloseReason,
+        label: &'static str,
+    ) -> ClosedSession<K, O> {
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        let summary = session.summary(reason, self.clock.now());
+        let bits = session.decoder.close(None);
+        instrument::session_closed(&self.redactor, &key, label, bits.symbol_count());
+        (key, bits, summary)
+    }
+}
+
+/// Cuts `timeout` short to a session's lifetime deadline.
+fn clamp_deadline(timeout: Instant, lifetime_deadline: Option<Instant>) -> Instant {
+    lifetime_deadline.map_or(timeout, |deadline| timeout.min(deadline))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bitvec::prelude::*;
+    use futures::StreamExt;
+    use tokio::time::timeout;
+
+    use super::TimerWheelConfig;
+    use crate::{
+        decoder::ThresholdDelayDecoder,
+        encoder::{DelayEncoder, ThresholdDelayEncoder},
+        session_store::DelaySessionStoreBuilder,
+        test_alloc,
+    };
+
+    #[tokio::test]
+    async fn million_idle_keys_stay_small_and_active_keys_decode() {
+        const IDLE_KEYS: u32 = 1_000_000;
+        const ACTIVE_KEYS: u32 = 100;
+
+        let mut encoder =
+            ThresholdDelayEncoder::new(Duration::from_millis(10), Duration::from_millis(30));
+        let threshold = encoder.threshold();
+        let (store, mut results) = DelaySessionStoreBuilder::<u32>::new(Duration::from_secs(3600))
+            .timer_wheel(TimerWheelConfig::default())
+            .build();
+        let start = store.clock().now();
+
+        let before = test_alloc::live_bytes();
+        for key in 0..IDLE_KEYS {
+            store
+                .push_signal(key, start, move || ThresholdDelayDecoder::new(threshold))
+                .await
+                .unwrap();
+        }
+        let per_key = (test_alloc::live_bytes() - before) / IDLE_KEYS as isize;
+        assert_eq!(store.session_count().await, IDLE_KEYS as usize);
+        // A session task with its channel and timer takes several KB.
+        assert!(per_key < 1024, "an idle session takes {per_key} bytes");
+
+        let message = bitvec![1, 0, 1, 1, 0, 0, 1, 0];
+        let delays = encoder.encode(&message);
+        for key in IDLE_KEYS..IDLE_KEYS + ACTIVE_KEYS {
+            let mut instant = start;
+            for delay in [Duration::ZERO].into_iter().chain(delays.iter().copied()) {
+                instant += delay;
+                store
+                    .push_signal_with_timeout(key, instant, Duration::from_millis(50), move || {
+                        ThresholdDelayDecoder::new(threshold)
+                    })
+                    .await
+                    .unwrap();
+            }
+        }
+
+        for _ in 0..ACTIVE_KEYS {
+            let (key, bits) = timeout(Duration::from_secs(10), results.next())
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(key >= IDLE_KEYS, "idle key {key} closed");
+            assert_eq!(bits, message);
+        }
+        assert_eq!(store.session_count().await, IDLE_KEYS as usize);
+    }
+}
@@ -0,0 +1,202 @@
+use alloc::vec::Vec;
+
+use bitvec::{order::Msb0, slice::BitSlice, vec::BitVec, view::BitView};
+
+#[cfg(feature = "std")]
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+/// Assembles `bits` into bytes, most significant bit first. A trailing
+/// partial byte is dropped.
+pub fn pack_bytes(bits: &BitSlice) -> Vec<u8> {
+    bits.chunks_exact(8)
+        .map(|byte| {
+            byte.iter()
+                .by_vals()
+                .fold(0, |acc, bit| acc << 1 | u8::from(bit))
+        })
+        .collect()
+}
+
+/// Splits `bytes` into bits, most significant bit first, the inverse of
+/// [`pack_bytes`].
+pub fn unpack_bits(bytes: &[u8]) -> BitVec {
+    bytes.view_bits::<Msb0>().iter().by_vals().collect()
+}
+
+/// A reversible transform of assembled payload bytes, e.g. obfuscation.
+pub trait PayloadTransform {
+    /// Recovers the payload from received bytes, in place.
+    fn decode(&self, payload: &mut [u8]);
+
+    /// Transforms a payload for sending, in place, so that `decode` recovers
+    /// it. Keystream transforms are their own inverse, which is the default.
+    fn encode(&self, payload: &mut [u8]) {
+        self.decode(payload);
+    }
+}
+
+/// XORs payloads with a repeating key. This obfuscates, but does not
+/// encrypt.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct RepeatingXor {
+    key: Vec<u8>,
+}
+
+impl RepeatingXor {
+    /// # Panics
+    ///
+    /// Panics if `key` is empty.
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        let key = key.into();
+        assert!(!key.is_empty(), "XOR key must not be empty");
+        Self { key }
+    }
+}
+
+impl PayloadTransform for RepeatingXor {
+    fn decode(&self, payload: &mut [u8]) {
+        for (byte, key) in payload.iter_mut().zip(self.key.iter().cycle()) {
+            *byte ^= key;
+        }
+    }
+}
+
+/// Applies the ChaCha20 keystream (RFC 8439) to payloads, starting at block
+/// counter zero. Every payload is encrypted with the same keystream, so a
+/// key and nonce pair should protect a single payload.
+#[cfg(feature = "crypto")]
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ChaCha20 {
+    key: [u8; 32],
+    nonce: [u8; 12],
+}
+
+#[cfg(feature = "crypto")]
+impl ChaCha20 {
+    pub const fn new(key: [u8; 32], nonce: [u8; 12]) -> Self {
+        Self { key, nonce }
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl core::fmt::Debug for ChaCha20 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ChaCha20")
+            .field("nonce", &self.nonce)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl PayloadTransform for ChaCha20 {
+    fn decode(&self, payload: &mut [u8]) {
+        use chacha20::cipher::{KeyIvInit, StreamCipher};
+
+        chacha20::ChaCha20::new(&self.key.into(), &self.nonce.into()).apply_keystream(payload);
+    }
+}
+
+/// Encodes `payload` with `transform` and splits it into the bits to send.
+pub fn encode_payload(transform: &impl PayloadTransform, payload: &[u8]) -> BitVec {
+    let mut payload = payload.to_vec();
+    transform.encode(&mut payload);
+    unpack_bits(&payload)
+}
+
+/// Assembles received `bits` into bytes and decodes them with `transform`.
+pub fn decode_payload(transform: &impl PayloadTransform, bits: &BitSlice) -> Vec<u8> {
+    let mut payload = pack_bytes(bits);
+    transform.decode(&mut payload);
+    payload
+}
+
+#[cfg(feature = "std")]
+type SharedTransform = Arc<dyn PayloadTransform + Send + Sync>;
+
+/// Per-key payload transforms, which can be changed while a store uses
+/// them through [`TransformRegistry::mapper`].
+#[cfg(feature = "std")]
+pub struct TransformRegistry<K> {
+    transforms: Arc<RwLock<HashMap<K, SharedTransform>>>,
+}
+
+#[cfg(feature = "std")]
+impl<K> Clone for TransformRegistry<K> {
+    fn clone(&self) -> Self {
+        Self {
+            transforms: self.transforms.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K> Default for TransformRegistry<K> {
+    fn default() -> Self {
+        Self {
+            transforms: Default::default(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K> core::fmt::Debug for TransformRegistry<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TransformRegistry").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K> TransformRegistry<K>
+where
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the payloads of `key` with `transform`, replacing any it
+    /// had before.
+    pub fn register(&self, key: K, transform: impl PayloadTransform + Send + Sync + 'static) {
+        self.transforms
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, Arc::new(transform));
+    }
+
+    pub fn unregister(&self, key: &K) {
+        self.transforms
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key);
+    }
+
+    /// Assembles `bits` into bytes and decodes them with the transform of
+    /// `key`, if it has one.
+    pub fn decode(&self, key: &K, bits: &BitSlice) -> Vec<u8> {
+        let mut payload = pack_bytes(bits);
+        let transform = self
+            .transforms
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .cloned();
+        if let Some(transform) = transform {
+            transform.decode(&mut payload);
+        }
+        payload
+    }
+
+    /// A result mapper for `DelaySessionStoreBuilder::result_mapper`
+    /// emitting every session's decoded payload.
+    pub fn mapper(&self) -> impl Fn(&K, BitVec) -> Option<Vec<u8>> + Send + Sync + 'static
+    where
+        K: Send + Sync + 'static,
+    {
+        let registry = self.clone();
+        move |key, bits| Some(registry.decode(key, &bits))
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Mutex, PoisonError},
+};
+
+use tokio::sync::oneshot;
+
+/// A caller awaiting a key's next result.
+trait Waiter<T>: Send {
+    fn is_closed(&self) -> bool;
+
+    fn fulfill(self: Box<Self>, result: &T);
+}
+
+impl<T> Waiter<T> for oneshot::Sender<T>
+where
+    T: Clone + Send,
+{
+    fn is_closed(&self) -> bool {
+        oneshot::Sender::is_closed(self)
+    }
+
+    fn fulfill(self: Box<Self>, result: &T) {
+        let _ = self.send(result.clone());
+    }
+}
+
+type BoxedWaiter<T> = Box<dyn Waiter<T>>;
+
+/// Hands copies of a store's results to the callers of
+/// `DelaySessionStore::await_result`, so the result stream still receives
+/// them. Cloning is left to the waiters, so stores of results that are not
+/// `Clone` pay nothing for it.
+pub(crate) struct ResultWaiters<K, T> {
+    waiters: Mutex<HashMap<K, Vec<BoxedWaiter<T>>>>,
+}
+
+impl<K, T> ResultWaiters<K, T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            waiters: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K, T> ResultWaiters<K, T>
+where
+    K: Eq + Hash,
+{
+    /// Registers a waiter for `key`'s next result.
+    pub(crate) fn register(&self, key: K) -> oneshot::Receiver<T>
+    where
+        T: Clone + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let mut waiters = self.waiters.lock().unwrap_or_else(PoisonError::into_inner);
+        let waiters = waiters.entry(key).or_default();
+        waiters.retain(|waiter| !waiter.is_closed());
+        waiters.push(Box::new(sender));
+        receiver
+    }
+
+    /// Drops the waiters of `key` that stopped waiting.
+    pub(crate) fn prune(&self, key: &K) {
+        let mut waiters = self.waiters.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(key_waiters) = waiters.get_mut(key) {
+            key_waiters.retain(|waiter| !waiter.is_closed());
+            if key_waiters.is_empty() {
+                waiters.remove(key);
+            }
+        }
+    }
+
+    /// Hands `result` to every waiter of `key`.
+    pub(crate) fn fulfill(&self, key: &K, result: &T) {
+        let waiters = {
+            let mut waiters = self.waiters.lock().unwrap_or_else(PoisonError::into_inner);
+            if waiters.is_empty() {
+                return;
+            }
+            waiters.remove(key)
+        };
+
+        for waiter in waiters.into_iter().flatten() {
+            waiter.fulfill(result);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::Stream;
+use tokio::{
+    sync::mpsc::{channel, Receiver},
+    time::MissedTickBehavior,
+};
+
+use crate::{
+    clock::Clock, decoder::DecoderOutput, task, task_backend::SharedSignalSenderMap,
+    timer_wheel::TimerWheelSessions,
+};
+
+/// Configuration of a store's watchdog.
+///
+/// Every `interval` the watchdog scans a snapshot of the active sessions and
+/// alerts about sessions open longer than one of the ascending `thresholds`,
+/// which act as escalation levels: a session alerts at most once per level.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct WatchdogConfig {
+    pub interval: Duration,
+    pub thresholds: Vec<Duration>,
+}
+
+/// A session that has been open longer than `thresholds[level]`.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct WatchdogAlert<K> {
+    pub key: K,
+    /// Time since the session's first signal, by the store's clock.
+    pub open_for: Duration,
+    /// Durations pushed to the session's decoder so far, which is its number
+    /// of bits for decoders emitting one bit per duration.
+    pub bits_so_far: u64,
+    pub level: usize,
+}
+
+#[derive(Debug)]
+pub struct WatchdogStream<K> {
+    pub(crate) receiver: Receiver<WatchdogAlert<K>>,
+}
+
+impl<K> Stream for WatchdogStream<K> {
+    type Item = WatchdogAlert<K>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// One active session, as copied out of the store for a scan.
+#[derive(Debug)]
+pub(crate) struct WatchedSession<K> {
+    pub(crate) key: K,
+    pub(crate) started_instant: Instant,
+    pub(crate) durations: u64,
+}
+
+/// The highest level each session has alerted at. A session is identified by
+/// its key and first instant, so a restarted session alerts afresh.
+#[derive(Debug)]
+struct Escalations<K> {
+    levels: HashMap<K, (Instant, usize)>,
+}
+
+impl<K> Escalations<K>
+where
+    K: Clone + Eq + Hash,
+{
+    fn new() -> Self {
+        Self {
+            levels: HashMap::new(),
+        }
+    }
+
+    /// Pushes an alert for every session that reached a new level, and
+    /// forgets sessions missing from `sessions`.
+    fn scan(
+        &mut self,
+        thresholds: &[Duration],
+        now: Instant,
+        sessions: Vec<WatchedSession<K>>,
+        alerts: &mut Vec<WatchdogAlert<K>>,
+    ) {
+        let mut levels = HashMap::with_capacity(self.levels.len());
+
+        for session in sessions {
+            let open_for = now.saturating_duration_since(session.started_instant);
+            let reached = thresholds
+                .iter()
+                .take_while(|threshold| open_for >= **threshold)
+                .count();
+            if reached == 0 {
+                continue;
+            }
+
+            let alerted = match self.levels.remove(&session.key) {
+                Some((started_instant, alerted)) if started_instant == session.started_instant => {
+                    alerted
+                }
+                _ => 0,
+            };
+            if reached > alerted {
+                alerts.push(WatchdogAlert {
+                    key: session.key.clone(),
+                    open_for,
+                    bits_so_far: session.durations,
+                    level: reached - 1,
+                });
+            }
+
+            levels.insert(session.key, (session.started_instant, reached));
+        }
+
+        self.levels = levels;
+    }
+}
+
+/// Weak handles to a store's sessions, so a watchdog or a result waiter does
+/// not keep them alive.
+pub(crate) enum WatchedSessions<K, O, M> {
+    Task(Weak<SharedSignalSenderMap<K, O, M>>),
+    TimerWheel {
+        sessions: Weak<TimerWheelSessions<K, O, M>>,
+        alive: Weak<()>,
+    },
+}
+
+impl<K, O, M> WatchedSessions<K, O, M>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    O: DecoderOutput + Clone + Send + 'static,
+    M: Send + 'static,
+{
+    /// Copies out every active session, or returns `None` once the store has
+    /// been dropped.
+    pub(crate) async fn snapshot(&self) -> Option<Vec<WatchedSession<K>>> {
+        match self {
+            Self::Task(sender_map) => {
+                let sender_map = sender_map.upgrade()?;
+                let sender_map = sender_map.lock().await;
+                Some(
+                    sender_map
+                        .iter()
+                        .map(|(key, entry)| WatchedSession {
+                            key: key.clone(),
+                            started_instant: entry.started_instant,
+                            durations: entry.durations,
+                        })
+                        .collect(),
+                )
+            }
+            Self::TimerWheel { sessions, alive } => {
+                if alive.strong_count() == 0 {
+                    return None;
+                }
+                Some(sessions.upgrade()?.snapshot())
+            }
+        }
+    }
+
+    /// Whether `key` has an active session, which it does not once the store
+    /// has been dropped.
+    pub(crate) async fn contains(&self, key: K) -> bool {
+        match self {
+            Self::Task(sender_map) => match sender_map.upgrade() {
+                Some(sender_map) => sender_map.lock().await.contains_key(&key),
+                None => false,
+            },
+            Self::TimerWheel { sessions, alive } => {
+                alive.strong_count() > 0
+                    && sessions
+                        .upgrade()
+                        .is_some_and(|sessions| sessions.contains(&key))
+            }
+        }
+    }
+}
+
+/// Spawns a watchdog scanning `sessions` as `config` says, named after
+/// `store`, which stops once the store or the returned stream is dropped.
+pub(crate) fn spawn_watchdog<K, O, M>(
+    store: &str,
+    sessions: WatchedSessions<K, O, M>,
+    config: WatchdogConfig,
+    clock: Arc<dyn Clock>,
+) -> WatchdogStream<K>
+where
+    K: Clone + Eq + Hash + Send + 'static,
+    O: DecoderOutput + Clone + Send + 'static,
+    M: Send + 'static,
+{
+    let (sender, receiver) = channel(8);
+
+    task::spawn(|| format!("{store} watchdog"), async move {
+        let mut interval = tokio::time::interval(config.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut escalations = Escalations::new();
+        let mut alerts = Vec::new();
+
+        loop {
+            interval.tick().await;
+
+            let Some(snapshot) = sessions.snapshot().await else {
+                break;
+            };
+            escalations.scan(&config.thresholds, clock.now(), snapshot, &mut alerts);
+            for alert in alerts.drain(..) {
+                if sender.send(alert).await.is_err() {
+                    return;
+                }
+            }
+
+            if sender.is_closed() {
+                break;
+            }
+        }
+    });
+
+    WatchdogStream { receiver }
+}